@@ -0,0 +1,140 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::fmt::Write;
+use trading23::analysis::backtesting_topix::{BacktestingTopixList, TopixDailyWindowList};
+use trading23::analysis::indicators::AnalysisParams;
+use trading23::analysis::stocks_daytrading::{Status, StocksDaytradingList};
+use trading23::analysis::stocks_window::{StocksWindow, StocksWindowList};
+use trading23::database::stocks_ohlc;
+use trading23::jquants::fetcher::Topix;
+
+mod common;
+
+const UNIT: f64 = 10000.0;
+
+fn bench_stocks_window_from_vec(c: &mut Criterion) {
+    let ohlc_vec = common::ohlc_vec(1000);
+    let date = common::date(common::DAYS - 1);
+    c.bench_function("StocksWindow::from_vec", |b| {
+        b.iter(|| {
+            StocksWindow::from_vec(
+                &ohlc_vec,
+                &common::stock_code(1000),
+                "銘柄名",
+                UNIT,
+                &date,
+                &AnalysisParams::default(),
+            )
+            .unwrap()
+        })
+    });
+}
+
+/// TOPIX bars over the same dates as `common::ohlc_vec`
+fn topix_daily_window_list() -> TopixDailyWindowList {
+    let mut json = String::from(r#"{"topix":["#);
+    for i in 0..common::DAYS {
+        let x = i as f64;
+        let open = 2000.0 + (x * 0.9).sin() * 20.0;
+        let close = open + (x * 1.7).cos() * 10.0;
+        if i > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            r#"{{"Date":"{}","Open":{},"High":{},"Low":{},"Close":{}}}"#,
+            common::date(i),
+            open,
+            open.max(close) + 3.0,
+            open.min(close) - 3.0,
+            close
+        )
+        .unwrap();
+    }
+    json.push_str("]}");
+
+    let topix: Topix = serde_json::from_str(&json).unwrap();
+    TopixDailyWindowList::new(&BacktestingTopixList::from(topix))
+}
+
+fn bench_bucket_statistics(c: &mut Criterion) {
+    let (from, to) = (common::date(0), common::date(common::DAYS - 1));
+    let mut list = StocksDaytradingList::new();
+    for code in common::CODES {
+        list.push_2(
+            common::ohlc_vec(code),
+            &common::stock_code(code),
+            "銘柄名",
+            UNIT,
+            &from,
+            &to,
+        );
+    }
+    let topix_daily_window_list = topix_daily_window_list();
+
+    c.bench_function("get_windows_related_result_2", |b| {
+        b.iter(|| {
+            list.get_windows_related_result_2(Status::BreakoutResistance, &topix_daily_window_list)
+        })
+    });
+}
+
+/// Fills a throwaway trading23.sqlite under the temp dir and points GDRIVE_PATH at it
+fn open_bench_db() -> rusqlite::Connection {
+    let gdrive_path = std::env::temp_dir().join("trading23_bench");
+    let db_path = gdrive_path.join("trading23").join("trading23.sqlite");
+    if db_path.exists() {
+        std::fs::remove_file(&db_path).unwrap();
+    }
+    std::fs::create_dir_all(db_path.parent().unwrap()).unwrap();
+    std::env::set_var("GDRIVE_PATH", &gdrive_path);
+
+    let conn = stocks_ohlc::open_db().unwrap();
+    conn.execute_batch("BEGIN").unwrap();
+    for code in common::CODES {
+        for ohlc in common::ohlc_vec(code) {
+            stocks_ohlc::insert(&conn, &ohlc).unwrap();
+        }
+    }
+    conn.execute_batch("COMMIT").unwrap();
+    conn
+}
+
+fn bench_stocks_window_list_from_db(c: &mut Criterion) {
+    let conn = open_bench_db();
+    let (from, to) = (
+        common::date(common::DAYS - 5),
+        common::date(common::DAYS - 1),
+    );
+    let trading_days = stocks_ohlc::select_dates(&conn, &from, &to).unwrap();
+
+    let mut group = c.benchmark_group("db");
+    group.sample_size(10);
+    group.bench_function("StocksWindowList::from_db (225 codes)", |b| {
+        b.iter(|| {
+            common::CODES
+                .map(|code| {
+                    StocksWindowList::from_db(
+                        &conn,
+                        &common::stock_code(code),
+                        "銘柄名",
+                        UNIT,
+                        &from,
+                        &to,
+                        &trading_days,
+                        None,
+                    )
+                    .unwrap()
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_stocks_window_from_vec,
+    bench_bucket_statistics,
+    bench_stocks_window_list_from_db
+);
+criterion_main!(benches);
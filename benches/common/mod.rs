@@ -0,0 +1,39 @@
+use chrono::{Duration, NaiveDate};
+use trading23::analysis::live::OhlcPremium;
+use trading23::stock_code::StockCode;
+
+/// Trading days per code, enough for 40 dates with a 60-day window
+pub const DAYS: usize = 100;
+/// Codes per day, the size of the Nikkei 225
+pub const CODES: std::ops::Range<usize> = 1000..1225;
+
+pub fn date(i: usize) -> String {
+    (NaiveDate::from_ymd_opt(2023, 1, 1).unwrap() + Duration::days(i as i64))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+pub fn stock_code(code: usize) -> StockCode {
+    StockCode::new(&code.to_string()).unwrap()
+}
+
+/// Deterministic wavy bars, different per code
+pub fn ohlc_vec(code: usize) -> Vec<OhlcPremium> {
+    (0..DAYS)
+        .map(|i| {
+            let x = (i + code * 7) as f64;
+            let open = 1000.0 + (x * 0.7).sin() * 50.0 + x;
+            let close = open + (x * 1.3).cos() * 15.0;
+            OhlcPremium::new(
+                stock_code(code),
+                date(i),
+                open,
+                open.max(close) + 5.0 + (i % 4) as f64,
+                open.min(close) - 5.0 - (i % 3) as f64,
+                close,
+                (open + close) / 2.0,
+                (open + close) / 2.0 + 1.0,
+            )
+        })
+        .collect()
+}
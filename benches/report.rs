@@ -0,0 +1,37 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::collections::HashSet;
+use trading23::analysis::stocks_window::StocksWindowList;
+use trading23::i18n::Lang;
+
+mod common;
+
+/// One day of the Nikkei 225
+fn stocks_window_list() -> StocksWindowList {
+    let date = common::date(common::DAYS - 2);
+    let mut list = StocksWindowList::new();
+    for code in common::CODES {
+        list.push(
+            common::ohlc_vec(code),
+            &common::stock_code(code),
+            "銘柄名テスト",
+            10000.0,
+            &date,
+            &date,
+            &HashSet::new(),
+        );
+    }
+    list
+}
+
+fn bench_report(c: &mut Criterion) {
+    let list = stocks_window_list();
+    c.bench_function("output_for_markdown_resistance_support", |b| {
+        b.iter(|| {
+            list.output_for_markdown_resistance_support(false, None, None, Lang::Ja)
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench_report);
+criterion_main!(benches);
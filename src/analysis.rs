@@ -1,6 +1,19 @@
 pub mod backtesting;
 pub mod backtesting_topix;
+pub mod capacity;
+pub mod code_history;
+pub mod correlation;
+pub mod dataset;
+pub mod drift;
+pub mod horizons;
+pub mod indicators;
 pub mod live;
+pub mod macd;
+pub mod scoring;
+pub mod sector_heat;
+pub mod sessions;
 pub mod stocks_afternoon;
 pub mod stocks_daytrading;
+pub mod stocks_quick;
 pub mod stocks_window;
+pub mod stress;
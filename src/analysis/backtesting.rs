@@ -1,5 +1,6 @@
 use super::live::Ohlc;
 use crate::my_error::MyError;
+use crate::rounding::{round_dp, trunc_dp};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,8 +71,7 @@ impl BacktestAnalyzer {
             .sum();
         let average_diff = diff_sum / testing_ohlc_60.len() as f64;
 
-        let standardized_diff =
-            (average_diff / (highest_high - lowest_low) * 1000.0).trunc() / 1000.0;
+        let standardized_diff = trunc_dp(average_diff / (highest_high - lowest_low), 3);
 
         // let mut ohlc_vec = testing_ohlc_60.to_vec();
         // for i in 0..testing_ohlc_60.len() - 1 {
@@ -92,10 +92,10 @@ impl BacktestAnalyzer {
         ) -> f64 {
             let result =
                 (future_ohlc[day_x].get_close() - future_ohlc[0].get_open()) / stop_loss_range;
-            let result_rounded = (result * 100.0).round() / 100.0;
+            let result_rounded = round_dp(result, 2);
             match long_or_short_or_control {
                 LongShortControl::Long | LongShortControl::Control => result_rounded,
-                LongShortControl::Short => result_rounded * -1.0,
+                LongShortControl::Short => -result_rounded,
             }
         }
 
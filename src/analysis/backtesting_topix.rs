@@ -1,5 +1,8 @@
+use crate::analysis::live::Ohlc;
+use crate::database::topix_ohlc;
 use crate::jquants::fetcher::Topix;
 use crate::my_error::MyError;
+use crate::rounding::round_dp;
 use chrono::{Datelike, NaiveDate};
 use log::info;
 // use reqwest::Client;
@@ -23,6 +26,13 @@ pub struct BacktestingTopix {
 pub struct BacktestingTopixList {
     data: Vec<BacktestingTopix>,
 }
+impl From<Topix> for BacktestingTopixList {
+    fn from(topix: Topix) -> Self {
+        Self {
+            data: Self::into_backtesting_topix_list(&topix.to_ohlc_vec()),
+        }
+    }
+}
 impl BacktestingTopixList {
     // pub async fn from_fetch_topix(client: &Client) -> Result<Self, MyError> {
     //     let topix = Topix::new(client).await?;
@@ -32,25 +42,34 @@ impl BacktestingTopixList {
     //     })
     // }
 
-    pub fn from_json_file() -> Result<Self, MyError> {
-        let path = crate::my_file_io::get_topix_ohlc_file_path().unwrap();
-        let file = File::open(path).unwrap();
-        let topix: Topix = serde_json::from_reader(file).unwrap();
+    /// Reads topix_ohlc. An empty table is first filled from topix.json, written by
+    /// earlier versions.
+    pub fn from_db() -> Result<Self, MyError> {
+        let mut conn = topix_ohlc::open_db()?;
+        let mut ohlc_vec = topix_ohlc::select_all(&conn)?;
+        if ohlc_vec.is_empty() {
+            let path = crate::my_file_io::get_topix_ohlc_file_path()?;
+            info!("topix_ohlc is empty, importing {}", path.display());
+            let topix: Topix = serde_json::from_reader(File::open(path)?)?;
+            ohlc_vec = topix.to_ohlc_vec();
+            topix_ohlc::insert(&mut conn, &ohlc_vec)?;
+        }
 
         Ok(Self {
-            data: Self::into_backtesting_topix_list(topix),
+            data: Self::into_backtesting_topix_list(&ohlc_vec),
         })
     }
 
-    fn into_backtesting_topix_list(topix: Topix) -> Vec<BacktestingTopix> {
+    /// `ohlc_vec` oldest first, the last day has no next open and is left out
+    fn into_backtesting_topix_list(ohlc_vec: &[Ohlc]) -> Vec<BacktestingTopix> {
         let mut backtesting_topix = Vec::new();
-        for i in 0..topix.get_len_of_topix() - 1 {
-            let ohlc = topix.get_ohlc(i);
+        for pair in ohlc_vec.windows(2) {
+            let (ohlc, next) = (&pair[0], &pair[1]);
             let date = NaiveDate::parse_from_str(ohlc.get_date(), "%Y-%m-%d").unwrap();
             let weekday = date.weekday().to_string();
-            let next_open = topix.get_ohlc(i + 1).get_open();
-            let window = ((next_open - ohlc.get_close()) * 100.0).round() / 100.0;
-            let window_diff = (next_open / ohlc.get_close() * 1000.0).round() / 1000.0;
+            let next_open = next.get_open();
+            let window = round_dp(next_open - ohlc.get_close(), 2);
+            let window_diff = round_dp(next_open / ohlc.get_close(), 3);
             let backtesting_inner = BacktestingTopix {
                 date: ohlc.get_date().to_string(),
                 open: ohlc.get_open(),
@@ -0,0 +1,220 @@
+use log::warn;
+use std::sync::OnceLock;
+
+use crate::config::GdriveJson;
+use crate::stock_code::StockCode;
+use crate::units::Yen;
+
+/// Required amounts are rounded up to this, so the plan never goes over the cash
+const STEP: usize = 1000;
+/// `maxParticipation` (%) when the config has none usable
+const DEFAULT_PARTICIPATION: f64 = 1.0;
+
+/// Short candidates are sold on margin, so they take the margin requirement of the
+/// broker out of the cash instead of the notional
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Margin {
+    /// Share of the notional, 0.3 at most brokers
+    rate: f64,
+    /// Smallest requirement of a position
+    minimum: Yen,
+}
+
+impl Margin {
+    pub fn new(rate: f64, minimum: Yen) -> Self {
+        Margin { rate, minimum }
+    }
+
+    /// Set when `shortOnMargin` is, read once per run
+    pub fn from_config() -> Option<Margin> {
+        static MARGIN: OnceLock<Option<Margin>> = OnceLock::new();
+        *MARGIN.get_or_init(|| GdriveJson::new().ok().and_then(|x| x.margin()))
+    }
+
+    pub fn requirement(&self, notional: Yen) -> Yen {
+        Yen(((notional.0 as f64 * self.rate) as i32).max(self.minimum.0))
+    }
+}
+
+/// `required_amount` of a candidate, the margin requirement for shorts when configured
+pub fn required_amount(notional: Yen, short: bool) -> Yen {
+    match (short, Margin::from_config()) {
+        (true, Some(margin)) => margin.requirement(notional),
+        _ => notional,
+    }
+}
+
+/// `maxParticipation` of config.json (%), read once per run. 1 when the config can't be
+/// read or the value isn't over 0 and up to 100
+pub fn max_participation() -> f64 {
+    static MAX_PARTICIPATION: OnceLock<f64> = OnceLock::new();
+    *MAX_PARTICIPATION.get_or_init(|| match GdriveJson::new() {
+        Ok(config) => match config.max_participation() {
+            x if x > 0.0 && x <= 100.0 => x,
+            x => {
+                warn!("maxParticipation {} ignored, not over 0 and up to 100", x);
+                DEFAULT_PARTICIPATION
+            }
+        },
+        Err(e) => {
+            warn!("maxParticipation unavailable: {}", e);
+            DEFAULT_PARTICIPATION
+        }
+    })
+}
+
+/// Yen a position can take of a stock trading `turnover` a day on average while staying
+/// within `participation` (%) of it
+pub fn absorbable(turnover: f64, participation: f64) -> f64 {
+    turnover * participation / 100.0
+}
+
+/// A nextday candidate to fit in the cash of the day
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    code: StockCode,
+    name: String,
+    required_amount: Yen,
+    score: f64,
+}
+
+impl Candidate {
+    pub fn new(code: StockCode, name: &str, required_amount: Yen, score: f64) -> Self {
+        Candidate {
+            code,
+            name: name.to_owned(),
+            required_amount,
+            score,
+        }
+    }
+
+    //getters
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_required_amount(&self) -> Yen {
+        self.required_amount
+    }
+    pub fn get_score(&self) -> f64 {
+        self.score
+    }
+
+    fn steps(&self) -> usize {
+        (self.required_amount.0.max(0) as usize).div_ceil(STEP)
+    }
+}
+
+/// The candidates taken and what is left of the cash
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    selected: Vec<Candidate>,
+    used: Yen,
+    leftover: Yen,
+}
+
+impl Plan {
+    //getters
+    pub fn get_selected(&self) -> &[Candidate] {
+        &self.selected
+    }
+    pub fn get_used(&self) -> Yen {
+        self.used
+    }
+    pub fn get_leftover(&self) -> Yen {
+        self.leftover
+    }
+}
+
+/// The candidates whose required amounts fit in `cash` with the largest total score
+/// (0/1 knapsack), in the order given
+pub fn plan(candidates: &[Candidate], cash: Yen) -> Plan {
+    let capacity = cash.0.max(0) as usize / STEP;
+    // best[i][c]: best total score of the first i candidates within c steps
+    let mut best = vec![vec![0.0f64; capacity + 1]; candidates.len() + 1];
+    for (i, candidate) in candidates.iter().enumerate() {
+        let steps = candidate.steps();
+        for c in 0..=capacity {
+            best[i + 1][c] = match steps <= c {
+                true => best[i][c].max(best[i][c - steps] + candidate.score),
+                false => best[i][c],
+            };
+        }
+    }
+
+    let mut selected = Vec::new();
+    let mut c = capacity;
+    for i in (0..candidates.len()).rev() {
+        if best[i + 1][c] != best[i][c] {
+            selected.push(candidates[i].clone());
+            c -= candidates[i].steps();
+        }
+    }
+    selected.reverse();
+
+    let used = selected.iter().map(|x| x.required_amount.0).sum::<i32>();
+    Plan {
+        selected,
+        used: Yen(used),
+        leftover: Yen(cash.0 - used),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let candidate = |code: &str, required_amount: i32, score: f64| {
+            Candidate::new(
+                StockCode::new(code).unwrap(),
+                code,
+                Yen(required_amount),
+                score,
+            )
+        };
+        let candidates = [
+            candidate("7203", 600_000, 0.9),
+            candidate("6758", 500_000, 0.6),
+            candidate("9984", 500_000, 0.6),
+            candidate("8306", 1_200_000, 2.0),
+        ];
+
+        // the two at 0.6 beat the best one alone
+        let plan = plan(&candidates, Yen(1_000_000));
+        let codes = plan
+            .get_selected()
+            .iter()
+            .map(|x| x.get_code().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(codes, ["6758", "9984"]);
+        assert_eq!(plan.get_used(), Yen(1_000_000));
+        assert_eq!(plan.get_leftover(), Yen(0));
+
+        // 600,500 yen takes 601 steps
+        let plan = super::plan(&[candidate("7203", 600_500, 0.9)], Yen(600_999));
+        assert!(plan.get_selected().is_empty());
+        assert_eq!(plan.get_leftover(), Yen(600_999));
+
+        let plan = super::plan(&candidates, Yen(2_000_000));
+        assert_eq!(plan.get_selected().len(), 2);
+        assert_eq!(plan.get_selected()[1].get_code().to_string(), "8306");
+        assert_eq!(plan.get_leftover(), Yen(200_000));
+    }
+
+    #[test]
+    fn test_absorbable() {
+        assert_eq!(absorbable(3_000_000_000.0, 1.0), 30_000_000.0);
+        assert_eq!(absorbable(3_000_000_000.0, 0.5), 15_000_000.0);
+    }
+
+    #[test]
+    fn test_margin() {
+        let margin = Margin::new(0.3, Yen(300_000));
+        assert_eq!(margin.requirement(Yen(2_000_000)), Yen(600_000));
+        assert_eq!(margin.requirement(Yen(500_000)), Yen(300_000));
+    }
+}
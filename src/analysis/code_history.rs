@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs::File;
+
+use crate::database::stocks_ohlc;
+use crate::i18n::{status_text, Lang, Msg};
+use crate::markdown::Markdown;
+use crate::my_error::MyError;
+use crate::my_file_io::get_code_page_path;
+use crate::profile::{self, Stage};
+use crate::stock_code::StockCode;
+use crate::units::AtrUnits;
+
+use super::live::OhlcPremium;
+
+const CHART_DAYS: usize = 250;
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 240.0;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum CandidateKind {
+    Resistance,
+    Support,
+}
+impl CandidateKind {
+    fn text(&self, lang: Lang) -> &'static str {
+        match self {
+            CandidateKind::Resistance => Msg::Resistance.text(lang),
+            CandidateKind::Support => Msg::Support.text(lang),
+        }
+    }
+    fn color(&self) -> &'static str {
+        match self {
+            CandidateKind::Resistance => "crimson",
+            CandidateKind::Support => "royalblue",
+        }
+    }
+}
+
+/// A day the code was listed in a daily report
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Appearance {
+    date: String,
+    kind: CandidateKind,
+    status: String,
+    standardized_diff: f64,
+    result_morning: Option<AtrUnits>,
+    result_afternoon: Option<AtrUnits>,
+    result_allday: Option<AtrUnits>,
+}
+impl Appearance {
+    pub fn new(
+        date: &str,
+        kind: CandidateKind,
+        status: &str,
+        standardized_diff: f64,
+        results: [Option<AtrUnits>; 3],
+    ) -> Self {
+        let [result_morning, result_afternoon, result_allday] = results;
+        Appearance {
+            date: date.to_owned(),
+            kind,
+            status: status.to_owned(),
+            standardized_diff,
+            result_morning,
+            result_afternoon,
+            result_allday,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CodeHistory {
+    code: StockCode,
+    name: String,
+    appearances: Vec<Appearance>,
+}
+
+impl CodeHistory {
+    pub fn load(code: &StockCode, name: &str) -> Result<Self, MyError> {
+        let path = get_code_page_path(code)?.with_extension("json");
+        match path.exists() {
+            true => Ok(serde_json::from_reader(File::open(path)?)?),
+            false => Ok(CodeHistory {
+                code: code.clone(),
+                name: name.to_owned(),
+                appearances: Vec::new(),
+            }),
+        }
+    }
+
+    /// Same date and kind are overwritten, so outcomes fill in on later runs
+    pub fn merge(&mut self, appearance: Appearance) {
+        self.appearances
+            .retain(|x| !(x.date == appearance.date && x.kind == appearance.kind));
+        self.appearances.push(appearance);
+        self.appearances.sort_by(|a, b| b.date.cmp(&a.date));
+    }
+
+    fn output_for_markdown(
+        &self,
+        ohlc_vec: &[OhlcPremium],
+        lang: Lang,
+    ) -> Result<Markdown, MyError> {
+        let _span = profile::span(Stage::Render);
+        let mut markdown = Markdown::new();
+        markdown.h1(&format!("{} {}", self.code, self.name))?;
+
+        markdown.h3(Msg::Chart.text(lang))?;
+        markdown.body(&svg_chart(ohlc_vec, &self.appearances)?)?;
+
+        markdown.h3(Msg::History.text(lang))?;
+        for appearance in &self.appearances {
+            let mut line = format!(
+                "{} {} {} SD: {}",
+                appearance.date,
+                appearance.kind.text(lang),
+                status_text(&appearance.status, lang),
+                appearance.standardized_diff
+            );
+            if let (Some(morning), Some(afternoon), Some(allday)) = (
+                appearance.result_morning,
+                appearance.result_afternoon,
+                appearance.result_allday,
+            ) {
+                write!(
+                    line,
+                    " → {}: {}, {}: {}, {}: {}",
+                    Msg::Morning.text(lang),
+                    morning,
+                    Msg::Afternoon.text(lang),
+                    afternoon,
+                    Msg::Allday.text(lang),
+                    allday
+                )?;
+            }
+            markdown.body(&line)?;
+        }
+
+        Ok(markdown)
+    }
+
+    /// Saves the history and rewrites the html page with a chart from the DB
+    pub fn write(&self, conn: &rusqlite::Connection, lang: Lang) -> Result<(), MyError> {
+        let path = get_code_page_path(&self.code)?;
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        serde_json::to_writer_pretty(File::create(path.with_extension("json"))?, self)?;
+
+        let mut ohlc_vec = stocks_ohlc::select_by_code(conn, &self.code)?
+            .into_iter()
+            .map(|x| x.get_inner())
+            .collect::<Vec<_>>();
+        ohlc_vec.sort_by(|a, b| a.get_date().cmp(b.get_date()));
+        let skip = ohlc_vec.len().saturating_sub(CHART_DAYS);
+
+        self.output_for_markdown(&ohlc_vec[skip..], lang)?
+            .write_to_html(&path)
+    }
+}
+
+/// Link to the code page, relative to a daily report (`<style>/YYYY-M/D.html`)
+pub fn code_link(code: &str) -> CodeLink<'_> {
+    CodeLink(code)
+}
+
+pub struct CodeLink<'a>(&'a str);
+impl std::fmt::Display for CodeLink<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}](../../jquants_codes/{}.html)", self.0, self.0)
+    }
+}
+
+/// Close line of `ohlc_vec` with markers on the days the code was listed
+fn svg_chart(ohlc_vec: &[OhlcPremium], appearances: &[Appearance]) -> Result<String, MyError> {
+    if ohlc_vec.len() < 2 {
+        return Ok(String::new());
+    }
+
+    let highest = ohlc_vec
+        .iter()
+        .map(|x| x.get_close())
+        .fold(f64::NAN, f64::max);
+    let lowest = ohlc_vec
+        .iter()
+        .map(|x| x.get_close())
+        .fold(f64::NAN, f64::min);
+    let range = match highest - lowest {
+        x if x > 0.0 => x,
+        _ => 1.0,
+    };
+    let point = |i: usize, close: f64| {
+        let x = i as f64 / (ohlc_vec.len() - 1) as f64 * CHART_WIDTH;
+        let y = (highest - close) / range * CHART_HEIGHT;
+        (x, y)
+    };
+
+    let mut buffer = String::new();
+    write!(
+        buffer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="-5 -5 {} {}">"#,
+        CHART_WIDTH,
+        CHART_HEIGHT,
+        CHART_WIDTH + 10.0,
+        CHART_HEIGHT + 10.0
+    )?;
+    write!(buffer, r#"<polyline fill="none" stroke="gray" points=""#)?;
+    for (i, ohlc) in ohlc_vec.iter().enumerate() {
+        let (x, y) = point(i, ohlc.get_close());
+        write!(buffer, "{:.1},{:.1} ", x, y)?;
+    }
+    write!(buffer, r#""/>"#)?;
+    for appearance in appearances {
+        if let Some(i) = ohlc_vec
+            .iter()
+            .position(|x| x.get_date() == appearance.date)
+        {
+            let (x, y) = point(i, ohlc_vec[i].get_close());
+            write!(
+                buffer,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="4" fill="{}"/>"#,
+                x,
+                y,
+                appearance.kind.color()
+            )?;
+        }
+    }
+    write!(buffer, "</svg>")?;
+
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn appearance(date: &str, kind: CandidateKind, allday: Option<f64>) -> Appearance {
+        Appearance::new(date, kind, "Rise", 0.1, [allday.map(AtrUnits); 3])
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut history = CodeHistory {
+            code: StockCode::new("7203").unwrap(),
+            name: "トヨタ".to_owned(),
+            appearances: Vec::new(),
+        };
+        history.merge(appearance("2024-01-04", CandidateKind::Resistance, None));
+        history.merge(appearance("2024-01-05", CandidateKind::Resistance, None));
+        history.merge(appearance("2024-01-04", CandidateKind::Support, None));
+        history.merge(appearance(
+            "2024-01-04",
+            CandidateKind::Resistance,
+            Some(0.5),
+        ));
+
+        assert_eq!(history.appearances.len(), 3);
+        assert_eq!(history.appearances[0].date, "2024-01-05");
+        assert!(
+            history
+                .appearances
+                .iter()
+                .any(|x| x.kind == CandidateKind::Resistance
+                    && x.result_allday == Some(AtrUnits(0.5)))
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate};
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+use crate::analysis::live::OhlcPremium;
+use crate::database::stocks_ohlc;
+use crate::my_error::MyError;
+use crate::rounding::round_dp;
+use crate::stock_code::StockCode;
+
+/// Daily returns the correlations are computed over
+pub const CORRELATION_PERIOD: usize = 60;
+/// Candidates correlated above this double up the same risk
+pub const MAX_CORRELATION: f64 = 0.8;
+/// Fewer common days than this give no correlation
+const MIN_OVERLAP: usize = 20;
+/// Calendar days of bars read for `CORRELATION_PERIOD` trading days
+const LOOKBACK_DAYS: i64 = 100;
+
+/// date -> close-to-close return of the last `period` bars of `ohlc_vec` (oldest first)
+pub fn daily_returns(ohlc_vec: &[OhlcPremium], period: usize) -> HashMap<String, f64> {
+    let start = ohlc_vec.len().saturating_sub(period + 1);
+    ohlc_vec[start..]
+        .windows(2)
+        .map(|x| {
+            let change = x[1].get_close() / x[0].get_close() - 1.0;
+            (x[1].get_date().to_owned(), change)
+        })
+        .collect()
+}
+
+/// Pearson correlation of the returns on the dates both have, None for fewer than
+/// `MIN_OVERLAP` of them or one that didn't move
+pub fn correlation(a: &HashMap<String, f64>, b: &HashMap<String, f64>) -> Option<f64> {
+    let pairs = a
+        .iter()
+        .filter_map(|(date, x)| b.get(date).map(|y| (*x, *y)))
+        .collect::<Vec<_>>();
+    if pairs.len() < MIN_OVERLAP {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_a = pairs.iter().map(|x| x.0).sum::<f64>() / n;
+    let mean_b = pairs.iter().map(|x| x.1).sum::<f64>() / n;
+    let (covariance, var_a, var_b) =
+        pairs
+            .iter()
+            .fold((0.0, 0.0, 0.0), |(cov, var_a, var_b), (x, y)| {
+                let (dx, dy) = (x - mean_a, y - mean_b);
+                (cov + dx * dy, var_a + dx * dx, var_b + dy * dy)
+            });
+    match var_a > 0.0 && var_b > 0.0 {
+        true => Some(covariance / (var_a * var_b).sqrt()),
+        false => None,
+    }
+}
+
+/// Correlations of the daily returns of every pair of the codes
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrelationMatrix {
+    codes: Vec<StockCode>,
+    /// row-major, rounded to 0.01, None where `correlation` is
+    values: Vec<Option<f64>>,
+}
+
+impl CorrelationMatrix {
+    pub fn new(returns: &[(StockCode, HashMap<String, f64>)]) -> Self {
+        let n = returns.len();
+        let mut values = vec![None; n * n];
+        for i in 0..n {
+            values[i * n + i] = Some(1.0);
+            for j in (i + 1)..n {
+                let rho = correlation(&returns[i].1, &returns[j].1).map(|x| round_dp(x, 2));
+                values[i * n + j] = rho;
+                values[j * n + i] = rho;
+            }
+        }
+        CorrelationMatrix {
+            codes: returns.iter().map(|x| x.0.clone()).collect(),
+            values,
+        }
+    }
+
+    //getters
+    pub fn get_codes(&self) -> &[StockCode] {
+        &self.codes
+    }
+
+    pub fn get(&self, a: &StockCode, b: &StockCode) -> Option<f64> {
+        let i = self.codes.iter().position(|x| x == a)?;
+        let j = self.codes.iter().position(|x| x == b)?;
+        self.values[i * self.codes.len() + j]
+    }
+
+    /// Pairs correlated above `threshold`, the most correlated first
+    pub fn pairs_above(&self, threshold: f64) -> Vec<(&StockCode, &StockCode, f64)> {
+        let n = self.codes.len();
+        let mut pairs = Vec::new();
+        for i in 0..n {
+            for j in (i + 1)..n {
+                if let Some(rho) = self.values[i * n + j].filter(|x| *x > threshold) {
+                    pairs.push((&self.codes[i], &self.codes[j], rho));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.2.total_cmp(&a.2));
+        pairs
+    }
+}
+
+/// The matrix of `codes` over the `CORRELATION_PERIOD` days until `date` ("YYYY-MM-DD")
+pub fn load(
+    conn: &Connection,
+    codes: &[StockCode],
+    date: &str,
+) -> Result<CorrelationMatrix, MyError> {
+    let to = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| MyError::Anyhow(anyhow!("{}: {}", date, e)))?;
+    let from = (to - Duration::days(LOOKBACK_DAYS)).to_string();
+    let mut returns = Vec::new();
+    for code in codes {
+        let bars = stocks_ohlc::select_by_code_and_range(conn, code, &from, date)?
+            .into_iter()
+            .map(|x| x.get_inner())
+            .collect::<Vec<_>>();
+        returns.push((code.clone(), daily_returns(&bars, CORRELATION_PERIOD)));
+    }
+    Ok(CorrelationMatrix::new(&returns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlation_matrix() {
+        let series = |f: &dyn Fn(f64) -> f64| {
+            (0..30)
+                .map(|i| (format!("2024-01-{:02}", i + 1), f(i as f64)))
+                .collect::<HashMap<_, _>>()
+        };
+        let base = series(&|i| ((i * 7.0) % 5.0 - 2.0) / 100.0);
+        let doubled = series(&|i| ((i * 7.0) % 5.0 - 2.0) / 50.0);
+        let opposite = series(&|i| (2.0 - (i * 7.0) % 5.0) / 100.0 + 0.001);
+        let flat = series(&|_| 0.0);
+        assert_eq!(
+            correlation(&base, &doubled).map(|x| round_dp(x, 2)),
+            Some(1.0)
+        );
+        assert_eq!(
+            correlation(&base, &opposite).map(|x| round_dp(x, 2)),
+            Some(-1.0)
+        );
+        assert_eq!(correlation(&base, &flat), None);
+        let short = base.iter().take(10).map(|(k, v)| (k.clone(), *v)).collect();
+        assert_eq!(correlation(&base, &short), None);
+
+        let code = |x: &str| StockCode::new(x).unwrap();
+        let matrix = CorrelationMatrix::new(&[
+            (code("7203"), base),
+            (code("7267"), doubled),
+            (code("6758"), opposite),
+        ]);
+        assert_eq!(matrix.get(&code("7203"), &code("7203")), Some(1.0));
+        assert_eq!(matrix.get(&code("6758"), &code("7267")), Some(-1.0));
+        assert_eq!(matrix.get(&code("7203"), &code("9984")), None);
+        let pairs = matrix.pairs_above(MAX_CORRELATION);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].0.as_str(), pairs[0].1.as_str()), ("7203", "7267"));
+    }
+
+    #[test]
+    fn test_daily_returns() {
+        let bar = |date: &str, close: f64| OhlcPremium::test_bar(date, close, close, close, close);
+        let ohlc_vec = [
+            bar("2024-01-04", 100.0),
+            bar("2024-01-05", 110.0),
+            bar("2024-01-09", 99.0),
+        ];
+        let returns = daily_returns(&ohlc_vec, 1);
+        assert_eq!(returns.len(), 1);
+        assert_eq!(round_dp(returns["2024-01-09"], 2), -0.1);
+        assert_eq!(daily_returns(&ohlc_vec, 60).len(), 2);
+    }
+}
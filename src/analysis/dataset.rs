@@ -0,0 +1,163 @@
+use serde::Serialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::live::BullBear;
+use super::stocks_window::create_stocks_window_list_db;
+use crate::database::stocks_master;
+use crate::my_error::MyError;
+use crate::my_file_io::{get_dataset_file_path, Universe};
+use crate::stock_code::StockCode;
+use crate::units::AtrUnits;
+
+/// One (code, date) of the windows analysis with its results on the next day,
+/// so models can be trained offline without recomputing the features
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DatasetRow {
+    pub(super) code: StockCode,
+    /// analysis date, "YYYY-MM-DD"
+    pub(super) date: String,
+    /// empty until `trading23 master` has run
+    pub(super) sector: Option<String>,
+    pub(super) current_price: f64,
+    pub(super) atr: f64,
+    pub(super) standardized_diff: f64,
+    pub(super) latest_move: f64,
+    pub(super) lower_bound: f64,
+    pub(super) upper_bound: f64,
+    pub(super) resistance_candles: usize,
+    pub(super) support_candles: usize,
+    pub(super) status: String,
+    pub(super) regime: BullBear,
+    /// forward returns in ATR, empty for the last date
+    pub(super) result_morning: Option<AtrUnits>,
+    pub(super) result_afternoon: Option<AtrUnits>,
+    pub(super) result_allday: Option<AtrUnits>,
+}
+
+/// Inputs of a scoring model, in this order, see `DatasetRow::features`
+pub const FEATURES: [&str; 9] = [
+    "current_price",
+    "atr",
+    "standardized_diff",
+    "latest_move",
+    "lower_bound",
+    "upper_bound",
+    "resistance_candles",
+    "support_candles",
+    "regime",
+];
+
+impl DatasetRow {
+    /// The numeric columns named in `FEATURES`, regime as Bull 1, Bear -1, NoTrend 0
+    pub fn features(&self) -> Vec<f64> {
+        let regime = match self.regime {
+            BullBear::Bull => 1.0,
+            BullBear::Bear => -1.0,
+            BullBear::NoTrend => 0.0,
+        };
+        vec![
+            self.current_price,
+            self.atr,
+            self.standardized_diff,
+            self.latest_move,
+            self.lower_bound,
+            self.upper_bound,
+            self.resistance_candles as f64,
+            self.support_candles as f64,
+            regime,
+        ]
+    }
+}
+
+/// Rows as csv with a header line
+pub fn write_csv<W: Write>(rows: &[DatasetRow], writer: W) -> Result<(), MyError> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    for row in rows {
+        wtr.serialize(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Windows of the universe between `from` and `to` ("YYYY-MM-DD") to
+/// trading23/datasets/{from}_{to}.csv, returns the path and the number of rows
+pub async fn export(
+    universe: &Universe,
+    from: &str,
+    to: &str,
+) -> Result<(PathBuf, usize), MyError> {
+    let stocks_window_list = create_stocks_window_list_db(universe, from, to).await?;
+    let rows = stocks_window_list.to_dataset_rows(&stocks_master::load_sectors());
+
+    let path = get_dataset_file_path(from, to)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_csv(&rows, std::fs::File::create(&path)?)?;
+    Ok((path, rows.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::live::OhlcPremium;
+    use crate::analysis::stocks_window::StocksWindowList;
+    use crate::database::stocks_master::Sectors;
+    use chrono::{Duration, NaiveDate};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_write_csv() {
+        let code = StockCode::new("7203").unwrap();
+        let ohlc_vec = (0..62)
+            .map(|i| {
+                let base = 1000.0 + i as f64 * 5.0;
+                OhlcPremium::new(
+                    code.clone(),
+                    (NaiveDate::from_ymd_opt(2023, 1, 1).unwrap() + Duration::days(i))
+                        .format("%Y-%m-%d")
+                        .to_string(),
+                    base,
+                    base + 10.0,
+                    base - 10.0,
+                    base + 5.0,
+                    base + 2.0,
+                    base + 3.0,
+                )
+            })
+            .collect::<Vec<_>>();
+        // the bars end on 03-03, 03-06 is a stale window
+        let trading_days = HashSet::from(["2023-03-06".to_owned()]);
+        let mut list = StocksWindowList::new();
+        list.push(
+            ohlc_vec,
+            &code,
+            "トヨタ",
+            10000.0,
+            "2023-03-01",
+            "2023-03-31",
+            &trading_days,
+        );
+        let mut sectors = Sectors::new();
+        sectors.insert(code.clone(), "輸送用機器".to_owned());
+        let rows = list.to_dataset_rows(&sectors);
+        assert_eq!(rows.len(), 3);
+
+        let mut buffer = Vec::new();
+        write_csv(&rows, &mut buffer).unwrap();
+        let csv = String::from_utf8(buffer).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "code,date,sector,current_price,atr,standardized_diff,latest_move,\
+            lower_bound,upper_bound,resistance_candles,support_candles,status,regime,\
+            result_morning,result_afternoon,result_allday"
+        );
+        assert!(lines[1].starts_with("7203,2023-03-01,輸送用機器,1300.0,20.0,"));
+        assert!(lines[1].contains(",Rise,Bull,"));
+        // no next day for the last date
+        assert!(lines[3].ends_with(",Bull,,,"));
+        assert_eq!(rows[0].features().len(), FEATURES.len());
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::{Duration, NaiveDate};
+use statrs::statistics::Statistics;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use super::dataset::DatasetRow;
+use super::stocks_window::create_stocks_window_list_db;
+use crate::database::stocks_master::Sectors;
+use crate::my_error::MyError;
+use crate::my_file_io::Universe;
+
+/// Trading days the day is compared with
+const BASELINE_DAYS: usize = 60;
+/// Fewer days than this are not a baseline yet
+const MIN_BASELINE_DAYS: usize = 20;
+/// Distance from the baseline, in its standard deviations, alerted
+const DRIFT_Z: f64 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Feature {
+    StandardizedDiff,
+    /// ATR in % of the price
+    AtrPct,
+}
+
+impl Feature {
+    const ALL: [Feature; 2] = [Feature::StandardizedDiff, Feature::AtrPct];
+
+    fn value(&self, row: &DatasetRow) -> f64 {
+        match self {
+            Feature::StandardizedDiff => row.standardized_diff,
+            Feature::AtrPct => row.atr / row.current_price * 100.0,
+        }
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Feature::StandardizedDiff => write!(f, "standardized_diff"),
+            Feature::AtrPct => write!(f, "ATR%"),
+        }
+    }
+}
+
+/// The cross-sectional median of a day far from those of the trailing days
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftAlert {
+    feature: Feature,
+    date: String,
+    median: f64,
+    baseline: f64,
+    z: f64,
+}
+
+impl Display for DriftAlert {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}: median {:.3}, baseline {:.3} (z {:+.1})",
+            self.feature, self.date, self.median, self.baseline, self.z
+        )
+    }
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.0,
+        _ => values[mid],
+    }
+}
+
+/// Compares the last date of `rows` with up to `BASELINE_DAYS` dates before it
+pub fn check_drift(rows: &[DatasetRow]) -> Vec<DriftAlert> {
+    let mut dates: BTreeMap<&str, Vec<&DatasetRow>> = BTreeMap::new();
+    for row in rows {
+        dates.entry(&row.date).or_default().push(row);
+    }
+    let Some((date, today)) = dates.pop_last() else {
+        return Vec::new();
+    };
+    let baseline_dates = dates.values().rev().take(BASELINE_DAYS).collect::<Vec<_>>();
+    if baseline_dates.len() < MIN_BASELINE_DAYS {
+        return Vec::new();
+    }
+
+    let daily_median = |rows: &[&DatasetRow], feature: Feature| {
+        median(&mut rows.iter().map(|x| feature.value(x)).collect::<Vec<_>>())
+    };
+    let mut alerts = Vec::new();
+    for feature in Feature::ALL {
+        let medians = baseline_dates
+            .iter()
+            .map(|rows| daily_median(rows, feature))
+            .collect::<Vec<_>>();
+        let baseline = medians.iter().mean();
+        let std_dev = medians.iter().std_dev();
+        let today_median = daily_median(&today, feature);
+        if std_dev == 0.0 || std_dev.is_nan() {
+            continue;
+        }
+        let z = (today_median - baseline) / std_dev;
+        if z.abs() > DRIFT_Z {
+            alerts.push(DriftAlert {
+                feature,
+                date: date.to_owned(),
+                median: today_median,
+                baseline,
+                z,
+            });
+        }
+    }
+    alerts
+}
+
+/// Drift of `date` ("YYYY-MM-DD") over the universe in stocks_ohlc
+pub async fn check(universe: &Universe, date: &str) -> Result<Vec<DriftAlert>, MyError> {
+    // about 60 trading days
+    let from = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| MyError::Anyhow(e.into()))?
+        - Duration::days(100);
+    let stocks_window_list =
+        create_stocks_window_list_db(universe, &from.format("%Y-%m-%d").to_string(), date).await?;
+    Ok(check_drift(
+        &stocks_window_list.to_dataset_rows(&Sectors::new()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::live::BullBear;
+    use crate::stock_code::StockCode;
+
+    fn row(date: &str, code: &str, standardized_diff: f64) -> DatasetRow {
+        DatasetRow {
+            code: StockCode::new(code).unwrap(),
+            date: date.to_owned(),
+            sector: None,
+            current_price: 1000.0,
+            atr: 20.0,
+            standardized_diff,
+            latest_move: 0.1,
+            lower_bound: 990.0,
+            upper_bound: 1010.0,
+            resistance_candles: 3,
+            support_candles: 2,
+            status: "Rise".to_owned(),
+            regime: BullBear::NoTrend,
+            result_morning: None,
+            result_afternoon: None,
+            result_allday: None,
+        }
+    }
+
+    #[test]
+    fn test_check_drift() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let mut rows = Vec::new();
+        for day in 0..30 {
+            let date = (start + Duration::days(day)).format("%Y-%m-%d").to_string();
+            let diff = 0.08 + (day % 3) as f64 * 0.002;
+            rows.push(row(&date, "7203", diff));
+            rows.push(row(&date, "6758", diff + 0.01));
+        }
+        assert!(check_drift(&rows).is_empty());
+
+        rows.push(row("2024-02-01", "7203", 0.3));
+        rows.push(row("2024-02-01", "6758", 0.3));
+        let alerts = check_drift(&rows);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].feature, Feature::StandardizedDiff);
+        assert!(alerts[0]
+            .to_string()
+            .starts_with("standardized_diff 2024-02-01: median 0.300, baseline 0.087"));
+
+        // not enough days for a baseline
+        assert!(check_drift(&rows[40..]).is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use super::indicators;
+use super::live::OhlcPremium;
+use crate::config::GdriveJson;
+use crate::i18n::{Lang, Msg};
+use crate::my_error::MyError;
+use crate::units::AtrUnits;
+
+/// Names of the horizons the dataset, stocks_window and the code history have columns
+/// for. They are always computed and can't be redefined, the configured horizons are
+/// added to them in the report only
+pub const MORNING: &str = "morning";
+pub const AFTERNOON: &str = "afternoon";
+pub const ALLDAY: &str = "allday";
+
+/// A price of a daily bar, in the order they trade
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "camelCase")]
+pub enum PricePoint {
+    Open,
+    MorningClose,
+    AfternoonOpen,
+    Close,
+}
+
+impl PricePoint {
+    pub fn price(&self, ohlc: &OhlcPremium) -> f64 {
+        match self {
+            PricePoint::Open => ohlc.get_open(),
+            PricePoint::MorningClose => ohlc.get_morning_close(),
+            PricePoint::AfternoonOpen => ohlc.get_afternoon_open(),
+            PricePoint::Close => ohlc.get_close(),
+        }
+    }
+}
+
+fn default_exit_day() -> usize {
+    1
+}
+
+/// A result of the windows analysis: entered at `entry` of the next day,
+/// exited at `exit` of `exitDay` trading days after the analysis
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Horizon {
+    name: String,
+    entry: PricePoint,
+    exit: PricePoint,
+    #[serde(rename = "exitDay", default = "default_exit_day")]
+    exit_day: usize,
+}
+
+impl Horizon {
+    pub fn new(name: &str, entry: PricePoint, exit: PricePoint, exit_day: usize) -> Self {
+        Horizon {
+            name: name.to_owned(),
+            entry,
+            exit,
+            exit_day,
+        }
+    }
+
+    /// Morning, afternoon and all day of the next day
+    pub fn defaults() -> Vec<Horizon> {
+        vec![
+            Horizon::new(MORNING, PricePoint::Open, PricePoint::MorningClose, 1),
+            Horizon::new(AFTERNOON, PricePoint::AfternoonOpen, PricePoint::Close, 1),
+            Horizon::new(ALLDAY, PricePoint::Open, PricePoint::Close, 1),
+        ]
+    }
+
+    /// The defaults followed by the other `resultHorizons` of the config, read once per
+    /// run. The defaults only when not set or invalid
+    pub fn from_config() -> &'static [Horizon] {
+        static HORIZONS: OnceLock<Vec<Horizon>> = OnceLock::new();
+        HORIZONS.get_or_init(|| {
+            let Ok(config) = GdriveJson::new() else {
+                return Horizon::defaults();
+            };
+            let horizons = with_defaults(config.result_horizons());
+            match validate(&horizons) {
+                Ok(_) => horizons,
+                Err(e) => {
+                    warn!("resultHorizons ignored: {}", e);
+                    Horizon::defaults()
+                }
+            }
+        })
+    }
+
+    //getters
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_exit_day(&self) -> usize {
+        self.exit_day
+    }
+
+    /// Column heading, the name for horizons other than the defaults
+    pub fn label(&self, lang: Lang) -> &str {
+        match self.name.as_str() {
+            MORNING => Msg::Morning.text(lang),
+            AFTERNOON => Msg::Afternoon.text(lang),
+            ALLDAY => Msg::Allday.text(lang),
+            name => name,
+        }
+    }
+
+    /// Summary line heading of the share of gainers
+    pub fn gainers_label(&self, lang: Lang) -> String {
+        match self.name.as_str() {
+            MORNING => Msg::MorningGainers.text(lang).to_owned(),
+            AFTERNOON => Msg::AfternoonGainers.text(lang).to_owned(),
+            ALLDAY => Msg::AlldayGainers.text(lang).to_owned(),
+            name => format!("{} {}", name, Msg::Gainers.text(lang)),
+        }
+    }
+
+    /// Result in ATR from the bars following the analysis date (`following[0]` is the
+    /// next day), None until the exit bar is stored
+    pub fn result(&self, following: &[OhlcPremium], atr: f64) -> Option<AtrUnits> {
+        let entry = following.first()?;
+        let exit = following.get(self.exit_day.checked_sub(1)?)?;
+        Some(indicators::result_in_atr(
+            self.entry.price(entry),
+            self.exit.price(exit),
+            atr,
+        ))
+    }
+}
+
+/// The defaults, then the horizons of `configured` that aren't one of them. A default
+/// name redefined is kept and rejected by `validate`
+fn with_defaults(configured: &[Horizon]) -> Vec<Horizon> {
+    let mut horizons = Horizon::defaults();
+    let extra = configured
+        .iter()
+        .filter(|x| !horizons.contains(x))
+        .cloned()
+        .collect::<Vec<_>>();
+    horizons.extend(extra);
+    horizons
+}
+
+/// Names are unique and every exit comes after its entry
+pub fn validate(horizons: &[Horizon]) -> Result<(), MyError> {
+    let mut names = HashSet::new();
+    for horizon in horizons {
+        if !names.insert(horizon.name.as_str()) {
+            return Err(MyError::Config(format!(
+                "duplicate horizon {}",
+                horizon.name
+            )));
+        }
+        if horizon.exit_day == 0 || (horizon.exit_day == 1 && horizon.exit <= horizon.entry) {
+            return Err(MyError::Config(format!(
+                "horizon {} exits before it enters",
+                horizon.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// (name, result) of each horizon, in the order of `horizons`
+pub fn results(
+    horizons: &[Horizon],
+    following: &[OhlcPremium],
+    atr: f64,
+) -> Vec<(String, Option<AtrUnits>)> {
+    horizons
+        .iter()
+        .map(|x| (x.name.clone(), x.result(following, atr)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_code::StockCode;
+
+    #[test]
+    fn test_results() {
+        let bar = |date: &str, open: f64, morning_close: f64, afternoon_open: f64, close: f64| {
+            OhlcPremium::new(
+                StockCode::new("7203").unwrap(),
+                date.to_owned(),
+                open,
+                open.max(close) + 10.0,
+                open.min(close) - 10.0,
+                close,
+                morning_close,
+                afternoon_open,
+            )
+        };
+        let following = [
+            bar("2024-01-05", 100.0, 110.0, 112.0, 120.0),
+            bar("2024-01-09", 121.0, 125.0, 126.0, 130.0),
+        ];
+        let mut horizons = Horizon::defaults();
+        horizons.push(
+            serde_json::from_str(
+                r#"{"name": "2day", "entry": "open", "exit": "close", "exitDay": 2}"#,
+            )
+            .unwrap(),
+        );
+        validate(&horizons).unwrap();
+
+        let results = results(&horizons, &following, 10.0);
+        assert_eq!(
+            results,
+            vec![
+                (MORNING.to_owned(), Some(AtrUnits(1.0))),
+                (AFTERNOON.to_owned(), Some(AtrUnits(0.8))),
+                (ALLDAY.to_owned(), Some(AtrUnits(2.0))),
+                ("2day".to_owned(), Some(AtrUnits(3.0))),
+            ]
+        );
+        // the exit bar isn't there yet
+        assert_eq!(horizons[3].result(&following[..1], 10.0), None);
+        assert_eq!(horizons[0].result(&[], 10.0), None);
+        assert_eq!(horizons[3].label(Lang::En), "2day");
+        assert_eq!(horizons[3].gainers_label(Lang::En), "2day Gainers");
+        assert_eq!(horizons[0].gainers_label(Lang::En), "Morning Gainers");
+
+        // the defaults are kept, a redefinition of one can't be stored
+        assert_eq!(with_defaults(&horizons[3..]), horizons);
+        assert_eq!(with_defaults(&horizons), horizons);
+        let morning = Horizon::new(MORNING, PricePoint::Open, PricePoint::Close, 1);
+        assert!(validate(&with_defaults(&[morning])).is_err());
+
+        let backwards = Horizon::new("back", PricePoint::Close, PricePoint::Open, 1);
+        assert!(validate(&[backwards]).is_err());
+        assert!(validate(&[horizons[0].clone(), horizons[0].clone()]).is_err());
+    }
+}
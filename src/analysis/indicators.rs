@@ -0,0 +1,938 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use super::live::{BullBear, Ohlc, OhlcPremium};
+use crate::config::GdriveJson;
+use crate::my_error::MyError;
+use crate::rounding::{round_dp, trunc_dp};
+use crate::units::{AtrUnits, Yen};
+
+pub fn highest_high(ohlc_vec: &[OhlcPremium]) -> f64 {
+    ohlc_vec
+        .iter()
+        .map(|ohlc| ohlc.get_high())
+        .fold(f64::NAN, f64::max)
+}
+
+pub fn lowest_low(ohlc_vec: &[OhlcPremium]) -> f64 {
+    ohlc_vec
+        .iter()
+        .map(|ohlc| ohlc.get_low())
+        .fold(f64::NAN, f64::min)
+}
+
+/// A price range left untraded since the opening gap of `date`, support below the price
+/// when the gap was up and resistance above it when down
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Gap {
+    date: String,
+    lower: f64,
+    upper: f64,
+    up: bool,
+}
+
+impl Gap {
+    //getters
+    pub fn get_date(&self) -> &str {
+        &self.date
+    }
+    pub fn get_lower(&self) -> f64 {
+        self.lower
+    }
+    pub fn get_upper(&self) -> f64 {
+        self.upper
+    }
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+    pub fn size(&self) -> f64 {
+        self.upper - self.lower
+    }
+}
+
+/// The gaps between the bars the later bars left unfilled, oldest first. A gap narrows
+/// as later bars trade into it and is gone once one crosses it
+pub fn unfilled_gaps(ohlc_vec: &[OhlcPremium]) -> Vec<Gap> {
+    let mut gaps = Vec::new();
+    for (i, pair) in ohlc_vec.windows(2).enumerate() {
+        let (prev, bar) = (&pair[0], &pair[1]);
+        let later = &ohlc_vec[i + 1..];
+        let gap = if bar.get_low() > prev.get_high() {
+            Some((prev.get_high(), lowest_low(later), true))
+        } else if bar.get_high() < prev.get_low() {
+            Some((highest_high(later), prev.get_low(), false))
+        } else {
+            None
+        };
+        if let Some((lower, upper, up)) = gap.filter(|(lower, upper, _)| lower < upper) {
+            gaps.push(Gap {
+                date: bar.get_date().to_owned(),
+                lower,
+                upper,
+                up,
+            });
+        }
+    }
+    gaps
+}
+
+/// Bars on each side a swing high (low) is the highest (lowest) of
+pub const SWING_BARS: usize = 2;
+/// Bars whose high or low came near a swing price that make it a level
+pub const MIN_TOUCHES: usize = 3;
+/// Near a level is within this many ATRs of it
+pub const LEVEL_TOLERANCE_ATR: f64 = 0.25;
+
+/// Horizontal support and resistance levels of `ohlc_vec`, lowest first: the swing highs
+/// and lows, those within `tolerance` of each other merged into their mean, whose price
+/// the high or low of at least `MIN_TOUCHES` bars came within `tolerance` of
+pub fn horizontal_levels(ohlc_vec: &[OhlcPremium], tolerance: f64) -> Vec<f64> {
+    let mut swings = Vec::new();
+    for i in SWING_BARS..ohlc_vec.len().saturating_sub(SWING_BARS) {
+        let around = &ohlc_vec[i - SWING_BARS..=i + SWING_BARS];
+        if ohlc_vec[i].get_high() == highest_high(around) {
+            swings.push(ohlc_vec[i].get_high());
+        }
+        if ohlc_vec[i].get_low() == lowest_low(around) {
+            swings.push(ohlc_vec[i].get_low());
+        }
+    }
+    swings.sort_by(f64::total_cmp);
+
+    let mut clusters: Vec<Vec<f64>> = Vec::new();
+    for price in swings {
+        match clusters.last_mut() {
+            Some(cluster) if price - cluster[cluster.len() - 1] <= tolerance => cluster.push(price),
+            _ => clusters.push(vec![price]),
+        }
+    }
+    let near = |x: f64, level: f64| (x - level).abs() <= tolerance;
+    clusters
+        .into_iter()
+        .map(|cluster| cluster.iter().sum::<f64>() / cluster.len() as f64)
+        .filter(|level| {
+            ohlc_vec
+                .iter()
+                .filter(|x| near(x.get_high(), *level) || near(x.get_low(), *level))
+                .count()
+                >= MIN_TOUCHES
+        })
+        .map(|level| round_dp(level, 1))
+        .collect()
+}
+
+/// The nearest of `levels` above `price` and below it
+pub fn nearest_levels(levels: &[f64], price: f64) -> (Option<f64>, Option<f64>) {
+    let above = levels
+        .iter()
+        .copied()
+        .filter(|x| *x > price)
+        .reduce(f64::min);
+    let below = levels
+        .iter()
+        .copied()
+        .filter(|x| *x < price)
+        .reduce(f64::max);
+    (above, below)
+}
+
+/// How the ATR measures a bar, `atrMethod` of config.json
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AtrMethod {
+    /// The largest of high - low, |high - previous close| and |low - previous close|
+    #[default]
+    TrueRange,
+    /// high - low, the ATR of earlier reports which misses the gaps, see `atr`
+    HighLow,
+}
+
+/// Average true range of the windows, afternoon and daytrading analyses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Atr {
+    period: usize,
+    method: AtrMethod,
+}
+
+impl Default for Atr {
+    fn default() -> Self {
+        Atr {
+            period: 5,
+            method: AtrMethod::TrueRange,
+        }
+    }
+}
+
+impl Atr {
+    pub fn new(period: usize, method: AtrMethod) -> Self {
+        Atr {
+            period: period.max(1),
+            method,
+        }
+    }
+
+    /// `atrPeriod` and `atrMethod` of the config, read once per run
+    pub fn from_config() -> Self {
+        static ATR: OnceLock<Atr> = OnceLock::new();
+        *ATR.get_or_init(|| match GdriveJson::new() {
+            Ok(config) => Atr::new(config.atr_period(), config.atr_method()),
+            Err(_) => Atr::default(),
+        })
+    }
+
+    //getters
+    pub fn get_period(&self) -> usize {
+        self.period
+    }
+
+    /// ATR of the last `period` bars of `ohlc_vec` (oldest first), rounded to 0.1.
+    /// The bar before them gives the previous close of the first, when there is one
+    pub fn of(&self, ohlc_vec: &[OhlcPremium]) -> f64 {
+        let start = ohlc_vec.len().saturating_sub(self.period);
+        match self.method {
+            AtrMethod::HighLow => atr(&ohlc_vec[start..]),
+            AtrMethod::TrueRange => {
+                let bars = &ohlc_vec[start..];
+                let prev_closes = std::iter::once(start.checked_sub(1).map(|i| &ohlc_vec[i]))
+                    .chain(bars.iter().map(Some))
+                    .map(|x| x.map(|x| x.get_close()));
+                let atr = bars
+                    .iter()
+                    .zip(prev_closes)
+                    .map(|(ohlc, prev_close)| true_range(ohlc, prev_close))
+                    .sum::<f64>()
+                    / bars.len() as f64;
+                round_dp(atr, 1)
+            }
+        }
+    }
+}
+
+/// Bar counts of the window, afternoon and daytrading analyses, `atrPeriod`,
+/// `breakoutPeriod` and `rangePeriod` of config.json
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnalysisParams {
+    atr_period: usize,
+    /// The last bar and the ones its close breaks out of
+    breakout_period: usize,
+    /// Bars of the resistance and support candles, the range and the regime
+    range_period: usize,
+}
+
+impl Default for AnalysisParams {
+    fn default() -> Self {
+        AnalysisParams::new(5, 20, 60)
+    }
+}
+
+impl AnalysisParams {
+    /// A breakout needs a bar before the last, the range is at least the breakout's
+    pub fn new(atr_period: usize, breakout_period: usize, range_period: usize) -> Self {
+        let breakout_period = breakout_period.max(2);
+        AnalysisParams {
+            atr_period: atr_period.max(1),
+            breakout_period,
+            range_period: range_period.max(breakout_period),
+        }
+    }
+
+    /// Read once per run
+    pub fn from_config() -> Self {
+        static PARAMS: OnceLock<AnalysisParams> = OnceLock::new();
+        *PARAMS.get_or_init(|| match GdriveJson::new() {
+            Ok(config) => config.analysis_params(),
+            Err(_) => AnalysisParams::default(),
+        })
+    }
+
+    //getters
+    pub fn get_atr_period(&self) -> usize {
+        self.atr_period
+    }
+    pub fn get_breakout_period(&self) -> usize {
+        self.breakout_period
+    }
+    pub fn get_range_period(&self) -> usize {
+        self.range_period
+    }
+
+    /// The ATR of `atrMethod` over `atr_period` bars
+    pub fn atr(&self) -> Atr {
+        Atr::new(self.atr_period, Atr::from_config().method)
+    }
+
+    /// The bars before the last of the breakout window ending at `position`, and the last
+    pub fn breakout_bars<'a>(
+        &self,
+        ohlc_vec: &'a [OhlcPremium],
+        position: usize,
+    ) -> (&'a [OhlcPremium], &'a OhlcPremium) {
+        let bars = &ohlc_vec[(position + 1 - self.breakout_period)..=position];
+        (&bars[..bars.len() - 1], &bars[bars.len() - 1])
+    }
+
+    /// The range window ending at `position`
+    pub fn range_bars<'a>(
+        &self,
+        ohlc_vec: &'a [OhlcPremium],
+        position: usize,
+    ) -> &'a [OhlcPremium] {
+        &ohlc_vec[(position + 1 - self.range_period)..=position]
+    }
+}
+
+/// high - low, widened to the previous close when the bar gapped away from it
+pub fn true_range(ohlc: &OhlcPremium, prev_close: Option<f64>) -> f64 {
+    let range = ohlc.get_high() - ohlc.get_low();
+    match prev_close {
+        Some(prev_close) => range
+            .max((ohlc.get_high() - prev_close).abs())
+            .max((ohlc.get_low() - prev_close).abs()),
+        None => range,
+    }
+}
+
+/// Mean of high - low, rounded to 0.1. Gaps are not counted, see `Atr`
+pub fn atr(ohlc_vec: &[OhlcPremium]) -> f64 {
+    let atr = ohlc_vec
+        .iter()
+        .map(|ohlc| ohlc.get_high() - ohlc.get_low())
+        .sum::<f64>()
+        / ohlc_vec.len() as f64;
+    round_dp(atr, 1)
+}
+
+/// `atr` of the last bars over that of the period before them, rounded to 0.01.
+/// None for fewer than two periods of bars or a flat earlier period
+pub fn atr_change(ohlc_vec: &[OhlcPremium], atr: Atr) -> Option<f64> {
+    let period = atr.get_period();
+    let before_end = ohlc_vec.len().checked_sub(period)?;
+    before_end.checked_sub(period)?;
+    let before = atr.of(&ohlc_vec[..before_end]);
+    match before > 0.0 {
+        true => Some(round_dp(atr.of(ohlc_vec) / before, 2)),
+        false => None,
+    }
+}
+
+/// Period of `rsi` in the windows
+pub const RSI_PERIOD: usize = 14;
+
+/// Wilder's RSI of the closes, 0 to 100 rounded to 0.1. The first `period` changes
+/// seed the averages, the later ones are smoothed in. None for `period` bars or fewer,
+/// 50 when the closes never moved
+pub fn rsi(ohlc_vec: &[OhlcPremium], period: usize) -> Option<f64> {
+    if period == 0 || ohlc_vec.len() <= period {
+        return None;
+    }
+    let changes = ohlc_vec
+        .windows(2)
+        .map(|x| x[1].get_close() - x[0].get_close())
+        .collect::<Vec<_>>();
+    let (seed, rest) = changes.split_at(period);
+    let mut gain = seed.iter().map(|x| x.max(0.0)).sum::<f64>() / period as f64;
+    let mut loss = seed.iter().map(|x| (-x).max(0.0)).sum::<f64>() / period as f64;
+    for change in rest {
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+    }
+    let rsi = match (gain > 0.0, loss > 0.0) {
+        (false, false) => 50.0,
+        (_, false) => 100.0,
+        _ => 100.0 - 100.0 / (1.0 + gain / loss),
+    };
+    Some(round_dp(rsi, 1))
+}
+
+/// Mean close of the last `period` bars, None for fewer bars
+pub fn sma(ohlc_vec: &[OhlcPremium], period: usize) -> Option<f64> {
+    let bars = ohlc_vec.get(ohlc_vec.len().checked_sub(period)?..)?;
+    match bars.is_empty() {
+        true => None,
+        false => Some(bars.iter().map(|x| x.get_close()).sum::<f64>() / period as f64),
+    }
+}
+
+/// A shorter moving average crossing a longer one
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MaCross {
+    /// crossed above
+    Golden,
+    /// crossed below
+    Dead,
+}
+
+impl MaCross {
+    /// The tag of the report
+    pub fn tag(&self) -> &'static str {
+        match self {
+            MaCross::Golden => "GC",
+            MaCross::Dead => "DC",
+        }
+    }
+}
+
+/// The `fast` SMA crossing the `slow` one on the last bar, None without a cross or
+/// for `slow` bars or fewer
+pub fn ma_cross(ohlc_vec: &[OhlcPremium], fast: usize, slow: usize) -> Option<MaCross> {
+    let before = &ohlc_vec[..ohlc_vec.len().checked_sub(1)?];
+    let diff_before = sma(before, fast)? - sma(before, slow)?;
+    let diff = sma(ohlc_vec, fast)? - sma(ohlc_vec, slow)?;
+    match (diff_before, diff) {
+        (x, y) if x <= 0.0 && y > 0.0 => Some(MaCross::Golden),
+        (x, y) if x >= 0.0 && y < 0.0 => Some(MaCross::Dead),
+        _ => None,
+    }
+}
+
+/// Closes of the Bollinger Bands and bars of the widths they are ranked in
+pub const BOLLINGER_PERIOD: usize = 20;
+pub const SQUEEZE_LOOKBACK: usize = 60;
+/// The band width is a squeeze in this bottom share of the lookback
+pub const SQUEEZE_QUANTILE: f64 = 0.1;
+
+/// Width of the ±2σ Bollinger Bands of the last `period` closes over their mean,
+/// (upper - lower) / middle. None for fewer bars or a zero mean
+pub fn bollinger_width(ohlc_vec: &[OhlcPremium], period: usize) -> Option<f64> {
+    let middle = sma(ohlc_vec, period)?;
+    if middle <= 0.0 {
+        return None;
+    }
+    let variance = ohlc_vec[ohlc_vec.len() - period..]
+        .iter()
+        .map(|x| (x.get_close() - middle).powi(2))
+        .sum::<f64>()
+        / period as f64;
+    Some(4.0 * variance.sqrt() / middle)
+}
+
+/// Share (0 to 1) of the band widths of the last `lookback` bars narrower than the
+/// latest one, so 0 is the narrowest. Fewer widths are ranked when the bars don't
+/// reach back, None below `period` of them
+pub fn bollinger_width_rank(
+    ohlc_vec: &[OhlcPremium],
+    period: usize,
+    lookback: usize,
+) -> Option<f64> {
+    let latest = bollinger_width(ohlc_vec, period)?;
+    let widths = (0..lookback)
+        .map_while(|i| bollinger_width(&ohlc_vec[..ohlc_vec.len() - i], period))
+        .collect::<Vec<_>>();
+    if widths.len() < period {
+        return None;
+    }
+    let narrower = widths.iter().filter(|x| **x < latest).count();
+    Some(narrower as f64 / widths.len() as f64)
+}
+
+/// How the nextday and afternoon reports pick consolidating stocks,
+/// `consolidationFilter` of config.json
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConsolidationFilter {
+    /// The Bollinger Band width in the bottom `SQUEEZE_QUANTILE` of its last
+    /// `SQUEEZE_LOOKBACK` bars
+    #[default]
+    Squeeze,
+    /// `standardized_diff` below 0.12, the filter of earlier reports
+    StandardizedDiff,
+}
+
+impl ConsolidationFilter {
+    /// Whether a stock with the squeeze flag and `standardized_diff` is consolidating
+    pub fn keeps(&self, squeeze: bool, standardized_diff: f64) -> bool {
+        match self {
+            ConsolidationFilter::Squeeze => squeeze,
+            ConsolidationFilter::StandardizedDiff => standardized_diff < 0.12,
+        }
+    }
+}
+
+/// Mean turnover of the bars that have one, None when none has
+pub fn average_turnover(ohlc_vec: &[OhlcPremium]) -> Option<f64> {
+    let turnovers = ohlc_vec
+        .iter()
+        .filter_map(|ohlc| ohlc.get_turnover())
+        .collect::<Vec<_>>();
+    match turnovers.is_empty() {
+        true => None,
+        false => Some(turnovers.iter().sum::<f64>() / turnovers.len() as f64),
+    }
+}
+
+/// Bars of the relative strength against TOPIX
+pub const RS_PERIOD: usize = 20;
+
+/// Change (%) of the close over the last `period` bars, None for fewer bars
+pub fn return_pct(ohlc_vec: &[OhlcPremium], period: usize) -> Option<f64> {
+    let from = ohlc_vec.len().checked_sub(period + 1)?;
+    Some((ohlc_vec[ohlc_vec.len() - 1].get_close() / ohlc_vec[from].get_close() - 1.0) * 100.0)
+}
+
+/// date -> change (%) of the TOPIX close over the `period` days until it, for the
+/// days with that many before them. `topix` is oldest first
+pub fn topix_returns(topix: &[Ohlc], period: usize) -> HashMap<String, f64> {
+    topix
+        .windows(period + 1)
+        .map(|x| {
+            let change = x[period].get_close() / x[0].get_close() - 1.0;
+            (x[period].get_date().to_owned(), change * 100.0)
+        })
+        .collect()
+}
+
+/// Turnover-weighted average price, total turnover over total volume of the bars that
+/// have both. None when none has or no share traded. The volume is split-adjusted like
+/// the prices and the turnover is in yen, so it compares with the prices
+pub fn vwap(ohlc_vec: &[OhlcPremium]) -> Option<f64> {
+    let (turnover, volume) = ohlc_vec
+        .iter()
+        .filter_map(|ohlc| ohlc.get_turnover().zip(ohlc.get_volume()))
+        .fold((0.0, 0.0), |(turnover, volume), (x, y)| {
+            (turnover + x, volume + y)
+        });
+    match volume > 0.0 {
+        true => Some(turnover / volume),
+        false => None,
+    }
+}
+
+/// Shares in a board lot (単元株), the TSE trades stocks in multiples of it
+pub const BOARD_LOT: i32 = 100;
+
+/// Shares risking `unit` yen per `atr`, rounded down to board lots. One lot at least,
+/// see `lot_over_budget` for the stocks it risks too much
+pub fn board_lot_units(unit: f64, atr: f64) -> i32 {
+    ((unit / atr) as i32 / BOARD_LOT).max(1) * BOARD_LOT
+}
+
+/// (unit, required_amount) of `board_lot_units`
+pub fn unit_and_required_amount(unit: f64, atr: f64, price: f64) -> (i32, Yen) {
+    let unit = board_lot_units(unit, atr);
+    (unit, Yen((unit as f64 * price) as i32))
+}
+
+/// A single board lot risks more than `unit` yen per ATR
+pub fn lot_over_budget(unit: f64, atr: f64) -> bool {
+    unit / atr < BOARD_LOT as f64
+}
+
+/// Mean of high - low divided by the whole range, truncated to 0.001.
+/// A flat series (highest high == lowest low) is `MyError::ZeroRange`.
+pub fn standardized_diff(ohlc_vec: &[OhlcPremium]) -> Result<f64, MyError> {
+    let highest_high = highest_high(ohlc_vec);
+    let lowest_low = lowest_low(ohlc_vec);
+    if highest_high.is_nan() || highest_high <= lowest_low {
+        return Err(MyError::ZeroRange("standardized_diff"));
+    }
+
+    let diff_sum: f64 = ohlc_vec
+        .iter()
+        .map(|ohlc| ohlc.get_high() - ohlc.get_low())
+        .sum();
+    let average_diff = diff_sum / ohlc_vec.len() as f64;
+
+    Ok(trunc_dp(average_diff / (highest_high - lowest_low), 3))
+}
+
+/// `change` relative to a bar range (`high - low`), rounded to 0.01.
+/// A zero range (stop-high / stop-low day, no trades) is `MyError::ZeroRange`.
+pub fn move_in_range(change: f64, range: f64, what: &'static str) -> Result<f64, MyError> {
+    match range > 0.0 {
+        true => Ok(round_dp(change / range, 2)),
+        false => Err(MyError::ZeroRange(what)),
+    }
+}
+
+/// Trend of the window, the rule of `OhlcAnalyzer::get_longer_ohlc_standardized_diff_and_trend`:
+/// a wide average bar is no trend, otherwise the last close in the bottom or top 20% of the range
+pub fn regime(ohlc_vec: &[OhlcPremium], standardized_diff: f64) -> BullBear {
+    let highest_high = highest_high(ohlc_vec);
+    let lowest_low = lowest_low(ohlc_vec);
+    let last_close = match ohlc_vec.last() {
+        Some(ohlc) => ohlc.get_close(),
+        None => return BullBear::NoTrend,
+    };
+    let last_close_position = (last_close - lowest_low) / (highest_high - lowest_low);
+
+    match (standardized_diff, last_close_position) {
+        (x, _) if x > 0.14 => BullBear::NoTrend,
+        (_, y) if (0.0..=0.2).contains(&y) => BullBear::Bear,
+        (_, y) if (0.8..=1.0).contains(&y) => BullBear::Bull,
+        (_, _) => BullBear::NoTrend,
+    }
+}
+
+/// Price move from `entry` to `exit` in ATR units, rounded to 0.01
+pub fn result_in_atr(entry: f64, exit: f64, atr: f64) -> AtrUnits {
+    AtrUnits(round_dp((exit - entry) / atr, 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_code::StockCode;
+    use proptest::prelude::*;
+
+    /// Valid bars (low <= open, close <= high), zero-range bars included
+    fn ohlc_vec_strategy(len: std::ops::Range<usize>) -> impl Strategy<Value = Vec<OhlcPremium>> {
+        let bar = (
+            1.0..10000.0,
+            prop_oneof![Just(0.0), 0.0..500.0],
+            0.0..=1.0,
+            0.0..=1.0,
+        );
+        prop::collection::vec(bar, len).prop_map(|bars| {
+            bars.into_iter()
+                .enumerate()
+                .map(
+                    |(i, (low, range, open, close)): (usize, (f64, f64, f64, f64))| {
+                        OhlcPremium::new(
+                            StockCode::new("1301").unwrap(),
+                            format!("2024-01-{:02}", i % 28 + 1),
+                            low + range * open,
+                            low + range,
+                            low,
+                            low + range * close,
+                            low + range * close,
+                            low + range * close,
+                        )
+                    },
+                )
+                .collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_atr_is_not_negative(ohlc_vec in ohlc_vec_strategy(1..60)) {
+            prop_assert!(atr(&ohlc_vec) >= 0.0);
+        }
+
+        #[test]
+        fn test_true_range_atr_is_not_below_high_low(ohlc_vec in ohlc_vec_strategy(1..60)) {
+            let high_low = Atr::new(5, AtrMethod::HighLow).of(&ohlc_vec);
+            prop_assert!(Atr::new(5, AtrMethod::TrueRange).of(&ohlc_vec) >= high_low);
+        }
+
+        #[test]
+        fn test_average_turnover_within_bars(
+            ohlc_vec in ohlc_vec_strategy(1..60),
+            turnovers in prop::collection::vec(prop::option::of(0.0..1e10), 60)
+        ) {
+            let ohlc_vec = ohlc_vec
+                .into_iter()
+                .zip(turnovers)
+                .map(|(ohlc, turnover)| ohlc.with_liquidity(None, turnover))
+                .collect::<Vec<_>>();
+            let known = ohlc_vec.iter().filter_map(|x| x.get_turnover()).collect::<Vec<_>>();
+            match average_turnover(&ohlc_vec) {
+                Some(x) => {
+                    let max = known.iter().cloned().fold(f64::NAN, f64::max);
+                    let min = known.iter().cloned().fold(f64::NAN, f64::min);
+                    prop_assert!(min - 1e-3 <= x && x <= max + 1e-3, "{} {} {}", min, x, max)
+                }
+                None => prop_assert!(known.is_empty()),
+            }
+        }
+
+        #[test]
+        fn test_rsi_in_range(ohlc_vec in ohlc_vec_strategy(1..60)) {
+            match rsi(&ohlc_vec, RSI_PERIOD) {
+                Some(x) => prop_assert!((0.0..=100.0).contains(&x), "{}", x),
+                None => prop_assert!(ohlc_vec.len() <= RSI_PERIOD),
+            }
+        }
+
+        #[test]
+        fn test_highest_high_is_above_lowest_low(ohlc_vec in ohlc_vec_strategy(1..60)) {
+            prop_assert!(highest_high(&ohlc_vec) >= lowest_low(&ohlc_vec));
+        }
+
+        #[test]
+        fn test_standardized_diff_in_unit_range(ohlc_vec in ohlc_vec_strategy(1..60)) {
+            match standardized_diff(&ohlc_vec) {
+                Ok(standardized_diff) => {
+                    prop_assert!((0.0..=1.0).contains(&standardized_diff), "{}", standardized_diff)
+                }
+                Err(MyError::ZeroRange(_)) => {
+                    prop_assert_eq!(highest_high(&ohlc_vec), lowest_low(&ohlc_vec))
+                }
+                Err(e) => prop_assert!(false, "{}", e),
+            }
+        }
+
+        #[test]
+        fn test_move_in_range_is_finite(change in -1000.0..1000.0, range in prop_oneof![Just(0.0), 0.0..100.0]) {
+            match move_in_range(change, range, "test") {
+                Ok(x) => prop_assert!(x.is_finite()),
+                Err(e) => prop_assert!(matches!(e, MyError::ZeroRange("test")) && range == 0.0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_atr_change() {
+        let bar =
+            |range: f64| OhlcPremium::test_bar("2024-01-05", 100.0, 100.0 + range, 100.0, 100.0);
+        let mut ohlc_vec = vec![bar(50.0); 3];
+        ohlc_vec.extend(vec![bar(10.0); 5]);
+        ohlc_vec.extend([10.0, 20.0, 20.0, 30.0, 30.0].map(bar));
+        for method in [AtrMethod::HighLow, AtrMethod::TrueRange] {
+            let atr = Atr::new(5, method);
+            assert_eq!(atr_change(&ohlc_vec, atr), Some(2.2));
+            assert_eq!(atr_change(&ohlc_vec[..9], atr), None);
+            assert_eq!(atr_change(&vec![bar(0.0); 10], atr), None);
+        }
+        assert_eq!(
+            atr_change(&ohlc_vec, Atr::new(3, AtrMethod::HighLow)),
+            Some(2.01)
+        );
+    }
+
+    #[test]
+    fn test_true_range_atr() {
+        let bar = |open: f64, high: f64, low: f64, close: f64| {
+            OhlcPremium::test_bar("2024-01-05", open, high, low, close)
+        };
+        let ohlc_vec = vec![
+            bar(100.0, 105.0, 95.0, 100.0),
+            // gap up of 20 over a range of 10
+            bar(120.0, 125.0, 115.0, 120.0),
+            bar(120.0, 124.0, 116.0, 118.0),
+            // gap down below the previous close
+            bar(100.0, 102.0, 98.0, 101.0),
+        ];
+        assert_eq!(true_range(&ohlc_vec[1], Some(100.0)), 25.0);
+        assert_eq!(true_range(&ohlc_vec[1], None), 10.0);
+
+        let true_range_atr = Atr::new(3, AtrMethod::TrueRange);
+        // (25 + 8 + 20) / 3
+        assert_eq!(true_range_atr.of(&ohlc_vec), 17.7);
+        // the first bar has no previous close
+        assert_eq!(true_range_atr.of(&ohlc_vec[1..]), 12.7);
+        assert_eq!(Atr::new(3, AtrMethod::HighLow).of(&ohlc_vec), 7.3);
+        assert_eq!(
+            Atr::new(10, AtrMethod::HighLow).of(&ohlc_vec),
+            atr(&ohlc_vec)
+        );
+    }
+
+    #[test]
+    fn test_analysis_params() {
+        let params = AnalysisParams::new(0, 1, 10);
+        assert_eq!(params.get_atr_period(), 1);
+        assert_eq!(params.get_breakout_period(), 2);
+        assert_eq!(AnalysisParams::new(5, 20, 10).get_range_period(), 20);
+
+        let ohlc_vec = (0..30)
+            .map(|i| {
+                let close = 100.0 + i as f64;
+                OhlcPremium::test_bar(&format!("2024-01-{:02}", i + 1), close, close, close, close)
+            })
+            .collect::<Vec<_>>();
+        let params = AnalysisParams::new(5, 10, 25);
+        let (prev, last) = params.breakout_bars(&ohlc_vec, 28);
+        assert_eq!(prev.len(), 9);
+        assert_eq!(prev[0].get_close(), 119.0);
+        assert_eq!(last.get_close(), 128.0);
+        let range = params.range_bars(&ohlc_vec, 28);
+        assert_eq!(range.len(), 25);
+        assert_eq!(range[0].get_close(), 104.0);
+    }
+
+    #[test]
+    fn test_horizontal_levels() {
+        let bar = |high: f64, low: f64| OhlcPremium::test_bar("2024-01-05", low, high, low, high);
+        let ohlc_vec = [
+            bar(100.0, 96.2),
+            bar(105.0, 98.0),
+            bar(110.0, 100.0),
+            bar(104.0, 97.0),
+            bar(102.0, 96.0),
+            bar(109.8, 99.0),
+            bar(103.0, 97.0),
+            bar(101.0, 95.0),
+            bar(110.1, 100.0),
+            bar(104.0, 98.0),
+            bar(103.0, 96.0),
+        ];
+        // the swing low at 95 is touched once
+        let levels = horizontal_levels(&ohlc_vec, 0.5);
+        assert_eq!(levels, [96.0, 110.0]);
+        assert_eq!(nearest_levels(&levels, 103.0), (Some(110.0), Some(96.0)));
+        assert_eq!(nearest_levels(&levels, 111.0), (None, Some(110.0)));
+        assert!(horizontal_levels(&ohlc_vec[..4], 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_unfilled_gaps() {
+        let bar =
+            |date: &str, high: f64, low: f64| OhlcPremium::test_bar(date, low, high, low, high);
+        let ohlc_vec = vec![
+            bar("2024-01-04", 100.0, 95.0),
+            // up from 100, narrowed to 100-101 by 01-11
+            bar("2024-01-05", 110.0, 105.0),
+            bar("2024-01-09", 112.0, 103.0),
+            bar("2024-01-10", 108.0, 104.0),
+            // down from 104, filled on 01-12
+            bar("2024-01-11", 102.0, 101.0),
+            bar("2024-01-12", 106.0, 102.0),
+            // down from 102, open
+            bar("2024-01-15", 101.5, 101.2),
+        ];
+        let gaps = unfilled_gaps(&ohlc_vec);
+        assert_eq!(gaps.len(), 2);
+        assert_eq!(gaps[0].get_date(), "2024-01-05");
+        assert!(gaps[0].is_up());
+        assert_eq!((gaps[0].get_lower(), gaps[0].get_upper()), (100.0, 101.0));
+        assert_eq!(gaps[1].get_date(), "2024-01-15");
+        assert!(!gaps[1].is_up());
+        assert_eq!(gaps[1].size(), 0.5);
+        assert!(unfilled_gaps(&ohlc_vec[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_returns() {
+        let closes = [100.0, 105.0, 110.0, 121.0];
+        let ohlc_vec = closes
+            .iter()
+            .map(|&close| OhlcPremium::test_bar("2024-01-05", close, close, close, close))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            return_pct(&ohlc_vec, 2).map(|x| round_dp(x, 2)),
+            Some(15.24)
+        );
+        assert_eq!(return_pct(&ohlc_vec, 4), None);
+
+        let topix = closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| Ohlc::new(format!("2024-01-0{}", i + 4), close, close, close, close))
+            .collect::<Vec<_>>();
+        let returns = topix_returns(&topix, 2);
+        assert_eq!(returns.len(), 2);
+        assert_eq!(round_dp(returns["2024-01-06"], 2), 10.0);
+        assert_eq!(round_dp(returns["2024-01-07"], 2), 15.24);
+    }
+
+    #[test]
+    fn test_vwap() {
+        let bar = |turnover: Option<f64>, volume: Option<f64>| {
+            OhlcPremium::test_bar("2024-01-05", 100.0, 110.0, 90.0, 105.0)
+                .with_liquidity(volume, turnover)
+        };
+        let ohlc_vec = vec![
+            bar(Some(100_000.0), Some(1_000.0)),
+            bar(Some(330_000.0), Some(3_000.0)),
+            // before turnover was kept
+            bar(None, Some(5_000.0)),
+        ];
+        assert_eq!(vwap(&ohlc_vec), Some(107.5));
+        assert_eq!(vwap(&ohlc_vec[2..]), None);
+        assert_eq!(vwap(&[bar(Some(0.0), Some(0.0))]), None);
+    }
+
+    #[test]
+    fn test_sma_and_ma_cross() {
+        let bars = |closes: &[f64]| {
+            closes
+                .iter()
+                .map(|&close| {
+                    OhlcPremium::test_bar("2024-01-05", close, close + 1.0, close - 1.0, close)
+                })
+                .collect::<Vec<_>>()
+        };
+        let ohlc_vec = bars(&[10.0, 10.0, 10.0, 8.0, 8.0, 14.0]);
+        assert_eq!(sma(&ohlc_vec, 2), Some(11.0));
+        assert_eq!(sma(&ohlc_vec, 6), Some(10.0));
+        assert_eq!(sma(&ohlc_vec, 7), None);
+        assert_eq!(sma(&ohlc_vec, 0), None);
+
+        // 2 bars over 4: 8 < 9 before, 11 > 10 on the last bar
+        assert_eq!(ma_cross(&ohlc_vec, 2, 4), Some(MaCross::Golden));
+        assert_eq!(
+            ma_cross(&bars(&[10.0, 10.0, 10.0, 10.0, 12.0, 6.0]), 2, 4),
+            Some(MaCross::Dead)
+        );
+        assert_eq!(ma_cross(&ohlc_vec[..5], 2, 4), None);
+        assert_eq!(ma_cross(&ohlc_vec[..4], 2, 4), None);
+        assert_eq!(ma_cross(&ohlc_vec, 2, 6), None);
+    }
+
+    #[test]
+    fn test_bollinger_width() {
+        let bars = |closes: &[f64]| {
+            closes
+                .iter()
+                .map(|&close| {
+                    OhlcPremium::test_bar("2024-01-05", close, close + 1.0, close - 1.0, close)
+                })
+                .collect::<Vec<_>>()
+        };
+        // mean 100, σ 2
+        let ohlc_vec = bars(&[98.0, 102.0, 98.0, 102.0]);
+        assert_eq!(bollinger_width(&ohlc_vec, 4), Some(0.08));
+        assert_eq!(bollinger_width(&ohlc_vec, 5), None);
+
+        // swings narrowing to nothing, the last width is the narrowest
+        let closes = (0..30)
+            .map(|i| 100.0 + (30 - i) as f64 * if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect::<Vec<_>>();
+        assert_eq!(bollinger_width_rank(&bars(&closes), 4, 20), Some(0.0));
+        let widening = closes.iter().rev().cloned().collect::<Vec<_>>();
+        assert!(bollinger_width_rank(&bars(&widening), 4, 20).unwrap() > 0.9);
+        // 27 widths of 4 bars, fewer than the 4 needed only below 7 bars
+        assert!(bollinger_width_rank(&bars(&closes), 4, 60).is_some());
+        assert_eq!(bollinger_width_rank(&bars(&closes[..6]), 4, 60), None);
+    }
+
+    #[test]
+    fn test_consolidation_filter() {
+        assert!(ConsolidationFilter::Squeeze.keeps(true, 0.5));
+        assert!(!ConsolidationFilter::Squeeze.keeps(false, 0.05));
+        assert!(ConsolidationFilter::StandardizedDiff.keeps(false, 0.05));
+        assert!(!ConsolidationFilter::StandardizedDiff.keeps(true, 0.12));
+    }
+
+    #[test]
+    fn test_rsi() {
+        let ohlc_vec = [100.0, 102.0, 101.0, 104.0, 103.0, 103.0]
+            .iter()
+            .map(|&close| {
+                OhlcPremium::test_bar("2024-01-05", close, close + 1.0, close - 1.0, close)
+            })
+            .collect::<Vec<_>>();
+        // gains 2 + 3 = 5 and losses 1 + 1 = 2 over 4 changes
+        assert_eq!(rsi(&ohlc_vec[..5], 4), Some(71.4));
+        // a flat close smooths both averages down by 3/4
+        assert_eq!(rsi(&ohlc_vec, 4), Some(71.4));
+        assert_eq!(rsi(&ohlc_vec[..4], 4), None);
+        assert_eq!(rsi(&ohlc_vec[..3], 1), Some(0.0));
+        assert_eq!(rsi(&ohlc_vec[..2], 1), Some(100.0));
+        assert_eq!(rsi(&ohlc_vec[4..], 1), Some(50.0));
+    }
+
+    #[test]
+    fn test_unit_and_required_amount() {
+        // 10000 yen per ATR of 73 is 136.9 shares
+        assert_eq!(
+            unit_and_required_amount(10000.0, 73.0, 2500.0),
+            (100, Yen(250000))
+        );
+        assert!(!lot_over_budget(10000.0, 73.0));
+        assert_eq!(
+            unit_and_required_amount(10000.0, 40.0, 900.0),
+            (200, Yen(180000))
+        );
+        // a lot of a stock with an ATR of 250 risks 25000 yen
+        assert_eq!(
+            unit_and_required_amount(10000.0, 250.0, 30000.0),
+            (100, Yen(3000000))
+        );
+        assert!(lot_over_budget(10000.0, 250.0));
+    }
+}
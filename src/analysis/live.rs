@@ -1,9 +1,17 @@
 use crate::gmo_coin::fx_public::Symbol;
+use crate::instrument::Instrument;
+use crate::my_error::MyError;
+use crate::rounding::trunc_dp;
+use crate::stock_code::StockCode;
 use std::{
     collections::HashMap,
     fmt::{Display, Formatter},
+    str::FromStr,
 };
 
+use anyhow::anyhow;
+use chrono::{Datelike, Duration, NaiveDate};
+use clap::ValueEnum;
 use cli_candlestick_chart::{Candle, Chart};
 use serde::{Deserialize, Serialize};
 
@@ -47,7 +55,7 @@ impl Ohlc {
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct OhlcPremium {
-    code: String,
+    code: StockCode,
     date: String,
     open: f64,
     high: f64,
@@ -55,11 +63,18 @@ pub struct OhlcPremium {
     close: f64,
     morning_close: f64,
     afternoon_open: f64,
+    /// Shares traded, split adjusted; None for bars stored before it was kept
+    #[serde(default)]
+    volume: Option<f64>,
+    /// Trading value in yen
+    #[serde(default)]
+    turnover: Option<f64>,
 }
 
 impl OhlcPremium {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        code: String,
+        code: StockCode,
         date: String,
         open: f64,
         high: f64,
@@ -77,16 +92,30 @@ impl OhlcPremium {
             close,
             morning_close,
             afternoon_open,
+            volume: None,
+            turnover: None,
         }
     }
 
-    // getters
-    pub fn get_code(&self) -> String {
-        match self.code.chars().count() > 4 {
-            true => self.code.chars().take(4).collect::<String>(),
-            false => self.code.clone(),
+    pub fn with_liquidity(self, volume: Option<f64>, turnover: Option<f64>) -> Self {
+        Self {
+            volume,
+            turnover,
+            ..self
         }
     }
+
+    /// A bar of 7203 for the tests, the morning close and the afternoon open at `close`
+    #[cfg(test)]
+    pub fn test_bar(date: &str, open: f64, high: f64, low: f64, close: f64) -> Self {
+        let code = StockCode::new("7203").unwrap();
+        Self::new(code, date.to_owned(), open, high, low, close, close, close)
+    }
+
+    // getters
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
     pub fn get_date(&self) -> &str {
         self.date.as_str()
     }
@@ -108,14 +137,33 @@ impl OhlcPremium {
     pub fn get_afternoon_open(&self) -> f64 {
         self.afternoon_open
     }
+    pub fn get_volume(&self) -> Option<f64> {
+        self.volume
+    }
+    pub fn get_turnover(&self) -> Option<f64> {
+        self.turnover
+    }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
 pub enum BullBear {
     Bull,
     Bear,
+    #[default]
     NoTrend,
 }
+/// The inverse of `Display`, as the regimes are stored
+impl FromStr for BullBear {
+    type Err = MyError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "Bull" => Ok(BullBear::Bull),
+            "Bear" => Ok(BullBear::Bear),
+            "NoTrend" => Ok(BullBear::NoTrend),
+            _ => Err(MyError::Anyhow(anyhow!("{} is not a regime", text))),
+        }
+    }
+}
 impl Display for BullBear {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -126,40 +174,34 @@ impl Display for BullBear {
     }
 }
 
-pub enum OhlcSource {
-    Jquants,
-    GmoCoinFx(Symbol),
-}
-
 pub struct OhlcAnalyzer {
-    source: OhlcSource,
+    instrument: Instrument,
     shorter_ohlc: Vec<Ohlc>,
     longer_ohlc: Vec<Ohlc>,
     position: Option<LongOrShort>,
 }
 
 impl OhlcAnalyzer {
-    pub fn from_jquants(raw_ohlc: Vec<Ohlc>) -> Self {
+    pub fn from_jquants(code: StockCode, raw_ohlc: Vec<Ohlc>) -> Self {
         let shorter_ohlc = raw_ohlc.clone().into_iter().rev().take(60).rev().collect();
-        let longer_ohlc = to_monthly_ohlc(raw_ohlc.clone());
+        let longer_ohlc = to_timeframe_ohlc(raw_ohlc.clone(), Timeframe::Monthly);
         Self {
-            source: OhlcSource::Jquants,
+            instrument: Instrument::Stock(code),
             shorter_ohlc,
             longer_ohlc,
             position: None,
         }
     }
 
+    /// The bars are used as fetched, `fetch_ohlc` keeps the configured number of them
     pub fn from_gmo_coin_fx(
         symbol: Symbol,
-        raw_ohlc_shorter: Vec<Ohlc>,
-        raw_ohlc_longer: Vec<Ohlc>,
+        shorter_ohlc: Vec<Ohlc>,
+        longer_ohlc: Vec<Ohlc>,
         position: Option<LongOrShort>,
     ) -> Self {
-        let shorter_ohlc = raw_ohlc_shorter.into_iter().rev().take(60).rev().collect();
-        let longer_ohlc = raw_ohlc_longer.into_iter().rev().take(60).rev().collect();
         Self {
-            source: OhlcSource::GmoCoinFx(symbol),
+            instrument: Instrument::Fx(symbol),
             shorter_ohlc,
             longer_ohlc,
             position,
@@ -197,26 +239,11 @@ impl OhlcAnalyzer {
                     .fold(f64::NAN, f64::max);
                 let low = last_20.iter().map(|ohlc| ohlc.low).fold(f64::NAN, f64::min);
                 let stop_loss_order_naked = high - (high - low) * 0.38;
-                let stop_loss_order = match &self.source {
-                    OhlcSource::Jquants => stop_loss_order_naked,
-                    OhlcSource::GmoCoinFx(symbol) => {
-                        let coefficient = 1_f64 / symbol.pips();
-                        (stop_loss_order_naked * coefficient).round() / coefficient
-                    }
-                };
-
-                let units = match &self.source {
-                    OhlcSource::Jquants => {
-                        (jquants_unit.unwrap() / (last[0].close - stop_loss_order)) as i32
-                    }
-                    OhlcSource::GmoCoinFx(symbol) => {
-                        let coefficient = match symbol {
-                            Symbol::EurUsd | Symbol::GbpUsd | Symbol::AudUsd => 0.01,
-                            _ => 1.0,
-                        };
-                        (3000.0 / (last[0].close - stop_loss_order) * coefficient).round() as i32
-                    }
-                };
+                let stop_loss_order = self.instrument.round_price(stop_loss_order_naked);
+
+                let units = self
+                    .instrument
+                    .units(last[0].close - stop_loss_order, jquants_unit);
                 let is_too_strong_to_entry =
                     ((last[0].high - last[0].low) / (last[0].high - low)) > 0.75;
                 let analyzed_at = last[0].date.to_string();
@@ -225,7 +252,7 @@ impl OhlcAnalyzer {
                     break_or_not: true,
                     long_or_short: Some(LongOrShort::Long),
                     stop_loss_order: Some(stop_loss_order),
-                    units: Some(units),
+                    units,
                     is_too_strong_to_entry: Some(is_too_strong_to_entry),
                     analyzed_at,
                 }
@@ -238,25 +265,10 @@ impl OhlcAnalyzer {
                 let low = last_20.iter().map(|ohlc| ohlc.low).fold(f64::NAN, f64::min);
                 let stop_loss_order_naked = low + (high - low) * 0.38;
 
-                let stop_loss_order = match &self.source {
-                    OhlcSource::Jquants => stop_loss_order_naked,
-                    OhlcSource::GmoCoinFx(symbol) => {
-                        let coefficient = 1_f64 / symbol.pips();
-                        (stop_loss_order_naked * coefficient).round() / coefficient
-                    }
-                };
-                let units = match &self.source {
-                    OhlcSource::Jquants => {
-                        (jquants_unit.unwrap() / (stop_loss_order - last[0].close)) as i32
-                    }
-                    OhlcSource::GmoCoinFx(symbol) => {
-                        let coefficient = match symbol {
-                            Symbol::EurUsd | Symbol::GbpUsd | Symbol::AudUsd => 0.01,
-                            _ => 1.0,
-                        };
-                        (3000.0 / (stop_loss_order - last[0].close) * coefficient).round() as i32
-                    }
-                };
+                let stop_loss_order = self.instrument.round_price(stop_loss_order_naked);
+                let units = self
+                    .instrument
+                    .units(stop_loss_order - last[0].close, jquants_unit);
                 let is_too_strong_to_entry =
                     ((last[0].high - last[0].low) / (high - last[0].low)) > 0.75;
                 let analyzed_at = last[0].date.to_string();
@@ -265,7 +277,7 @@ impl OhlcAnalyzer {
                     break_or_not: true,
                     long_or_short: Some(LongOrShort::Short),
                     stop_loss_order: Some(stop_loss_order),
-                    units: Some(units),
+                    units,
                     is_too_strong_to_entry: Some(is_too_strong_to_entry),
                     analyzed_at,
                 }
@@ -303,41 +315,11 @@ impl OhlcAnalyzer {
             .sum();
         let average_diff = diff_sum / self.shorter_ohlc.len() as f64;
 
-        (average_diff / (highest_high - lowest_low) * 1000.0).trunc() / 1000.0
+        trunc_dp(average_diff / (highest_high - lowest_low), 3)
     }
 
     pub fn get_longer_ohlc_standardized_diff_and_trend(&self) -> (f64, BullBear) {
-        let highest_high = self
-            .longer_ohlc
-            .iter()
-            .map(|ohlc| ohlc.high)
-            .fold(f64::NAN, f64::max);
-        let lowest_low = self
-            .longer_ohlc
-            .iter()
-            .map(|ohlc| ohlc.low)
-            .fold(f64::NAN, f64::min);
-
-        let diff_sum: f64 = self
-            .longer_ohlc
-            .iter()
-            .map(|ohlc| ohlc.high - ohlc.low)
-            .sum();
-        let average_diff = diff_sum / self.longer_ohlc.len() as f64;
-        let standardized_diff =
-            (average_diff / (highest_high - lowest_low) * 1000.0).trunc() / 1000.0;
-
-        let last_close = self.longer_ohlc.last().unwrap().close;
-        let last_close_position = (last_close - lowest_low) / (highest_high - lowest_low);
-
-        let bull_bear = match (standardized_diff, last_close_position) {
-            (x, _) if x > 0.14 => BullBear::NoTrend,
-            (_, y) if (0.0..=0.2).contains(&y) => BullBear::Bear,
-            (_, y) if (0.8..=1.0).contains(&y) => BullBear::Bull,
-            (_, _) => BullBear::NoTrend,
-        };
-
-        (standardized_diff, bull_bear)
+        standardized_diff_and_trend(&self.longer_ohlc)
     }
 
     pub fn get_shorter_chart(&self) {
@@ -384,53 +366,97 @@ impl OhlcAnalyzer {
         match self.position {
             Some(LongOrShort::Long) => {
                 let stop_loss_order_naked = high - (high - low) * 0.38;
-                match &self.source {
-                    OhlcSource::Jquants => stop_loss_order_naked,
-                    OhlcSource::GmoCoinFx(symbol) => {
-                        let coefficient = 1_f64 / symbol.pips();
-                        (stop_loss_order_naked * coefficient).round() / coefficient
-                    }
-                }
+                self.instrument.round_price(stop_loss_order_naked)
             }
             Some(LongOrShort::Short) => {
                 let stop_loss_order_naked = low + (high - low) * 0.38;
-                match &self.source {
-                    OhlcSource::Jquants => stop_loss_order_naked,
-                    OhlcSource::GmoCoinFx(symbol) => {
-                        let coefficient = 1_f64 / symbol.pips();
-                        (stop_loss_order_naked * coefficient).round() / coefficient
-                    }
-                }
+                self.instrument.round_price(stop_loss_order_naked)
             }
             None => panic!("No position"),
         }
     }
 }
 
-fn to_monthly_ohlc(ohlc_vec: Vec<Ohlc>) -> Vec<Ohlc> {
-    let mut monthly_ohlc_map: HashMap<String, Vec<Ohlc>> = HashMap::new();
+/// Average range of the bars over their whole range and where the last close stands in
+/// it: no trend for a choppy range, bull in its top fifth and bear in its bottom fifth
+pub fn standardized_diff_and_trend(ohlc_vec: &[Ohlc]) -> (f64, BullBear) {
+    let Some(last) = ohlc_vec.last() else {
+        return (f64::NAN, BullBear::NoTrend);
+    };
+    let highest_high = ohlc_vec
+        .iter()
+        .map(|ohlc| ohlc.high)
+        .fold(f64::NAN, f64::max);
+    let lowest_low = ohlc_vec
+        .iter()
+        .map(|ohlc| ohlc.low)
+        .fold(f64::NAN, f64::min);
+
+    let diff_sum: f64 = ohlc_vec.iter().map(|ohlc| ohlc.high - ohlc.low).sum();
+    let average_diff = diff_sum / ohlc_vec.len() as f64;
+    let standardized_diff = trunc_dp(average_diff / (highest_high - lowest_low), 3);
+
+    let last_close_position = (last.close - lowest_low) / (highest_high - lowest_low);
+
+    let bull_bear = match (standardized_diff, last_close_position) {
+        (x, _) if x > 0.14 => BullBear::NoTrend,
+        (_, y) if (0.0..=0.2).contains(&y) => BullBear::Bear,
+        (_, y) if (0.8..=1.0).contains(&y) => BullBear::Bull,
+        (_, _) => BullBear::NoTrend,
+    };
+
+    (standardized_diff, bull_bear)
+}
+
+/// The longer bars daily bars are aggregated into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timeframe {
+    /// Monday to Friday, dated by the Monday
+    Weekly,
+    /// dated "YYYY-MM"
+    Monthly,
+}
+
+impl Timeframe {
+    /// The date of the longer bar `date` ("YYYY-MM-DD") falls in
+    fn key(&self, date: &str) -> String {
+        match self {
+            Timeframe::Weekly => match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                Ok(day) => {
+                    let monday = day - Duration::days(day.weekday().num_days_from_monday() as i64);
+                    monday.format("%Y-%m-%d").to_string()
+                }
+                Err(_) => date.to_owned(),
+            },
+            Timeframe::Monthly => date[0..7].to_owned(), // extract yyyy-mm
+        }
+    }
+}
+
+/// Daily bars (oldest first) into `timeframe` bars, oldest first
+pub fn to_timeframe_ohlc(ohlc_vec: Vec<Ohlc>, timeframe: Timeframe) -> Vec<Ohlc> {
+    let mut longer_ohlc_map: HashMap<String, Vec<Ohlc>> = HashMap::new();
 
     for ohlc in ohlc_vec {
-        let month = &ohlc.date[0..7]; // extract yyyy-mm
-        monthly_ohlc_map
-            .entry(month.to_string())
+        longer_ohlc_map
+            .entry(timeframe.key(&ohlc.date))
             .or_default()
             .push(ohlc);
     }
 
-    let mut monthly_ohlc_vec: Vec<Ohlc> = Vec::new();
+    let mut longer_ohlc_vec: Vec<Ohlc> = Vec::new();
 
-    for (month, ohlcs) in monthly_ohlc_map {
+    for (date, ohlcs) in longer_ohlc_map {
         let open = ohlcs.first().unwrap().open;
         let close = ohlcs.last().unwrap().close;
         let high = ohlcs.iter().map(|ohlc| ohlc.high).fold(f64::NAN, f64::max);
         let low = ohlcs.iter().map(|ohlc| ohlc.low).fold(f64::NAN, f64::min);
-        monthly_ohlc_vec.push(Ohlc::new(month, open, high, low, close));
+        longer_ohlc_vec.push(Ohlc::new(date, open, high, low, close));
     }
 
-    monthly_ohlc_vec.sort_by(|a, b| a.date.cmp(&b.date));
+    longer_ohlc_vec.sort_by(|a, b| a.date.cmp(&b.date));
 
-    monthly_ohlc_vec
+    longer_ohlc_vec
 }
 
 #[derive(Debug)]
@@ -473,8 +499,9 @@ impl Last20Analysis {
     pub fn get_stop_loss_order(&self) -> f64 {
         self.stop_loss_order.unwrap()
     }
-    pub fn get_units(&self) -> i32 {
-        self.units.unwrap()
+    /// None without a breakout, or for a stock analyzed without the J-Quants unit
+    pub fn get_units(&self) -> Option<i32> {
+        self.units
     }
     pub fn get_analyzed_at(&self) -> &str {
         self.analyzed_at.as_str()
@@ -514,6 +541,126 @@ pub fn get_candlestick_chart(ohlc_vec: Vec<Ohlc>) {
 
 #[cfg(test)]
 mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Daily bars from 2024-01-01, zero-range bars included
+    fn ohlc_vec_strategy(len: std::ops::Range<usize>) -> impl Strategy<Value = Vec<Ohlc>> {
+        let bar = (
+            1.0..10000.0,
+            prop_oneof![Just(0.0), 0.0..500.0],
+            0.0..=1.0,
+            0.0..=1.0,
+        );
+        prop::collection::vec(bar, len).prop_map(|bars| {
+            let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+            bars.into_iter()
+                .enumerate()
+                .map(
+                    |(i, (low, range, open, close)): (usize, (f64, f64, f64, f64))| {
+                        Ohlc::new(
+                            (start + Duration::days(i as i64))
+                                .format("%Y-%m-%d")
+                                .to_string(),
+                            low + range * open,
+                            low + range,
+                            low,
+                            low + range * close,
+                        )
+                    },
+                )
+                .collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_stop_loss_order_within_last20_range(
+            mut ohlc_vec in ohlc_vec_strategy(19..80),
+            long in any::<bool>(),
+            gap in 0.01..100.0,
+            range in 0.0..100.0,
+        ) {
+            // append a bar breaking out of the previous 19
+            let prev_19 = &ohlc_vec[ohlc_vec.len() - 19..];
+            let high = prev_19.iter().map(|x| x.high).fold(f64::NAN, f64::max);
+            let low = prev_19.iter().map(|x| x.low).fold(f64::NAN, f64::min);
+            let date = "2099-12-31".to_owned();
+            ohlc_vec.push(match long {
+                true => Ohlc::new(date, high, high + gap + range, high, high + gap),
+                false => Ohlc::new(date, low, low, low - gap - range, low - gap),
+            });
+
+            let last_20 = &ohlc_vec[ohlc_vec.len() - 20..];
+            let high = last_20.iter().map(|x| x.high).fold(f64::NAN, f64::max);
+            let low = last_20.iter().map(|x| x.low).fold(f64::NAN, f64::min);
+
+            let analysis = OhlcAnalyzer::from_jquants(StockCode::new("7203").unwrap(), ohlc_vec).analyze_last20(Some(10000.0));
+            prop_assert!(analysis.get_break_or_not());
+            let stop_loss_order = analysis.get_stop_loss_order();
+            prop_assert!((low..=high).contains(&stop_loss_order), "{} not in {}..={}", stop_loss_order, low, high);
+        }
+
+        #[test]
+        fn test_monthly_ohlc_keeps_high_above_low(ohlc_vec in ohlc_vec_strategy(1..120)) {
+            let monthly = to_timeframe_ohlc(ohlc_vec.clone(), Timeframe::Monthly);
+            let months = ohlc_vec.iter().map(|x| &x.date[0..7]).collect::<std::collections::HashSet<_>>();
+            prop_assert_eq!(monthly.len(), months.len());
+            for ohlc in &monthly {
+                prop_assert!(ohlc.high >= ohlc.low);
+                prop_assert!((ohlc.low..=ohlc.high).contains(&ohlc.open));
+                prop_assert!((ohlc.low..=ohlc.high).contains(&ohlc.close));
+            }
+        }
+    }
+
+    #[test]
+    fn test_weekly_ohlc() {
+        let bar = |date: &str, open: f64, high: f64, low: f64, close: f64| {
+            Ohlc::new(date.to_owned(), open, high, low, close)
+        };
+        // 2024-01-08 was a holiday, 01-04 and 01-05 fall in the week of 01-01
+        let weekly = to_timeframe_ohlc(
+            vec![
+                bar("2024-01-04", 100.0, 105.0, 98.0, 104.0),
+                bar("2024-01-05", 104.0, 108.0, 101.0, 106.0),
+                bar("2024-01-09", 107.0, 112.0, 106.0, 111.0),
+                bar("2024-01-12", 111.0, 115.0, 109.0, 114.0),
+            ],
+            Timeframe::Weekly,
+        );
+        assert_eq!(weekly.len(), 2);
+        assert_eq!(weekly[0].get_date(), "2024-01-01");
+        assert_eq!(
+            (
+                weekly[0].open,
+                weekly[0].high,
+                weekly[0].low,
+                weekly[0].close
+            ),
+            (100.0, 108.0, 98.0, 106.0)
+        );
+        assert_eq!(weekly[1].get_date(), "2024-01-08");
+        assert_eq!(weekly[1].close, 114.0);
+        // two bars as wide as their range
+        assert_eq!(standardized_diff_and_trend(&weekly).1, BullBear::NoTrend);
+
+        let start = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let rising = (0..10)
+            .map(|i| {
+                let date = (start + Duration::days(7 * i))
+                    .format("%Y-%m-%d")
+                    .to_string();
+                let low = 100.0 + 5.0 * i as f64;
+                Ohlc::new(date, low, low + 2.0, low, low + 2.0)
+            })
+            .collect::<Vec<_>>();
+        let weekly = to_timeframe_ohlc(rising, Timeframe::Weekly);
+        assert_eq!(weekly[0].get_date(), "2024-01-08");
+        assert_eq!(standardized_diff_and_trend(&weekly).1, BullBear::Bull);
+        assert_eq!(standardized_diff_and_trend(&[]).1, BullBear::NoTrend);
+    }
+
     #[test]
     fn test_float() {
         let a = 142.3466;
@@ -0,0 +1,159 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::live::OhlcPremium;
+use crate::rounding::round_dp;
+
+/// Periods of the usual MACD (12, 26, 9) of the closes
+pub const FAST: usize = 12;
+pub const SLOW: usize = 26;
+pub const SIGNAL: usize = 9;
+
+/// MACD of one bar, rounded to 0.01
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Macd {
+    /// EMA of `FAST` closes - EMA of `SLOW` closes
+    macd: f64,
+    /// EMA of `SIGNAL` MACDs
+    signal: f64,
+    /// macd - signal
+    histogram: f64,
+}
+
+impl Macd {
+    //getters
+    pub fn get_macd(&self) -> f64 {
+        self.macd
+    }
+    pub fn get_signal(&self) -> f64 {
+        self.signal
+    }
+    pub fn get_histogram(&self) -> f64 {
+        self.histogram
+    }
+}
+
+/// The MACD crossed its signal line on the last bar
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MacdCross {
+    /// crossed above
+    Bullish,
+    /// crossed below
+    Bearish,
+}
+
+impl MacdCross {
+    /// The mark of the report column
+    pub fn mark(&self) -> &'static str {
+        match self {
+            MacdCross::Bullish => "↑",
+            MacdCross::Bearish => "↓",
+        }
+    }
+}
+
+/// EMA of `values` from the `period`th one on, seeded with their mean.
+/// Empty when there are fewer values
+pub fn ema(values: &[f64], period: usize) -> Vec<f64> {
+    if period == 0 || values.len() < period {
+        return Vec::new();
+    }
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let seed = values[..period].iter().sum::<f64>() / period as f64;
+    let mut emas = vec![seed];
+    for value in &values[period..] {
+        let prev = emas[emas.len() - 1];
+        emas.push(prev + alpha * (value - prev));
+    }
+    emas
+}
+
+/// MACD of each bar from the `SLOW + SIGNAL - 1`th on (oldest first)
+pub fn macd_series(ohlc_vec: &[OhlcPremium]) -> Vec<Macd> {
+    let closes = ohlc_vec.iter().map(|x| x.get_close()).collect::<Vec<_>>();
+    let fast = ema(&closes, FAST);
+    let slow = ema(&closes, SLOW);
+    // fast starts SLOW - FAST bars earlier than slow
+    let macds = slow
+        .iter()
+        .zip(&fast[(SLOW - FAST).min(fast.len())..])
+        .map(|(slow, fast)| fast - slow)
+        .collect::<Vec<_>>();
+    let signals = ema(&macds, SIGNAL);
+    macds[(SIGNAL - 1).min(macds.len())..]
+        .iter()
+        .zip(signals)
+        .map(|(macd, signal)| Macd {
+            macd: round_dp(*macd, 2),
+            signal: round_dp(signal, 2),
+            histogram: round_dp(macd - signal, 2),
+        })
+        .collect()
+}
+
+/// The MACD of the last bar and its cross, None for fewer than `SLOW + SIGNAL` bars.
+/// A cross is the histogram changing sign from the bar before
+pub fn of(ohlc_vec: &[OhlcPremium]) -> (Option<Macd>, Option<MacdCross>) {
+    let series = macd_series(ohlc_vec);
+    let cross = match series.as_slice() {
+        [.., before, last] if before.histogram <= 0.0 && last.histogram > 0.0 => {
+            Some(MacdCross::Bullish)
+        }
+        [.., before, last] if before.histogram >= 0.0 && last.histogram < 0.0 => {
+            Some(MacdCross::Bearish)
+        }
+        _ => None,
+    };
+    (series.last().copied(), cross)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars(closes: &[f64]) -> Vec<OhlcPremium> {
+        closes
+            .iter()
+            .map(|&close| {
+                OhlcPremium::test_bar("2024-01-05", close, close + 5.0, close - 5.0, close)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ema() {
+        // seeded with (1 + 2 + 3) / 3, then alpha 0.5
+        assert_eq!(ema(&[1.0, 2.0, 3.0, 6.0, 2.0], 3), vec![2.0, 4.0, 3.0]);
+        assert!(ema(&[1.0, 2.0], 3).is_empty());
+    }
+
+    #[test]
+    fn test_macd_cross() {
+        // a long fall then a sharp rise turns the histogram positive
+        let mut closes = (0..40).map(|i| 1000.0 - i as f64 * 5.0).collect::<Vec<_>>();
+        assert_eq!(macd_series(&bars(&closes[..SLOW + SIGNAL - 2])).len(), 0);
+        assert_eq!(macd_series(&bars(&closes[..SLOW + SIGNAL - 1])).len(), 1);
+
+        let (macd, cross) = of(&bars(&closes));
+        assert!(macd.unwrap().get_macd() < 0.0);
+        assert_eq!(cross, None);
+
+        let mut crossed = None;
+        while crossed.is_none() && closes.len() < 60 {
+            closes.push(closes[closes.len() - 1] + 30.0);
+            crossed = of(&bars(&closes)).1;
+        }
+        assert_eq!(crossed, Some(MacdCross::Bullish));
+        // the next bar up is no fresh cross
+        closes.push(closes[closes.len() - 1] + 30.0);
+        assert_eq!(of(&bars(&closes)).1, None);
+
+        let mut crossed = None;
+        while crossed.is_none() && closes.len() < 100 {
+            closes.push(closes[closes.len() - 1] - 30.0);
+            crossed = of(&bars(&closes)).1;
+        }
+        assert_eq!(crossed, Some(MacdCross::Bearish));
+    }
+}
@@ -0,0 +1,239 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+use super::dataset::{DatasetRow, FEATURES};
+use crate::config::GdriveJson;
+use crate::my_error::MyError;
+
+/// `model` in config.json
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ModelConfig {
+    /// relative to trading23/, e.g. "models/nextday.json" or "models/nextday.onnx"
+    path: String,
+    /// shown in the report, e.g. "2024-01-lr"
+    version: String,
+}
+
+/// Probability of a candidate, from the features of `dataset::FEATURES`
+pub trait Scorer: Send + Sync {
+    fn score(&self, features: &[f64]) -> Result<f64, MyError>;
+}
+
+/// Logistic regression exported as json:
+/// `{"features": [...FEATURES], "weights": [...], "bias": -1.2}`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LogisticModel {
+    features: Vec<String>,
+    weights: Vec<f64>,
+    bias: f64,
+}
+
+impl LogisticModel {
+    /// The model must take the features of `dataset::FEATURES` in the same order
+    fn validate(&self) -> Result<(), MyError> {
+        if self.features != FEATURES {
+            return Err(MyError::Config(format!(
+                "model features {:?} don't match {:?}",
+                self.features, FEATURES
+            )));
+        }
+        if self.weights.len() != FEATURES.len() {
+            return Err(MyError::Config(format!(
+                "{} weights for {} features",
+                self.weights.len(),
+                FEATURES.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Scorer for LogisticModel {
+    fn score(&self, features: &[f64]) -> Result<f64, MyError> {
+        if features.len() != self.weights.len() {
+            return Err(MyError::Config(format!(
+                "{} features for {} weights",
+                features.len(),
+                self.weights.len()
+            )));
+        }
+        let z = self.bias
+            + self
+                .weights
+                .iter()
+                .zip(features)
+                .map(|(w, x)| w * x)
+                .sum::<f64>();
+        Ok(1.0 / (1.0 + (-z).exp()))
+    }
+}
+
+/// ONNX model run by tract, taking the features of `dataset::FEATURES` as a
+/// `[1, FEATURES.len()]` float input. The first output is the probability as `[1]` or
+/// `[1, 1]`, or the class probabilities as `[1, 2]` (skl2onnx with `zipmap=False`),
+/// the positive class second
+#[cfg(feature = "onnx")]
+pub struct OnnxModel {
+    plan: tract_onnx::prelude::TypedRunnableModel<tract_onnx::prelude::TypedModel>,
+}
+
+#[cfg(feature = "onnx")]
+impl OnnxModel {
+    pub fn load(path: &Path) -> Result<Self, MyError> {
+        use tract_onnx::prelude::*;
+
+        let plan = tract_onnx::onnx()
+            .model_for_path(path)?
+            .with_input_fact(0, f32::fact([1, FEATURES.len()]).into())?
+            .into_optimized()?
+            .into_runnable()?;
+        Ok(OnnxModel { plan })
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl Scorer for OnnxModel {
+    fn score(&self, features: &[f64]) -> Result<f64, MyError> {
+        use tract_onnx::prelude::*;
+
+        if features.len() != FEATURES.len() {
+            return Err(MyError::Config(format!(
+                "{} features for {}",
+                features.len(),
+                FEATURES.len()
+            )));
+        }
+        let input = Tensor::from_shape(
+            &[1, features.len()],
+            &features.iter().map(|x| *x as f32).collect::<Vec<_>>(),
+        )?;
+        let outputs = self.plan.run(tvec!(input.into()))?;
+        let output = outputs[0].cast_to::<f32>()?;
+        let probabilities = output.as_slice::<f32>()?;
+        match probabilities {
+            [p] | [_, p] => Ok(*p as f64),
+            _ => Err(MyError::Config(format!(
+                "model output of shape {:?}, [1], [1, 1] or [1, 2] expected",
+                output.shape()
+            ))),
+        }
+    }
+}
+
+/// By extension, ONNX with the `onnx` feature (tract)
+pub fn load_scorer(path: &Path) -> Result<Box<dyn Scorer>, MyError> {
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("json") => {
+            let model: LogisticModel = serde_json::from_reader(File::open(path)?)?;
+            model.validate()?;
+            Ok(Box::new(model))
+        }
+        #[cfg(feature = "onnx")]
+        Some("onnx") => Ok(Box::new(OnnxModel::load(path)?)),
+        #[cfg(not(feature = "onnx"))]
+        Some("onnx") => Err(MyError::Config(format!(
+            "{}: built without the onnx feature",
+            path.display()
+        ))),
+        _ => Err(MyError::Config(format!(
+            "{}: unknown model format",
+            path.display()
+        ))),
+    }
+}
+
+/// Optional stage of the nextday screening, adds a score column to the tables
+pub struct ScoringStage {
+    scorer: Box<dyn Scorer>,
+    version: String,
+}
+
+impl ScoringStage {
+    pub fn new(scorer: Box<dyn Scorer>, version: &str) -> Self {
+        ScoringStage {
+            scorer,
+            version: version.to_owned(),
+        }
+    }
+
+    /// None without `model` in config.json, or when the model can't be loaded
+    pub fn from_config() -> Option<Self> {
+        let config = GdriveJson::new().ok()?;
+        let model = config.model()?;
+        let result = std::env::var("GDRIVE_PATH")
+            .map_err(MyError::from)
+            .and_then(|gdrive_path| {
+                load_scorer(&Path::new(&gdrive_path).join("trading23").join(&model.path))
+            });
+        match result {
+            Ok(scorer) => Some(ScoringStage::new(scorer, &model.version)),
+            Err(e) => {
+                warn!("model {} unavailable, not scored: {}", model.version, e);
+                None
+            }
+        }
+    }
+
+    //getters
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    /// None (shown as "-") when the row can't be scored
+    pub fn score(&self, row: &DatasetRow) -> Option<f64> {
+        match self.scorer.score(&row.features()) {
+            Ok(score) => Some(score),
+            Err(e) => {
+                warn!("{} {} not scored: {}", row.code, row.date, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logistic_model() {
+        let model: LogisticModel = serde_json::from_str(&format!(
+            r#"{{"features": {}, "weights": [0, 0, 0, 0, 0, 0, 0.5, -0.5, 0], "bias": 0}}"#,
+            serde_json::to_string(&FEATURES).unwrap()
+        ))
+        .unwrap();
+        model.validate().unwrap();
+        let features = [1000.0, 20.0, 0.1, 0.2, 990.0, 1010.0, 3.0, 1.0, 1.0];
+        let score = model.score(&features).unwrap();
+        assert!((score - 1.0 / (1.0 + (-1.0f64).exp())).abs() < 1e-9);
+        assert!(model.score(&features[..8]).is_err());
+
+        let reordered = LogisticModel {
+            features: FEATURES.iter().rev().map(|x| x.to_string()).collect(),
+            ..model
+        };
+        assert!(reordered.validate().is_err());
+        assert!(load_scorer(Path::new("nextday.pkl")).is_err());
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    #[test]
+    fn test_onnx_without_feature() {
+        assert!(matches!(
+            load_scorer(Path::new("nextday.onnx")),
+            Err(MyError::Config(_))
+        ));
+    }
+
+    #[cfg(feature = "onnx")]
+    #[test]
+    fn test_onnx_missing_file() {
+        // an I/O error, not the rejection of builds without the feature
+        assert!(!matches!(
+            load_scorer(Path::new("no_such_model.onnx")),
+            Err(MyError::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use crate::rounding::round_dp;
+
+/// Stocks of the universe not in stocks_master yet
+pub const UNKNOWN_SECTOR: &str = "-";
+
+/// A 33-sector on the analysis date
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectorHeat {
+    sector: String,
+    stocks: usize,
+    /// Mean daily change (%) of its stocks
+    average_change: f64,
+    /// Stocks that closed above the high of their previous 19 bars
+    breakouts: usize,
+    /// and below the low
+    breakdowns: usize,
+}
+
+impl SectorHeat {
+    //getters
+    pub fn get_sector(&self) -> &str {
+        &self.sector
+    }
+    pub fn get_stocks(&self) -> usize {
+        self.stocks
+    }
+    pub fn get_average_change(&self) -> f64 {
+        self.average_change
+    }
+    pub fn get_breakouts(&self) -> usize {
+        self.breakouts
+    }
+    pub fn get_breakdowns(&self) -> usize {
+        self.breakdowns
+    }
+}
+
+/// One stock of the aggregation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SectorMove<'a> {
+    pub sector: &'a str,
+    /// daily change (%)
+    pub change: f64,
+    pub breakout: bool,
+    pub breakdown: bool,
+}
+
+/// The sectors of `moves`, the hottest (highest mean change) first
+pub fn aggregate<'a>(moves: impl IntoIterator<Item = SectorMove<'a>>) -> Vec<SectorHeat> {
+    let mut sectors: HashMap<&str, (usize, f64, usize, usize)> = HashMap::new();
+    for x in moves {
+        let entry = sectors.entry(x.sector).or_default();
+        entry.0 += 1;
+        entry.1 += x.change;
+        entry.2 += x.breakout as usize;
+        entry.3 += x.breakdown as usize;
+    }
+    let mut heat = sectors
+        .into_iter()
+        .map(
+            |(sector, (stocks, change, breakouts, breakdowns))| SectorHeat {
+                sector: sector.to_owned(),
+                stocks,
+                average_change: round_dp(change / stocks as f64, 2),
+                breakouts,
+                breakdowns,
+            },
+        )
+        .collect::<Vec<_>>();
+    heat.sort_by(|a, b| {
+        b.average_change
+            .total_cmp(&a.average_change)
+            .then_with(|| a.sector.cmp(&b.sector))
+    });
+    heat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate() {
+        let x = |sector, change, breakout, breakdown| SectorMove {
+            sector,
+            change,
+            breakout,
+            breakdown,
+        };
+        let heat = aggregate([
+            x("銀行業", 1.0, true, false),
+            x("輸送用機器", -0.5, false, true),
+            x("銀行業", 2.0, true, false),
+            x("輸送用機器", 0.2, false, false),
+            x(UNKNOWN_SECTOR, 0.0, false, false),
+        ]);
+        assert_eq!(
+            heat.iter().map(|x| x.get_sector()).collect::<Vec<_>>(),
+            ["銀行業", UNKNOWN_SECTOR, "輸送用機器"]
+        );
+        assert_eq!(heat[0].get_stocks(), 2);
+        assert_eq!(heat[0].get_average_change(), 1.5);
+        assert_eq!(heat[0].get_breakouts(), 2);
+        assert_eq!(heat[2].get_average_change(), -0.15);
+        assert_eq!(heat[2].get_breakdowns(), 1);
+    }
+}
@@ -0,0 +1,210 @@
+use chrono::{Duration, NaiveDateTime, Timelike};
+use std::collections::BTreeMap;
+
+use super::live::{Ohlc, OhlcPremium};
+use crate::jquants::fetcher::PricesAmInner;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// One bar from consecutive bars, dated like the first of them. None when `bars` is empty
+pub fn merge(bars: &[Ohlc]) -> Option<Ohlc> {
+    let (first, last) = (bars.first()?, bars.last()?);
+    Some(Ohlc::new(
+        first.get_date().to_owned(),
+        first.get_open(),
+        bars.iter().map(|x| x.get_high()).fold(f64::NAN, f64::max),
+        bars.iter().map(|x| x.get_low()).fold(f64::NAN, f64::min),
+        last.get_close(),
+    ))
+}
+
+/// Mean of high - low, unrounded so it works for FX prices too
+pub fn average_range(bars: &[Ohlc]) -> f64 {
+    bars.iter().map(|x| x.get_high() - x.get_low()).sum::<f64>() / bars.len() as f64
+}
+
+/// Morning and afternoon bars of a stored day.
+/// stocks_ohlc has the day's high and low only, so the session that made an extreme
+/// is known from the morning prices (prices_am) when they were kept. A session whose
+/// extreme is unknown is bounded by its open and close, like
+/// `PricesAmInner::from_ohlc_premium`
+pub fn split_tse(day: &OhlcPremium, morning: Option<&PricesAmInner>) -> [Ohlc; 2] {
+    let date = day.get_date().to_owned();
+    let (afternoon_open, close) = (day.get_afternoon_open(), day.get_close());
+    let (open_close_high, open_close_low) = (afternoon_open.max(close), afternoon_open.min(close));
+    match morning.filter(|x| x.has_ohlc()) {
+        Some(am) => {
+            let afternoon_high = match day.get_high() > am.get_high() {
+                true => day.get_high(),
+                false => open_close_high,
+            };
+            let afternoon_low = match day.get_low() < am.get_low() {
+                true => day.get_low(),
+                false => open_close_low,
+            };
+            [
+                Ohlc::new(
+                    date.clone(),
+                    am.get_open(),
+                    am.get_high(),
+                    am.get_low(),
+                    am.get_close(),
+                ),
+                Ohlc::new(date, afternoon_open, afternoon_high, afternoon_low, close),
+            ]
+        }
+        None => {
+            let am = PricesAmInner::from_ohlc_premium(day);
+            [
+                Ohlc::new(
+                    date.clone(),
+                    am.get_open(),
+                    am.get_high(),
+                    am.get_low(),
+                    am.get_close(),
+                ),
+                Ohlc::new(date, afternoon_open, open_close_high, open_close_low, close),
+            ]
+        }
+    }
+}
+
+/// FX trading sessions in Tokyo time, summer time isn't followed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FxSession {
+    /// 9:00-15:00
+    Tokyo,
+    /// 16:00-1:00
+    London,
+    /// 22:00-6:00
+    NewYork,
+}
+
+impl FxSession {
+    /// (start hour, hours)
+    fn hours(&self) -> (i64, i64) {
+        match self {
+            FxSession::Tokyo => (9, 6),
+            FxSession::London => (16, 9),
+            FxSession::NewYork => (22, 8),
+        }
+    }
+
+    /// Start of the session `time` is in, None outside the session
+    fn start_of(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let (start, hours) = self.hours();
+        let shifted = time - Duration::hours(start);
+        match (shifted.hour() as i64) < hours {
+            true => shifted.date().and_hms_opt(start as u32, 0, 0),
+            false => None,
+        }
+    }
+}
+
+/// One bar per session from intraday bars ("YYYY-MM-DD HH:MM:SS", an hour or shorter),
+/// dated at the session start. Bars outside the session or with another date format
+/// are left out
+pub fn fx_session_bars(bars: &[Ohlc], session: FxSession) -> Vec<Ohlc> {
+    let mut sessions: BTreeMap<NaiveDateTime, Vec<Ohlc>> = BTreeMap::new();
+    for bar in bars {
+        let Ok(time) = NaiveDateTime::parse_from_str(bar.get_date(), DATE_FORMAT) else {
+            continue;
+        };
+        if let Some(start) = session.start_of(time) {
+            sessions.entry(start).or_default().push(bar.clone());
+        }
+    }
+    sessions
+        .into_iter()
+        .filter_map(|(start, bars)| {
+            let merged = merge(&bars)?;
+            Some(Ohlc::new(
+                start.format(DATE_FORMAT).to_string(),
+                merged.get_open(),
+                merged.get_high(),
+                merged.get_low(),
+                merged.get_close(),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_code::StockCode;
+
+    fn day() -> OhlcPremium {
+        OhlcPremium::new(
+            StockCode::new("7203").unwrap(),
+            "2024-01-05".to_owned(),
+            100.0,
+            120.0,
+            90.0,
+            110.0,
+            105.0,
+            104.0,
+        )
+    }
+
+    #[test]
+    fn test_split_tse() {
+        // the high was made in the afternoon, the low in the morning
+        let am = PricesAmInner::new(
+            "2024-01-05".to_owned(),
+            StockCode::new("7203").unwrap(),
+            Some(100.0),
+            Some(108.0),
+            Some(90.0),
+            Some(105.0),
+            None,
+            None,
+        );
+        let [morning, afternoon] = split_tse(&day(), Some(&am));
+        assert_eq!((morning.get_high(), morning.get_low()), (108.0, 90.0));
+        assert_eq!(
+            (
+                afternoon.get_open(),
+                afternoon.get_high(),
+                afternoon.get_low()
+            ),
+            (104.0, 120.0, 104.0)
+        );
+        assert_eq!(afternoon.get_close(), 110.0);
+
+        let [morning, afternoon] = split_tse(&day(), None);
+        assert_eq!((morning.get_high(), morning.get_low()), (105.0, 100.0));
+        assert_eq!((afternoon.get_high(), afternoon.get_low()), (110.0, 104.0));
+        assert_eq!(average_range(&[morning, afternoon]), 5.5);
+    }
+
+    #[test]
+    fn test_fx_session_bars() {
+        let bar = |date: &str, close: f64| {
+            Ohlc::new(date.to_owned(), close, close + 0.1, close - 0.1, close)
+        };
+        let bars = vec![
+            bar("2024-01-04 15:00:00", 143.0),
+            bar("2024-01-04 16:00:00", 144.0),
+            bar("2024-01-04 23:00:00", 145.0),
+            bar("2024-01-05 00:00:00", 146.0),
+            bar("2024-01-05 01:00:00", 147.0),
+            bar("2024-01-05 16:00:00", 148.0),
+        ];
+
+        let london = fx_session_bars(&bars, FxSession::London);
+        assert_eq!(london.len(), 2);
+        assert_eq!(london[0].get_date(), "2024-01-04 16:00:00");
+        assert_eq!(
+            (london[0].get_open(), london[0].get_close()),
+            (144.0, 146.0)
+        );
+        assert_eq!(london[0].get_high(), 146.1);
+        assert_eq!(london[1].get_date(), "2024-01-05 16:00:00");
+
+        let new_york = fx_session_bars(&bars, FxSession::NewYork);
+        assert_eq!(new_york.len(), 1);
+        assert_eq!(new_york[0].get_close(), 147.0);
+        assert!(fx_session_bars(&bars, FxSession::Tokyo).is_empty());
+    }
+}
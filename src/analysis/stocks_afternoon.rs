@@ -1,117 +1,126 @@
-use log::{debug, error, info};
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
+use crate::config::GdriveJson;
+use crate::database::blacklist;
+use crate::database::corporate_events;
+use crate::database::market_regime;
+use crate::database::runs::Coverage;
+use crate::database::stocks_master::{self, Sectors};
+use crate::i18n::{status_text, Lang, Msg};
 use crate::jquants::fetcher::{PricesAm, PricesAmInner};
-use crate::markdown::Markdown;
+use crate::markdown::{short_name, unit_text, Column, Markdown};
 use crate::my_error::MyError;
-use crate::my_file_io::{get_fetched_ohlc_file_path, load_nikkei225_list, AssetType, JquantsStyle};
-
-use super::live::OhlcPremium;
-use anyhow::anyhow;
+use crate::my_file_io::Universe;
+use crate::output_sink::Report;
+use crate::profile::{self, Stage};
+use crate::report_kind::ReportKind;
+use crate::signal::{Signal, SignalId, AFTERNOON_SOURCE};
+use crate::stock_code::StockCode;
+use crate::units::{AtrUnits, Yen};
+
+use super::backtesting_topix::TopixDailyWindowList;
+use super::code_history::code_link;
+use super::indicators::{self, AnalysisParams, ConsolidationFilter};
+use super::live::{BullBear, OhlcPremium};
+use super::stocks_daytrading::TTestResult;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::sync::Arc;
+
+pub const AFTERNOON: ReportKind = ReportKind::new("afternoon", "jquants_afternoon");
+pub const CONSOLIDATING_AFTERNOON: ReportKind =
+    ReportKind::new("consolidating_afternoon", "jquants_consolidating_an");
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct StocksAfternoon {
-    code: String,
+    code: StockCode,
     name: String,
     atr: f64,
     unit: i32,
-    required_amount: i32,
+    required_amount: Yen,
+    /// A single board lot risks more than the unit budget
+    #[serde(default)]
+    over_budget: bool,
     latest_move: f64,
     standardized_diff: f64,
+    /// The 20-day Bollinger Band width is in the bottom decile of the last 60 bars
+    #[serde(default)]
+    squeeze: bool,
     number_of_resistance_candles: usize,
     number_of_support_candles: usize,
     status: String,
     yesterday_close: f64,
     morning_open: f64,
     morning_close: f64,
+    result_afternoon: Option<AtrUnits>,
     analyzed_at: String,
+    /// Regime of TOPIX on the analysis date from the market_regime table, None before
+    /// it has the date
+    #[serde(default)]
+    market_regime: Option<BullBear>,
 }
 
 impl StocksAfternoon {
     pub fn from_vec(
         ohlc_vec: &[OhlcPremium],
         prices_am: PricesAmInner,
-        code: &str,
+        code: &StockCode,
         name: &str,
         unit: f64,
         date: &str,
+        params: &AnalysisParams,
     ) -> Result<Self, MyError> {
+        let _span = profile::span(Stage::Window);
         let position = match ohlc_vec[ohlc_vec.len() - 1].get_date() {
             x if x == date => ohlc_vec.len() - 2,
             _ => ohlc_vec.len() - 1,
         };
 
-        if position < 60 {
+        if position < params.get_range_period() {
             return Err(MyError::OutOfRange);
         }
 
-        let ohlc_5 = &ohlc_vec[(position - 4)..=position];
-        let ohlc_20 = &ohlc_vec[(position - 19)..=position];
-        let ohlc_60 = &ohlc_vec[(position - 59)..=position];
+        let ohlc_range = params.range_bars(ohlc_vec, position);
 
         let (morning_open, morning_close) = (prices_am.get_open(), prices_am.get_close());
 
-        let (prev_19, last) = ohlc_20.split_at(19);
-        // let prev_19_high = prev_19
-        //     .iter()
-        //     .map(|ohlc| ohlc.get_high())
-        //     .fold(f64::NAN, f64::max);
-        // let prev_19_low = prev_19
-        //     .iter()
-        //     .map(|ohlc| ohlc.get_low())
-        //     .fold(f64::NAN, f64::min);
-
-        let atr = ohlc_5
-            .iter()
-            .map(|ohlc| (ohlc.get_high() - ohlc.get_low()))
-            .sum::<f64>()
-            / ohlc_5.len() as f64;
-        let atr = (atr * 10.0).round() / 10.0;
-
-        let (unit, required_amount) = {
-            let unit = unit / atr;
-            let required_amount = (unit * last[0].get_close()) as i32;
-            (unit as i32, required_amount)
-        };
-
-        let highest_high = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_high())
-            .fold(f64::NAN, f64::max);
-        let lowest_low = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_low())
-            .fold(f64::NAN, f64::min);
-
-        let diff_sum: f64 = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_high() - ohlc.get_low())
-            .sum();
-        let average_diff = diff_sum / ohlc_60.len() as f64;
-
-        let standardized_diff =
-            (average_diff / (highest_high - lowest_low) * 1000.0).trunc() / 1000.0;
+        let last = &ohlc_vec[position];
+
+        let atr = params.atr().of(&ohlc_vec[..=position]);
+        let over_budget = indicators::lot_over_budget(unit, atr);
+        let (unit, required_amount) =
+            indicators::unit_and_required_amount(unit, atr, last.get_close());
+        let standardized_diff = indicators::standardized_diff(ohlc_range)?;
+        let squeeze = indicators::bollinger_width_rank(
+            &ohlc_vec[..=position],
+            indicators::BOLLINGER_PERIOD,
+            indicators::SQUEEZE_LOOKBACK,
+        )
+        .is_some_and(|rank| rank < indicators::SQUEEZE_QUANTILE);
 
-        let number_of_resistance_candles = ohlc_60
+        let number_of_resistance_candles = ohlc_range
             .iter()
             .filter(|ohlc| ohlc.get_high() > prices_am.get_high() && morning_close > ohlc.get_low())
             .count();
-        let number_of_support_candles = ohlc_60
+        let number_of_support_candles = ohlc_range
             .iter()
             .filter(|ohlc| ohlc.get_high() > morning_close && prices_am.get_low() > ohlc.get_low())
             .count();
 
-        let status = match prices_am.get_close() - ohlc_5[4].get_open() {
+        let status = match prices_am.get_close() - last.get_open() {
             x if x > 0.0 => {
-                if prices_am.get_close() - ohlc_5[4].get_open() > 0.0 {
+                if prices_am.get_close() - last.get_open() > 0.0 {
                     "Rise"
                 } else {
                     "Rise bounded"
                 }
             }
             x if x < 0.0 => {
-                if prices_am.get_close() - ohlc_5[4].get_open() > 0.0 {
+                if prices_am.get_close() - last.get_open() > 0.0 {
                     "Fall bounded"
                 } else {
                     "Fall"
@@ -122,71 +131,122 @@ impl StocksAfternoon {
 
         let yesterday_close = ohlc_vec[position - 1].get_close();
 
-        let latest_move = (morning_close - morning_open) / (last[0].get_high() - last[0].get_low());
-        let latest_move = (latest_move * 100.0).round() / 100.0;
-        let latest_move = latest_move.abs();
+        let latest_move = indicators::move_in_range(
+            morning_close - morning_open,
+            last.get_high() - last.get_low(),
+            "latest_move",
+        )?
+        .abs();
+
+        let result_afternoon = match ohlc_vec[ohlc_vec.len() - 1].get_date() == date {
+            true => {
+                let today = &ohlc_vec[ohlc_vec.len() - 1];
+                Some(indicators::result_in_atr(
+                    today.get_afternoon_open(),
+                    today.get_close(),
+                    atr,
+                ))
+            }
+            false => None,
+        };
 
         Ok(Self {
-            code: code.to_owned(),
+            code: code.clone(),
             name: name.to_owned(),
             atr,
             unit,
             required_amount,
+            over_budget,
             latest_move,
             standardized_diff,
+            squeeze,
             number_of_resistance_candles,
             number_of_support_candles,
             status: status.to_owned(),
             yesterday_close,
             morning_open,
             morning_close,
+            result_afternoon,
             analyzed_at: date.to_owned(),
+            market_regime: None,
         })
     }
 
-    fn markdown_body_output(&self) -> Result<String, MyError> {
-        let mut buffer = String::new();
-        let name = match self.name.chars().count() > 5 {
-            true => {
-                let name: String = self.name.chars().take(4).collect();
-                name
-            }
-            false => self.name.to_owned(),
-        };
+    /// The same for every run of the analysis of the code on `analyzed_at`
+    pub fn signal_id(&self) -> Result<SignalId, MyError> {
+        SignalId::new(AFTERNOON_SOURCE, &self.code, &self.analyzed_at)
+    }
+    fn to_signal(&self) -> Result<Signal, MyError> {
+        Ok(Signal::new(
+            self.signal_id()?,
+            &self.code,
+            &self.analyzed_at,
+            &self.status,
+        ))
+    }
 
-        let morning_result = (self.morning_close - self.morning_open) / self.atr;
-        let morning_result = (morning_result * 100.0).round() / 100.0;
-
-        writeln!(
-            buffer,
-            "{} {}, {}円, {} [R: {}, S: {}] LM: {}",
-            self.code,
-            name,
-            self.morning_close,
-            self.status,
-            self.number_of_resistance_candles,
-            self.number_of_support_candles,
-            self.latest_move,
-        )?;
-
-        writeln!(
-            buffer,
-            "ATR: {}, Unit: {}, 必要金額: {}円",
-            self.atr, self.unit, self.required_amount
-        )?;
-
-        writeln!(buffer, "Morning Result: {}", morning_result)?;
-
-        Ok(buffer)
+    /// The afternoon result is added once the session has closed
+    fn table_columns(lang: Lang, afternoon_result: bool) -> Vec<Column<'static>> {
+        let mut columns = vec![
+            Column::left(Msg::Code.text(lang)),
+            Column::left(Msg::Name.text(lang)),
+            Column::left(Msg::Sector.text(lang)),
+            Column::right(Msg::Price.text(lang)),
+            Column::left(Msg::Status.text(lang)),
+            Column::right("R"),
+            Column::right("S"),
+            Column::right("LM"),
+            Column::right("ATR"),
+            Column::right("Unit"),
+            Column::right(Msg::RequiredAmount.text(lang)),
+            Column::right(Msg::MorningResult.text(lang)),
+        ];
+        if afternoon_result {
+            columns.push(Column::right(Msg::AfternoonResult.text(lang)));
+        }
+        columns.push(Column::left("ID"));
+        columns
+    }
+
+    fn table_row(&self, afternoon_result: bool, sectors: &Sectors, lang: Lang) -> Vec<String> {
+        let morning_result =
+            indicators::result_in_atr(self.morning_open, self.morning_close, self.atr);
+
+        let mut row = vec![
+            code_link(&self.code).to_string(),
+            short_name(&self.name).to_owned(),
+            sectors
+                .get(&self.code)
+                .map_or("-", |x| x.as_str())
+                .to_owned(),
+            lang.yen(self.morning_close).to_string(),
+            status_text(&self.status, lang).to_owned(),
+            self.number_of_resistance_candles.to_string(),
+            self.number_of_support_candles.to_string(),
+            self.latest_move.to_string(),
+            self.atr.to_string(),
+            unit_text(self.unit, self.over_budget),
+            lang.yen(self.required_amount.0).to_string(),
+            morning_result.to_string(),
+        ];
+        if afternoon_result {
+            row.push(
+                self.result_afternoon
+                    .map_or("-".to_owned(), |x| x.to_string()),
+            );
+        }
+        row.push(self.signal_id().map_or("-".to_owned(), |x| x.to_string()));
+        row
     }
 }
 
+/// Rows are shared with `Arc`, so grouping and top 10 lists don't copy them
 #[derive(Debug, Clone)]
 pub struct StocksAfternoonList {
-    data: Vec<StocksAfternoon>,
+    data: Vec<Arc<StocksAfternoon>>,
 }
-impl From<Vec<StocksAfternoon>> for StocksAfternoonList {
-    fn from(data: Vec<StocksAfternoon>) -> Self {
+impl From<Vec<Arc<StocksAfternoon>>> for StocksAfternoonList {
+    fn from(data: Vec<Arc<StocksAfternoon>>) -> Self {
         StocksAfternoonList { data }
     }
 }
@@ -195,23 +255,40 @@ impl StocksAfternoonList {
     //     Self { data: Vec::new() }
     // }
     fn from_vec(vec: Vec<StocksAfternoon>) -> Self {
-        Self { data: vec }
+        Self {
+            data: vec.into_iter().map(Arc::new).collect(),
+        }
     }
 
     // fn append(&mut self, mut stocks_daytrading_list: StocksAfternoonList) {
     //     self.data.append(&mut stocks_daytrading_list.data);
     // }
 
-    pub fn from_nikkei225(prices_am: &PricesAm) -> Result<Self, MyError> {
+    pub fn from_nikkei225(prices_am: &PricesAm, universe: &Universe) -> Result<Self, MyError> {
         let today = chrono::Local::now().format("%Y-%m-%d").to_string();
 
-        let nikkei225 = load_nikkei225_list()?;
-        info!("Nikkei225 has been loaded");
+        let nikkei225 = universe.load()?;
+        info!("{} has been loaded", universe);
 
         let config = crate::config::GdriveJson::new()?;
+        prices_am.check_age(config.prices_am_max_age_minutes())?;
         let unit = config.jquants_unit();
         info!("unit: {}", unit);
 
+        let mut coverage = Coverage::new(&today, nikkei225.len());
+        for row in &nikkei225 {
+            match prices_am
+                .get_stock_am(row.get_code())
+                .is_ok_and(|x| x.has_ohlc())
+            {
+                true => coverage.add_fetched(),
+                false => coverage.add_missing(row.get_code()),
+            }
+        }
+        coverage.check(config.min_coverage())?;
+        info!("prices_am {}", coverage);
+
+        let params = config.analysis_params();
         let result = nikkei225
             .into_iter()
             .filter(|row| {
@@ -231,96 +308,342 @@ impl StocksAfternoonList {
                 // debug!("{:?}", ohlc_vec);
 
                 let stock_am = prices_am.get_stock_am(code)?;
-                let stocks_afternoon =
-                    StocksAfternoon::from_vec(&ohlc_vec, stock_am, code, name, unit, &today)?;
-                Ok(stocks_afternoon)
+                match StocksAfternoon::from_vec(
+                    &ohlc_vec, stock_am, code, name, unit, &today, &params,
+                ) {
+                    Ok(stocks_afternoon) => Ok(Some(stocks_afternoon)),
+                    Err(e @ MyError::ZeroRange(_)) => {
+                        warn!("{} {}: skipped, {}", code, today, e);
+                        Ok(None)
+                    }
+                    Err(e) => Err(e),
+                }
             })
-            .collect::<Result<Vec<StocksAfternoon>, MyError>>()
-            .map(Self::from_vec);
+            .collect::<Result<Vec<Option<StocksAfternoon>>, MyError>>()
+            .map(|data| Self::from_vec(data.into_iter().flatten().collect()));
 
-        result
+        let mut list = result?;
+        list.set_market_regimes()?;
+        Ok(list)
     }
 
-    fn filter_by_standardized_diff(&mut self, diff: f64) {
-        self.data.retain(|x| x.standardized_diff < diff);
+    /// Replays the afternoon strategy over stored history. Each morning session is
+    /// reconstructed from the stored open / morning_close of the day.
+    pub fn from_backtest(universe: &Universe, from: &str, to: &str) -> Result<Self, MyError> {
+        let parse = |date: &str| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|e| MyError::Anyhow(anyhow!("{}: {}", date, e)))
+        };
+        let (from, to) = (parse(from)?, parse(to)?);
+
+        let nikkei225 = universe.load()?;
+        info!("{} has been loaded", universe);
+
+        let config = crate::config::GdriveJson::new()?;
+        let unit = config.jquants_unit();
+        info!("unit: {}", unit);
+        let params = config.analysis_params();
+
+        let conn = crate::database::stocks_ohlc::open_db()?;
+
+        let mut data = Vec::new();
+        for row in nikkei225 {
+            let (code, name) = (row.get_code(), row.get_name());
+
+            let mut ohlc_vec = crate::database::stocks_ohlc::select_by_code(&conn, code)?
+                .into_iter()
+                .map(|ohlc| ohlc.get_inner())
+                .collect::<Vec<_>>();
+            ohlc_vec.sort_by(|a, b| a.get_date().cmp(b.get_date()));
+
+            for (i, ohlc) in ohlc_vec.iter().enumerate() {
+                let date = parse(ohlc.get_date())?;
+                if date < from || date > to {
+                    continue;
+                }
+
+                let prices_am = PricesAmInner::from_ohlc_premium(ohlc);
+                match StocksAfternoon::from_vec(
+                    &ohlc_vec[..=i],
+                    prices_am,
+                    code,
+                    name,
+                    unit,
+                    ohlc.get_date(),
+                    &params,
+                ) {
+                    Ok(stocks_afternoon) => data.push(stocks_afternoon),
+                    Err(MyError::OutOfRange) => {}
+                    Err(e @ MyError::ZeroRange(_)) => {
+                        warn!("{} {}: skipped, {}", code, ohlc.get_date(), e)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        let mut list = Self::from_vec(data);
+        list.set_market_regimes()?;
+        Ok(list)
+    }
+
+    /// Tags the rows with the regime of their analysis date, see `database::market_regime`
+    fn set_market_regimes(&mut self) -> Result<(), MyError> {
+        let dates = self.data.iter().map(|x| x.analyzed_at.as_str());
+        let (Some(from), Some(to)) = (dates.clone().min(), dates.max()) else {
+            return Ok(());
+        };
+        let regimes = market_regime::select_by_date_range(&market_regime::open_db()?, from, to)?;
+        for stocks_afternoon in self.data.iter_mut() {
+            let regime = regimes.get(&stocks_afternoon.analyzed_at).copied();
+            Arc::make_mut(stocks_afternoon).market_regime = regime;
+        }
+        Ok(())
+    }
+
+    fn filter_by_consolidation(&mut self, filter: ConsolidationFilter) {
+        self.data
+            .retain(|x| filter.keeps(x.squeeze, x.standardized_diff));
     }
 
     fn filter_by_latest_move(&mut self, latest_move: f64) {
         self.data.retain(|x| x.latest_move < latest_move);
     }
 
-    fn get_resistance_candles_top10(&self) -> StocksAfternoonList {
-        let mut resistance_candles_top10 = StocksAfternoonList::from(self.data.to_vec());
-        resistance_candles_top10.data.sort_by(|a, b| {
-            b.number_of_resistance_candles
-                .partial_cmp(&a.number_of_resistance_candles)
-                .unwrap()
-        });
-        StocksAfternoonList::from(
-            resistance_candles_top10
-                .data
-                .into_iter()
-                .take(10)
-                .collect::<Vec<_>>(),
-        )
+    /// Top 10 by `key` (descending, ties keep the list order). Keys are computed once
+    /// and only the `Arc`s are sorted.
+    fn top10_by(
+        &self,
+        key: fn(&StocksAfternoon) -> usize,
+    ) -> impl Iterator<Item = &Arc<StocksAfternoon>> {
+        let mut keyed = self
+            .data
+            .iter()
+            .map(|x| (Reverse(key(x)), x))
+            .collect::<Vec<_>>();
+        keyed.sort_by_key(|(key, _)| *key);
+        keyed.into_iter().take(10).map(|(_, x)| x)
     }
-    fn get_support_candles_top10(&self) -> StocksAfternoonList {
-        let mut support_candles_top10 = StocksAfternoonList::from(self.data.to_vec());
-        support_candles_top10.data.sort_by(|a, b| {
-            b.number_of_support_candles
-                .partial_cmp(&a.number_of_support_candles)
-                .unwrap()
-        });
-        StocksAfternoonList::from(
-            support_candles_top10
-                .data
-                .into_iter()
-                .take(10)
-                .collect::<Vec<_>>(),
-        )
+    fn get_resistance_candles_top10(&self) -> impl Iterator<Item = &Arc<StocksAfternoon>> {
+        self.top10_by(|x| x.number_of_resistance_candles)
+    }
+    fn get_support_candles_top10(&self) -> impl Iterator<Item = &Arc<StocksAfternoon>> {
+        self.top10_by(|x| x.number_of_support_candles)
     }
 
-    fn output_for_markdown_afternoon(&self, date: &str) -> Result<Markdown, MyError> {
-        let mut markdown = Markdown::new();
-        markdown.h1(date)?;
-        markdown.h2("Afternoon Strategy")?;
+    /// Rows of the resistance and support tables, each once
+    fn signals(&self) -> Result<Vec<Signal>, MyError> {
+        let mut seen = HashSet::new();
+        self.get_resistance_candles_top10()
+            .chain(self.get_support_candles_top10())
+            .filter(|x| seen.insert(&x.code))
+            .map(|x| x.to_signal())
+            .collect()
+    }
+
+    fn group_by_date(&self) -> HashMap<String, StocksAfternoonList> {
+        let mut date_to_stocks: HashMap<String, Vec<Arc<StocksAfternoon>>> = HashMap::new();
+        for stocks_afternoon in &self.data {
+            date_to_stocks
+                .entry(stocks_afternoon.analyzed_at.clone())
+                .or_default()
+                .push(Arc::clone(stocks_afternoon));
+        }
+        date_to_stocks
+            .into_iter()
+            .map(|(date, data)| (date, StocksAfternoonList::from(data)))
+            .collect()
+    }
+
+    fn t_test(&self) -> String {
+        let afternoon = self
+            .data
+            .iter()
+            .filter_map(|stocks_afternoon| stocks_afternoon.result_afternoon.map(|r| r.0))
+            .collect::<Vec<_>>();
+
+        match afternoon.len() > 1 {
+            true => format!("afternoon: {}", TTestResult::new(afternoon)),
+            false => "afternoon: -".to_owned(),
+        }
+    }
 
-        let resistance_candles_top10 = self.get_resistance_candles_top10();
-        markdown.h3("Resistance Candles Top 10")?;
-        for stocks_afternoon in &resistance_candles_top10.data {
-            markdown.body(&stocks_afternoon.markdown_body_output()?)?;
+    /// Same breakdown as the nextday backtest (TOPIX window x standardized diff),
+    /// applied to the daily resistance / support top 10 of the afternoon strategy.
+    pub fn get_windows_related_result(
+        &self,
+        topix_daily_window_list: &TopixDailyWindowList,
+    ) -> String {
+        let mut resistance = Vec::new();
+        let mut support = Vec::new();
+        for (_, stocks_afternoon_list) in self.group_by_date() {
+            resistance.extend(
+                stocks_afternoon_list
+                    .get_resistance_candles_top10()
+                    .cloned(),
+            );
+            support.extend(stocks_afternoon_list.get_support_candles_top10().cloned());
         }
 
-        let support_candles_top10 = self.get_support_candles_top10();
-        markdown.h3("Support Candles Top 10")?;
-        for stocks_afternoon in &support_candles_top10.data {
-            markdown.body(&stocks_afternoon.markdown_body_output()?)?;
+        let windows = [
+            (
+                "Strong Positive",
+                topix_daily_window_list.get_strong_positive(),
+            ),
+            ("Mild Positive", topix_daily_window_list.get_mild_positive()),
+            ("Mild Negative", topix_daily_window_list.get_mild_negative()),
+            (
+                "Strong Negative",
+                topix_daily_window_list.get_strong_negative(),
+            ),
+        ];
+        let limit = [(0.0, 0.09), (0.09, 0.12), (0.12, 0.40)];
+
+        let mut buffer = String::new();
+        for (title, data) in [
+            ("Resistance Candles Top 10", resistance),
+            ("Support Candles Top 10", support),
+        ] {
+            writeln!(buffer).unwrap();
+            writeln!(buffer, "<{}>", title).unwrap();
+
+            for (window, dates) in windows.iter() {
+                writeln!(buffer).unwrap();
+                writeln!(buffer, "{}", window).unwrap();
+                for (lower_limit, upper_limit) in limit.iter() {
+                    let filtered = data
+                        .iter()
+                        .filter(|stocks_afternoon| {
+                            dates.contains(&stocks_afternoon.analyzed_at)
+                                && (*lower_limit..*upper_limit)
+                                    .contains(&stocks_afternoon.standardized_diff)
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    let filtered_list = StocksAfternoonList::from(filtered);
+                    writeln!(
+                        buffer,
+                        "{}-{}: N={}",
+                        lower_limit,
+                        upper_limit,
+                        filtered_list.data.len()
+                    )
+                    .unwrap();
+                    writeln!(buffer, "{}", filtered_list.t_test()).unwrap();
+                }
+            }
         }
 
+        buffer
+    }
+
+    fn write_table<'a>(
+        markdown: &mut Markdown,
+        rows: impl Iterator<Item = &'a Arc<StocksAfternoon>>,
+        sectors: &Sectors,
+        lang: Lang,
+    ) -> Result<(), MyError> {
+        let rows = rows.collect::<Vec<_>>();
+        let afternoon_result = rows.iter().any(|x| x.result_afternoon.is_some());
+        markdown.table(
+            &StocksAfternoon::table_columns(lang, afternoon_result),
+            rows.iter()
+                .map(|x| x.table_row(afternoon_result, sectors, lang)),
+        )
+    }
+
+    fn output_for_markdown_afternoon(
+        &self,
+        date: &str,
+        summary: &[String],
+        lang: Lang,
+    ) -> Result<Markdown, MyError> {
+        let _span = profile::span(Stage::Render);
+        let sectors = stocks_master::load_sectors();
+        let mut markdown = Markdown::new();
+        // 20 rows of about 300 bytes
+        markdown.reserve(8 * 1024);
+        markdown.section(date, |m| {
+            m.section(Msg::AfternoonStrategy.text(lang), |m| {
+                m.section(Msg::Summary.text(lang), |m| {
+                    for line in summary {
+                        m.body(line)?;
+                    }
+                    Ok(())
+                })?;
+                m.section(Msg::ResistanceTop10.text(lang), |m| {
+                    Self::write_table(m, self.get_resistance_candles_top10(), &sectors, lang)
+                })?;
+                m.section(Msg::SupportTop10.text(lang), |m| {
+                    Self::write_table(m, self.get_support_candles_top10(), &sectors, lang)
+                })
+            })
+        })?;
+
         info!("{}", markdown.buffer());
 
         Ok(markdown)
     }
 
-    pub fn for_resistance_strategy(&mut self, consolidating: bool) -> Result<(), MyError> {
+    /// The report of today, for `output_sink::dispatch_all`
+    pub fn for_resistance_strategy(&mut self, consolidating: bool) -> Result<Report, MyError> {
         let today = chrono::Local::now().format("%Y-%m-%d").to_string();
-        self.filter_by_standardized_diff(0.12);
+        let excluded = blacklist::exclude(&mut self.data, &blacklist::load(), |x| {
+            (&x.code, x.analyzed_at.as_str())
+        });
+        let (tender_offers, flagged) =
+            corporate_events::exclude(&mut self.data, &corporate_events::load(), |x| {
+                (&x.code, x.analyzed_at.as_str())
+            });
+        self.filter_by_consolidation(GdriveJson::new()?.consolidation_filter());
         if consolidating {
             self.filter_by_latest_move(0.25);
         }
 
-        let markdown = self.output_for_markdown_afternoon(&today)?;
-        let path = match consolidating {
-            true => {
-                crate::my_file_io::get_jquants_path(JquantsStyle::ConsolidatingAfternoon, &today)?
-            }
-            false => crate::my_file_io::get_jquants_path(JquantsStyle::Afternoon, &today)?,
+        let lang = Lang::from_config();
+        let mut summary = vec![format!(
+            "{}: {}",
+            Msg::NumberOfStocks.text(lang),
+            self.data.len()
+        )];
+        if let Some(regime) = self.data.iter().find_map(|x| x.market_regime) {
+            summary.push(format!("{}: {}", Msg::MarketRegime.text(lang), regime));
+        }
+        let excluded = excluded
+            .iter()
+            .map(|x| x.to_string())
+            .chain(tender_offers.iter().map(|x| x.to_string()))
+            .collect::<Vec<_>>();
+        if !excluded.is_empty() {
+            summary.push(format!(
+                "{}: {}",
+                Msg::Excluded.text(lang),
+                excluded.join(", ")
+            ));
+        }
+        if !flagged.is_empty() {
+            let flagged = flagged.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            summary.push(format!(
+                "{}: {}",
+                Msg::Flagged.text(lang),
+                flagged.join(", ")
+            ));
+        }
+        let markdown = self.output_for_markdown_afternoon(&today, &summary, lang)?;
+        let kind = match consolidating {
+            true => CONSOLIDATING_AFTERNOON,
+            false => AFTERNOON,
         };
-        markdown.write_to_html(&path)?;
-
-        Ok(())
+        Ok(Report::new(
+            kind,
+            &today,
+            &format!("{} {}", today, Msg::AfternoonStrategy.text(lang)),
+            summary,
+            markdown,
+        )
+        .with_signals(self.signals()?))
     }
-    pub fn for_resistance_strategy_default(&mut self) -> Result<(), MyError> {
+    pub fn for_resistance_strategy_default(&mut self) -> Result<Report, MyError> {
         self.for_resistance_strategy(false)
     }
 }
@@ -1,9 +1,21 @@
+use crate::database::market_regime;
+use crate::database::{blacklist, corporate_events};
+use crate::markdown::Markdown;
 use crate::my_file_io::Nikkei225;
-use crate::my_file_io::{get_fetched_ohlc_file_path, load_nikkei225_list, AssetType};
-use crate::{analysis::live::OhlcPremium, my_error::MyError};
+use crate::my_file_io::{get_fetched_ohlc_file_path, AssetType, Universe};
+use crate::rounding::round_dp;
+use crate::signal::{SignalId, DAYTRADING_SOURCE};
+use crate::stock_code::StockCode;
+use crate::units::{AtrUnits, Yen};
+use crate::{
+    analysis::capacity,
+    analysis::indicators::{self, AnalysisParams},
+    analysis::live::{BullBear, OhlcPremium},
+    my_error::MyError,
+};
 use anyhow::anyhow;
 use chrono::{Duration, NaiveDate};
-use log::{error, info};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use statrs::distribution::ContinuousCDF;
 use statrs::distribution::StudentsT;
@@ -15,55 +27,51 @@ use super::backtesting_topix::TopixDailyWindowList;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct StocksDaytrading {
-    code: String,
+    code: StockCode,
     name: String,
     status: Status,
     atr: f64,
     unit: i32,
-    required_amount: i32,
+    required_amount: Yen,
     standardized_diff: f64,
-    result_push_close: Option<f64>,
-    result_morning_close: Option<f64>,
-    result_afternoon_open: Option<f64>,
-    result_close: Option<f64>,
+    result_push_close: Option<AtrUnits>,
+    result_morning_close: Option<AtrUnits>,
+    result_afternoon_open: Option<AtrUnits>,
+    result_close: Option<AtrUnits>,
     analyzed_at: String,
+    /// Regime of TOPIX on the analysis date from the market_regime table, None before
+    /// it has the date
+    #[serde(default)]
+    market_regime: Option<BullBear>,
 }
 impl StocksDaytrading {
     pub fn from_vec(
-        ohlc_vec: &Vec<OhlcPremium>,
-        code: &str,
+        ohlc_vec: &[OhlcPremium],
+        code: &StockCode,
         name: &str,
         unit: f64,
         date: &str,
+        params: &AnalysisParams,
     ) -> Result<Self, MyError> {
         let position = match ohlc_vec.iter().position(|ohlc| ohlc.get_date() == date) {
             Some(res) => res,
             None => return Err(MyError::OutOfRange),
         };
 
-        if position < 59 {
+        if position + 1 < params.get_range_period() {
             return Err(MyError::OutOfRange);
         }
 
-        let ohlc_5 = &ohlc_vec[(position - 4)..=position];
-        let ohlc_20 = &ohlc_vec[(position - 19)..=position];
-        let ohlc_60 = &ohlc_vec[(position - 59)..=position];
-
-        let (prev_19, last) = ohlc_20.split_at(19);
-        let (last_high, last_low, last_close) =
-            { (last[0].get_high(), last[0].get_low(), last[0].get_close()) };
-        let prev_19_high = prev_19
-            .iter()
-            .map(|ohlc| ohlc.get_high())
-            .fold(f64::NAN, f64::max);
-        let prev_19_low = prev_19
-            .iter()
-            .map(|ohlc| ohlc.get_low())
-            .fold(f64::NAN, f64::min);
-
-        let status = match (last_close > prev_19_high) || (last_close < prev_19_low) {
+        let ohlc_range = params.range_bars(ohlc_vec, position);
+
+        let (prev, last) = params.breakout_bars(ohlc_vec, position);
+        let (last_high, last_low, last_close) = (last.get_high(), last.get_low(), last.get_close());
+        let prev_high = indicators::highest_high(prev);
+        let prev_low = indicators::lowest_low(prev);
+
+        let status = match (last_close > prev_high) || (last_close < prev_low) {
             true => {
-                if last_close > prev_19_high {
+                if last_close > prev_high {
                     Status::BreakoutResistance
                 } else {
                     Status::BreakoutSupport
@@ -71,9 +79,9 @@ impl StocksDaytrading {
             }
 
             false => {
-                if last_high > prev_19_high {
+                if last_high > prev_high {
                     Status::FailedBreakoutResistance
-                } else if last_low < prev_19_low {
+                } else if last_low < prev_low {
                     Status::FailedBreakoutSupport
                 } else {
                     Status::NoChange
@@ -81,76 +89,28 @@ impl StocksDaytrading {
             }
         };
 
-        let atr = ohlc_5
-            .iter()
-            .map(|ohlc| (ohlc.get_high() - ohlc.get_low()))
-            .sum::<f64>()
-            / ohlc_5.len() as f64;
-        let atr = (atr * 10.0).round() / 10.0;
-
-        let (unit, required_amount) = {
-            let unit = unit / atr;
-            let required_amount = (unit * last_close) as i32;
-            (unit as i32, required_amount)
-        };
-
-        let highest_high = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_high())
-            .fold(f64::NAN, f64::max);
-        let lowest_low = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_low())
-            .fold(f64::NAN, f64::min);
-
-        let diff_sum: f64 = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_high() - ohlc.get_low())
-            .sum();
-        let average_diff = diff_sum / ohlc_60.len() as f64;
-
-        let standardized_diff =
-            (average_diff / (highest_high - lowest_low) * 1000.0).trunc() / 1000.0;
-
-        let result_push_close = match ohlc_vec.len() > position + 1 {
-            true => {
-                let mean_price = (ohlc_vec[position + 1].get_morning_close()
-                    + ohlc_vec[position + 1].get_open())
-                    / 2.0;
-                let result_push_price = (mean_price - ohlc_vec[position + 1].get_open()) / atr;
-                Some((result_push_price * 100.0).round() / 100.0)
-            }
-            false => None,
-        };
-        let result_morning_close = match ohlc_vec.len() > position + 1 {
-            true => {
-                let result_morning_close = (ohlc_vec[position + 1].get_morning_close()
-                    - ohlc_vec[position + 1].get_open())
-                    / atr;
-                Some((result_morning_close * 100.0).round() / 100.0)
-            }
-            false => None,
-        };
-        let result_afternoon_open = match ohlc_vec.len() > position + 1 {
-            true => {
-                let result_afternoon_open = (ohlc_vec[position + 1].get_afternoon_open()
-                    - ohlc_vec[position + 1].get_open())
-                    / atr;
-                Some((result_afternoon_open * 100.0).round() / 100.0)
-            }
-            false => None,
-        };
-        let result_close = match ohlc_vec.len() > position + 1 {
-            true => {
-                let result_close =
-                    (ohlc_vec[position + 1].get_close() - ohlc_vec[position + 1].get_open()) / atr;
-                Some((result_close * 100.0).round() / 100.0)
-            }
-            false => None,
-        };
+        let atr = params.atr().of(&ohlc_vec[..=position]);
+        let (unit, notional) = indicators::unit_and_required_amount(unit, atr, last_close);
+        let required_amount =
+            capacity::required_amount(notional, status == Status::BreakoutSupport);
+        let standardized_diff = indicators::standardized_diff(ohlc_range)?;
+
+        let nextday = ohlc_vec.get(position + 1);
+        let result_push_close = nextday.map(|nextday| {
+            let mean_price = (nextday.get_morning_close() + nextday.get_open()) / 2.0;
+            indicators::result_in_atr(nextday.get_open(), mean_price, atr)
+        });
+        let result_morning_close = nextday.map(|nextday| {
+            indicators::result_in_atr(nextday.get_open(), nextday.get_morning_close(), atr)
+        });
+        let result_afternoon_open = nextday.map(|nextday| {
+            indicators::result_in_atr(nextday.get_open(), nextday.get_afternoon_open(), atr)
+        });
+        let result_close = nextday
+            .map(|nextday| indicators::result_in_atr(nextday.get_open(), nextday.get_close(), atr));
 
         Ok(Self {
-            code: code.to_owned(),
+            code: code.clone(),
             name: name.to_owned(),
             status,
             atr,
@@ -162,9 +122,15 @@ impl StocksDaytrading {
             result_afternoon_open,
             result_close,
             analyzed_at: date.to_owned(),
+            market_regime: None,
         })
     }
 
+    /// The same for every run of the analysis of the code on `analyzed_at`
+    pub fn signal_id(&self) -> Result<SignalId, MyError> {
+        SignalId::new(DAYTRADING_SOURCE, &self.code, &self.analyzed_at)
+    }
+
     fn markdown_body_output(&self) -> String {
         let mut buffer = String::new();
         let name = match self.name.chars().count() > 5 {
@@ -177,18 +143,25 @@ impl StocksDaytrading {
 
         writeln!(
             buffer,
-            "{} {}, ({}, {}, {}), {}円",
-            self.code, name, self.atr, self.unit, self.standardized_diff, self.required_amount
+            "{} {}, ({}, {}, {}), {} {} {}",
+            self.code,
+            name,
+            self.atr,
+            self.unit,
+            self.standardized_diff,
+            self.required_amount,
+            self.market_regime.map_or("-".to_owned(), |x| x.to_string()),
+            self.signal_id().map_or("-".to_owned(), |x| x.to_string())
         )
         .unwrap();
 
-        if self.result_close.is_some() {
+        if let Some(result_close) = self.result_close {
             writeln!(
                 buffer,
                 "MC: {}, AO: {}, AC: {}",
                 self.result_morning_close.unwrap(),
                 self.result_afternoon_open.unwrap(),
-                self.result_close.unwrap()
+                result_close
             )
             .unwrap();
         }
@@ -197,16 +170,29 @@ impl StocksDaytrading {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct StocksDaytradingList {
     data: Vec<StocksDaytrading>,
+    /// Blacklist entries and tender offers that dropped a row
+    excluded: Vec<String>,
+    /// Offerings of rows kept
+    flagged: Vec<String>,
 }
 impl StocksDaytradingList {
     pub fn new() -> Self {
-        Self { data: Vec::new() }
+        Self {
+            data: Vec::new(),
+            excluded: Vec::new(),
+            flagged: Vec::new(),
+        }
+    }
+
+    //getters
+    pub fn get_excluded(&self) -> &[String] {
+        &self.excluded
     }
-    fn from_vec(vec: Vec<StocksDaytrading>) -> Self {
-        Self { data: vec }
+    pub fn get_flagged(&self) -> &[String] {
+        &self.flagged
     }
 
     // pub fn push(&mut self, stocks_daytrading: StocksDaytrading) {
@@ -216,7 +202,7 @@ impl StocksDaytradingList {
     pub fn push_2(
         &mut self,
         ohlc_vec: Vec<OhlcPremium>,
-        code: &str,
+        code: &StockCode,
         name: &str,
         unit: f64,
         from: &str,
@@ -224,6 +210,7 @@ impl StocksDaytradingList {
     ) {
         let from = NaiveDate::parse_from_str(from, "%Y-%m-%d").unwrap();
         let to = NaiveDate::parse_from_str(to, "%Y-%m-%d").unwrap();
+        let params = AnalysisParams::from_config();
         let mut date = from;
         while date <= to {
             match StocksDaytrading::from_vec(
@@ -232,6 +219,7 @@ impl StocksDaytradingList {
                 name,
                 unit,
                 &date.format("%Y-%m-%d").to_string(),
+                &params,
             ) {
                 Ok(stocks_daytrading) => {
                     if stocks_daytrading.status != Status::NoChange {
@@ -240,6 +228,7 @@ impl StocksDaytradingList {
                 }
                 Err(e) => match e {
                     MyError::OutOfRange => {}
+                    MyError::ZeroRange(_) => warn!("{} {}: skipped, {}", code, date, e),
                     _ => {
                         error!("{}", e);
                         return;
@@ -254,6 +243,18 @@ impl StocksDaytradingList {
         self.data.append(&mut stocks_daytrading_list.data);
     }
 
+    /// Tags the rows with the regime of their analysis date, see `database::market_regime`
+    fn set_market_regimes(&mut self, from: &str, to: &str) -> Result<(), MyError> {
+        let regimes = market_regime::select_by_date_range(&market_regime::open_db()?, from, to)?;
+        if regimes.is_empty() {
+            warn!("no market_regime until {}, the rows are untagged", to);
+        }
+        for stocks_daytrading in self.data.iter_mut() {
+            stocks_daytrading.market_regime = regimes.get(&stocks_daytrading.analyzed_at).copied();
+        }
+        Ok(())
+    }
+
     // pub fn sort_by_standardized_diff(&mut self) {
     //     self.data.sort_by(|a, b| {
     //         a.standardized_diff
@@ -262,53 +263,50 @@ impl StocksDaytradingList {
     //     });
     // }
 
-    // pub fn output_for_markdown(&self, date: &str) -> Result<Markdown, MyError> {
-    //     let mut markdown = Markdown::new();
-    //     markdown.h1(date)?;
-
-    //     let mut markdown_br = Markdown::new();
-    //     let mut markdown_fbr = Markdown::new();
-    //     let mut markdown_fbs = Markdown::new();
-    //     let mut markdown_bs = Markdown::new();
-
-    //     markdown_br.h2("Breakout Resistance")?;
-    //     markdown_fbr.h2("Failed Breakout Resistance")?;
-    //     markdown_fbs.h2("Failed Breakout Support")?;
-    //     markdown_bs.h2("Breakout Support")?;
-
-    //     for stocks_daytrading in &self.data {
-    //         match stocks_daytrading.status {
-    //             Status::BreakoutResistance => {
-    //                 markdown_br.body(&stocks_daytrading.markdown_body_output())?;
-    //             }
-    //             Status::FailedBreakoutResistance => {
-    //                 markdown_fbr.body(&stocks_daytrading.markdown_body_output())?;
-    //             }
-    //             Status::FailedBreakoutSupport => {
-    //                 markdown_fbs.body(&stocks_daytrading.markdown_body_output())?;
-    //             }
-    //             Status::BreakoutSupport => {
-    //                 markdown_bs.body(&stocks_daytrading.markdown_body_output())?;
-    //             }
-    //             _ => {}
-    //         }
-    //     }
+    pub fn output_for_markdown(&self, date: &str) -> Result<Markdown, MyError> {
+        let mut markdown = Markdown::new();
+        markdown.section(date, |m| {
+            if !self.excluded.is_empty() {
+                m.body(&format!("Excluded: {}", self.excluded.join(", ")))?;
+            }
+            if !self.flagged.is_empty() {
+                m.body(&format!("Flagged: {}", self.flagged.join(", ")))?;
+            }
+            for (status, title) in [
+                (Status::BreakoutResistance, "Breakout Resistance"),
+                (
+                    Status::FailedBreakoutResistance,
+                    "Failed Breakout Resistance",
+                ),
+                (Status::FailedBreakoutSupport, "Failed Breakout Support"),
+                (Status::BreakoutSupport, "Breakout Support"),
+            ] {
+                m.section(title, |m| {
+                    for stocks_daytrading in self.data.iter().filter(|x| x.status == status) {
+                        m.body(&stocks_daytrading.markdown_body_output())?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        })?;
 
-    //     markdown.append(markdown_br);
-    //     markdown.append(markdown_fbr);
-    //     markdown.append(markdown_fbs);
-    //     markdown.append(markdown_bs);
+        info!("{}", markdown.buffer());
 
-    //     info!("{}", markdown.buffer());
+        Ok(markdown)
+    }
 
-    //     Ok(markdown)
-    // }
+    fn t_test(data: &[&StocksDaytrading]) -> String {
+        // StudentsT needs df > 0
+        if data.len() < 2 {
+            return "morning_close: -\nclose: -\n".to_owned();
+        }
 
-    fn t_test(&self) -> String {
         let morning_close = TTestResult::new(
-            self.data
-                .iter()
-                .map(|stocks_daytrading| stocks_daytrading.result_morning_close.unwrap_or(0.0))
+            data.iter()
+                .map(|stocks_daytrading| {
+                    stocks_daytrading.result_morning_close.map_or(0.0, |r| r.0)
+                })
                 .collect::<Vec<_>>(),
         );
 
@@ -320,9 +318,8 @@ impl StocksDaytradingList {
         // );
 
         let close = TTestResult::new(
-            self.data
-                .iter()
-                .map(|stocks_daytrading| stocks_daytrading.result_close.unwrap_or(0.0))
+            data.iter()
+                .map(|stocks_daytrading| stocks_daytrading.result_close.map_or(0.0, |r| r.0))
                 .collect::<Vec<_>>(),
         );
 
@@ -644,117 +641,47 @@ impl StocksDaytradingList {
         writeln!(buffer).unwrap();
         writeln!(buffer, "<{:?}>", status).unwrap();
 
+        let windows = [
+            (
+                "Strong Positive",
+                topix_daily_window_list.get_strong_positive(),
+            ),
+            ("Mild Positive", topix_daily_window_list.get_mild_positive()),
+            ("Mild Negative", topix_daily_window_list.get_mild_negative()),
+            (
+                "Strong Negative",
+                topix_daily_window_list.get_strong_negative(),
+            ),
+        ];
         let limit = [(0.0, 0.09), (0.09, 0.12), (0.12, 0.40)];
 
-        writeln!(buffer, "Strong Positive").unwrap();
-        for (lower_limit, upper_limit) in limit.iter() {
-            let data = self.data.clone();
-            let strong_positive = data
-                .into_iter()
-                .filter(|stocks_daytrading| {
-                    stocks_daytrading.status == status
-                        && topix_daily_window_list
-                            .get_strong_positive()
-                            .contains(&stocks_daytrading.analyzed_at)
-                        && (*lower_limit..*upper_limit)
-                            .contains(&stocks_daytrading.standardized_diff)
-                })
-                .collect::<Vec<_>>();
-            let strong_positive_list = StocksDaytradingList::from_vec(strong_positive);
-            writeln!(
-                buffer,
-                "{}-{}: N={}",
-                lower_limit,
-                upper_limit,
-                strong_positive_list.data.len(),
-                // strong_positive_list.t_test()
-            )
-            .unwrap();
-            writeln!(buffer, "{}", strong_positive_list.t_test()).unwrap();
-        }
-
-        writeln!(buffer).unwrap();
-        writeln!(buffer, "Mild Positive").unwrap();
-        for (lower_limit, upper_limit) in limit.iter() {
-            let data = self.data.clone();
-            let mild_positive = data
-                .into_iter()
-                .filter(|stocks_daytrading| {
-                    stocks_daytrading.status == status
-                        && topix_daily_window_list
-                            .get_mild_positive()
-                            .contains(&stocks_daytrading.analyzed_at)
-                        && (*lower_limit..*upper_limit)
-                            .contains(&stocks_daytrading.standardized_diff)
-                })
-                .collect::<Vec<_>>();
-            let mild_positive_list = StocksDaytradingList::from_vec(mild_positive);
-            writeln!(
-                buffer,
-                "{}-{}: N={}",
-                lower_limit,
-                upper_limit,
-                mild_positive_list.data.len(),
-                // mild_positive_list.t_test()
-            )
-            .unwrap();
-            writeln!(buffer, "{}", mild_positive_list.t_test()).unwrap();
-        }
-
-        writeln!(buffer).unwrap();
-        writeln!(buffer, "Mild Negative").unwrap();
-        for (lower_limit, upper_limit) in limit.iter() {
-            let data = self.data.clone();
-            let mild_negative = data
-                .into_iter()
-                .filter(|stocks_daytrading| {
-                    stocks_daytrading.status == status
-                        && topix_daily_window_list
-                            .get_mild_negative()
-                            .contains(&stocks_daytrading.analyzed_at)
-                        && (*lower_limit..*upper_limit)
-                            .contains(&stocks_daytrading.standardized_diff)
-                })
-                .collect::<Vec<_>>();
-            let mild_negative_list = StocksDaytradingList::from_vec(mild_negative);
-            writeln!(
-                buffer,
-                "{}-{}: N={}",
-                lower_limit,
-                upper_limit,
-                mild_negative_list.data.len(),
-                // mild_negative_list.t_test()
-            )
-            .unwrap();
-            writeln!(buffer, "{}", mild_negative_list.t_test()).unwrap();
-        }
-
-        writeln!(buffer).unwrap();
-        writeln!(buffer, "Strong Negative").unwrap();
-        for (lower_limit, upper_limit) in limit.iter() {
-            let data = self.data.clone();
-            let strong_negative = data
-                .into_iter()
-                .filter(|stocks_daytrading| {
-                    stocks_daytrading.status == status
-                        && topix_daily_window_list
-                            .get_strong_negative()
-                            .contains(&stocks_daytrading.analyzed_at)
-                        && (*lower_limit..*upper_limit)
-                            .contains(&stocks_daytrading.standardized_diff)
-                })
-                .collect::<Vec<_>>();
-            let strong_negative_list = StocksDaytradingList::from_vec(strong_negative);
-            writeln!(
-                buffer,
-                "{}-{}: N={}",
-                lower_limit,
-                upper_limit,
-                strong_negative_list.data.len(),
-                // strong_negative_list.t_test()
-            )
-            .unwrap();
-            writeln!(buffer, "{}", strong_negative_list.t_test()).unwrap();
+        for (i, (window, dates)) in windows.iter().enumerate() {
+            if i > 0 {
+                writeln!(buffer).unwrap();
+            }
+            writeln!(buffer, "{}", window).unwrap();
+            for (lower_limit, upper_limit) in limit.iter() {
+                // 参照のままフィルタして、バケットごとのcloneを避ける
+                let filtered = self
+                    .data
+                    .iter()
+                    .filter(|stocks_daytrading| {
+                        stocks_daytrading.status == status
+                            && dates.contains(&stocks_daytrading.analyzed_at)
+                            && (*lower_limit..*upper_limit)
+                                .contains(&stocks_daytrading.standardized_diff)
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(
+                    buffer,
+                    "{}-{}: N={}",
+                    lower_limit,
+                    upper_limit,
+                    filtered.len(),
+                )
+                .unwrap();
+                writeln!(buffer, "{}", StocksDaytradingList::t_test(&filtered)).unwrap();
+            }
         }
 
         buffer
@@ -944,12 +871,12 @@ impl StocksDaytradingList {
     // t_test
 }
 
-struct TTestResult {
+pub struct TTestResult {
     mean: f64,
     p_value: f64,
 }
 impl TTestResult {
-    fn new(data: Vec<f64>) -> Self {
+    pub fn new(data: Vec<f64>) -> Self {
         let mean = data.clone().mean();
         let variance = data.clone().variance();
         let len = data.len() as f64;
@@ -958,14 +885,8 @@ impl TTestResult {
         let t_distribution = StudentsT::new(0.0, 1.0, df).unwrap();
 
         let (mean, p_value) = match mean >= 0.0 {
-            true => (
-                (mean * 1000.0).round() / 1000.0,
-                1.0 - (t_distribution.cdf(t) * 1000.0).round() / 1000.0,
-            ),
-            false => (
-                (mean * 1000.0).round() / 1000.0,
-                (t_distribution.cdf(t) * 1000.0).round() / 1000.0,
-            ),
+            true => (round_dp(mean, 3), 1.0 - round_dp(t_distribution.cdf(t), 3)),
+            false => (round_dp(mean, 3), round_dp(t_distribution.cdf(t), 3)),
         };
 
         Self { mean, p_value }
@@ -980,7 +901,7 @@ impl TTestResult {
 }
 impl std::fmt::Display for TTestResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let p_value = (self.get_p_value() * 100.0).round() / 100.0;
+        let p_value = round_dp(self.get_p_value(), 2);
         match p_value < 0.05 {
             true => write!(
                 f,
@@ -1027,7 +948,11 @@ pub enum Status {
     BreakoutSupport,
 }
 
-pub async fn async_exec(from: &str, to: &str) -> Result<StocksDaytradingList, MyError> {
+pub async fn async_exec(
+    universe: &Universe,
+    from: &str,
+    to: &str,
+) -> Result<StocksDaytradingList, MyError> {
     async fn inner(
         row: Nikkei225,
         unit: f64,
@@ -1037,7 +962,7 @@ pub async fn async_exec(from: &str, to: &str) -> Result<StocksDaytradingList, My
         let code = row.get_code();
         let name = row.get_name();
         let ohlc_vec_path = match get_fetched_ohlc_file_path(AssetType::Stocks {
-            code: Some(code.to_owned()),
+            code: Some(code.clone()),
         }) {
             Ok(res) => res,
             Err(e) => {
@@ -1061,14 +986,14 @@ pub async fn async_exec(from: &str, to: &str) -> Result<StocksDaytradingList, My
         Ok(stocks_daytrading_list)
     }
 
-    let nikkei225 = match load_nikkei225_list() {
+    let nikkei225 = match universe.load() {
         Ok(res) => res,
         Err(e) => {
             error!("{}", e);
             return Err(e);
         }
     };
-    info!("Nikkei225 has been loaded");
+    info!("{} has been loaded", universe);
 
     let config = crate::config::GdriveJson::new()?;
     let unit = config.jquants_unit();
@@ -1103,5 +1028,96 @@ pub async fn async_exec(from: &str, to: &str) -> Result<StocksDaytradingList, My
     let end_time = Instant::now();
 
     info!("Elapsed time: {:?}", end_time - start_time);
+    fn key(x: &StocksDaytrading) -> (&StockCode, &str) {
+        (&x.code, &x.analyzed_at)
+    }
+    let excluded = blacklist::exclude(&mut stocks_daytrading_list.data, &blacklist::load(), key);
+    let (tender_offers, flagged) = corporate_events::exclude(
+        &mut stocks_daytrading_list.data,
+        &corporate_events::load(),
+        key,
+    );
+    stocks_daytrading_list.excluded = excluded
+        .iter()
+        .map(|x| x.to_string())
+        .chain(tender_offers.iter().map(|x| x.to_string()))
+        .collect();
+    stocks_daytrading_list.flagged = flagged.iter().map(|x| x.to_string()).collect();
+    stocks_daytrading_list.set_market_regimes(from, to)?;
     Ok(stocks_daytrading_list)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::horizons::{ALLDAY, MORNING};
+    use crate::analysis::stocks_window::StocksWindow;
+
+    fn ohlc_vec(len: usize) -> Vec<OhlcPremium> {
+        (0..len)
+            .map(|i| {
+                let base = 1000.0 + (i as f64 * 0.7).sin() * 50.0 + i as f64;
+                let open = base;
+                let close = base + (i as f64 * 1.3).cos() * 15.0;
+                let high = open.max(close) + 5.0 + (i % 4) as f64;
+                let low = open.min(close) - 5.0 - (i % 3) as f64;
+                OhlcPremium::new(
+                    StockCode::new("7203").unwrap(),
+                    (NaiveDate::from_ymd_opt(2023, 1, 1).unwrap() + Duration::days(i as i64))
+                        .format("%Y-%m-%d")
+                        .to_string(),
+                    open,
+                    high,
+                    low,
+                    close,
+                    (open + close) / 2.0,
+                    (open + close) / 2.0 + 1.0,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_consistency_with_stocks_window() {
+        let ohlc_vec = ohlc_vec(80);
+        let pairs = [
+            ("atr", "atr"),
+            ("unit", "unit"),
+            ("required_amount", "required_amount"),
+            ("standardized_diff", "standardized_diff"),
+            ("result_morning", "result_morning_close"),
+            ("result_allday", "result_close"),
+        ];
+
+        let code = StockCode::new("7203").unwrap();
+        let params = AnalysisParams::default();
+        let mut disagreements = Vec::new();
+        for ohlc in &ohlc_vec[59..] {
+            let date = ohlc.get_date();
+            let stocks_window =
+                StocksWindow::from_vec(&ohlc_vec, &code, "トヨタ", 10000.0, date, &params).unwrap();
+            let mut window = serde_json::to_value(&stocks_window).unwrap();
+            // results are kept per horizon
+            for name in [MORNING, ALLDAY] {
+                window[format!("result_{}", name)] =
+                    serde_json::to_value(stocks_window.result(name)).unwrap();
+            }
+            let daytrading = serde_json::to_value(
+                StocksDaytrading::from_vec(&ohlc_vec, &code, "トヨタ", 10000.0, date, &params)
+                    .unwrap(),
+            )
+            .unwrap();
+
+            for (window_key, daytrading_key) in pairs {
+                if window[window_key] != daytrading[daytrading_key] {
+                    disagreements.push(format!(
+                        "{} {}: {} != {}",
+                        date, window_key, window[window_key], daytrading[daytrading_key]
+                    ));
+                }
+            }
+        }
+
+        assert!(disagreements.is_empty(), "{:#?}", disagreements);
+    }
+}
@@ -0,0 +1,207 @@
+use log::info;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::database::stocks_ohlc;
+use crate::i18n::Lang;
+use crate::jquants::fetcher::{PricesAm, PricesAmInner};
+use crate::my_error::MyError;
+use crate::my_file_io::Universe;
+use crate::rounding::round_dp;
+use crate::stock_code::StockCode;
+use crate::units::AtrUnits;
+
+use super::indicators;
+use super::live::OhlcPremium;
+
+const ATR_DAYS: usize = 5;
+
+/// `morning_volume` over the average volume of `ohlc_vec`, None when a volume is missing
+fn rvol(ohlc_vec: &[OhlcPremium], morning_volume: Option<f64>) -> Option<f64> {
+    let volumes = ohlc_vec
+        .iter()
+        .map(|x| x.get_volume())
+        .collect::<Option<Vec<_>>>()?;
+    let average = volumes.iter().sum::<f64>() / volumes.len() as f64;
+    match average > 0.0 {
+        true => Some(morning_volume? / average),
+        false => None,
+    }
+}
+
+/// Morning snapshot ranked without the 60-day window analysis
+#[derive(Debug, Clone)]
+pub struct StocksQuick {
+    code: StockCode,
+    name: String,
+    atr: f64,
+    gap: AtrUnits,
+    morning_move: AtrUnits,
+    morning_close: f64,
+    turnover_value: Option<f64>,
+    /// Morning volume over the average daily volume of the latest bars, None without volumes
+    rvol: Option<f64>,
+}
+
+impl StocksQuick {
+    /// `ohlc_vec`: latest daily bars before today, oldest first
+    pub fn from_vec(
+        ohlc_vec: &[OhlcPremium],
+        prices_am: &PricesAmInner,
+        code: &StockCode,
+        name: &str,
+    ) -> Result<Self, MyError> {
+        if ohlc_vec.len() < ATR_DAYS || !prices_am.has_ohlc() {
+            return Err(MyError::OutOfRange);
+        }
+
+        let atr = indicators::atr(ohlc_vec);
+        let yesterday_close = ohlc_vec[ohlc_vec.len() - 1].get_close();
+
+        Ok(Self {
+            code: code.clone(),
+            name: name.to_owned(),
+            atr,
+            gap: indicators::result_in_atr(yesterday_close, prices_am.get_open(), atr),
+            morning_move: indicators::result_in_atr(
+                prices_am.get_open(),
+                prices_am.get_close(),
+                atr,
+            ),
+            morning_close: prices_am.get_close(),
+            turnover_value: prices_am.get_turnover_value(),
+            rvol: rvol(ohlc_vec, prices_am.get_volume()),
+        })
+    }
+
+    /// |gap| + |morning move|, both in ATR, times RVOL when known
+    fn score(&self) -> f64 {
+        (self.gap.0.abs() + self.morning_move.0.abs()) * self.rvol.unwrap_or(1.0)
+    }
+}
+
+pub struct StocksQuickList {
+    data: Vec<StocksQuick>,
+}
+
+impl StocksQuickList {
+    /// Reads only the latest few bars per code with one query per date
+    pub fn from_nikkei225(prices_am: &PricesAm, universe: &Universe) -> Result<Self, MyError> {
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let nikkei225 = universe.load()?;
+
+        let conn = stocks_ohlc::open_db()?;
+        let mut code_to_ohlc: HashMap<StockCode, Vec<OhlcPremium>> = HashMap::new();
+        for date in stocks_ohlc::select_latest_dates(&conn, &today, ATR_DAYS)? {
+            for ohlc in stocks_ohlc::select_by_date(&conn, &date)? {
+                let ohlc = ohlc.get_inner();
+                code_to_ohlc
+                    .entry(ohlc.get_code().clone())
+                    .or_default()
+                    .push(ohlc);
+            }
+        }
+
+        let mut data = Vec::new();
+        for row in nikkei225 {
+            let (code, name) = (row.get_code(), row.get_name());
+            let (Some(ohlc_vec), Ok(stock_am)) =
+                (code_to_ohlc.get_mut(code), prices_am.get_stock_am(code))
+            else {
+                continue;
+            };
+            ohlc_vec.sort_by(|a, b| a.get_date().cmp(b.get_date()));
+            match StocksQuick::from_vec(ohlc_vec, &stock_am, code, name) {
+                Ok(stocks_quick) => data.push(stocks_quick),
+                Err(MyError::OutOfRange) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        info!("quick scan: {} stocks", data.len());
+
+        Ok(Self { data })
+    }
+
+    pub fn top(mut self, n: usize) -> Self {
+        self.data.sort_by(|a, b| b.score().total_cmp(&a.score()));
+        self.data.truncate(n);
+        self
+    }
+
+    pub fn output(&self, lang: Lang) -> Result<String, MyError> {
+        let mut buffer = String::new();
+        for stocks_quick in &self.data {
+            writeln!(
+                buffer,
+                "{} {} {} ATR: {} Gap: {} Move: {} Score: {}{}{}",
+                stocks_quick.code,
+                stocks_quick.name,
+                lang.yen(stocks_quick.morning_close),
+                stocks_quick.atr,
+                stocks_quick.gap,
+                stocks_quick.morning_move,
+                round_dp(stocks_quick.score(), 2),
+                match stocks_quick.rvol {
+                    Some(x) => format!(" RVOL: {}", round_dp(x, 2)),
+                    None => String::new(),
+                },
+                match stocks_quick.turnover_value {
+                    Some(x) => format!(" TV: {}M", round_dp(x / 1_000_000.0, 0)),
+                    None => String::new(),
+                }
+            )?;
+        }
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top() {
+        let stocks_quick =
+            |code: &str, gap: f64, morning_move: f64, rvol: Option<f64>| StocksQuick {
+                code: StockCode::new(code).unwrap(),
+                name: code.to_owned(),
+                atr: 10.0,
+                gap: AtrUnits(gap),
+                morning_move: AtrUnits(morning_move),
+                morning_close: 1000.0,
+                turnover_value: None,
+                rvol,
+            };
+        let list = StocksQuickList {
+            data: vec![
+                stocks_quick("1111", 0.1, 0.1, None),
+                stocks_quick("2222", -1.5, 0.2, None),
+                stocks_quick("3333", 0.5, 0.5, None),
+                // first on the moves alone, but traded little
+                stocks_quick("4444", 1.0, 1.0, Some(0.2)),
+                // traded three times as much as usual
+                stocks_quick("5555", 0.5, 0.5, Some(3.0)),
+            ],
+        }
+        .top(3);
+
+        let codes = list
+            .data
+            .iter()
+            .map(|x| x.code.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(codes, vec!["5555", "2222", "3333"]);
+    }
+
+    #[test]
+    fn test_rvol() {
+        let bar = |volume: Option<f64>| {
+            OhlcPremium::test_bar("2024-01-04", 100.0, 110.0, 90.0, 105.0)
+                .with_liquidity(volume, None)
+        };
+        let ohlc_vec = vec![bar(Some(1000.0)), bar(Some(3000.0))];
+        assert_eq!(rvol(&ohlc_vec, Some(1500.0)), Some(0.75));
+        assert_eq!(rvol(&ohlc_vec, None), None);
+        assert_eq!(rvol(&[bar(Some(1000.0)), bar(None)], Some(1500.0)), None);
+    }
+}
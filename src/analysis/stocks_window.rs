@@ -1,27 +1,73 @@
 use anyhow::anyhow;
 use chrono::{Duration, NaiveDate};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::{fmt::Write, time::Instant};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
 
 use crate::{
-    markdown::Markdown,
-    my_error::MyError,
-    my_file_io::{
-        get_fetched_ohlc_file_path, load_nikkei225_list, AssetType, JquantsStyle, Nikkei225,
+    config::GdriveJson,
+    database::{
+        blacklist::{self, BlacklistEntry},
+        corporate_events::{self, CorporateEvent},
+        statements::{self, ResultsCalendar},
+        stocks_master::{self, Sectors},
+        trades_spec,
     },
+    i18n::{status_text, Lang, Msg},
+    markdown::{short_name, unit_text, Column, Markdown},
+    my_error::MyError,
+    my_file_io::{Nikkei225, Universe},
+    output_sink::Report,
+    profile::{self, Stage},
+    report_kind::ReportKind,
+    rounding::round_dp,
+    signal::{Signal, SignalCandidate, SignalId, WINDOW_SOURCE},
+    stock_code::StockCode,
+    units::{AtrUnits, Pct, Yen},
 };
 
-use super::live::OhlcPremium;
+use super::{
+    capacity::{self, Candidate, Plan},
+    code_history::{code_link, Appearance, CandidateKind, CodeHistory},
+    correlation::{self, CorrelationMatrix, MAX_CORRELATION},
+    dataset::DatasetRow,
+    horizons::{self, Horizon, AFTERNOON, ALLDAY, MORNING},
+    indicators::{self, AnalysisParams, ConsolidationFilter, Gap, MaCross},
+    live::{self, BullBear, Ohlc, OhlcPremium, Timeframe},
+    macd::{self, Macd, MacdCross},
+    scoring::ScoringStage,
+    sector_heat::{self, SectorMove, UNKNOWN_SECTOR},
+};
+
+/// code -> probability from the scoring model
+type Scores = HashMap<StockCode, f64>;
+
+pub const RESISTANCE: ReportKind = ReportKind::new("resistance", "jquants_resistance");
+pub const CONSOLIDATING: ReportKind = ReportKind::new("consolidating", "jquants_consolidating");
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct StocksWindow {
-    code: String,
+    code: StockCode,
     name: String,
     atr: f64,
     unit: i32,
-    required_amount: i32,
+    required_amount: Yen,
+    /// A single board lot risks more than the unit budget
+    #[serde(default)]
+    over_budget: bool,
+    /// ATR of the last `atrPeriod` bars over that of the period before, see `indicators::atr_change`
+    #[serde(default)]
+    atr_change: Option<f64>,
+    /// Closed below the low of the bars before it in the breakout window (19 by default),
+    /// `required_amount` is the margin when shorts are on margin
+    #[serde(default)]
+    short: bool,
+    /// and above their high
+    #[serde(default)]
+    breakout: bool,
     latest_move: f64,
     standardized_diff: f64,
     current_price: f64,
@@ -29,92 +75,177 @@ pub struct StocksWindow {
     upper_bound: f64,
     number_of_resistance_candles: usize,
     number_of_support_candles: usize,
+    /// RSI of the closes of the 60 bars, see `indicators::rsi`
+    #[serde(default)]
+    rsi: Option<f64>,
+    /// MACD of the closes of the 60 bars on the analysis date, see `macd::of`
+    #[serde(default)]
+    macd: Option<Macd>,
+    /// The MACD crossed its signal line on the analysis date
+    #[serde(default)]
+    macd_cross: Option<MacdCross>,
+    /// 5, 20 and 60-day SMAs of the closes
+    #[serde(default)]
+    ma: [f64; 3],
+    /// Change of the 20-day SMA over the last 5 bars in ATR, None without the bars
+    #[serde(default)]
+    ma_20_slope: Option<f64>,
+    /// The 20-day SMA crossed the 60-day one on the analysis date
+    #[serde(default)]
+    ma_cross: Option<MaCross>,
+    /// Width of the 20-day Bollinger Bands over the 20-day SMA
+    #[serde(default)]
+    bollinger_width: Option<f64>,
+    /// The band width is in the bottom decile of the last 60 bars
+    #[serde(default)]
+    squeeze: bool,
     status: String,
-    result_morning: Option<f64>,
-    result_afternoon: Option<f64>,
-    result_allday: Option<f64>,
+    regime: BullBear,
+    /// Trend of the last `WEEKLY_BARS` weekly bars, see `live::standardized_diff_and_trend`
+    #[serde(default)]
+    weekly_trend: BullBear,
+    /// Regime of TOPIX on the analysis date from the market_regime table, None before
+    /// it has the date
+    #[serde(default)]
+    market_regime: Option<BullBear>,
+    /// (horizon name, result) of `Horizon::from_config`, the defaults first
+    #[serde(default)]
+    results: Vec<(String, Option<AtrUnits>)>,
     nextday_morning_close: Option<f64>,
     morning_move: Option<f64>,
     analyzed_at: String,
     result_at: Option<String>,
+    /// Date of the latest bar when it is older than `analyzed_at` (fetch miss),
+    /// the window is then built on that bar and left out of the statistics
+    stale_at: Option<String>,
+    /// 20-day average trading value in yen, None when the bars predate it
+    #[serde(default)]
+    turnover_20: Option<f64>,
+    /// 20-day turnover-weighted average price, None when the bars predate turnover
+    #[serde(default)]
+    vwap_20: Option<f64>,
+    /// Open of the analysis date over the previous close, as `TopixDailyWindow`
+    #[serde(default)]
+    window_diff: f64,
+    /// The largest gap of the 60 bars left unfilled
+    #[serde(default)]
+    gap: Option<Gap>,
+    /// The nearest horizontal level of the 60 bars above the price, see
+    /// `indicators::horizontal_levels`
+    #[serde(default)]
+    level_above: Option<f64>,
+    /// and below it
+    #[serde(default)]
+    level_below: Option<f64>,
+    /// Change (%) of the close from the previous one
+    #[serde(default)]
+    return_1: f64,
+    /// Change (%) of the close over the last 20 bars
+    #[serde(default)]
+    return_20: f64,
+    /// The same of TOPIX, None without its bars
+    #[serde(default)]
+    topix_return_20: Option<f64>,
 }
 
 impl StocksWindow {
     pub fn from_vec(
         ohlc_vec: &[OhlcPremium],
-        code: &str,
+        code: &StockCode,
         name: &str,
         unit: f64,
         date: &str,
+        params: &AnalysisParams,
     ) -> Result<Self, MyError> {
+        let _span = profile::span(Stage::Window);
         let position = match ohlc_vec.iter().position(|ohlc| ohlc.get_date() == date) {
             Some(res) => res,
             None => return Err(MyError::OutOfRange),
         };
 
-        if position < 59 {
+        // the 60-day moving average takes 60 bars whatever the range
+        if position + 1 < params.get_range_period().max(60) {
             return Err(MyError::OutOfRange);
         }
 
         let current_price = ohlc_vec[position].get_close();
 
         let ohlc_2 = &ohlc_vec[(position - 1)..=position];
-        let ohlc_5 = &ohlc_vec[(position - 4)..=position];
         let ohlc_20 = &ohlc_vec[(position - 19)..=position];
-        let ohlc_60 = &ohlc_vec[(position - 59)..=position];
-
-        let (prev_19, last) = ohlc_20.split_at(19);
-        let last_close = last[0].get_close();
-        // let last2_close = prev_19[18].get_close();
-        let prev_19_high = prev_19
-            .iter()
-            .map(|ohlc| ohlc.get_high())
-            .fold(f64::NAN, f64::max);
-        let prev_19_low = prev_19
-            .iter()
-            .map(|ohlc| ohlc.get_low())
-            .fold(f64::NAN, f64::min);
-        // let latest_move = (last_close - last2_close) / (prev_19_high - prev_19_low);
-        // let latest_move = (latest_move * 100.0).round() / 100.0;
-
-        let atr = ohlc_5
-            .iter()
-            .map(|ohlc| (ohlc.get_high() - ohlc.get_low()))
-            .sum::<f64>()
-            / ohlc_5.len() as f64;
-        let atr = (atr * 10.0).round() / 10.0;
-
-        let (unit, required_amount) = {
-            let unit = unit / atr;
-            let required_amount = (unit * last_close) as i32;
-            (unit as i32, required_amount)
-        };
-
-        let highest_high = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_high())
-            .fold(f64::NAN, f64::max);
-        let lowest_low = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_low())
-            .fold(f64::NAN, f64::min);
-
-        let diff_sum: f64 = ohlc_60
-            .iter()
-            .map(|ohlc| ohlc.get_high() - ohlc.get_low())
-            .sum();
-        let average_diff = diff_sum / ohlc_60.len() as f64;
-
-        let standardized_diff =
-            (average_diff / (highest_high - lowest_low) * 1000.0).trunc() / 1000.0;
+        let ohlc_range = params.range_bars(ohlc_vec, position);
+
+        let (prev, last) = params.breakout_bars(ohlc_vec, position);
+        let last_close = last.get_close();
+        let prev_high = indicators::highest_high(prev);
+        let prev_low = indicators::lowest_low(prev);
+
+        let atr = params.atr().of(&ohlc_vec[..=position]);
+        let atr_change = indicators::atr_change(&ohlc_vec[..=position], params.atr());
+        let turnover_20 = indicators::average_turnover(ohlc_20);
+        let vwap_20 = indicators::vwap(ohlc_20).map(|x| round_dp(x, 1));
+        let return_1 = round_dp(indicators::return_pct(ohlc_2, 1).unwrap(), 2);
+        let return_20 = round_dp(
+            indicators::return_pct(&ohlc_vec[..=position], indicators::RS_PERIOD).unwrap(),
+            2,
+        );
+        let window_diff = round_dp(ohlc_2[1].get_open() / ohlc_2[0].get_close(), 3);
+        let gap = indicators::unfilled_gaps(ohlc_range)
+            .into_iter()
+            .max_by(|a, b| a.size().total_cmp(&b.size()));
+        let levels =
+            indicators::horizontal_levels(ohlc_range, atr * indicators::LEVEL_TOLERANCE_ATR);
+        let (level_above, level_below) = indicators::nearest_levels(&levels, last_close);
+        let over_budget = indicators::lot_over_budget(unit, atr);
+        let (unit, notional) = indicators::unit_and_required_amount(unit, atr, last_close);
+        let short = last_close < prev_low;
+        let breakout = last_close > prev_high;
+        let required_amount = capacity::required_amount(notional, short);
+
+        let highest_high = indicators::highest_high(ohlc_range);
+        let lowest_low = indicators::lowest_low(ohlc_range);
+        let standardized_diff = indicators::standardized_diff(ohlc_range)?;
+        let regime = indicators::regime(ohlc_range, standardized_diff);
+        let rsi = indicators::rsi(ohlc_range, indicators::RSI_PERIOD);
+        let (macd, macd_cross) = macd::of(ohlc_range);
+        let until = &ohlc_vec[..=position];
+        let ma = [5, 20, 60].map(|period| round_dp(indicators::sma(until, period).unwrap(), 1));
+        let ma_20_slope = indicators::sma(&until[..until.len() - 5], 20)
+            .filter(|_| atr > 0.0)
+            .map(|before| round_dp((indicators::sma(until, 20).unwrap() - before) / atr, 2));
+        let ma_cross = indicators::ma_cross(until, 20, 60);
+        let weekly = live::to_timeframe_ohlc(
+            until
+                .iter()
+                .map(|x| {
+                    Ohlc::new(
+                        x.get_date().to_owned(),
+                        x.get_open(),
+                        x.get_high(),
+                        x.get_low(),
+                        x.get_close(),
+                    )
+                })
+                .collect(),
+            Timeframe::Weekly,
+        );
+        let (_, weekly_trend) =
+            live::standardized_diff_and_trend(&weekly[weekly.len().saturating_sub(WEEKLY_BARS)..]);
+        let bollinger_width = indicators::bollinger_width(until, indicators::BOLLINGER_PERIOD)
+            .map(|x| round_dp(x, 3));
+        let squeeze = indicators::bollinger_width_rank(
+            until,
+            indicators::BOLLINGER_PERIOD,
+            indicators::SQUEEZE_LOOKBACK,
+        )
+        .is_some_and(|rank| rank < indicators::SQUEEZE_QUANTILE);
 
-        let number_of_resistance_candles = ohlc_60
+        let number_of_resistance_candles = ohlc_range
             .iter()
             .filter(|ohlc| {
                 ohlc.get_high() > ohlc_vec[position].get_high() && current_price > ohlc.get_low()
             })
             .count();
-        let number_of_support_candles = ohlc_60
+        let number_of_support_candles = ohlc_range
             .iter()
             .filter(|ohlc| {
                 ohlc.get_high() > current_price && ohlc_vec[position].get_low() > ohlc.get_low()
@@ -139,16 +270,18 @@ impl StocksWindow {
             _ => "Stable",
         };
 
-        let latest_move = (ohlc_2[1].get_close() - ohlc_2[1].get_open())
-            / (ohlc_2[0].get_high() - ohlc_2[0].get_low());
-        let latest_move = (latest_move * 100.0).round() / 100.0;
-        let latest_move = latest_move.abs();
+        let latest_move = indicators::move_in_range(
+            ohlc_2[1].get_close() - ohlc_2[1].get_open(),
+            ohlc_2[0].get_high() - ohlc_2[0].get_low(),
+            "latest_move",
+        )?
+        .abs();
 
         let range = highest_high - lowest_low;
         let step = range / 5.0;
 
         let mut ranges = [0; 5];
-        for ohlc in ohlc_60 {
+        for ohlc in ohlc_range {
             let values = vec![
                 ohlc.get_open(),
                 ohlc.get_high(),
@@ -172,66 +305,43 @@ impl StocksWindow {
             ..(lowest_low + step * (max_range_index as f64 + 1.0));
 
         let (lower_bound, upper_bound) = {
-            let lower_bound = (max_range.start * 10.0).round() / 10.0;
-            let upper_bound = (max_range.end * 10.0).round() / 10.0;
+            let lower_bound = round_dp(max_range.start, 1);
+            let upper_bound = round_dp(max_range.end, 1);
             (lower_bound, upper_bound)
         };
 
         let analyzed_at = ohlc_vec[position].get_date().to_owned();
 
-        let (
-            nextday_morning_close,
-            result_morning,
-            result_afternoon,
-            result_allday,
-            morning_move,
-            result_at,
-        ) = match ohlc_vec.len() > position + 1 {
+        let results = horizons::results(Horizon::from_config(), &ohlc_vec[position + 1..], atr);
+        let (nextday_morning_close, morning_move, result_at) = match ohlc_vec.len() > position + 1 {
             true => {
-                let nextday_morning_close = ohlc_vec[position + 1].get_morning_close();
-                let result_morning = {
-                    let price = (ohlc_vec[position + 1].get_morning_close()
-                        - ohlc_vec[position + 1].get_open())
-                        / atr;
-                    (price * 100.0).round() / 100.0
-                };
-                let result_afternoon = {
-                    let price = (ohlc_vec[position + 1].get_close()
-                        - ohlc_vec[position + 1].get_afternoon_open())
-                        / atr;
-                    (price * 100.0).round() / 100.0
-                };
-                let result_allday = {
-                    let price = (ohlc_vec[position + 1].get_close()
-                        - ohlc_vec[position + 1].get_open())
-                        / atr;
-                    (price * 100.0).round() / 100.0
-                };
-                let morning_move = {
-                    let price = (ohlc_vec[position + 1].get_morning_close()
-                        - ohlc_vec[position].get_close())
-                        / (prev_19_high - prev_19_low);
-                    (price * 100.0).round() / 100.0
-                };
+                let nextday = &ohlc_vec[position + 1];
+                let nextday_morning_close = nextday.get_morning_close();
+                let morning_move = indicators::move_in_range(
+                    nextday.get_morning_close() - ohlc_vec[position].get_close(),
+                    prev_high - prev_low,
+                    "morning_move",
+                )?;
                 let result_at = ohlc_vec[position + 1].get_date().to_owned();
                 (
                     Some(nextday_morning_close),
-                    Some(result_morning),
-                    Some(result_afternoon),
-                    Some(result_allday),
                     Some(morning_move),
                     Some(result_at),
                 )
             }
-            false => (None, None, None, None, None, None),
+            false => (None, None, None),
         };
 
         Ok(Self {
-            code: code.to_owned(),
+            code: code.clone(),
             name: name.to_owned(),
             atr,
             unit,
             required_amount,
+            over_budget,
+            atr_change,
+            short,
+            breakout,
             latest_move,
             standardized_diff,
             current_price,
@@ -239,17 +349,218 @@ impl StocksWindow {
             upper_bound,
             number_of_resistance_candles,
             number_of_support_candles,
+            rsi,
+            macd,
+            macd_cross,
+            ma,
+            ma_20_slope,
+            ma_cross,
+            bollinger_width,
+            squeeze,
             status: status.to_owned(),
-            result_morning,
-            result_afternoon,
-            result_allday,
+            regime,
+            weekly_trend,
+            market_regime: None,
+            results,
             nextday_morning_close,
             morning_move,
             analyzed_at,
             result_at,
+            stale_at: None,
+            turnover_20,
+            vwap_20,
+            window_diff,
+            gap,
+            level_above,
+            level_below,
+            return_1,
+            return_20,
+            topix_return_20: None,
         })
     }
 
+    /// The window of the latest bar standing in for `date`, without results
+    fn into_stale(self, date: &str) -> Self {
+        Self {
+            stale_at: Some(self.analyzed_at),
+            analyzed_at: date.to_owned(),
+            result_at: None,
+            results: Vec::new(),
+            nextday_morning_close: None,
+            morning_move: None,
+            ..self
+        }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale_at.is_some()
+    }
+
+    //getters
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_atr(&self) -> f64 {
+        self.atr
+    }
+    pub fn get_unit(&self) -> i32 {
+        self.unit
+    }
+    pub fn get_standardized_diff(&self) -> f64 {
+        self.standardized_diff
+    }
+    pub fn get_latest_move(&self) -> f64 {
+        self.latest_move
+    }
+    pub fn get_current_price(&self) -> f64 {
+        self.current_price
+    }
+    pub fn get_resistance_candles(&self) -> usize {
+        self.number_of_resistance_candles
+    }
+    pub fn get_support_candles(&self) -> usize {
+        self.number_of_support_candles
+    }
+    pub fn get_rsi(&self) -> Option<f64> {
+        self.rsi
+    }
+    pub fn get_macd(&self) -> Option<Macd> {
+        self.macd
+    }
+    pub fn get_macd_cross(&self) -> Option<MacdCross> {
+        self.macd_cross
+    }
+    /// 5, 20 and 60-day SMAs
+    pub fn get_ma(&self) -> [f64; 3] {
+        self.ma
+    }
+    pub fn get_ma_20_slope(&self) -> Option<f64> {
+        self.ma_20_slope
+    }
+    pub fn get_ma_cross(&self) -> Option<MaCross> {
+        self.ma_cross
+    }
+    pub fn get_bollinger_width(&self) -> Option<f64> {
+        self.bollinger_width
+    }
+    pub fn is_squeeze(&self) -> bool {
+        self.squeeze
+    }
+    pub fn get_status(&self) -> &str {
+        &self.status
+    }
+    pub fn get_regime(&self) -> BullBear {
+        self.regime
+    }
+    pub fn get_weekly_trend(&self) -> BullBear {
+        self.weekly_trend
+    }
+    pub fn get_market_regime(&self) -> Option<BullBear> {
+        self.market_regime
+    }
+    /// Morning, afternoon and all day of the next day, in ATR
+    pub fn get_results(&self) -> [Option<AtrUnits>; 3] {
+        [MORNING, AFTERNOON, ALLDAY].map(|x| self.result(x))
+    }
+
+    /// Result of the horizon named `name`, None until its exit bar is stored
+    /// or when it isn't configured
+    pub fn result(&self, name: &str) -> Option<AtrUnits> {
+        self.results
+            .iter()
+            .find(|(x, _)| x == name)
+            .and_then(|(_, result)| *result)
+    }
+
+    fn has_results(&self) -> bool {
+        self.results.iter().any(|(_, result)| result.is_some())
+    }
+    pub fn get_analyzed_at(&self) -> &str {
+        &self.analyzed_at
+    }
+    pub fn get_result_at(&self) -> Option<&str> {
+        self.result_at.as_deref()
+    }
+    pub fn get_turnover_20(&self) -> Option<f64> {
+        self.turnover_20
+    }
+    /// Yen a position can take within `maxParticipation` of the 20-day turnover, to the
+    /// thousand
+    pub fn capacity(&self) -> Option<f64> {
+        self.turnover_20
+            .map(|x| round_dp(capacity::absorbable(x, capacity::max_participation()), -3))
+    }
+    pub fn get_vwap_20(&self) -> Option<f64> {
+        self.vwap_20
+    }
+    pub fn is_short(&self) -> bool {
+        self.short
+    }
+    pub fn is_breakout(&self) -> bool {
+        self.breakout
+    }
+    pub fn get_return_1(&self) -> f64 {
+        self.return_1
+    }
+    pub fn get_return_20(&self) -> f64 {
+        self.return_20
+    }
+    pub fn get_topix_return_20(&self) -> Option<f64> {
+        self.topix_return_20
+    }
+    /// 20-day return over TOPIX's in percentage points
+    pub fn relative_strength(&self) -> Option<f64> {
+        self.topix_return_20
+            .map(|topix| round_dp(self.return_20 - topix, 2))
+    }
+    pub fn get_window_diff(&self) -> f64 {
+        self.window_diff
+    }
+    pub fn get_gap(&self) -> Option<&Gap> {
+        self.gap.as_ref()
+    }
+    pub fn get_level_above(&self) -> Option<f64> {
+        self.level_above
+    }
+    pub fn get_level_below(&self) -> Option<f64> {
+        self.level_below
+    }
+    /// `level` and how far it is from `price` in ATR, e.g. "2450 (+1.2)"
+    fn level_text(&self, level: Option<f64>, price: f64) -> String {
+        match level.filter(|_| self.atr > 0.0) {
+            Some(level) => format!("{} ({:+})", level, round_dp((level - price) / self.atr, 2)),
+            None => "-".to_owned(),
+        }
+    }
+    /// `price` above (+) or below (-) the 20-day VWAP in ATR
+    pub fn vwap_distance(&self, price: f64) -> Option<AtrUnits> {
+        self.vwap_20
+            .filter(|_| self.atr > 0.0)
+            .map(|vwap| AtrUnits(round_dp((price - vwap) / self.atr, 2)))
+    }
+    /// The same for every run of the analysis of the code on `analyzed_at`
+    pub fn signal_id(&self) -> Result<SignalId, MyError> {
+        SignalId::new(WINDOW_SOURCE, &self.code, &self.analyzed_at)
+    }
+    pub fn to_signal(&self) -> Result<Signal, MyError> {
+        Ok(Signal::new(
+            self.signal_id()?,
+            &self.code,
+            &self.analyzed_at,
+            &self.status,
+        )
+        .with_candidate(SignalCandidate {
+            name: self.name.clone(),
+            price: self.current_price,
+            atr: self.atr,
+            unit: self.unit,
+            short: self.short,
+        }))
+    }
+
     // fn markdown_body_output_for_cloud(&self, afternoon: bool) -> Result<String, MyError> {
     //     let mut buffer = String::new();
 
@@ -324,99 +635,286 @@ impl StocksWindow {
     //     self.markdown_body_output_for_cloud(false)
     // }
 
-    fn markdown_body_output_for_resistance(&self, afternoon: bool) -> Result<String, MyError> {
-        let mut buffer = String::new();
+    /// Results are added when the top 10 has them (afternoon)
+    /// and the score when a model is configured
+    fn table_columns(lang: Lang, results: bool, scored: bool) -> Vec<Column<'static>> {
+        let mut columns = vec![
+            Column::left(Msg::Code.text(lang)),
+            Column::left(Msg::Name.text(lang)),
+            Column::left(Msg::Sector.text(lang)),
+            Column::right(Msg::Price.text(lang)),
+            Column::left(Msg::Status.text(lang)),
+            Column::center("W"),
+            Column::right("R"),
+            Column::right("S"),
+            Column::right("RSI"),
+            Column::right("MACD"),
+            Column::right("RS"),
+            Column::right("LM"),
+            Column::right("VWAP"),
+            Column::right("Lv↑"),
+            Column::right("Lv↓"),
+            Column::right("ATR"),
+            Column::right("ATR×"),
+            Column::right("Unit"),
+            Column::right(Msg::RequiredAmount.text(lang)),
+            Column::right("Cap"),
+        ];
+        if scored {
+            columns.push(Column::right(Msg::Score.text(lang)));
+        }
+        if results {
+            for horizon in Horizon::from_config() {
+                columns.push(Column::right(horizon.label(lang)));
+            }
+        }
+        columns.push(Column::left("ID"));
+        columns
+    }
 
-        let (current_price, latest_move) = match afternoon {
-            true => (
-                self.nextday_morning_close.unwrap(),
-                self.morning_move.unwrap(),
+    fn table_row(
+        &self,
+        afternoon: bool,
+        results: bool,
+        sectors: &Sectors,
+        scores: Option<&Scores>,
+        lang: Lang,
+    ) -> Vec<String> {
+        let (current_price, latest_move) =
+            match (afternoon, self.nextday_morning_close, self.morning_move) {
+                (true, Some(close), Some(morning_move)) => (close, morning_move),
+                _ => (self.current_price, self.latest_move),
+            };
+        let name = match &self.stale_at {
+            Some(stale_at) => format!(
+                "{} ⚠{} {}",
+                short_name(&self.name),
+                Msg::Stale.text(lang),
+                stale_at
             ),
-            false => (self.current_price, self.latest_move),
-        };
-
-        let name = match self.name.chars().count() > 5 {
-            true => {
-                let name: String = self.name.chars().take(4).collect();
-                name
-            }
-            false => self.name.to_owned(),
+            None => short_name(&self.name).to_owned(),
         };
 
-        writeln!(
-            buffer,
-            "{} {}, {}円, {} [R: {}, S: {}] LM: {}",
-            self.code,
+        let mut row = vec![
+            code_link(&self.code).to_string(),
             name,
-            current_price,
-            self.status,
-            self.number_of_resistance_candles,
-            self.number_of_support_candles,
-            latest_move
-        )?;
-
-        writeln!(
-            buffer,
-            "ATR: {}, Unit: {}, 必要金額: {}円",
-            self.atr, self.unit, self.required_amount
-        )?;
-
-        if self.result_allday.is_some() {
-            writeln!(
-                buffer,
-                "Morning: {}, Afternoon: {}, Allday: {}",
-                self.result_morning.unwrap(),
-                self.result_afternoon.unwrap(),
-                self.result_allday.unwrap()
-            )?;
+            sectors
+                .get(&self.code)
+                .map_or("-", |x| x.as_str())
+                .to_owned(),
+            lang.yen(current_price).to_string(),
+            std::iter::once(status_text(&self.status, lang))
+                .chain(self.ma_cross.map(|cross| cross.tag()))
+                .chain(self.squeeze.then_some("SQ"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            match self.weekly_trend {
+                BullBear::Bull => "↑",
+                BullBear::Bear => "↓",
+                BullBear::NoTrend => "-",
+            }
+            .to_owned(),
+            self.number_of_resistance_candles.to_string(),
+            self.number_of_support_candles.to_string(),
+            self.rsi.map_or("-".to_owned(), |x| x.to_string()),
+            self.macd_cross.map_or("-", |x| x.mark()).to_owned(),
+            self.relative_strength()
+                .map_or("-".to_owned(), |x| format!("{:+}", x)),
+            latest_move.to_string(),
+            self.vwap_distance(current_price)
+                .map_or("-".to_owned(), |x| format!("{:+}", x.0)),
+            self.level_text(self.level_above, current_price),
+            self.level_text(self.level_below, current_price),
+            self.atr.to_string(),
+            self.atr_change.map_or("-".to_owned(), |x| x.to_string()),
+            unit_text(self.unit, self.over_budget),
+            lang.yen(self.required_amount.0).to_string(),
+            self.capacity()
+                .map_or("-".to_owned(), |x| lang.yen(x).to_string()),
+        ];
+        if let Some(scores) = scores {
+            row.push(
+                scores
+                    .get(&self.code)
+                    .map_or("-".to_owned(), |x| format!("{:.2}", x)),
+            );
         }
-
-        Ok(buffer)
+        if results {
+            for horizon in Horizon::from_config() {
+                let result = self.result(horizon.get_name());
+                row.push(result.map_or("-".to_owned(), |x| x.to_string()));
+            }
+        }
+        row.push(self.signal_id().map_or("-".to_owned(), |x| x.to_string()));
+        row
     }
-    fn markdown_body_output_for_resistance_default(&self) -> Result<String, MyError> {
-        self.markdown_body_output_for_resistance(false)
+    fn to_dataset_row(&self, sectors: &Sectors) -> DatasetRow {
+        DatasetRow {
+            code: self.code.clone(),
+            date: self.analyzed_at.clone(),
+            sector: sectors.get(&self.code).cloned(),
+            current_price: self.current_price,
+            atr: self.atr,
+            standardized_diff: self.standardized_diff,
+            latest_move: self.latest_move,
+            lower_bound: self.lower_bound,
+            upper_bound: self.upper_bound,
+            resistance_candles: self.number_of_resistance_candles,
+            support_candles: self.number_of_support_candles,
+            status: self.status.clone(),
+            regime: self.regime,
+            result_morning: self.result(MORNING),
+            result_afternoon: self.result(AFTERNOON),
+            result_allday: self.result(ALLDAY),
+        }
+    }
+    fn to_appearance(&self, kind: CandidateKind) -> Appearance {
+        Appearance::new(
+            &self.analyzed_at,
+            kind,
+            &self.status,
+            self.standardized_diff,
+            self.get_results(),
+        )
     }
 }
 
-#[derive(Debug, Clone)]
+/// Weekly bars of the higher timeframe trend, about the 60 daily bars of a window
+const WEEKLY_BARS: usize = 12;
+/// Nextday candidates whose ATR grew this many times in `atrPeriod` bars are dropped
+const MAX_ATR_CHANGE: f64 = 2.0;
+
+/// Calendar days read before `from`, enough for the 60 bars of a window over the holidays.
+/// Twice the range of `rangePeriod` when it is longer
+const LOOKBACK_DAYS: i64 = 120;
+/// Calendar days read after `to` per trading day of the furthest result horizon,
+/// enough to reach the exit bars over the holidays
+const LOOKAHEAD_DAYS: i64 = 14;
+
+/// Calendar days read before the first window
+pub fn lookback_days() -> i64 {
+    let range_period = AnalysisParams::from_config().get_range_period() as i64;
+    LOOKBACK_DAYS.max(range_period * 2)
+}
+
+/// Dates of the bars the windows between `from` and `to` are computed from
+fn window_range(from: &str, to: &str) -> Result<(String, String), MyError> {
+    let parse = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| MyError::Anyhow(anyhow!("{}: {}", date, e)))
+    };
+    let from = parse(from)? - Duration::days(lookback_days());
+    let exit_days = Horizon::from_config()
+        .iter()
+        .map(|x| x.get_exit_day())
+        .max()
+        .unwrap_or(1);
+    let to = parse(to)? + Duration::days(LOOKAHEAD_DAYS * exit_days as i64);
+    Ok((
+        from.format("%Y-%m-%d").to_string(),
+        to.format("%Y-%m-%d").to_string(),
+    ))
+}
+
+/// Rows are shared with `Arc`, so grouping and top 10 lists don't copy them
+#[derive(Debug, Clone, Default)]
 pub struct StocksWindowList {
-    data: Vec<StocksWindow>,
+    data: Vec<Arc<StocksWindow>>,
+    /// blacklisted stocks and those under a tender offer left out, noted in the summary
+    excluded: Vec<String>,
+    /// stocks kept but under a corporate event, noted in the summary
+    flagged: Vec<String>,
+    /// The latest analysis date and the correlations of the universe until it
+    correlations: Option<(String, Arc<CorrelationMatrix>)>,
 }
-impl From<Vec<StocksWindow>> for StocksWindowList {
-    fn from(data: Vec<StocksWindow>) -> Self {
-        StocksWindowList { data }
+impl From<Vec<Arc<StocksWindow>>> for StocksWindowList {
+    fn from(data: Vec<Arc<StocksWindow>>) -> Self {
+        StocksWindowList {
+            data,
+            excluded: Vec::new(),
+            flagged: Vec::new(),
+            correlations: None,
+        }
     }
 }
 impl StocksWindowList {
     pub fn new() -> Self {
-        Self { data: Vec::new() }
+        Self {
+            data: Vec::new(),
+            excluded: Vec::new(),
+            flagged: Vec::new(),
+            correlations: None,
+        }
+    }
+    /// Windows of one code between `from` and `to`, read from the stocks_ohlc table.
+    /// `trading_days` (see `stocks_ohlc::select_dates`) after the latest bar of the code
+    /// get a stale window.
+    /// `known_at` leaves out the bars stored after it, see `create_stocks_window_list_as_of`
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_db(
+        conn: &rusqlite::Connection,
+        code: &StockCode,
+        name: &str,
+        unit: f64,
+        from: &str,
+        to: &str,
+        trading_days: &HashSet<String>,
+        known_at: Option<&str>,
+    ) -> Result<Self, MyError> {
+        let (range_from, range_to) = window_range(from, to)?;
+        let records = crate::database::stocks_ohlc::select_by_code_and_range_known_at(
+            conn,
+            code,
+            &range_from,
+            &range_to,
+            known_at,
+        )?;
+        let ohlc_vec: Vec<OhlcPremium> = records
+            .into_iter()
+            .map(|x| x.get_inner())
+            .collect::<Vec<_>>();
+        // debug!("{:?}", ohlc_vec);
+        let mut stocks_window_list = StocksWindowList::new();
+        stocks_window_list.push(ohlc_vec, code, name, unit, from, to, trading_days);
+
+        Ok(stocks_window_list)
     }
     // fn from_vec(vec: Vec<StocksWindow>) -> Self {
     //     Self { data: vec }
     // }
+    #[allow(clippy::too_many_arguments)]
     pub fn push(
         &mut self,
         ohlc_vec: Vec<OhlcPremium>,
-        code: &str,
+        code: &StockCode,
         name: &str,
         unit: f64,
         from: &str,
         to: &str,
+        trading_days: &HashSet<String>,
     ) {
         let from = NaiveDate::parse_from_str(from, "%Y-%m-%d").unwrap();
         let to = NaiveDate::parse_from_str(to, "%Y-%m-%d").unwrap();
+        let params = AnalysisParams::from_config();
+        let latest = ohlc_vec.last().map(|x| x.get_date().to_owned());
         let mut date = from;
         while date <= to {
-            match StocksWindow::from_vec(
-                &ohlc_vec,
-                code,
-                name,
-                unit,
-                &date.format("%Y-%m-%d").to_string(),
-            ) {
-                Ok(stocks_window) => self.data.push(stocks_window),
+            let date_str = date.format("%Y-%m-%d").to_string();
+            let stale = trading_days.contains(&date_str)
+                && latest.as_ref().is_some_and(|latest| *latest < date_str);
+            let window = match (stale, &latest) {
+                (true, Some(latest)) => {
+                    warn!("{} {}: no bar, the latest is {}", code, date_str, latest);
+                    StocksWindow::from_vec(&ohlc_vec, code, name, unit, latest, &params)
+                        .map(|x| x.into_stale(&date_str))
+                }
+                _ => StocksWindow::from_vec(&ohlc_vec, code, name, unit, &date_str, &params),
+            };
+            match window {
+                Ok(stocks_window) => self.data.push(Arc::new(stocks_window)),
                 Err(e) => match e {
                     MyError::OutOfRange => {}
+                    MyError::ZeroRange(_) => warn!("{} {}: skipped, {}", code, date, e),
                     _ => {
                         error!("{}", e);
                         return;
@@ -427,216 +925,810 @@ impl StocksWindowList {
         }
     }
 
+    /// Stores every window but the stale ones in the stocks_window table, returns the number stored
+    pub fn save(&self) -> Result<usize, MyError> {
+        let mut conn = crate::database::stocks_window::open_db()?;
+        crate::database::stocks_window::insert(&mut conn, &self.data)
+    }
+
+    /// Every window but the stale ones as a dataset row, ordered by date and code
+    pub fn to_dataset_rows(&self, sectors: &Sectors) -> Vec<DatasetRow> {
+        let mut rows = self
+            .data
+            .iter()
+            .filter(|x| !x.is_stale())
+            .map(|x| x.to_dataset_row(sectors))
+            .collect::<Vec<_>>();
+        rows.sort_by(|a, b| (&a.date, &a.code).cmp(&(&b.date, &b.code)));
+        rows
+    }
+
     fn append(&mut self, mut stocks_daytrading_list: StocksWindowList) {
         self.data.append(&mut stocks_daytrading_list.data);
     }
 
-    fn filter_by_standardized_diff(&mut self, diff: f64) {
-        self.data.retain(|x| x.standardized_diff < diff);
+    /// Keeps stocks whose Bollinger Band width is in the bottom decile of the last
+    /// 60 bars
+    pub fn filter_by_squeeze(&mut self) {
+        self.data.retain(|x| x.squeeze);
+    }
+
+    fn filter_by_consolidation(&mut self, filter: ConsolidationFilter) {
+        self.data
+            .retain(|x| filter.keeps(x.squeeze, x.standardized_diff));
     }
 
     fn filter_by_latest_move(&mut self, latest_move: f64) {
         self.data.retain(|x| x.latest_move < latest_move);
     }
 
-    fn get_resistance_candles_top10(&self) -> StocksWindowList {
-        let mut resistance_candles_top10 = StocksWindowList::from(self.data.to_vec());
-        resistance_candles_top10.data.sort_by(|a, b| {
-            b.number_of_resistance_candles
-                .partial_cmp(&a.number_of_resistance_candles)
-                .unwrap()
+    /// Drops stocks trading less than `min` yen a day over 20 days, returns their codes.
+    /// Stocks without turnover (bars stored before it was kept) are left in.
+    fn filter_by_turnover(&mut self, min: f64) -> Vec<StockCode> {
+        let mut excluded = Vec::new();
+        self.data.retain(|x| match x.turnover_20 {
+            Some(turnover) if turnover < min => {
+                excluded.push(x.code.clone());
+                false
+            }
+            _ => true,
         });
-        StocksWindowList::from(
-            resistance_candles_top10
-                .data
-                .into_iter()
-                .take(10)
-                .collect::<Vec<_>>(),
-        )
+        excluded
     }
-    fn get_support_candles_top10(&self) -> StocksWindowList {
-        let mut support_candles_top10 = StocksWindowList::from(self.data.to_vec());
-        support_candles_top10.data.sort_by(|a, b| {
-            b.number_of_support_candles
-                .partial_cmp(&a.number_of_support_candles)
-                .unwrap()
+
+    /// Drops stocks whose ATR grew `max` times or more in the last `atrPeriod` bars
+    /// (after news), the unit is sized on an ATR that may not last. Noted as excluded
+    fn filter_by_atr_change(&mut self, max: f64) {
+        let mut excluded = Vec::new();
+        self.data.retain(|x| match x.atr_change {
+            Some(change) if change >= max => {
+                excluded.push(format!("{} ATR x{}", x.code, change));
+                false
+            }
+            _ => true,
+        });
+        self.excluded.extend(excluded);
+    }
+
+    /// Drops stocks blacklisted on their analysis date
+    fn filter_by_blacklist(&mut self, entries: &[BlacklistEntry]) {
+        let excluded = blacklist::exclude(&mut self.data, entries, |x| {
+            (&x.code, x.analyzed_at.as_str())
+        });
+        self.excluded.extend(excluded.iter().map(|x| x.to_string()));
+    }
+
+    /// Drops stocks under a tender offer on their analysis date and flags those
+    /// under an offering
+    fn filter_by_corporate_events(&mut self, events: &[CorporateEvent]) {
+        let (excluded, flagged) = corporate_events::exclude(&mut self.data, events, |x| {
+            (&x.code, x.analyzed_at.as_str())
+        });
+        self.excluded.extend(excluded.iter().map(|x| x.to_string()));
+        self.flagged.extend(flagged.iter().map(|x| x.to_string()));
+    }
+
+    /// Drops stocks reporting results within `days` after the analysis, noted in the
+    /// summary with the date of the results
+    fn filter_by_earnings(&mut self, calendar: &ResultsCalendar, days: i64, lang: Lang) {
+        let mut excluded: Vec<String> = Vec::new();
+        self.data.retain(|x| {
+            let Ok(date) = NaiveDate::parse_from_str(&x.analyzed_at, "%Y-%m-%d") else {
+                return true;
+            };
+            let Some(results) = calendar.reports_within(&x.code, date, days) else {
+                return true;
+            };
+            let note = format!("{} {} {}", x.code, Msg::ResultsOn.text(lang), results);
+            if !excluded.contains(&note) {
+                excluded.push(note);
+            }
+            false
+        });
+        self.excluded.extend(excluded);
+    }
+
+    /// Keeps stocks whose sector short ratio (%) on the analysis day is at least `min`,
+    /// stocks without a ratio are dropped too
+    pub fn filter_by_short_ratio(&mut self, ratios: &HashMap<(String, StockCode), f64>, min: f64) {
+        self.data.retain(|x| {
+            ratios
+                .get(&(x.analyzed_at.clone(), x.code.clone()))
+                .is_some_and(|ratio| *ratio >= min)
         });
-        StocksWindowList::from(
-            support_candles_top10
-                .data
-                .into_iter()
-                .take(10)
-                .collect::<Vec<_>>(),
-        )
     }
 
-    fn number_of_morning_gainers(&self) -> f64 {
+    /// Keeps stocks closing above their 20-day SMA on the analysis day
+    pub fn filter_by_above_ma20(&mut self) {
+        self.data.retain(|x| x.current_price > x.ma[1]);
+    }
+
+    /// Keeps stocks whose 20-day SMA rose over the last 5 bars
+    pub fn filter_by_ma20_rising(&mut self) {
         self.data
+            .retain(|x| x.ma_20_slope.is_some_and(|slope| slope > 0.0));
+    }
+
+    /// Keeps stocks whose 20-day SMA crossed the 60-day one in the `cross` direction
+    /// on the analysis day
+    pub fn filter_by_ma_cross(&mut self, cross: MaCross) {
+        self.data.retain(|x| x.ma_cross == Some(cross));
+    }
+
+    /// Keeps stocks whose weekly bars trend `trend`, the daily breakouts along the higher
+    /// timeframe
+    pub fn filter_by_weekly_trend(&mut self, trend: BullBear) {
+        self.data.retain(|x| x.weekly_trend == trend);
+    }
+
+    /// Keeps stocks whose MACD crossed its signal line in the `cross` direction
+    /// on the analysis day
+    pub fn filter_by_macd_cross(&mut self, cross: MacdCross) {
+        self.data.retain(|x| x.macd_cross == Some(cross));
+    }
+
+    /// Top 10 by `key` (descending, ties keep the list order). Keys are computed once
+    /// and only references are sorted.
+    fn top10_by(&self, key: fn(&StocksWindow) -> usize) -> impl Iterator<Item = &StocksWindow> {
+        let mut keyed = self
+            .data
             .iter()
-            .filter(|x| x.result_morning.unwrap_or(0.0) > 0.0)
-            .count() as f64
+            .map(|x| (Reverse(key(x)), x.as_ref()))
+            .collect::<Vec<_>>();
+        keyed.sort_by_key(|(key, _)| *key);
+        keyed.into_iter().take(10).map(|(_, x)| x)
+    }
+    fn get_resistance_candles_top10(&self) -> impl Iterator<Item = &StocksWindow> {
+        self.top10_by(|x| x.number_of_resistance_candles)
+    }
+    fn get_support_candles_top10(&self) -> impl Iterator<Item = &StocksWindow> {
+        self.top10_by(|x| x.number_of_support_candles)
+    }
+
+    /// TOPIX's 20-day return (%) on the analysis date, None without its bars
+    fn topix_return_20(&self) -> Option<f64> {
+        self.data.iter().find_map(|x| x.topix_return_20)
+    }
+
+    /// Breakouts (either way) by relative strength, the leaders first while TOPIX rose
+    /// over the 20 days and the laggards first while it fell
+    fn get_rs_top10(&self) -> impl Iterator<Item = &StocksWindow> {
+        let strong = self.topix_return_20().is_some_and(|x| x >= 0.0);
+        let mut ranked = self
+            .data
+            .iter()
+            .filter(|x| x.breakout || x.short)
+            .filter_map(|x| x.relative_strength().map(|rs| (rs, x.as_ref())))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(a, _), (b, _)| match strong {
+            true => b.total_cmp(a),
+            false => a.total_cmp(b),
+        });
+        ranked.into_iter().take(10).map(|(_, x)| x)
+    }
+
+    /// Sets TOPIX's 20-day returns (date -> %) of the analysis dates, see
+    /// `indicators::topix_returns`
+    pub fn set_topix_returns(&mut self, returns: &HashMap<String, f64>) {
+        for stocks_window in self.data.iter_mut() {
+            let topix_return = returns
+                .get(&stocks_window.analyzed_at)
+                .map(|x| round_dp(*x, 2));
+            Arc::make_mut(stocks_window).topix_return_20 = topix_return;
+        }
+    }
+
+    /// Tags the windows with the market regime (date -> regime) of their analysis date,
+    /// see `database::market_regime`
+    pub fn set_market_regimes(&mut self, regimes: &HashMap<String, BullBear>) {
+        for stocks_window in self.data.iter_mut() {
+            let regime = regimes.get(&stocks_window.analyzed_at).copied();
+            Arc::make_mut(stocks_window).market_regime = regime;
+        }
+    }
+
+    /// Windows of the resistance and support tables, each once
+    fn signals(&self) -> Result<Vec<Signal>, MyError> {
+        let mut seen = HashSet::new();
+        self.get_resistance_candles_top10()
+            .chain(self.get_support_candles_top10())
+            .filter(|x| seen.insert(&x.code))
+            .map(|x| x.to_signal())
+            .collect()
+    }
+
+    /// The correlations of the universe over the `CORRELATION_PERIOD` days until `date`,
+    /// see `correlation::load`
+    fn set_correlations(&mut self, date: &str, matrix: CorrelationMatrix) {
+        self.correlations = Some((date.to_owned(), Arc::new(matrix)));
+    }
+
+    /// Pairs of the resistance and support tables whose daily returns correlated above
+    /// `MAX_CORRELATION`, taking both doubles up the same risk. None without the
+    /// correlations of the analysis date
+    fn correlated_pairs(&self) -> Option<Vec<(StockCode, StockCode, f64)>> {
+        let (date, matrix) = self.correlations.as_ref()?;
+        let mut seen = HashSet::new();
+        let codes = self
+            .get_resistance_candles_top10()
+            .chain(self.get_support_candles_top10())
+            .filter(|x| &x.analyzed_at == date && !x.is_stale() && seen.insert(&x.code))
+            .map(|x| &x.code)
+            .collect::<Vec<_>>();
+        let mut pairs = Vec::new();
+        for (i, a) in codes.iter().enumerate() {
+            for b in &codes[i + 1..] {
+                if let Some(rho) = matrix.get(a, b).filter(|x| *x > MAX_CORRELATION) {
+                    pairs.push(((*a).clone(), (*b).clone(), rho));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.2.total_cmp(&a.2));
+        Some(pairs)
     }
-    fn number_of_afternoon_gainers(&self) -> f64 {
+
+    fn number_of_gainers(&self, horizon: &Horizon) -> f64 {
         self.data
             .iter()
-            .filter(|x| x.result_afternoon.unwrap_or(0.0) > 0.0)
+            .filter(|x| !x.is_stale())
+            .filter(|x| x.result(horizon.get_name()).is_some_and(|r| r.0 > 0.0))
             .count() as f64
     }
-    fn number_of_allday_gainers(&self) -> f64 {
+
+    /// Stale windows are left out of the counts and listed
+    fn summary(&self, scoring: Option<&ScoringStage>, lang: Lang) -> Vec<String> {
+        let len = self.data.iter().filter(|x| !x.is_stale()).count() as f64;
+        let percentage = |count: f64| Pct(round_dp(count / len * 100.0, 0));
+
+        let mut summary = vec![format!("{}: {}", Msg::NumberOfStocks.text(lang), len)];
+        if let Some(regime) = self.data.iter().find_map(|x| x.market_regime) {
+            summary.push(format!("{}: {}", Msg::MarketRegime.text(lang), regime));
+        }
+        for horizon in Horizon::from_config() {
+            summary.push(format!(
+                "{}: {}",
+                horizon.gainers_label(lang),
+                percentage(self.number_of_gainers(horizon))
+            ));
+        }
+        if !self.excluded.is_empty() {
+            summary.push(format!(
+                "{}: {}",
+                Msg::Excluded.text(lang),
+                self.excluded.join(", ")
+            ));
+        }
+        if !self.flagged.is_empty() {
+            summary.push(format!(
+                "{}: {}",
+                Msg::Flagged.text(lang),
+                self.flagged.join(", ")
+            ));
+        }
+        let stale = self
+            .data
+            .iter()
+            .filter_map(|x| {
+                x.stale_at
+                    .as_ref()
+                    .map(|stale_at| format!("{} ({})", x.code, stale_at))
+            })
+            .collect::<Vec<_>>();
+        if !stale.is_empty() {
+            summary.push(format!("{}: {}", Msg::Stale.text(lang), stale.join(", ")));
+        }
+        // a strategy spreads over its top 10, so it takes what they take together
+        for (msg, top10) in [
+            (
+                Msg::Resistance,
+                self.get_resistance_candles_top10().collect::<Vec<_>>(),
+            ),
+            (Msg::Support, self.get_support_candles_top10().collect()),
+        ] {
+            let capacity = top10.iter().filter_map(|x| x.capacity()).sum::<f64>();
+            if capacity > 0.0 {
+                summary.push(format!(
+                    "{} {}: {}",
+                    Msg::StrategyCapacity.text(lang),
+                    msg.text(lang),
+                    lang.yen(round_dp(capacity, -6))
+                ));
+            }
+        }
+        if let Some(scoring) = scoring {
+            summary.push(format!(
+                "{}: {}",
+                Msg::Model.text(lang),
+                scoring.get_version()
+            ));
+        }
+        summary
+    }
+
+    /// Candidates of both top 10 lists, scored by the model when the report is scored
+    /// and by their candles out of the 60 bars otherwise
+    fn capacity_candidates(&self, scores: Option<&Scores>) -> Vec<Candidate> {
+        let mut seen = HashSet::new();
+        self.get_resistance_candles_top10()
+            .chain(self.get_support_candles_top10())
+            .filter(|x| !x.is_stale() && seen.insert(&x.code))
+            .map(|x| {
+                let score = match scores {
+                    Some(scores) => scores.get(&x.code).copied().unwrap_or(0.0),
+                    None => {
+                        x.number_of_resistance_candles
+                            .max(x.number_of_support_candles) as f64
+                            / 60.0
+                    }
+                };
+                Candidate::new(x.code.clone(), &x.name, x.required_amount, score)
+            })
+            .collect()
+    }
+
+    fn write_capacity(
+        markdown: &mut Markdown,
+        plan: &Plan,
+        available_cash: Yen,
+        lang: Lang,
+    ) -> Result<(), MyError> {
+        markdown.body(&format!(
+            "{}: {} / {}: {} / {}: {}",
+            Msg::AvailableCash.text(lang),
+            lang.yen(available_cash.0),
+            Msg::Used.text(lang),
+            lang.yen(plan.get_used().0),
+            Msg::Leftover.text(lang),
+            lang.yen(plan.get_leftover().0),
+        ))?;
+        markdown.table(
+            &[
+                Column::left(Msg::Code.text(lang)),
+                Column::left(Msg::Name.text(lang)),
+                Column::right(Msg::RequiredAmount.text(lang)),
+                Column::right(Msg::Score.text(lang)),
+            ],
+            plan.get_selected().iter().map(|x| {
+                [
+                    code_link(x.get_code()).to_string(),
+                    short_name(x.get_name()).to_owned(),
+                    lang.yen(x.get_required_amount().0).to_string(),
+                    format!("{:.2}", x.get_score()),
+                ]
+            }),
+        )
+    }
+
+    /// Daily change and 19-bar breakouts per 33-sector, the hottest first
+    fn write_sector_heat(
+        &self,
+        markdown: &mut Markdown,
+        sectors: &Sectors,
+        lang: Lang,
+    ) -> Result<(), MyError> {
+        let heat = sector_heat::aggregate(self.data.iter().filter(|x| !x.is_stale()).map(|x| {
+            SectorMove {
+                sector: sectors.get(&x.code).map_or(UNKNOWN_SECTOR, |x| x.as_str()),
+                change: x.return_1,
+                breakout: x.breakout,
+                breakdown: x.short,
+            }
+        }));
+        markdown.table(
+            &[
+                Column::left(Msg::Sector.text(lang)),
+                Column::right(Msg::NumberOfStocks.text(lang)),
+                Column::right("%"),
+                Column::right(Msg::Breakouts.text(lang)),
+                Column::right(Msg::Breakdowns.text(lang)),
+            ],
+            heat.iter().map(|x| {
+                [
+                    x.get_sector().to_owned(),
+                    x.get_stocks().to_string(),
+                    format!("{:+}", x.get_average_change()),
+                    x.get_breakouts().to_string(),
+                    x.get_breakdowns().to_string(),
+                ]
+            }),
+        )
+    }
+
+    /// The 10 largest unfilled gaps in ATR, with the distance of the close to their
+    /// nearer edge
+    fn write_gaps(&self, markdown: &mut Markdown, lang: Lang) -> Result<(), MyError> {
+        let mut gaps = self
+            .data
+            .iter()
+            .filter(|x| !x.is_stale() && x.atr > 0.0)
+            .filter_map(|x| x.gap.as_ref().map(|gap| (x, gap)))
+            .collect::<Vec<_>>();
+        gaps.sort_by(|(a, a_gap), (b, b_gap)| {
+            (b_gap.size() / b.atr).total_cmp(&(a_gap.size() / a.atr))
+        });
+        markdown.table(
+            &[
+                Column::left(Msg::Code.text(lang)),
+                Column::left(Msg::Name.text(lang)),
+                Column::left(Msg::Date.text(lang)),
+                Column::right(Msg::Price.text(lang)),
+                Column::right(Msg::UnfilledGaps.text(lang)),
+                Column::right("Size"),
+                Column::right("Dist"),
+                Column::left("S/R"),
+            ],
+            gaps.into_iter().take(10).map(|(x, gap)| {
+                let (distance, side) = match gap.is_up() {
+                    true => (x.current_price - gap.get_upper(), Msg::Support),
+                    false => (gap.get_lower() - x.current_price, Msg::Resistance),
+                };
+                [
+                    code_link(&x.code).to_string(),
+                    short_name(&x.name).to_owned(),
+                    gap.get_date().to_owned(),
+                    lang.yen(x.current_price).to_string(),
+                    format!("{}-{}", gap.get_lower(), gap.get_upper()),
+                    format!("{:.2}", gap.size() / x.atr),
+                    format!("{:.2}", distance / x.atr),
+                    side.text(lang).to_owned(),
+                ]
+            }),
+        )
+    }
+
+    fn scores(&self, scoring: &ScoringStage, sectors: &Sectors) -> Scores {
         self.data
             .iter()
-            .filter(|x| x.result_allday.unwrap_or(0.0) > 0.0)
-            .count() as f64
+            .filter(|x| !x.is_stale())
+            .filter_map(|x| {
+                scoring
+                    .score(&x.to_dataset_row(sectors))
+                    .map(|score| (x.code.clone(), score))
+            })
+            .collect()
     }
 
-    fn output_for_markdown_resistance_support(
+    fn write_table<'a>(
+        markdown: &mut Markdown,
+        rows: impl Iterator<Item = &'a StocksWindow>,
+        afternoon: bool,
+        sectors: &Sectors,
+        scores: Option<&Scores>,
+        lang: Lang,
+    ) -> Result<(), MyError> {
+        let rows = rows.collect::<Vec<_>>();
+        let results = rows.iter().any(|x| x.has_results());
+        markdown.table(
+            &StocksWindow::table_columns(lang, results, scores.is_some()),
+            rows.iter()
+                .map(|x| x.table_row(afternoon, results, sectors, scores, lang)),
+        )
+    }
+
+    pub fn output_for_markdown_resistance_support(
         &self,
         afternoon: bool,
+        scoring: Option<&ScoringStage>,
+        available_cash: Option<Yen>,
+        lang: Lang,
     ) -> Result<(Markdown, String), MyError> {
+        let _span = profile::span(Stage::Render);
         let (date, title) = match afternoon {
-            true => (self.data[0].result_at.clone().unwrap(), "This afternoon"),
-            false => (self.data[0].analyzed_at.clone(), "Nextday"),
+            true => (
+                self.data.iter().find_map(|x| x.result_at.clone()).unwrap(),
+                Msg::ThisAfternoon,
+            ),
+            false => (self.data[0].analyzed_at.clone(), Msg::Nextday),
         };
 
-        let len = self.data.len() as f64;
-
-        let resistance = self.get_resistance_candles_top10();
-        let support = self.get_support_candles_top10();
-
+        let sectors = stocks_master::load_sectors();
+        let scores = scoring.map(|x| self.scores(x, &sectors));
+        let flows = trades_spec::load_latest();
+        let correlated = self.correlated_pairs().unwrap_or_else(|| {
+            warn!("no correlations of {}", self.data[0].analyzed_at);
+            Vec::new()
+        });
+        for (a, b, rho) in &correlated {
+            warn!("{} and {} correlated {}", a, b, rho);
+        }
         let mut markdown = Markdown::new();
-        markdown.h1(&date)?;
-        markdown.h2(title)?;
-
-        markdown.h3("Summary")?;
-        markdown.body(&format!("Number of Stocks: {}", len))?;
-        markdown.body(&format!(
-            "Morning Gainers: {}%",
-            (self.number_of_morning_gainers() / len * 100.0).round()
-        ))?;
-        markdown.body(&format!(
-            "Afternoon Gainers: {}%",
-            (self.number_of_afternoon_gainers() / len * 100.0).round()
-        ))?;
-        markdown.body(&format!(
-            "Allday Gainers: {}%",
-            (self.number_of_allday_gainers() / len * 100.0).round()
-        ))?;
-
-        markdown.h3("Resistance Candles Top 10")?;
-
-        for resistance_row in resistance.data {
-            match afternoon {
-                true => {
-                    markdown.body(&resistance_row.markdown_body_output_for_resistance(true)?)?
+        // 20 rows of about 300 bytes
+        markdown.reserve(8 * 1024);
+        markdown.section(&date, |m| {
+            m.section(title.text(lang), |m| {
+                if !afternoon {
+                    m.section(Msg::SectorHeat.text(lang), |m| {
+                        self.write_sector_heat(m, &sectors, lang)
+                    })?;
                 }
-                false => {
-                    markdown.body(&resistance_row.markdown_body_output_for_resistance_default()?)?
+                m.section(Msg::Summary.text(lang), |m| {
+                    for line in self.summary(scoring, lang) {
+                        m.body(&line)?;
+                    }
+                    Ok(())
+                })?;
+                if let Some(flows) = &flows {
+                    m.section(Msg::InvestorFlows.text(lang), |m| {
+                        for line in flows.lines(lang) {
+                            m.body(&line)?;
+                        }
+                        Ok(())
+                    })?;
                 }
-            }
-        }
-        markdown.h3("Support Candles Top 10")?;
-        for support_row in support.data {
-            match afternoon {
-                true => markdown.body(&support_row.markdown_body_output_for_resistance(true)?)?,
-                false => {
-                    markdown.body(&support_row.markdown_body_output_for_resistance_default()?)?
+                m.section(Msg::ResistanceTop10.text(lang), |m| {
+                    Self::write_table(
+                        m,
+                        self.get_resistance_candles_top10(),
+                        afternoon,
+                        &sectors,
+                        scores.as_ref(),
+                        lang,
+                    )
+                })?;
+                m.section(Msg::SupportTop10.text(lang), |m| {
+                    Self::write_table(
+                        m,
+                        self.get_support_candles_top10(),
+                        afternoon,
+                        &sectors,
+                        scores.as_ref(),
+                        lang,
+                    )
+                })?;
+                if !correlated.is_empty() {
+                    m.section(Msg::CorrelatedPairs.text(lang), |m| {
+                        m.table(
+                            &[Column::left("A"), Column::left("B"), Column::right("ρ")],
+                            correlated.iter().map(|(a, b, rho)| {
+                                [
+                                    code_link(a).to_string(),
+                                    code_link(b).to_string(),
+                                    rho.to_string(),
+                                ]
+                            }),
+                        )
+                    })?;
                 }
-            }
-        }
+                if let Some(topix_return) = self.topix_return_20() {
+                    m.section(Msg::RsTop10.text(lang), |m| {
+                        let tape = match topix_return >= 0.0 {
+                            true => Msg::StrongTape,
+                            false => Msg::WeakTape,
+                        };
+                        m.body(&format!("TOPIX {:+}%, {}", topix_return, tape.text(lang)))?;
+                        Self::write_table(
+                            m,
+                            self.get_rs_top10(),
+                            afternoon,
+                            &sectors,
+                            scores.as_ref(),
+                            lang,
+                        )
+                    })?;
+                }
+                if !afternoon {
+                    m.section(Msg::UnfilledGaps.text(lang), |m| self.write_gaps(m, lang))?;
+                }
+                match (afternoon, available_cash) {
+                    (false, Some(available_cash)) => m.section(Msg::Capacity.text(lang), |m| {
+                        let plan = capacity::plan(
+                            &self.capacity_candidates(scores.as_ref()),
+                            available_cash,
+                        );
+                        Self::write_capacity(m, &plan, available_cash, lang)
+                    }),
+                    _ => Ok(()),
+                }
+            })
+        })?;
 
         debug!("{}", markdown.buffer());
 
         Ok((markdown, date))
     }
 
-    pub fn for_resistance_strategy(&self, consolidating: bool) -> Result<(), MyError> {
+    /// Adds today's top 10 to the per-code history pages
+    fn update_code_histories(&self, lang: Lang) -> Result<(), MyError> {
+        let conn = crate::database::stocks_ohlc::open_db()?;
+        let resistance = self.get_resistance_candles_top10().collect::<Vec<_>>();
+        let support = self.get_support_candles_top10().collect::<Vec<_>>();
+        for (kind, list) in [
+            (CandidateKind::Resistance, resistance),
+            (CandidateKind::Support, support),
+        ] {
+            for stocks_window in list.into_iter().filter(|x| !x.is_stale()) {
+                let mut history = CodeHistory::load(&stocks_window.code, &stocks_window.name)?;
+                history.merge(stocks_window.to_appearance(kind));
+                history.write(&conn, lang)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// One report per analysis date, for `output_sink::dispatch_all`
+    pub fn for_resistance_strategy(&self, consolidating: bool) -> Result<Vec<Report>, MyError> {
+        self.reports(consolidating, true)
+    }
+    pub fn for_resistance_strategy_default(&self) -> Result<Vec<Report>, MyError> {
+        self.for_resistance_strategy(false)
+    }
+
+    /// The resistance report held as a draft, the code pages are left as they are until
+    /// a report is published without `--draft`
+    pub fn for_resistance_strategy_draft(&self) -> Result<Vec<Report>, MyError> {
+        self.reports(false, false)
+    }
+
+    /// The resistance and consolidating reports without updating the code pages,
+    /// for `trading23 stocks asof`
+    pub fn as_of_reports(&self) -> Result<Vec<Report>, MyError> {
+        let mut reports = self.reports(false, false)?;
+        reports.extend(self.reports(true, false)?);
+        Ok(reports)
+    }
+
+    /// `code_histories` updates the code pages with the resistance report
+    fn reports(&self, consolidating: bool, code_histories: bool) -> Result<Vec<Report>, MyError> {
+        let lang = Lang::from_config();
+        let config = GdriveJson::new()?;
+        let earnings_window = config.earnings_window_days();
+        let min_turnover = config.min_turnover();
+        let available_cash = config.available_cash();
+        let consolidation_filter = config.consolidation_filter();
+        let calendar = match earnings_window {
+            Some(_) => ResultsCalendar::select(&statements::open_db()?)?,
+            None => ResultsCalendar::default(),
+        };
+        let scoring = ScoringStage::from_config();
+        let blacklist = blacklist::load();
+        let events = corporate_events::load();
         let mut date_to_stocks: HashMap<_, Vec<_>> = HashMap::new();
+        let mut reports = Vec::new();
 
         for stocks_window in &self.data {
             date_to_stocks
                 .entry(stocks_window.analyzed_at.clone())
                 .or_default()
-                .push(stocks_window.clone());
+                .push(Arc::clone(stocks_window));
         }
 
-        for (_, stocks_window_list) in date_to_stocks {
+        for (date, stocks_window_list) in date_to_stocks {
             let mut stocks_window_list = StocksWindowList::from(stocks_window_list);
-            stocks_window_list.filter_by_standardized_diff(0.12);
+            stocks_window_list.correlations = self.correlations.clone().filter(|(x, _)| *x == date);
+            stocks_window_list.filter_by_blacklist(&blacklist);
+            stocks_window_list.filter_by_corporate_events(&events);
+            let illiquid = stocks_window_list.filter_by_turnover(min_turnover);
+            if !illiquid.is_empty() {
+                let illiquid = illiquid.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+                info!("turnover below {}: {}", min_turnover, illiquid.join(","));
+            }
+            stocks_window_list.filter_by_atr_change(MAX_ATR_CHANGE);
+            stocks_window_list.filter_by_consolidation(consolidation_filter);
             if consolidating {
                 stocks_window_list.filter_by_latest_move(0.25);
             }
+            if let Some(days) = earnings_window {
+                stocks_window_list.filter_by_earnings(&calendar, days, lang);
+            }
+            // strict filters (e.g. --macd-cross) may leave nothing on a date
+            if stocks_window_list.data.is_empty() {
+                warn!("{}: no candidates left", date);
+                continue;
+            }
 
-            let (markdown, analyzed_at) =
-                stocks_window_list.output_for_markdown_resistance_support(false)?;
-            let path = match consolidating {
-                true => {
-                    crate::my_file_io::get_jquants_path(JquantsStyle::Consolidating, &analyzed_at)?
-                }
-                false => {
-                    crate::my_file_io::get_jquants_path(JquantsStyle::Resistance, &analyzed_at)?
-                }
+            let (markdown, analyzed_at) = stocks_window_list
+                .output_for_markdown_resistance_support(
+                    false,
+                    scoring.as_ref(),
+                    available_cash,
+                    lang,
+                )?;
+            let kind = match consolidating {
+                true => CONSOLIDATING,
+                false => RESISTANCE,
             };
-            info!("{}", path.display());
-            markdown.write_to_html(&path)?;
+            if !consolidating && code_histories {
+                stocks_window_list.update_code_histories(lang)?;
+            }
+            reports.push(
+                Report::new(
+                    kind,
+                    &analyzed_at,
+                    &format!("{} {}", analyzed_at, Msg::Nextday.text(lang)),
+                    stocks_window_list.summary(scoring.as_ref(), lang),
+                    markdown,
+                )
+                .with_signals(stocks_window_list.signals()?),
+            );
         }
 
-        Ok(())
-    }
-    pub fn for_resistance_strategy_default(&self) -> Result<(), MyError> {
-        self.for_resistance_strategy(false)
+        Ok(reports)
     }
 }
 
 pub async fn create_stocks_window_list_db(
+    universe: &Universe,
     from: &str,
     to: &str,
+) -> Result<StocksWindowList, MyError> {
+    create_stocks_window_list_known_at(universe, from, to, None).await
+}
+
+/// The windows of `date` ("YYYY-MM-DD") from the bars stored by the end of that day,
+/// as the nextday run of the day saw them. The results are left out as their bars
+/// came later. The universe, blacklist, events and config are today's
+pub async fn create_stocks_window_list_as_of(
+    universe: &Universe,
+    date: &str,
+) -> Result<StocksWindowList, MyError> {
+    let known_at = format!("{} 23:59:59", date);
+    create_stocks_window_list_known_at(universe, date, date, Some(known_at)).await
+}
+
+async fn create_stocks_window_list_known_at(
+    universe: &Universe,
+    from: &str,
+    to: &str,
+    known_at: Option<String>,
 ) -> Result<StocksWindowList, MyError> {
     async fn inner(
         row: Nikkei225,
         unit: f64,
         from: String,
         to: String,
+        trading_days: Arc<HashSet<String>>,
+        known_at: Option<String>,
     ) -> Result<StocksWindowList, MyError> {
-        let code = row.get_code();
-        let name = row.get_name();
         let conn = crate::database::stocks_ohlc::open_db()?;
-
-        let records = crate::database::stocks_ohlc::select_by_code(&conn, code)?;
-        let mut ohlc_vec: Vec<OhlcPremium> = records
-            .into_iter()
-            .map(|x| x.get_inner())
-            .collect::<Vec<_>>();
-        ohlc_vec.sort_by(|a, b| {
-            let date_a = NaiveDate::parse_from_str(a.get_date(), "%Y-%m-%d").unwrap();
-            let date_b = NaiveDate::parse_from_str(b.get_date(), "%Y-%m-%d").unwrap();
-            date_a.partial_cmp(&date_b).unwrap()
-        });
-        // debug!("{:?}", ohlc_vec);
-        let mut stocks_window_list = StocksWindowList::new();
-        stocks_window_list.push(ohlc_vec, code, name, unit, &from, &to);
-        // debug!("{:?}", ohlc_vec);
+        let stocks_window_list = StocksWindowList::from_db(
+            &conn,
+            row.get_code(),
+            row.get_name(),
+            unit,
+            &from,
+            &to,
+            &trading_days,
+            known_at.as_deref(),
+        )?;
 
         Ok(stocks_window_list)
     }
 
-    let nikkei225 = match load_nikkei225_list() {
+    let nikkei225 = match universe.load() {
         Ok(res) => res,
         Err(e) => {
             error!("{}", e);
             return Err(e);
         }
     };
-    info!("Nikkei225 has been loaded");
+    info!("{} has been loaded", universe);
 
     let config = crate::config::GdriveJson::new()?;
     let unit = config.jquants_unit();
     info!("unit: {}", unit);
 
+    // days any code has a bar, the others are missing them
+    let trading_days = Arc::new(crate::database::stocks_ohlc::select_dates_known_at(
+        &crate::database::stocks_ohlc::open_db()?,
+        from,
+        to,
+        known_at.as_deref(),
+    )?);
+
     let start_time = Instant::now();
 
     let handles = nikkei225
         .into_iter()
-        .map(|row| tokio::spawn(inner(row, unit, from.to_owned(), to.to_owned())))
+        .map(|row| {
+            tokio::spawn(inner(
+                row,
+                unit,
+                from.to_owned(),
+                to.to_owned(),
+                Arc::clone(&trading_days),
+                known_at.clone(),
+            ))
+        })
         .collect::<Vec<_>>();
 
     let results = futures::future::join_all(handles).await;
@@ -655,6 +1747,41 @@ pub async fn create_stocks_window_list_db(
         }
     }
     info!("Elapsed time: {:?}", start_time.elapsed());
+
+    // TOPIX closes are final once stored, so `known_at` doesn't apply
+    let (range_from, _) = window_range(from, to)?;
+    let topix = crate::database::topix_ohlc::select_by_date_range(
+        &crate::database::topix_ohlc::open_db()?,
+        &range_from,
+        to,
+    )?;
+    let topix_returns = indicators::topix_returns(&topix, indicators::RS_PERIOD);
+    if topix_returns.is_empty() {
+        warn!("no topix_ohlc until {}, no relative strength", to);
+    }
+    stocks_daytrading_list.set_topix_returns(&topix_returns);
+
+    let market_regimes = crate::database::market_regime::select_by_date_range(
+        &crate::database::market_regime::open_db()?,
+        from,
+        to,
+    )?;
+    if market_regimes.is_empty() {
+        warn!("no market_regime until {}, the windows are untagged", to);
+    }
+    stocks_daytrading_list.set_market_regimes(&market_regimes);
+
+    // of the universe, for the candidates picked after the filters of the report
+    let codes = stocks_daytrading_list
+        .data
+        .iter()
+        .filter(|x| x.analyzed_at == to && !x.is_stale())
+        .map(|x| x.code.clone())
+        .collect::<Vec<_>>();
+    match correlation::load(&crate::database::stocks_ohlc::open_db()?, &codes, to) {
+        Ok(matrix) => stocks_daytrading_list.set_correlations(to, matrix),
+        Err(e) => warn!("correlations unavailable: {}", e),
+    }
     debug!("{:?}", stocks_daytrading_list);
     Ok(stocks_daytrading_list)
 }
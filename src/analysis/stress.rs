@@ -0,0 +1,223 @@
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate};
+use log::warn;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+use crate::analysis::indicators::AnalysisParams;
+use crate::analysis::live::{Ohlc, OhlcPremium};
+use crate::database::journal::{Position, Side};
+use crate::database::{stocks_ohlc, topix_ohlc};
+use crate::instrument::Instrument;
+use crate::my_error::MyError;
+use crate::rounding::round_dp;
+
+/// TOPIX move of the index gap scenario (%), each stock moving by its beta times it
+pub const INDEX_GAP_PCT: f64 = -3.0;
+/// ATRs each position moves against itself in the adverse move scenario
+pub const ADVERSE_ATR: f64 = 2.0;
+/// Daily returns the beta is estimated from
+pub const BETA_PERIOD: usize = 60;
+/// Calendar days of bars read for `BETA_PERIOD` trading days: five of every seven days,
+/// and a month for the holidays
+const LOOKBACK_DAYS: i64 = BETA_PERIOD as i64 * 7 / 5 + 30;
+
+/// Beta of the stock against the index from (stock, index) daily returns, None for
+/// fewer than two or an index that didn't move
+pub fn beta(returns: &[(f64, f64)]) -> Option<f64> {
+    if returns.len() < 2 {
+        return None;
+    }
+    let n = returns.len() as f64;
+    let mean_stock = returns.iter().map(|x| x.0).sum::<f64>() / n;
+    let mean_index = returns.iter().map(|x| x.1).sum::<f64>() / n;
+    let (covariance, variance) = returns.iter().fold((0.0, 0.0), |(cov, var), (s, i)| {
+        (
+            cov + (s - mean_stock) * (i - mean_index),
+            var + (i - mean_index).powi(2),
+        )
+    });
+    match variance > 0.0 {
+        true => Some(covariance / variance),
+        false => None,
+    }
+}
+
+/// Close-to-close returns of the stock and TOPIX on the days both have a bar and a
+/// bar before it, the last `BETA_PERIOD` of them
+fn daily_returns(stock: &[OhlcPremium], topix: &[Ohlc]) -> Vec<(f64, f64)> {
+    let topix = topix
+        .windows(2)
+        .map(|x| (x[1].get_date(), x[1].get_close() / x[0].get_close() - 1.0))
+        .collect::<HashMap<_, _>>();
+    let returns = stock
+        .windows(2)
+        .filter_map(|x| {
+            let index = topix.get(x[1].get_date())?;
+            Some((x[1].get_close() / x[0].get_close() - 1.0, *index))
+        })
+        .collect::<Vec<_>>();
+    let start = returns.len().saturating_sub(BETA_PERIOD);
+    returns[start..].to_vec()
+}
+
+/// An open stock position under the scenarios, P&L in yen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stress {
+    code: String,
+    side: Side,
+    quantity: f64,
+    /// last close
+    price: f64,
+    atr: f64,
+    /// None when there were too few bars, the gap scenario takes 1
+    beta: Option<f64>,
+    index_gap: f64,
+    adverse_move: f64,
+}
+
+impl Stress {
+    pub fn new(position: &Position, price: f64, atr: f64, beta: Option<f64>) -> Self {
+        let quantity = position.get_quantity();
+        let side = position.get_side();
+        let index_gap =
+            side.sign() * quantity * price * beta.unwrap_or(1.0) * INDEX_GAP_PCT / 100.0;
+        Stress {
+            code: position.get_instrument().code(),
+            side,
+            quantity,
+            price,
+            atr,
+            beta: beta.map(|x| round_dp(x, 2)),
+            index_gap: index_gap.round(),
+            adverse_move: (-ADVERSE_ATR * atr * quantity).round(),
+        }
+    }
+
+    //getters
+    pub fn get_code(&self) -> &str {
+        &self.code
+    }
+    pub fn get_side(&self) -> Side {
+        self.side
+    }
+    pub fn get_quantity(&self) -> f64 {
+        self.quantity
+    }
+    pub fn get_price(&self) -> f64 {
+        self.price
+    }
+    pub fn get_atr(&self) -> f64 {
+        self.atr
+    }
+    pub fn get_beta(&self) -> Option<f64> {
+        self.beta
+    }
+    pub fn get_index_gap(&self) -> f64 {
+        self.index_gap
+    }
+    pub fn get_adverse_move(&self) -> f64 {
+        self.adverse_move
+    }
+}
+
+/// Totals of the index gap and the adverse move scenarios
+pub fn totals(stress: &[Stress]) -> (f64, f64) {
+    stress.iter().fold((0.0, 0.0), |(gap, adverse), x| {
+        (gap + x.index_gap, adverse + x.adverse_move)
+    })
+}
+
+/// The open stock positions of the journal under the scenarios, with the bars until
+/// `date` ("YYYY-MM-DD"). FX positions have no TOPIX beta and are left out, as are
+/// stocks without bars
+pub fn evaluate(
+    conn: &Connection,
+    positions: &[Position],
+    date: &str,
+) -> Result<Vec<Stress>, MyError> {
+    let to = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| MyError::Anyhow(anyhow!("{}: {}", date, e)))?;
+    let from = (to - Duration::days(LOOKBACK_DAYS)).to_string();
+    let topix = topix_ohlc::select_by_date_range(conn, &from, date)?;
+    let atr = AnalysisParams::from_config().atr();
+
+    let mut stress = Vec::new();
+    for position in positions.iter().filter(|x| x.is_open()) {
+        let Instrument::Stock(code) = position.get_instrument() else {
+            continue;
+        };
+        let bars = stocks_ohlc::select_by_code_and_range(conn, code, &from, date)?
+            .into_iter()
+            .map(|x| x.get_inner())
+            .collect::<Vec<_>>();
+        let Some(last) = bars.last() else {
+            warn!("no bars of {} until {}, left out of the stress", code, date);
+            continue;
+        };
+        let returns = daily_returns(&bars, &topix);
+        if returns.len() < BETA_PERIOD {
+            warn!(
+                "beta of {} from {} daily returns only, {} wanted",
+                code,
+                returns.len(),
+                BETA_PERIOD
+            );
+        }
+        let beta = beta(&returns);
+        stress.push(Stress::new(position, last.get_close(), atr.of(&bars), beta));
+    }
+    Ok(stress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_code::StockCode;
+
+    #[test]
+    fn test_beta() {
+        let returns = [(0.02, 0.01), (-0.04, -0.02), (0.006, 0.003)];
+        assert_eq!(beta(&returns).map(|x| round_dp(x, 2)), Some(2.0));
+        assert_eq!(beta(&[(0.01, 0.01)]), None);
+        assert_eq!(beta(&[(0.01, 0.01), (0.02, 0.01)]), None);
+
+        let bar = |date: &str, close: f64| OhlcPremium::test_bar(date, close, close, close, close);
+        let topix = |date: &str, close: f64| Ohlc::new(date.to_owned(), close, close, close, close);
+        let stock = [
+            bar("2024-01-04", 100.0),
+            bar("2024-01-05", 102.0),
+            bar("2024-01-09", 98.0),
+        ];
+        // 01-05 has no TOPIX bar before it
+        let index = [topix("2024-01-05", 2000.0), topix("2024-01-09", 1980.0)];
+        let returns = daily_returns(&stock, &index);
+        assert_eq!(returns.len(), 1);
+        assert_eq!(round_dp(returns[0].0, 4), -0.0392);
+        assert_eq!(round_dp(returns[0].1, 4), -0.01);
+    }
+
+    #[test]
+    fn test_stress() {
+        let position = |side: Side| {
+            Position::new(
+                Instrument::Stock(StockCode::new("7203").unwrap()),
+                side,
+                100.0,
+                2400.0,
+                "2024-01-04",
+                None,
+                None,
+                "",
+            )
+        };
+        let long = Stress::new(&position(Side::Long), 2500.0, 50.0, Some(1.2));
+        // 100 x 2500 x 1.2 x -3%
+        assert_eq!(long.get_index_gap(), -9000.0);
+        assert_eq!(long.get_adverse_move(), -10000.0);
+        let short = Stress::new(&position(Side::Short), 2500.0, 50.0, None);
+        assert_eq!(short.get_index_gap(), 7500.0);
+        assert_eq!(short.get_adverse_move(), -10000.0);
+        assert_eq!(totals(&[long, short]), (-1500.0, -20000.0));
+    }
+}
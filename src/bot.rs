@@ -0,0 +1,533 @@
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Duration, Local};
+use log::{error, info, warn};
+use reqwest::Client;
+use ring::constant_time;
+use ring::hmac::{self, Key, HMAC_SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::analysis::indicators;
+use crate::briefing::Briefing;
+use crate::config::GdriveJson;
+use crate::database::{runs, stocks_ohlc};
+use crate::halt;
+use crate::i18n::{Lang, Msg};
+use crate::jquants::fetcher::FETCH_NIKKEI225;
+use crate::my_error::MyError;
+use crate::rate_limit::Provider;
+use crate::stock_code::StockCode;
+
+/// `bot` of config.json, the chat webhooks `trading23 serve` answers
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BotConfig {
+    /// Channel secret of the LINE Messaging API, checks `X-Line-Signature`
+    #[serde(default)]
+    line_channel_secret: Option<String>,
+    /// Channel access token of the LINE Messaging API, for the replies
+    #[serde(default)]
+    line_channel_access_token: Option<String>,
+    /// `secret_token` of Telegram's setWebhook, checks
+    /// `X-Telegram-Bot-Api-Secret-Token`
+    #[serde(default)]
+    telegram_secret_token: Option<String>,
+    /// LINE user IDs and Telegram user IDs allowed to command, nobody when empty
+    #[serde(default)]
+    allowed_ids: Vec<String>,
+}
+
+/// Bars of the "chart" reply
+const CHART_BARS: usize = 20;
+
+/// What a chat message asks for, the first word without a leading "/"
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// latest bars stored, their coverage and the halt
+    Status,
+    /// the briefing of today
+    Today,
+    /// stops the reports going out, with a reason
+    Halt(String),
+    Resume,
+    /// the closes of the last `CHART_BARS` bars
+    Chart(StockCode),
+    Help,
+}
+
+impl Command {
+    /// Unknown commands and a "chart" without a valid code are `Help`
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let word = word.trim_start_matches('/').to_lowercase();
+        // "/chart@trading23_bot" in Telegram groups
+        let word = word.split('@').next().unwrap_or_default();
+        let rest = rest.trim();
+        match word {
+            "status" => Command::Status,
+            "today" => Command::Today,
+            "halt" => Command::Halt(rest.to_owned()),
+            "resume" => Command::Resume,
+            "chart" => match StockCode::new(rest) {
+                Ok(code) => Command::Chart(code),
+                Err(_) => Command::Help,
+            },
+            _ => Command::Help,
+        }
+    }
+}
+
+/// A text message from the chat
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inbound {
+    /// LINE user ID or Telegram user ID, checked against `allowedIds`
+    sender: String,
+    /// LINE reply token or Telegram chat ID
+    reply_to: String,
+    text: String,
+}
+
+impl Inbound {
+    //getters
+    pub fn get_sender(&self) -> &str {
+        &self.sender
+    }
+    pub fn get_reply_to(&self) -> &str {
+        &self.reply_to
+    }
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// The text messages of a LINE webhook, other events are left out
+pub fn parse_line(body: &[u8]) -> Result<Vec<Inbound>, MyError> {
+    let body: Value = serde_json::from_slice(body)?;
+    let events = body["events"].as_array().cloned().unwrap_or_default();
+    Ok(events
+        .iter()
+        .filter(|x| x["type"] == "message" && x["message"]["type"] == "text")
+        .filter_map(|x| {
+            Some(Inbound {
+                sender: x["source"]["userId"].as_str()?.to_owned(),
+                reply_to: x["replyToken"].as_str()?.to_owned(),
+                text: x["message"]["text"].as_str()?.to_owned(),
+            })
+        })
+        .collect())
+}
+
+/// The text message of a Telegram update, None for other updates
+pub fn parse_telegram(body: &[u8]) -> Result<Option<Inbound>, MyError> {
+    let body: Value = serde_json::from_slice(body)?;
+    let message = &body["message"];
+    let (Some(sender), Some(chat), Some(text)) = (
+        message["from"]["id"].as_i64(),
+        message["chat"]["id"].as_i64(),
+        message["text"].as_str(),
+    ) else {
+        return Ok(None);
+    };
+    Ok(Some(Inbound {
+        sender: sender.to_string(),
+        reply_to: chat.to_string(),
+        text: text.to_owned(),
+    }))
+}
+
+/// `X-Line-Signature` is the base64 HMAC-SHA256 of the body with the channel secret
+pub fn verify_line(secret: &str, body: &[u8], signature: &str) -> bool {
+    let key = Key::new(HMAC_SHA256, secret.as_bytes());
+    match STANDARD.decode(signature.trim()) {
+        Ok(tag) => hmac::verify(&key, body, &tag).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// The reply to `command`, errors are replied too
+pub async fn respond(command: &Command, lang: Lang) -> String {
+    let reply = match command {
+        Command::Status => status(lang),
+        Command::Today => {
+            let today = Local::now().format("%Y-%m-%d").to_string();
+            Ok(Briefing::collect(&today).await.message(lang))
+        }
+        Command::Halt(reason) => {
+            halt::set(reason).map(|x| format!("{}: {}", Msg::HaltSet.text(lang), x))
+        }
+        Command::Resume => halt::clear().map(|halted| match halted {
+            true => Msg::Resumed.text(lang).to_owned(),
+            false => Msg::Running.text(lang).to_owned(),
+        }),
+        Command::Chart(code) => chart(code, lang),
+        Command::Help => Ok(Msg::BotHelp.text(lang).to_owned()),
+    };
+    reply.unwrap_or_else(|e| format!("{}: {}", Msg::Failed.text(lang), e))
+}
+
+fn status(lang: Lang) -> Result<String, MyError> {
+    let mut lines = vec![match halt::current()? {
+        Some(halt) => format!("{}: {}", Msg::Halted.text(lang), halt),
+        None => Msg::Running.text(lang).to_owned(),
+    }];
+    let conn = stocks_ohlc::open_db()?;
+    match stocks_ohlc::select_latest_dates(&conn, "9999-12-31", 1)?.pop() {
+        Some(latest) => {
+            lines.push(format!("{}: {}", Msg::LatestBar.text(lang), latest));
+            if let Some(coverage) =
+                runs::select_latest(&runs::open_db()?, FETCH_NIKKEI225, &latest)?
+            {
+                lines.push(format!("{}: {}", Msg::Coverage.text(lang), coverage));
+            }
+        }
+        None => lines.push(Msg::NoBars.text(lang).to_owned()),
+    }
+    Ok(lines.join("\n"))
+}
+
+fn chart(code: &StockCode, lang: Lang) -> Result<String, MyError> {
+    let to = Local::now().format("%Y-%m-%d").to_string();
+    // enough calendar days for the bars and the RSI over the holidays
+    let from = (Local::now() - Duration::days(90))
+        .format("%Y-%m-%d")
+        .to_string();
+    let ohlc_vec =
+        stocks_ohlc::select_by_code_and_range(&stocks_ohlc::open_db()?, code, &from, &to)?
+            .into_iter()
+            .map(|x| x.get_inner())
+            .collect::<Vec<_>>();
+    let Some(last) = ohlc_vec.last() else {
+        return Ok(format!("{} {}", code, Msg::NoBars.text(lang)));
+    };
+    let bars = &ohlc_vec[ohlc_vec.len().saturating_sub(CHART_BARS)..];
+    let closes = bars.iter().map(|x| x.get_close()).collect::<Vec<_>>();
+    let rsi = indicators::rsi(&ohlc_vec, indicators::RSI_PERIOD)
+        .map_or("-".to_owned(), |x| x.to_string());
+    Ok([
+        format!("{} {}", code, last.get_date()),
+        sparkline(&closes),
+        format!(
+            "O {} H {} L {} C {}",
+            last.get_open(),
+            last.get_high(),
+            last.get_low(),
+            last.get_close()
+        ),
+        format!(
+            "ATR {} RSI {}",
+            indicators::Atr::from_config().of(&ohlc_vec),
+            rsi
+        ),
+    ]
+    .join("\n"))
+}
+
+/// The values as block characters, lowest ▁ to highest █
+pub fn sparkline(values: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = values.iter().cloned().fold(f64::NAN, f64::min);
+    let max = values.iter().cloned().fold(f64::NAN, f64::max);
+    values
+        .iter()
+        .map(|x| match max > min {
+            true => BLOCKS[(((x - min) / (max - min)) * 7.0).round() as usize],
+            false => BLOCKS[3],
+        })
+        .collect()
+}
+
+/// Answers the LINE (POST /line) and Telegram (POST /telegram) webhooks on `port`
+/// until stopped. Commands from senders not in `allowedIds` are ignored
+pub async fn serve(client: &Client, port: u16) -> Result<(), MyError> {
+    let config = GdriveJson::new()?
+        .bot()
+        .cloned()
+        .ok_or_else(|| MyError::Config("bot is not set".to_owned()))?;
+    let config = Arc::new(config);
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    info!("bot listening on {}", listener.local_addr()?);
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let client = client.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &client, &config).await {
+                error!("{}: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// A parsed request, enough for the webhooks
+pub struct Request {
+    method: String,
+    /// with the query
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(x, _)| x.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    //getters
+    pub fn get_method(&self) -> &str {
+        &self.method
+    }
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+    pub fn get_body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+/// Webhook bodies are small, anything bigger is refused
+const MAX_BODY: usize = 1 << 20;
+/// A request not read in full by then is dropped, so idle connections don't pile up
+const READ_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+/// Reads an HTTP/1.1 request of at most `MAX_BODY` bytes within `READ_TIMEOUT`
+pub async fn read_request(stream: &mut TcpStream) -> Result<Request, MyError> {
+    tokio::time::timeout(READ_TIMEOUT, read_request_inner(stream))
+        .await
+        .map_err(|_| MyError::Anyhow(anyhow!("request not read in {:?}", READ_TIMEOUT)))?
+}
+
+async fn read_request_inner(stream: &mut TcpStream) -> Result<Request, MyError> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(MyError::Anyhow(anyhow!("connection closed")));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buf.windows(4).position(|x| x == b"\r\n\r\n") {
+            break i + 4;
+        }
+        if buf.len() > MAX_BODY {
+            return Err(MyError::Anyhow(anyhow!("headers too large")));
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let path = request_line.next().unwrap_or_default().to_owned();
+    let headers = lines
+        .filter_map(|x| x.split_once(':'))
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .collect::<Vec<_>>();
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: buf[header_end..].to_vec(),
+    };
+    let length = request
+        .header("Content-Length")
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(MyError::Anyhow(anyhow!("body of {} bytes", length)));
+    }
+    while request.body.len() < length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        request.body.extend_from_slice(&chunk[..n]);
+    }
+    request.body.truncate(length);
+    Ok(request)
+}
+
+/// Whether the `X-Telegram-Bot-Api-Secret-Token` is the secret, compared in constant time
+pub fn verify_telegram(secret: &str, token: Option<&str>) -> bool {
+    token.is_some_and(|x| {
+        constant_time::verify_slices_are_equal(secret.as_bytes(), x.as_bytes()).is_ok()
+    })
+}
+
+async fn write_response(stream: &mut TcpStream, status: &str, body: &str) -> Result<(), MyError> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+async fn handle(mut stream: TcpStream, client: &Client, config: &BotConfig) -> Result<(), MyError> {
+    let request = read_request(&mut stream).await?;
+    let lang = Lang::from_config();
+    let allowed = |x: &Inbound| {
+        let allowed = config.allowed_ids.contains(&x.sender);
+        if !allowed {
+            warn!("command from {} ignored: {}", x.sender, x.text);
+        }
+        allowed
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/line") => {
+            let (Some(secret), Some(token)) = (
+                &config.line_channel_secret,
+                &config.line_channel_access_token,
+            ) else {
+                return write_response(&mut stream, "404 Not Found", "{}").await;
+            };
+            let signature = request.header("X-Line-Signature").unwrap_or_default();
+            if !verify_line(secret, &request.body, signature) {
+                return write_response(&mut stream, "401 Unauthorized", "{}").await;
+            }
+            // LINE wants the 200 quickly, the replies go through the reply API
+            write_response(&mut stream, "200 OK", "{}").await?;
+            for inbound in parse_line(&request.body)?.iter().filter(|x| allowed(x)) {
+                let reply = respond(&Command::parse(&inbound.text), lang).await;
+                reply_line(client, token, &inbound.reply_to, &reply).await?;
+            }
+            Ok(())
+        }
+        ("POST", "/telegram") => {
+            let Some(secret) = &config.telegram_secret_token else {
+                return write_response(&mut stream, "404 Not Found", "{}").await;
+            };
+            if !verify_telegram(secret, request.header("X-Telegram-Bot-Api-Secret-Token")) {
+                return write_response(&mut stream, "401 Unauthorized", "{}").await;
+            }
+            let body = match parse_telegram(&request.body)?.filter(|x| allowed(x)) {
+                // Telegram takes the reply as the response of the webhook
+                Some(inbound) => json!({
+                    "method": "sendMessage",
+                    "chat_id": inbound.reply_to,
+                    "text": respond(&Command::parse(&inbound.text), lang).await,
+                })
+                .to_string(),
+                None => "{}".to_owned(),
+            };
+            write_response(&mut stream, "200 OK", &body).await
+        }
+        _ => write_response(&mut stream, "404 Not Found", "{}").await,
+    }
+}
+
+async fn reply_line(
+    client: &Client,
+    token: &str,
+    reply_token: &str,
+    text: &str,
+) -> Result<(), MyError> {
+    let _permit = Provider::Line.limiter().acquire().await;
+    let res = client
+        .post("https://api.line.me/v2/bot/message/reply")
+        .bearer_auth(token)
+        .json(&json!({
+            "replyToken": reply_token,
+            "messages": [{"type": "text", "text": text}],
+        }))
+        .send()
+        .await?;
+    let status = res.status();
+    match status.is_success() {
+        true => Ok(()),
+        false => Err(MyError::Anyhow(anyhow!(
+            "LINE reply, status code: {}, {}",
+            status,
+            res.text().await?
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Command::parse("status"), Command::Status);
+        assert_eq!(Command::parse(" /Today "), Command::Today);
+        assert_eq!(
+            Command::parse("halt earnings week"),
+            Command::Halt("earnings week".to_owned())
+        );
+        assert_eq!(Command::parse("halt"), Command::Halt(String::new()));
+        assert_eq!(Command::parse("resume"), Command::Resume);
+        assert_eq!(
+            Command::parse("/chart@trading23_bot 7203"),
+            Command::Chart(StockCode::new("7203").unwrap())
+        );
+        assert_eq!(Command::parse("chart"), Command::Help);
+        assert_eq!(Command::parse("buy 7203"), Command::Help);
+    }
+
+    #[test]
+    fn test_parse_webhooks() {
+        let line = br#"{"destination": "U0", "events": [
+            {"type": "message", "replyToken": "r1", "source": {"type": "user", "userId": "U1"},
+                "message": {"type": "text", "id": "1", "text": "status"}},
+            {"type": "message", "replyToken": "r2", "source": {"type": "user", "userId": "U1"},
+                "message": {"type": "sticker", "id": "2"}},
+            {"type": "follow", "replyToken": "r3", "source": {"type": "user", "userId": "U2"}}
+        ]}"#;
+        let inbound = parse_line(line).unwrap();
+        assert_eq!(inbound.len(), 1);
+        assert_eq!(inbound[0].get_sender(), "U1");
+        assert_eq!(inbound[0].get_reply_to(), "r1");
+        assert_eq!(inbound[0].get_text(), "status");
+
+        let telegram = br#"{"update_id": 1, "message": {"message_id": 2,
+            "from": {"id": 111, "is_bot": false}, "chat": {"id": -222, "type": "group"},
+            "text": "/chart 7203"}}"#;
+        let inbound = parse_telegram(telegram).unwrap().unwrap();
+        assert_eq!(inbound.get_sender(), "111");
+        assert_eq!(inbound.get_reply_to(), "-222");
+        assert_eq!(inbound.get_text(), "/chart 7203");
+        assert!(parse_telegram(br#"{"update_id": 1, "edited_message": {}}"#)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_verify_line() {
+        let body = br#"{"events":[]}"#;
+        let key = Key::new(HMAC_SHA256, b"channel secret");
+        let signature = STANDARD.encode(hmac::sign(&key, body).as_ref());
+        assert!(verify_line("channel secret", body, &signature));
+        assert!(!verify_line("other secret", body, &signature));
+        assert!(!verify_line(
+            "channel secret",
+            br#"{"events":[{}]}"#,
+            &signature
+        ));
+        assert!(!verify_line("channel secret", body, "not base64!"));
+    }
+
+    #[test]
+    fn test_verify_telegram() {
+        assert!(verify_telegram("secret", Some("secret")));
+        assert!(!verify_telegram("secret", Some("secreT")));
+        assert!(!verify_telegram("secret", Some("secret ")));
+        assert!(!verify_telegram("secret", None));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[1.0, 2.0, 3.0, 8.0]), "▁▂▃█");
+        assert_eq!(sparkline(&[5.0, 5.0]), "▄▄");
+        assert_eq!(sparkline(&[]), "");
+    }
+}
@@ -0,0 +1,394 @@
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+
+use crate::analysis::code_history::code_link;
+use crate::analysis::stocks_window::RESISTANCE;
+use crate::analysis::stress::{self, Stress, ADVERSE_ATR, INDEX_GAP_PCT};
+use crate::database::journal;
+use crate::gmo_coin::fx_public::{self, FxScan};
+use crate::i18n::{Lang, Msg};
+use crate::markdown::{Column, Markdown};
+use crate::my_error::MyError;
+use crate::my_file_io::get_draft_dir;
+use crate::output_sink::Report;
+use crate::report_diff::ReportSnapshot;
+use crate::report_kind::ReportKind;
+
+pub const BRIEFING: ReportKind = ReportKind::new("briefing", "briefing");
+
+/// Events within this from the briefing are alerted
+const UPCOMING_HOURS: i64 = 24;
+
+/// The evening's stocks and FX in one page and one message
+#[derive(Debug)]
+pub struct Briefing {
+    date: String,
+    /// summary and candidates of the nextday report, None when it wasn't written
+    nextday: Option<ReportSnapshot>,
+    fx: FxScan,
+    /// dates with drafts waiting for `publish`
+    pending_drafts: Vec<String>,
+    /// open stock positions of the journal under the overnight scenarios
+    stress: Vec<Stress>,
+    at: DateTime<Utc>,
+}
+
+/// The nextday report of `date`, published or still a draft (html only)
+fn load_nextday(date: &str) -> Result<Option<ReportSnapshot>, MyError> {
+    let published = RESISTANCE.path(date)?.with_extension("html");
+    let draft = get_draft_dir(date)?
+        .join(RESISTANCE.dir_name())
+        .with_extension("html");
+    for path in [published, draft] {
+        if path.exists() {
+            return Ok(Some(ReportSnapshot::load(&path)?));
+        }
+    }
+    Ok(None)
+}
+
+/// The open positions of the journal under the scenarios of `stress`
+fn load_stress(date: &str) -> Result<Vec<Stress>, MyError> {
+    let conn = journal::open_db()?;
+    stress::evaluate(&conn, &journal::select_all(&conn)?, date)
+}
+
+impl Briefing {
+    pub fn new(
+        date: &str,
+        nextday: Option<ReportSnapshot>,
+        fx: FxScan,
+        pending_drafts: Vec<String>,
+        at: DateTime<Utc>,
+    ) -> Self {
+        Briefing {
+            date: date.to_owned(),
+            nextday,
+            fx,
+            pending_drafts,
+            stress: Vec::new(),
+            at,
+        }
+    }
+
+    pub fn with_stress(mut self, stress: Vec<Stress>) -> Self {
+        self.stress = stress;
+        self
+    }
+
+    /// Reads the nextday report of `date` ("YYYY-MM-DD") and runs the FX scan
+    pub async fn collect(date: &str) -> Self {
+        let nextday = load_nextday(date).unwrap_or_else(|e| {
+            warn!("nextday report unavailable: {}", e);
+            None
+        });
+        let pending_drafts = match crate::draft::pending() {
+            Ok(pending) => pending.into_keys().collect(),
+            Err(e) => {
+                warn!("drafts unavailable: {}", e);
+                Vec::new()
+            }
+        };
+        let stress = load_stress(date).unwrap_or_else(|e| {
+            warn!("stress unavailable: {}", e);
+            Vec::new()
+        });
+        let fx = fx_public::fetch_gmo_coin_fx().await;
+        Briefing::new(date, nextday, fx, pending_drafts, Utc::now()).with_stress(stress)
+    }
+
+    /// Totals of the scenarios, e.g. "Index gap -3%: ¥-9000 / Adverse move 2ATR: ¥-10000"
+    fn stress_totals(&self, lang: Lang) -> String {
+        let (index_gap, adverse_move) = stress::totals(&self.stress);
+        format!(
+            "{} {}%: {} / {} {}ATR: {}",
+            Msg::IndexGap.text(lang),
+            INDEX_GAP_PCT,
+            lang.yen(index_gap),
+            Msg::AdverseMove.text(lang),
+            ADVERSE_ATR,
+            lang.yen(adverse_move)
+        )
+    }
+
+    /// Net exposure per currency of the open FX positions
+    fn exposure_text(&self, lang: Lang) -> String {
+        self.fx.get_exposure().map_or_else(
+            || Msg::ExposureUnknown.text(lang).to_owned(),
+            |x| x.to_string(),
+        )
+    }
+
+    fn alerts(&self, lang: Lang) -> Vec<String> {
+        let mut alerts = Vec::new();
+        if self.nextday.is_none() {
+            alerts.push(format!(
+                "{}: {}",
+                Msg::Nextday.text(lang),
+                Msg::NoReport.text(lang)
+            ));
+        }
+        for setup in self.fx.get_setups().iter().filter(|x| x.is_skipped()) {
+            alerts.push(format!(
+                "{} {}: {}",
+                setup.get_symbol(),
+                Msg::Skipped.text(lang),
+                setup.get_notes().join(", ")
+            ));
+        }
+        let until = self.at + Duration::hours(UPCOMING_HOURS);
+        for event in self.fx.get_events() {
+            if (self.at..=until).contains(&event.get_at()) {
+                alerts.push(format!("{}: {}", Msg::UpcomingEvent.text(lang), event));
+            }
+        }
+        match self.fx.get_exposure() {
+            Some(exposure) => {
+                for (currency, exposure) in exposure.doubled() {
+                    alerts.push(format!(
+                        "{}: {} {}",
+                        Msg::DoubledExposure.text(lang),
+                        currency,
+                        exposure
+                    ));
+                }
+            }
+            None => alerts.push(Msg::ExposureUnknown.text(lang).to_owned()),
+        }
+        if !self.pending_drafts.is_empty() {
+            alerts.push(format!(
+                "{}: {}",
+                Msg::PendingDrafts.text(lang),
+                self.pending_drafts.join(", ")
+            ));
+        }
+        alerts
+    }
+
+    pub fn to_markdown(&self, lang: Lang) -> Result<Markdown, MyError> {
+        let mut markdown = Markdown::new();
+        let title = format!("{} {}", self.date, Msg::Briefing.text(lang));
+        markdown.section(&title, |m| {
+            m.section(Msg::Alerts.text(lang), |m| {
+                for alert in self.alerts(lang) {
+                    m.body(&format!("- {}", alert))?;
+                }
+                Ok(())
+            })?;
+            m.section(Msg::Nextday.text(lang), |m| match &self.nextday {
+                Some(nextday) => {
+                    for (key, value) in nextday.get_stats() {
+                        m.body(&format!("{}: {}", key, value))?;
+                    }
+                    for (heading, codes) in nextday.get_sections() {
+                        let links = codes
+                            .iter()
+                            .map(|x| code_link(x).to_string())
+                            .collect::<Vec<_>>();
+                        m.body(&format!("{}: {}", heading, links.join(", ")))?;
+                    }
+                    Ok(())
+                }
+                None => m.body(Msg::NoReport.text(lang)),
+            })?;
+            m.section(Msg::FxSetups.text(lang), |m| {
+                m.table(
+                    &[
+                        Column::left(Msg::Symbol.text(lang)),
+                        Column::left(Msg::Signal.text(lang)),
+                        Column::left(Msg::Notes.text(lang)),
+                    ],
+                    self.fx.get_setups().iter().map(|x| {
+                        [
+                            x.get_symbol().to_string(),
+                            x.signal_text(lang),
+                            x.get_notes().join(", "),
+                        ]
+                    }),
+                )
+            })?;
+            m.section(Msg::Positions.text(lang), |m| {
+                m.body(&self.exposure_text(lang))
+            })?;
+            if self.stress.is_empty() {
+                return Ok(());
+            }
+            m.section(Msg::Stress.text(lang), |m| {
+                m.body(&self.stress_totals(lang))?;
+                m.table(
+                    &[
+                        Column::left(Msg::Code.text(lang)),
+                        Column::left("Side"),
+                        Column::right("Qty"),
+                        Column::right(Msg::Price.text(lang)),
+                        Column::right("ATR"),
+                        Column::right("Beta"),
+                        Column::right(Msg::IndexGap.text(lang)),
+                        Column::right(Msg::AdverseMove.text(lang)),
+                    ],
+                    self.stress.iter().map(|x| {
+                        [
+                            x.get_code().to_owned(),
+                            x.get_side().to_string(),
+                            x.get_quantity().to_string(),
+                            x.get_price().to_string(),
+                            x.get_atr().to_string(),
+                            x.get_beta().map_or("-".to_owned(), |x| x.to_string()),
+                            x.get_index_gap().to_string(),
+                            x.get_adverse_move().to_string(),
+                        ]
+                    }),
+                )
+            })
+        })?;
+        Ok(markdown)
+    }
+
+    /// Plain text for LINE, the page has the tables and links
+    pub fn message(&self, lang: Lang) -> String {
+        let mut lines = vec![format!("{} {}", self.date, Msg::Briefing.text(lang))];
+        lines.push(format!("[{}]", Msg::Nextday.text(lang)));
+        match &self.nextday {
+            Some(nextday) => {
+                for (key, value) in nextday.get_stats() {
+                    lines.push(format!("{}: {}", key, value));
+                }
+                for (heading, codes) in nextday.get_sections() {
+                    lines.push(format!("{}: {}", heading, codes.join(", ")));
+                }
+            }
+            None => lines.push(Msg::NoReport.text(lang).to_owned()),
+        }
+        lines.push(format!("[{}]", Msg::FxSetups.text(lang)));
+        for setup in self.fx.get_setups() {
+            lines.push(format!(
+                "{}: {}",
+                setup.get_symbol(),
+                setup.signal_text(lang)
+            ));
+        }
+        lines.push(format!("[{}]", Msg::Positions.text(lang)));
+        lines.push(self.exposure_text(lang));
+        if !self.stress.is_empty() {
+            lines.push(format!("[{}]", Msg::Stress.text(lang)));
+            lines.push(self.stress_totals(lang));
+        }
+        let alerts = self.alerts(lang);
+        if !alerts.is_empty() {
+            lines.push(format!("[{}]", Msg::Alerts.text(lang)));
+            lines.extend(alerts.into_iter().map(|x| format!("- {}", x)));
+        }
+        lines.join("\n")
+    }
+
+    /// The page, with the message as its summary so notifications match `message`
+    pub fn to_report(&self, lang: Lang) -> Result<Report, MyError> {
+        let message = self.message(lang);
+        let mut lines = message.lines().map(str::to_owned);
+        let title = lines.next().unwrap_or_default();
+        Ok(Report::new(
+            BRIEFING,
+            &self.date,
+            &title,
+            lines.collect(),
+            self.to_markdown(lang)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::journal::{Position, Side};
+    use crate::economic_calendar::EconomicEvent;
+    use crate::gmo_coin::exposure::Exposure;
+    use crate::gmo_coin::fx_private::PositionSummary;
+    use crate::gmo_coin::fx_public::{FxSetup, Symbol};
+    use crate::instrument::Instrument;
+    use crate::stock_code::StockCode;
+
+    #[test]
+    fn test_message() {
+        let mut report = Markdown::new();
+        report
+            .section("2024-01-05", |m| {
+                m.section("Summary", |m| m.body("Number of Stocks: 20"))?;
+                m.section("Resistance Candles Top 10", |m| {
+                    m.body("[7203](../../jquants_codes/7203.html)")
+                })
+            })
+            .unwrap();
+        let nextday = ReportSnapshot::from_html(&report.to_html());
+
+        let at = DateTime::parse_from_rfc3339("2024-01-05T09:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let fx = FxScan::new(
+            vec![
+                FxSetup::new(
+                    Symbol::UsdJpy,
+                    "Long 1500 units, stop loss order 144.1",
+                    true,
+                    vec!["spread 2.5 pips (normal 0.2, max x3)".to_owned()],
+                ),
+                FxSetup::new(Symbol::EurUsd, "no breakout", false, Vec::new()),
+            ],
+            Some(
+                Exposure::from_positions(&[PositionSummary::new("USD_JPY", "BUY", 10000.0, 140.0)])
+                    .unwrap(),
+            ),
+            vec![
+                EconomicEvent::new("Non-Farm Employment Change", "USD", at + Duration::hours(4)),
+                EconomicEvent::new("BOJ Policy Rate", "JPY", at - Duration::hours(4)),
+            ],
+        );
+        let position = Position::new(
+            Instrument::Stock(StockCode::new("7203").unwrap()),
+            Side::Long,
+            100.0,
+            2400.0,
+            "2024-01-04",
+            None,
+            None,
+            "",
+        );
+        let briefing = Briefing::new("2024-01-05", Some(nextday), fx, Vec::new(), at)
+            .with_stress(vec![Stress::new(&position, 2500.0, 50.0, Some(1.2))]);
+
+        assert_eq!(
+            briefing.message(Lang::En),
+            "2024-01-05 Daily Briefing\n\
+            [Nextday]\n\
+            Number of Stocks: 20\n\
+            Resistance Candles Top 10: 7203\n\
+            [FX Setups]\n\
+            USD_JPY: Long 1500 units, stop loss order 144.1 (skipped)\n\
+            EUR_USD: no breakout\n\
+            [Positions]\n\
+            JPY -1400000 (USD_JPY Long) / USD +10000 (USD_JPY Long)\n\
+            [Stress]\n\
+            Index gap -3%: ¥-9000 / Adverse move 2ATR: ¥-10000\n\
+            [Alerts]\n\
+            - USD_JPY skipped: spread 2.5 pips (normal 0.2, max x3)\n\
+            - upcoming event: 01-05 22:00 USD Non-Farm Employment Change"
+        );
+        assert!(briefing
+            .to_markdown(Lang::En)
+            .unwrap()
+            .buffer()
+            .contains("| USD_JPY | Long 1500 units, stop loss order 144.1 (skipped) |"));
+        assert!(briefing
+            .to_markdown(Lang::En)
+            .unwrap()
+            .buffer()
+            .contains("| 7203 | Long | 100 | 2500 | 50 | 1.2 | -9000 | -10000 |"));
+        let report = briefing.to_report(Lang::En).unwrap();
+        assert_eq!(report.message(), briefing.message(Lang::En));
+
+        // a failed positionSummary isn't shown as flat
+        let briefing = Briefing::new("2024-01-05", None, FxScan::default(), Vec::new(), at);
+        let message = briefing.message(Lang::En);
+        assert!(message.contains("[Positions]\npositions unknown\n"));
+        assert!(message.ends_with("- positions unknown"));
+    }
+}
@@ -1,8 +1,22 @@
+use chrono::{Duration, Local, NaiveDateTime};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::path::Path;
 
+use crate::analysis::capacity::Margin;
+use crate::analysis::horizons::Horizon;
+use crate::analysis::indicators::{AnalysisParams, AtrMethod, ConsolidationFilter};
+use crate::analysis::scoring::ModelConfig;
+use crate::bot::BotConfig;
+use crate::database::retention::Retention;
+use crate::gmo_coin::fx_public::FxSettings;
+use crate::i18n::Lang;
 use crate::my_error::MyError;
+use crate::output_sink::SinkConfig;
+use crate::pipeline::{Fallback, Step};
+use crate::rate_limit::Provider;
+use crate::units::Yen;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GdriveJson {
@@ -14,20 +28,209 @@ pub struct GdriveJson {
     jquants_refresh_token: String,
     #[serde(rename = "jquantsIdToken")]
     jquants_id_token: String,
+    /// "YYYY-MM-DD HH:MM:SS" the ID token was fetched, it is valid for 24 hours
+    #[serde(rename = "jquantsIdTokenAt", default)]
+    jquants_id_token_at: Option<String>,
     #[serde(rename = "jquantsUnit")]
     jquants_unit: String,
+    /// Base of the J-Quants endpoints, another server (e.g. a mock) when set
+    #[serde(rename = "jquantsApiUrl", default = "default_jquants_api_url")]
+    jquants_api_url: String,
     #[serde(rename = "lineToken")]
     line_token: String,
     #[serde(rename = "gmoCoinFxApiKey")]
     gmo_coin_fx_api_key: String,
     #[serde(rename = "gmoCoinFxApiSecret")]
     gmo_coin_fx_api_secret: String,
+    #[serde(default)]
+    language: Lang,
+    /// Reports are not generated when less of the universe (%) has data
+    #[serde(rename = "minCoverage", default = "default_min_coverage")]
+    min_coverage: f64,
+    /// Report name -> directory under trading23/, see `ReportKind`
+    #[serde(rename = "reportDirs", default)]
+    report_dirs: HashMap<String, String>,
+    /// Symbol ("USD_JPY") -> bars and intervals of the FX analysis
+    #[serde(rename = "fxSymbols", default)]
+    fx_symbols: HashMap<String, FxSettings>,
+    /// Symbol ("USD_JPY") -> usual spread in pips outside news and rollover, the FX
+    /// breakouts are vetoed while the spread is `maxSpreadMultiple` times wider
+    #[serde(rename = "normalSpreadPips", default = "default_normal_spread_pips")]
+    normal_spread_pips: HashMap<String, f64>,
+    /// URL trading23/ is published at, the links of the report feeds. file:// URLs of
+    /// the reports when not set
+    #[serde(rename = "reportsUrl", default)]
+    reports_url: Option<String>,
+    /// Nextday candidates with results due within this many days are dropped, on the
+    /// announced schedule or a quarter after the last results
+    #[serde(rename = "earningsWindowDays", default)]
+    earnings_window_days: Option<i64>,
+    /// Report name -> where it goes, see `Dispatcher::for_kind`
+    #[serde(default)]
+    outputs: HashMap<String, Vec<SinkConfig>>,
+    /// Days back the fetch looks for trading days missing in stocks_ohlc
+    #[serde(rename = "gapLookbackDays", default = "default_gap_lookback_days")]
+    gap_lookback_days: i64,
+    /// Tries of a J-Quants request before giving up on 429s, 5xxs and timeouts
+    #[serde(
+        rename = "jquantsMaxAttempts",
+        default = "default_jquants_max_attempts"
+    )]
+    jquants_max_attempts: u32,
+    /// J-Quants requests in flight at once
+    #[serde(rename = "jquantsConcurrency", default)]
+    jquants_concurrency: Option<usize>,
+    /// Minimum spacing of J-Quants request starts, in milliseconds
+    #[serde(rename = "jquantsIntervalMs", default)]
+    jquants_interval_ms: Option<u64>,
+    /// GMO Coin FX public requests in flight at once
+    #[serde(rename = "gmoConcurrency", default)]
+    gmo_concurrency: Option<usize>,
+    /// Minimum spacing of GMO Coin FX public request starts, in milliseconds
+    #[serde(rename = "gmoIntervalMs", default)]
+    gmo_interval_ms: Option<u64>,
+    /// Notion requests in flight at once
+    #[serde(rename = "notionConcurrency", default)]
+    notion_concurrency: Option<usize>,
+    /// Minimum spacing of Notion request starts, in milliseconds
+    #[serde(rename = "notionIntervalMs", default)]
+    notion_interval_ms: Option<u64>,
+    /// LINE replies of the chat bot in flight at once
+    #[serde(rename = "lineConcurrency", default)]
+    line_concurrency: Option<usize>,
+    /// Minimum spacing of LINE reply starts, in milliseconds
+    #[serde(rename = "lineIntervalMs", default)]
+    line_interval_ms: Option<u64>,
+    /// The afternoon analysis refuses morning data older than this, see `PricesAm::age`
+    #[serde(
+        rename = "pricesAmMaxAgeMinutes",
+        default = "default_prices_am_max_age_minutes"
+    )]
+    prices_am_max_age_minutes: i64,
+    /// Nextday candidates trading less than this (yen, 20-day average) are dropped
+    #[serde(rename = "minTurnover", default = "default_min_turnover")]
+    min_turnover: f64,
+    /// Share (%) of a candidate's 20-day average turnover a position may take, over 0 and
+    /// up to 100. The "Cap" column of the window reports and the strategy totals of
+    /// their summary
+    #[serde(rename = "maxParticipation", default = "default_max_participation")]
+    max_participation: f64,
+    /// Minutes between progress notifications of long fetches, none when not set
+    #[serde(rename = "progressIntervalMinutes", default)]
+    progress_interval_minutes: Option<u64>,
+    /// Scores the nextday candidates when set, see `scoring::ScoringStage`
+    #[serde(default)]
+    model: Option<ModelConfig>,
+    /// Results of the windows analysis shown in the report besides morning, afternoon and
+    /// all day of the next day, which alone are stored, see `horizons::Horizon`
+    #[serde(rename = "resultHorizons", default = "Horizon::defaults")]
+    result_horizons: Vec<Horizon>,
+    /// Yen to put in the nextday candidates each day, the report plans which fit when set
+    #[serde(rename = "availableCash", default)]
+    available_cash: Option<i32>,
+    /// Short candidates require the margin instead of the notional, see `capacity::Margin`
+    #[serde(rename = "shortOnMargin", default)]
+    short_on_margin: bool,
+    #[serde(rename = "marginRate", default = "default_margin_rate")]
+    margin_rate: f64,
+    #[serde(rename = "marginMinimum", default = "default_margin_minimum")]
+    margin_minimum: i32,
+    /// Bars of the ATR of the windows, afternoon and daytrading analyses
+    #[serde(rename = "atrPeriod", default = "default_atr_period")]
+    atr_period: usize,
+    /// "highLow" for the ATR of earlier reports, to compare with
+    #[serde(rename = "atrMethod", default)]
+    atr_method: AtrMethod,
+    /// Bars of the Donchian channel, the last close against the highs and lows of the
+    /// ones before it
+    #[serde(rename = "breakoutPeriod", default = "default_breakout_period")]
+    breakout_period: usize,
+    /// Bars the resistance and support candles, the range and the regime are read from
+    #[serde(rename = "rangePeriod", default = "default_range_period")]
+    range_period: usize,
+    /// "standardizedDiff" for the consolidation filter of earlier reports
+    #[serde(rename = "consolidationFilter", default)]
+    consolidation_filter: ConsolidationFilter,
+    /// The chat webhooks of `trading23 serve`, it refuses to start when not set
+    #[serde(default)]
+    bot: Option<BotConfig>,
+    /// Step -> what its failure does to the job, see `Step::default_fallback`
+    #[serde(default)]
+    degradation: HashMap<Step, Fallback>,
+    /// Days the snapshot and log tables keep, applied by `db --optimize`
+    #[serde(default)]
+    retention: Retention,
+}
+
+fn default_atr_period() -> usize {
+    5
+}
+
+fn default_breakout_period() -> usize {
+    20
+}
+
+fn default_range_period() -> usize {
+    60
+}
+
+fn default_margin_rate() -> f64 {
+    0.3
+}
+
+fn default_margin_minimum() -> i32 {
+    300_000
+}
+
+fn default_jquants_api_url() -> String {
+    "https://api.jquants.com/v1".to_owned()
+}
+
+fn default_min_coverage() -> f64 {
+    90.0
+}
+
+fn default_gap_lookback_days() -> i64 {
+    100
+}
+
+fn default_jquants_max_attempts() -> u32 {
+    4
+}
+
+fn default_prices_am_max_age_minutes() -> i64 {
+    120
+}
+
+fn default_min_turnover() -> f64 {
+    100_000_000.0
+}
+
+fn default_max_participation() -> f64 {
+    1.0
+}
+
+/// The GMO Coin FX price list
+pub fn default_normal_spread_pips() -> HashMap<String, f64> {
+    [
+        ("USD_JPY", 0.2),
+        ("EUR_JPY", 0.5),
+        ("GBP_JPY", 1.0),
+        ("AUD_JPY", 0.7),
+        ("EUR_USD", 0.4),
+        ("GBP_USD", 1.0),
+        ("AUD_USD", 0.8),
+    ]
+    .into_iter()
+    .map(|(symbol, pips)| (symbol.to_owned(), pips))
+    .collect()
 }
 
 impl GdriveJson {
     pub fn new() -> Result<Self, MyError> {
         let file_path = {
-            let gdrive_path = std::env::var("GDRIVE_PATH")?;
+            let gdrive_path = std::env::var("GDRIVE_PATH")
+                .map_err(|_| MyError::Config("GDRIVE_PATH is not set".to_owned()))?;
             Path::new(&gdrive_path)
                 .join("trading23")
                 .join("config.json")
@@ -42,9 +245,11 @@ impl GdriveJson {
                 .output()?;
         };
 
-        let file = File::open(file_path)?;
+        let file = File::open(&file_path)
+            .map_err(|e| MyError::Config(format!("{}: {}", file_path.display(), e)))?;
 
-        let res = serde_json::from_reader(file)?;
+        let res = serde_json::from_reader(file)
+            .map_err(|e| MyError::Config(format!("{}: {}", file_path.display(), e)))?;
         Ok(res)
     }
 
@@ -68,6 +273,13 @@ impl GdriveJson {
     pub fn jquants_id_token(&self) -> &str {
         &self.jquants_id_token
     }
+    /// false when the ID token may have expired, or it is unknown when it was fetched
+    pub fn jquants_id_token_is_fresh(&self) -> bool {
+        self.jquants_id_token_at
+            .as_deref()
+            .and_then(|x| NaiveDateTime::parse_from_str(x, "%Y-%m-%d %H:%M:%S").ok())
+            .is_some_and(|at| Local::now().naive_local() - at < Duration::hours(23))
+    }
     pub fn jquants_refresh_token(&self) -> &str {
         &self.jquants_refresh_token
     }
@@ -77,19 +289,116 @@ impl GdriveJson {
     pub fn jquants_unit(&self) -> f64 {
         self.jquants_unit.parse::<f64>().unwrap()
     }
+    /// `path` ("/prices/daily_quotes") under `jquantsApiUrl`
+    pub fn jquants_url(&self, path: &str) -> String {
+        format!("{}{}", self.jquants_api_url.trim_end_matches('/'), path)
+    }
     pub fn line_token(&self) -> &str {
         &self.line_token
     }
-    pub fn _gmo_coin_fx_api_key(&self) -> &str {
+    pub fn gmo_coin_fx_api_key(&self) -> &str {
         &self.gmo_coin_fx_api_key
     }
-    pub fn _gmo_coin_fx_api_secret(&self) -> &str {
+    pub fn gmo_coin_fx_api_secret(&self) -> &str {
         &self.gmo_coin_fx_api_secret
     }
+    pub fn language(&self) -> Lang {
+        self.language
+    }
+    pub fn min_coverage(&self) -> f64 {
+        self.min_coverage
+    }
+    pub fn report_dirs(&self) -> &HashMap<String, String> {
+        &self.report_dirs
+    }
+    pub fn fx_settings(&self, symbol: &str) -> Option<&FxSettings> {
+        self.fx_symbols.get(symbol)
+    }
+    pub fn normal_spread_pips(&self) -> &HashMap<String, f64> {
+        &self.normal_spread_pips
+    }
+    pub fn reports_url(&self) -> Option<&str> {
+        self.reports_url.as_deref()
+    }
+    pub fn earnings_window_days(&self) -> Option<i64> {
+        self.earnings_window_days
+    }
+    pub fn outputs(&self, name: &str) -> Option<&[SinkConfig]> {
+        self.outputs.get(name).map(|x| x.as_slice())
+    }
+    pub fn gap_lookback_days(&self) -> i64 {
+        self.gap_lookback_days
+    }
+    pub fn jquants_max_attempts(&self) -> u32 {
+        self.jquants_max_attempts
+    }
+    /// Requests in flight and milliseconds between starts, see `Provider::limiter`
+    pub fn rate_limit(&self, provider: Provider) -> (usize, u64) {
+        let (concurrency, interval_ms) = match provider {
+            Provider::JQuants => (self.jquants_concurrency, self.jquants_interval_ms),
+            Provider::Gmo => (self.gmo_concurrency, self.gmo_interval_ms),
+            Provider::Notion => (self.notion_concurrency, self.notion_interval_ms),
+            Provider::Line => (self.line_concurrency, self.line_interval_ms),
+        };
+        let (default_concurrency, default_interval_ms) = provider.default_limits();
+        (
+            concurrency.unwrap_or(default_concurrency),
+            interval_ms.unwrap_or(default_interval_ms),
+        )
+    }
+    pub fn prices_am_max_age_minutes(&self) -> i64 {
+        self.prices_am_max_age_minutes
+    }
+    pub fn min_turnover(&self) -> f64 {
+        self.min_turnover
+    }
+    pub fn max_participation(&self) -> f64 {
+        self.max_participation
+    }
+    pub fn progress_interval_minutes(&self) -> Option<u64> {
+        self.progress_interval_minutes
+    }
+    pub fn model(&self) -> Option<&ModelConfig> {
+        self.model.as_ref()
+    }
+    pub fn result_horizons(&self) -> &[Horizon] {
+        &self.result_horizons
+    }
+    pub fn available_cash(&self) -> Option<Yen> {
+        self.available_cash.map(Yen)
+    }
+    pub fn margin(&self) -> Option<Margin> {
+        match self.short_on_margin {
+            true => Some(Margin::new(self.margin_rate, Yen(self.margin_minimum))),
+            false => None,
+        }
+    }
+    pub fn atr_period(&self) -> usize {
+        self.atr_period
+    }
+    pub fn atr_method(&self) -> AtrMethod {
+        self.atr_method
+    }
+    pub fn analysis_params(&self) -> AnalysisParams {
+        AnalysisParams::new(self.atr_period, self.breakout_period, self.range_period)
+    }
+    pub fn consolidation_filter(&self) -> ConsolidationFilter {
+        self.consolidation_filter
+    }
+    pub fn bot(&self) -> Option<&BotConfig> {
+        self.bot.as_ref()
+    }
+    pub fn degradation(&self) -> &HashMap<Step, Fallback> {
+        &self.degradation
+    }
+    pub fn retention(&self) -> &Retention {
+        &self.retention
+    }
     pub fn set_jquants_refresh_token(&mut self, token: String) {
         self.jquants_refresh_token = token;
     }
     pub fn set_jquants_id_token(&mut self, token: String) {
         self.jquants_id_token = token;
+        self.jquants_id_token_at = Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
     }
 }
@@ -1,2 +1,125 @@
+pub mod blacklist;
+pub mod corporate_events;
+pub mod economic_events;
+pub mod export;
+pub mod import;
+pub mod journal;
+pub mod lease;
+pub mod market_regime;
+pub mod merge;
+pub mod migrations;
+pub mod prices_am;
+pub mod prune;
+pub mod retention;
+pub mod runs;
+pub mod short_selling;
+pub mod statements;
 pub mod stocks;
+pub mod stocks_master;
 pub mod stocks_ohlc;
+pub mod stocks_window;
+pub mod topix_ohlc;
+pub mod trades_spec;
+pub mod trading_calendar;
+
+use rusqlite::Connection;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::{env, path::Path};
+
+use crate::my_error::MyError;
+
+/// Path of the database instead of GDRIVE_PATH/trading23/trading23.sqlite,
+/// `MEMORY` for one that only lasts the run
+pub const DB_ENV: &str = "TRADING23_DB";
+pub const MEMORY: &str = ":memory:";
+
+/// Connections to the one in-memory database of the process. It lives as long as a
+/// connection to it does, so the first is kept open until the end of the run
+fn open_memory() -> Result<Connection, MyError> {
+    static KEEP_ALIVE: OnceLock<Mutex<Connection>> = OnceLock::new();
+    let open = || Connection::open("file:trading23?mode=memory&cache=shared");
+    if KEEP_ALIVE.get().is_none() {
+        let _ = KEEP_ALIVE.set(Mutex::new(open()?));
+    }
+    Ok(open()?)
+}
+
+/// GDRIVE_PATH/trading23/trading23.sqlite or the database of TRADING23_DB, migrated to
+/// the latest schema by the first open of the run
+pub fn open() -> Result<Connection, MyError> {
+    static MIGRATED: AtomicBool = AtomicBool::new(false);
+    let mut conn = match env::var(DB_ENV) {
+        Ok(path) if path == MEMORY => open_memory()?,
+        Ok(path) => Connection::open(path)?,
+        Err(_) => {
+            let gdrive_path = env::var("GDRIVE_PATH")?;
+            Connection::open(
+                Path::new(&gdrive_path)
+                    .join("trading23")
+                    .join("trading23.sqlite"),
+            )?
+        }
+    };
+    if !MIGRATED.load(Ordering::Acquire) {
+        migrations::migrate(&mut conn)?;
+        MIGRATED.store(true, Ordering::Release);
+    }
+    Ok(conn)
+}
+
+/// Refreshes the query planner statistics and compacts trading23.sqlite, returns
+/// the size in bytes before and after
+pub fn optimize(conn: &Connection) -> Result<(i64, i64), MyError> {
+    let size = || -> Result<i64, MyError> {
+        Ok(conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?)
+    };
+    let before = size()?;
+    conn.execute_batch("ANALYZE; VACUUM;")?;
+    let after = size()?;
+    Ok((before, after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_memory() {
+        let conn = open_memory().unwrap();
+        stocks_ohlc::create_table(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO stocks_ohlc (code, date, open, high, low, close, morning_close,
+            afternoon_open, created_at) VALUES ('7203', '2024-01-05', 1, 1, 1, 1, 1, 1, '')",
+            (),
+        )
+        .unwrap();
+        drop(conn);
+
+        // the rows outlive the connection that wrote them
+        let count: i64 = open_memory()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM stocks_ohlc", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_optimize() {
+        let conn = Connection::open_in_memory().unwrap();
+        stocks_ohlc::create_table(&conn).unwrap();
+        optimize(&conn).unwrap();
+        let analyzed: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE name = 'sqlite_stat1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(analyzed, 1);
+    }
+}
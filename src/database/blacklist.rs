@@ -0,0 +1,196 @@
+use chrono::Local;
+use log::warn;
+use rusqlite::Connection;
+use std::fmt::{Display, Formatter};
+
+use crate::{my_error::MyError, stock_code::StockCode};
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS blacklist (
+            code TEXT PRIMARY KEY,
+            reason TEXT NOT NULL,
+            expires_on TEXT,
+            added_at TEXT NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// A code the screening leaves out, e.g. under a tender offer
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlacklistEntry {
+    code: StockCode,
+    reason: String,
+    /// "YYYY-MM-DD", the last day excluded; None until removed
+    expires_on: Option<String>,
+}
+
+impl BlacklistEntry {
+    pub fn new(code: StockCode, reason: &str, expires_on: Option<&str>) -> Self {
+        BlacklistEntry {
+            code,
+            reason: reason.to_owned(),
+            expires_on: expires_on.map(|x| x.to_owned()),
+        }
+    }
+
+    //getters
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
+
+    /// Whether the code is excluded on `date` ("YYYY-MM-DD")
+    pub fn is_active(&self, date: &str) -> bool {
+        self.expires_on
+            .as_deref()
+            .is_none_or(|expires_on| date <= expires_on)
+    }
+}
+
+impl Display for BlacklistEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)?;
+        if !self.reason.is_empty() {
+            write!(f, " {}", self.reason)?;
+        }
+        if let Some(expires_on) = &self.expires_on {
+            write!(f, " (~{})", expires_on)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds the code, or replaces its reason and expiry
+pub fn add(conn: &Connection, entry: &BlacklistEntry) -> Result<(), MyError> {
+    let added_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    conn.execute(
+        "INSERT OR REPLACE INTO blacklist (code, reason, expires_on, added_at)
+        VALUES (?1, ?2, ?3, ?4)",
+        (&entry.code, &entry.reason, &entry.expires_on, &added_at),
+    )?;
+    Ok(())
+}
+
+/// false when the code wasn't in the list
+pub fn remove(conn: &Connection, code: &StockCode) -> Result<bool, MyError> {
+    let removed = conn.execute("DELETE FROM blacklist WHERE code = ?1", [code])?;
+    Ok(removed > 0)
+}
+
+/// Expired entries included, ordered by code
+pub fn select_all(conn: &Connection) -> Result<Vec<BlacklistEntry>, MyError> {
+    let mut stmt = conn.prepare("SELECT code, reason, expires_on FROM blacklist ORDER BY code")?;
+    let rows = stmt.query_map((), |row| {
+        Ok(BlacklistEntry {
+            code: row.get(0)?,
+            reason: row.get(1)?,
+            expires_on: row.get(2)?,
+        })
+    })?;
+    let mut entries = Vec::new();
+    for row in rows {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+/// Entries for the screening, which checks the expiry against each analysis date.
+/// Empty when the table can't be read.
+pub fn load() -> Vec<BlacklistEntry> {
+    match open_db().and_then(|conn| select_all(&conn)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("blacklist unavailable, nothing is excluded: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// The entry excluding `code` on `date`, if any
+pub fn find_active<'a>(
+    entries: &'a [BlacklistEntry],
+    code: &StockCode,
+    date: &str,
+) -> Option<&'a BlacklistEntry> {
+    entries
+        .iter()
+        .find(|x| &x.code == code && x.is_active(date))
+}
+
+/// Drops the rows blacklisted on their date, the filter shared by the screenings.
+/// `key` gives the code and "YYYY-MM-DD" date of a row. Returns the entries that
+/// dropped a row, each once
+pub fn exclude<T>(
+    data: &mut Vec<T>,
+    entries: &[BlacklistEntry],
+    key: impl Fn(&T) -> (&StockCode, &str),
+) -> Vec<BlacklistEntry> {
+    let mut excluded: Vec<BlacklistEntry> = Vec::new();
+    data.retain(|x| {
+        let (code, date) = key(x);
+        match find_active(entries, code, date) {
+            Some(entry) => {
+                if !excluded.contains(entry) {
+                    excluded.push(entry.clone());
+                }
+                false
+            }
+            None => true,
+        }
+    });
+    excluded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blacklist() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let toyota = StockCode::new("7203").unwrap();
+        let sony = StockCode::new("6758").unwrap();
+        add(
+            &conn,
+            &BlacklistEntry::new(toyota.clone(), "TOB", Some("2024-01-31")),
+        )
+        .unwrap();
+        add(&conn, &BlacklistEntry::new(sony.clone(), "illiquid", None)).unwrap();
+        add(&conn, &BlacklistEntry::new(sony.clone(), "", None)).unwrap();
+
+        let entries = select_all(&conn).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].to_string(), "6758");
+        assert_eq!(entries[1].to_string(), "7203 TOB (~2024-01-31)");
+
+        assert!(find_active(&entries, &toyota, "2024-01-31").is_some());
+        assert!(find_active(&entries, &toyota, "2024-02-01").is_none());
+        assert!(find_active(&entries, &sony, "2099-01-01").is_some());
+
+        let mut rows = vec![
+            (toyota.clone(), "2024-01-31"),
+            (toyota.clone(), "2024-02-01"),
+            (sony.clone(), "2024-01-31"),
+            (sony.clone(), "2024-02-01"),
+        ];
+        let excluded = exclude(&mut rows, &entries, |x| (&x.0, x.1));
+        assert_eq!(rows, vec![(toyota.clone(), "2024-02-01")]);
+        // in the order of the rows
+        assert_eq!(excluded.len(), 2);
+        assert_eq!(excluded[0].get_code(), &toyota);
+        assert_eq!(excluded[1].get_code(), &sony);
+
+        assert!(remove(&conn, &sony).unwrap());
+        assert!(!remove(&conn, &sony).unwrap());
+        assert_eq!(select_all(&conn).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,309 @@
+use clap::ValueEnum;
+use log::warn;
+use rusqlite::Connection;
+use std::fmt::{Display, Formatter};
+
+use crate::{my_error::MyError, stock_code::StockCode};
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS corporate_events (
+            id INTEGER PRIMARY KEY,
+            code TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            starts_on TEXT NOT NULL,
+            ends_on TEXT,
+            note TEXT NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum EventKind {
+    /// tender offer, the price is pinned to the offer
+    Tob,
+    /// management buyout, a tender offer followed by delisting
+    Mbo,
+    /// public offering or large secondary distribution
+    Offering,
+}
+
+impl EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Tob => "tob",
+            EventKind::Mbo => "mbo",
+            EventKind::Offering => "offering",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "tob" => Some(EventKind::Tob),
+            "mbo" => Some(EventKind::Mbo),
+            "offering" => Some(EventKind::Offering),
+            _ => None,
+        }
+    }
+
+    /// Breakouts mean nothing under a tender offer, an offering is only flagged
+    pub fn excludes(&self) -> bool {
+        match self {
+            EventKind::Tob | EventKind::Mbo => true,
+            EventKind::Offering => false,
+        }
+    }
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventKind::Tob => write!(f, "TOB"),
+            EventKind::Mbo => write!(f, "MBO"),
+            EventKind::Offering => write!(f, "offering"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorporateEvent {
+    id: i64,
+    code: StockCode,
+    kind: EventKind,
+    /// "YYYY-MM-DD", usually the announcement
+    starts_on: String,
+    /// "YYYY-MM-DD", e.g. the end of the tender offer period; None until removed
+    ends_on: Option<String>,
+    note: String,
+}
+
+impl CorporateEvent {
+    pub fn new(
+        code: StockCode,
+        kind: EventKind,
+        starts_on: &str,
+        ends_on: Option<&str>,
+        note: &str,
+    ) -> Self {
+        CorporateEvent {
+            id: 0,
+            code,
+            kind,
+            starts_on: starts_on.to_owned(),
+            ends_on: ends_on.map(|x| x.to_owned()),
+            note: note.to_owned(),
+        }
+    }
+
+    //getters
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
+    pub fn get_kind(&self) -> EventKind {
+        self.kind
+    }
+
+    /// Whether the event is going on on `date` ("YYYY-MM-DD")
+    pub fn is_active(&self, date: &str) -> bool {
+        self.starts_on.as_str() <= date
+            && self
+                .ends_on
+                .as_deref()
+                .is_none_or(|ends_on| date <= ends_on)
+    }
+}
+
+impl Display for CorporateEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code, self.kind)?;
+        if !self.note.is_empty() {
+            write!(f, " {}", self.note)?;
+        }
+        match &self.ends_on {
+            Some(ends_on) => write!(f, " ({}~{})", self.starts_on, ends_on),
+            None => write!(f, " ({}~)", self.starts_on),
+        }
+    }
+}
+
+/// Returns the id of the event
+pub fn insert(conn: &Connection, event: &CorporateEvent) -> Result<i64, MyError> {
+    conn.execute(
+        "INSERT INTO corporate_events (code, kind, starts_on, ends_on, note)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        (
+            &event.code,
+            event.kind.as_str(),
+            &event.starts_on,
+            &event.ends_on,
+            &event.note,
+        ),
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// false when there was no such event
+pub fn remove(conn: &Connection, id: i64) -> Result<bool, MyError> {
+    let removed = conn.execute("DELETE FROM corporate_events WHERE id = ?1", [id])?;
+    Ok(removed > 0)
+}
+
+/// Ended events included, newest first. Rows of unknown kinds are skipped.
+pub fn select_all(conn: &Connection) -> Result<Vec<CorporateEvent>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, code, kind, starts_on, ends_on, note FROM corporate_events
+        ORDER BY starts_on DESC, id DESC",
+    )?;
+    let rows = stmt.query_map((), |row| {
+        let kind: String = row.get(2)?;
+        let Some(kind) = EventKind::parse(&kind) else {
+            return Ok(None);
+        };
+        Ok(Some(CorporateEvent {
+            id: row.get(0)?,
+            code: row.get(1)?,
+            kind,
+            starts_on: row.get(3)?,
+            ends_on: row.get(4)?,
+            note: row.get(5)?,
+        }))
+    })?;
+    let mut events = Vec::new();
+    for row in rows {
+        if let Some(event) = row? {
+            events.push(event);
+        }
+    }
+    Ok(events)
+}
+
+/// Events for the screening, empty when the table can't be read
+pub fn load() -> Vec<CorporateEvent> {
+    match open_db().and_then(|conn| select_all(&conn)) {
+        Ok(events) => events,
+        Err(e) => {
+            warn!("corporate_events unavailable, nothing is flagged: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// The event of `code` going on on `date`, if any; excluding events come first
+pub fn find_active<'a>(
+    events: &'a [CorporateEvent],
+    code: &StockCode,
+    date: &str,
+) -> Option<&'a CorporateEvent> {
+    let mut active = events
+        .iter()
+        .filter(|x| &x.code == code && x.is_active(date));
+    let first = active.next()?;
+    match first.kind.excludes() {
+        true => Some(first),
+        false => Some(active.find(|x| x.kind.excludes()).unwrap_or(first)),
+    }
+}
+
+/// Drops the rows under a tender offer on their date and keeps those under an
+/// offering, the filter shared by the screenings. `key` gives the code and
+/// "YYYY-MM-DD" date of a row. Returns the events that dropped a row and those that
+/// flagged one, each once
+pub fn exclude<T>(
+    data: &mut Vec<T>,
+    events: &[CorporateEvent],
+    key: impl Fn(&T) -> (&StockCode, &str),
+) -> (Vec<CorporateEvent>, Vec<CorporateEvent>) {
+    let mut excluded: Vec<CorporateEvent> = Vec::new();
+    let mut flagged: Vec<CorporateEvent> = Vec::new();
+    data.retain(|x| {
+        let (code, date) = key(x);
+        let Some(event) = find_active(events, code, date) else {
+            return true;
+        };
+        let (list, keep) = match event.kind.excludes() {
+            true => (&mut excluded, false),
+            false => (&mut flagged, true),
+        };
+        if !list.contains(event) {
+            list.push(event.clone());
+        }
+        keep
+    });
+    (excluded, flagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corporate_events() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let code = StockCode::new("4755").unwrap();
+        insert(
+            &conn,
+            &CorporateEvent::new(
+                code.clone(),
+                EventKind::Offering,
+                "2024-01-10",
+                Some("2024-01-20"),
+                "",
+            ),
+        )
+        .unwrap();
+        let id = insert(
+            &conn,
+            &CorporateEvent::new(
+                code.clone(),
+                EventKind::Tob,
+                "2024-01-15",
+                Some("2024-02-15"),
+                "¥2000",
+            ),
+        )
+        .unwrap();
+
+        let events = select_all(&conn).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].get_id(), id);
+        assert_eq!(
+            events[0].to_string(),
+            "4755 TOB ¥2000 (2024-01-15~2024-02-15)"
+        );
+
+        let kind = |date| find_active(&events, &code, date).map(|x| x.get_kind());
+        assert_eq!(kind("2024-01-09"), None);
+        assert_eq!(kind("2024-01-12"), Some(EventKind::Offering));
+        assert_eq!(kind("2024-01-16"), Some(EventKind::Tob));
+        assert_eq!(kind("2024-02-16"), None);
+
+        let mut rows = vec![
+            (code.clone(), "2024-01-12"),
+            (code.clone(), "2024-01-13"),
+            (code.clone(), "2024-01-16"),
+            (code.clone(), "2024-02-16"),
+        ];
+        let (excluded, flagged) = exclude(&mut rows, &events, |x| (&x.0, x.1));
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2].1, "2024-02-16");
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(excluded[0].get_kind(), EventKind::Tob);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].get_kind(), EventKind::Offering);
+
+        assert!(remove(&conn, id).unwrap());
+        assert!(!remove(&conn, id).unwrap());
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::Connection;
+
+use crate::economic_calendar::EconomicEvent;
+use crate::my_error::MyError;
+
+/// UTC, sortable as text
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS economic_events (
+            id INTEGER PRIMARY KEY,
+            currency TEXT NOT NULL,
+            at TEXT NOT NULL,
+            title TEXT NOT NULL,
+            UNIQUE(currency, at, title))",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Events already stored are ignored
+pub fn insert(conn: &Connection, events: &[EconomicEvent]) -> Result<(), MyError> {
+    let mut stmt = conn.prepare(
+        "INSERT OR IGNORE INTO economic_events (currency, at, title) VALUES (?1, ?2, ?3)",
+    )?;
+    for event in events {
+        stmt.execute((
+            event.get_currency(),
+            event.get_at().format(DATE_FORMAT).to_string(),
+            event.get_title(),
+        ))?;
+    }
+    Ok(())
+}
+
+/// Events from `from` to `to`, both included, oldest first
+pub fn select_between(
+    conn: &Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<EconomicEvent>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT currency, at, title FROM economic_events
+        WHERE at BETWEEN ?1 AND ?2 ORDER BY at",
+    )?;
+    let rows = stmt.query_map(
+        [
+            from.format(DATE_FORMAT).to_string(),
+            to.format(DATE_FORMAT).to_string(),
+        ],
+        |row| {
+            let currency: String = row.get(0)?;
+            let at: String = row.get(1)?;
+            let title: String = row.get(2)?;
+            Ok((currency, at, title))
+        },
+    )?;
+
+    let mut events = Vec::new();
+    for row in rows {
+        let (currency, at, title) = row?;
+        let at = NaiveDateTime::parse_from_str(&at, DATE_FORMAT)
+            .map_err(|e| MyError::Anyhow(anyhow!("economic_events.at {}: {}", at, e)))?
+            .and_utc();
+        events.push(EconomicEvent::new(&title, &currency, at));
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_insert_and_select() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let at = DateTime::parse_from_rfc3339("2024-01-05T13:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let nfp = EconomicEvent::new("Non-Farm Employment Change", "USD", at);
+        let boj = EconomicEvent::new("BOJ Policy Rate", "JPY", at + Duration::days(18));
+        insert(&conn, &[nfp.clone(), boj.clone()]).unwrap();
+        insert(&conn, std::slice::from_ref(&nfp)).unwrap();
+
+        let events =
+            select_between(&conn, at - Duration::hours(1), at + Duration::days(30)).unwrap();
+        assert_eq!(events, vec![nfp.clone(), boj]);
+        let events = select_between(&conn, at, at).unwrap();
+        assert_eq!(events, vec![nfp]);
+    }
+}
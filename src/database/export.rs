@@ -0,0 +1,228 @@
+use clap::ValueEnum;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use rusqlite::Connection;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::stocks_ohlc;
+use crate::analysis::live::OhlcPremium;
+use crate::my_error::MyError;
+use crate::stock_code::StockCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Bars as csv with a header line, the columns of stocks_ohlc without id and created_at
+pub fn write_csv<W: Write>(bars: &[OhlcPremium], writer: W) -> Result<(), MyError> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    for bar in bars {
+        wtr.serialize(bar)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+const PARQUET_SCHEMA: &str = "
+message stocks_ohlc {
+    REQUIRED BYTE_ARRAY code (UTF8);
+    REQUIRED BYTE_ARRAY date (UTF8);
+    REQUIRED DOUBLE open;
+    REQUIRED DOUBLE high;
+    REQUIRED DOUBLE low;
+    REQUIRED DOUBLE close;
+    REQUIRED DOUBLE morning_close;
+    REQUIRED DOUBLE afternoon_open;
+    OPTIONAL DOUBLE volume;
+    OPTIONAL DOUBLE turnover;
+}";
+
+/// Bars as one uncompressed row group with the columns of `write_csv`
+pub fn write_parquet<W: Write + Send>(bars: &[OhlcPremium], writer: W) -> Result<(), MyError> {
+    write_parquet_with(bars, writer, Compression::UNCOMPRESSED)
+}
+
+/// `write_parquet` compressed with zstd, for archives kept rather than read by other tools
+pub fn write_parquet_compressed<W: Write + Send>(
+    bars: &[OhlcPremium],
+    writer: W,
+) -> Result<(), MyError> {
+    write_parquet_with(bars, writer, Compression::ZSTD(ZstdLevel::default()))
+}
+
+fn write_parquet_with<W: Write + Send>(
+    bars: &[OhlcPremium],
+    writer: W,
+    compression: Compression,
+) -> Result<(), MyError> {
+    let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+    let props = Arc::new(
+        WriterProperties::builder()
+            .set_compression(compression)
+            .build(),
+    );
+    let mut writer = SerializedFileWriter::new(writer, schema, props)?;
+    let mut row_group = writer.next_row_group()?;
+
+    let strings = |f: fn(&OhlcPremium) -> String| {
+        bars.iter()
+            .map(|x| ByteArray::from(f(x).as_str()))
+            .collect::<Vec<_>>()
+    };
+    let prices: [fn(&OhlcPremium) -> f64; 6] = [
+        OhlcPremium::get_open,
+        OhlcPremium::get_high,
+        OhlcPremium::get_low,
+        OhlcPremium::get_close,
+        OhlcPremium::get_morning_close,
+        OhlcPremium::get_afternoon_open,
+    ];
+    let liquidity: [fn(&OhlcPremium) -> Option<f64>; 2] =
+        [OhlcPremium::get_volume, OhlcPremium::get_turnover];
+
+    for values in [
+        strings(|x| x.get_code().to_string()),
+        strings(|x| x.get_date().to_owned()),
+    ] {
+        let mut column = row_group.next_column()?.unwrap();
+        column
+            .typed::<ByteArrayType>()
+            .write_batch(&values, None, None)?;
+        column.close()?;
+    }
+    for price in prices {
+        let values = bars.iter().map(price).collect::<Vec<_>>();
+        let mut column = row_group.next_column()?.unwrap();
+        column
+            .typed::<DoubleType>()
+            .write_batch(&values, None, None)?;
+        column.close()?;
+    }
+    for value in liquidity {
+        let values = bars.iter().filter_map(value).collect::<Vec<_>>();
+        let levels = bars
+            .iter()
+            .map(|x| value(x).is_some() as i16)
+            .collect::<Vec<_>>();
+        let mut column = row_group.next_column()?.unwrap();
+        column
+            .typed::<DoubleType>()
+            .write_batch(&values, Some(&levels), None)?;
+        column.close()?;
+    }
+
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Bars of `code` (all codes when None) between `from` and `to` ("YYYY-MM-DD", both
+/// included, open ended when None) to `path`, returns the number of bars
+pub fn export(
+    conn: &Connection,
+    format: ExportFormat,
+    code: Option<&StockCode>,
+    from: Option<&str>,
+    to: Option<&str>,
+    path: &Path,
+) -> Result<usize, MyError> {
+    let bars = stocks_ohlc::select_for_export(conn, code, from, to)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = std::fs::File::create(path)?;
+    match format {
+        ExportFormat::Csv => write_csv(&bars, file)?,
+        ExportFormat::Parquet => write_parquet(&bars, file)?,
+    }
+    Ok(bars.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn test_export() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        stocks_ohlc::create_table(&conn).unwrap();
+        let bar = |code: &str, date: &str, close: f64| {
+            OhlcPremium::new(
+                StockCode::new(code).unwrap(),
+                date.to_owned(),
+                close - 5.0,
+                close + 10.0,
+                close - 10.0,
+                close,
+                close - 2.0,
+                close - 1.0,
+            )
+        };
+        stocks_ohlc::insert_batch(
+            &mut conn,
+            &[
+                bar("7203", "2024-01-04", 2500.0).with_liquidity(Some(1000.0), Some(2.5e6)),
+                bar("7203", "2024-01-05", 2510.0),
+                bar("7203", "2024-01-09", 2520.0),
+                bar("6758", "2024-01-05", 13000.0),
+            ],
+        )
+        .unwrap();
+
+        let toyota = StockCode::new("7203").unwrap();
+        let bars =
+            stocks_ohlc::select_for_export(&conn, Some(&toyota), None, Some("2024-01-05")).unwrap();
+        let mut csv = Vec::new();
+        write_csv(&bars, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "code,date,open,high,low,close,morning_close,afternoon_open,volume,turnover\n\
+            7203,2024-01-04,2495.0,2510.0,2490.0,2500.0,2498.0,2499.0,1000.0,2500000.0\n\
+            7203,2024-01-05,2505.0,2520.0,2500.0,2510.0,2508.0,2509.0,,\n"
+        );
+
+        let dir = std::env::temp_dir().join(format!("trading23_export_{}", std::process::id()));
+        let path = dir.join("stocks_ohlc.parquet");
+        let exported = export(
+            &conn,
+            ExportFormat::Parquet,
+            None,
+            Some("2024-01-05"),
+            None,
+            &path,
+        )
+        .unwrap();
+        assert_eq!(exported, 3);
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+        let rows = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|x| x.unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows[0],
+            "{code: \"6758\", date: \"2024-01-05\", open: 12995.0, high: 13010.0, \
+            low: 12990.0, close: 13000.0, morning_close: 12998.0, afternoon_open: 12999.0, \
+            volume: null, turnover: null}"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use std::io::Read;
+
+use super::stocks_ohlc;
+use crate::analysis::live::OhlcPremium;
+use crate::my_error::MyError;
+
+/// Columns a file needs, volume and turnover may be left out.
+/// The session prices are required since the windows results are read from them
+const REQUIRED_COLUMNS: [&str; 8] = [
+    "code",
+    "date",
+    "open",
+    "high",
+    "low",
+    "close",
+    "morning_close",
+    "afternoon_open",
+];
+
+/// Invalid rows reported by line, the rest are only counted
+const MAX_REPORTED: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportSummary {
+    inserted: usize,
+    duplicates: usize,
+    invalid: usize,
+    /// "line N: reason" of the first invalid rows
+    reported: Vec<String>,
+}
+
+impl ImportSummary {
+    //getters
+    pub fn get_inserted(&self) -> usize {
+        self.inserted
+    }
+    pub fn get_duplicates(&self) -> usize {
+        self.duplicates
+    }
+    pub fn get_invalid(&self) -> usize {
+        self.invalid
+    }
+    pub fn get_reported(&self) -> &[String] {
+        &self.reported
+    }
+
+    fn reject(&mut self, line: u64, reason: impl std::fmt::Display) {
+        self.invalid += 1;
+        if self.reported.len() < MAX_REPORTED {
+            self.reported.push(format!("line {}: {}", line, reason));
+        }
+    }
+}
+
+/// A bar dated "YYYY-MM-DD" with positive prices inside its high and low
+fn validate(bar: &OhlcPremium) -> Result<(), String> {
+    if NaiveDate::parse_from_str(bar.get_date(), "%Y-%m-%d").is_err() {
+        return Err(format!("date {} is not YYYY-MM-DD", bar.get_date()));
+    }
+    let prices = [
+        bar.get_open(),
+        bar.get_close(),
+        bar.get_morning_close(),
+        bar.get_afternoon_open(),
+    ];
+    if prices
+        .iter()
+        .chain([bar.get_high(), bar.get_low()].iter())
+        .any(|x| !x.is_finite() || *x <= 0.0)
+    {
+        return Err("prices must be positive".to_owned());
+    }
+    if prices
+        .iter()
+        .any(|x| *x > bar.get_high() || *x < bar.get_low())
+    {
+        return Err(format!(
+            "prices outside high {} and low {}",
+            bar.get_high(),
+            bar.get_low()
+        ));
+    }
+    Ok(())
+}
+
+/// Bars of a csv with the columns of `export::write_csv` (other columns are ignored).
+/// Fails when a required column is missing, invalid rows are skipped and counted
+pub fn read_csv<R: Read>(reader: R) -> Result<(Vec<OhlcPremium>, ImportSummary), MyError> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let headers = rdr.headers()?.clone();
+    let missing = REQUIRED_COLUMNS
+        .iter()
+        .filter(|x| !headers.iter().any(|header| header == **x))
+        .copied()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(MyError::Anyhow(anyhow!(
+            "missing columns {}",
+            missing.join(", ")
+        )));
+    }
+
+    let mut summary = ImportSummary::default();
+    let mut bars = Vec::new();
+    for record in rdr.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |x| x.line());
+        match record.deserialize::<OhlcPremium>(Some(&headers)) {
+            Ok(bar) => match validate(&bar) {
+                Ok(_) => bars.push(bar),
+                Err(reason) => summary.reject(line, reason),
+            },
+            Err(e) => summary.reject(line, e),
+        }
+    }
+    Ok((bars, summary))
+}
+
+/// Inserts the bars of the csv not in stocks_ohlc yet, in one transaction.
+/// A (code, date) stored already or repeated in the file is a duplicate
+pub fn import<R: Read>(conn: &mut Connection, reader: R) -> Result<ImportSummary, MyError> {
+    let (bars, mut summary) = read_csv(reader)?;
+    let (Some(from), Some(to)) = (
+        bars.iter().map(|x| x.get_date()).min(),
+        bars.iter().map(|x| x.get_date()).max(),
+    ) else {
+        return Ok(summary);
+    };
+    let mut cells = stocks_ohlc::select_cells(conn, from, to)?;
+    let new = bars
+        .iter()
+        .filter(|x| cells.insert((x.get_code().clone(), x.get_date().to_owned())))
+        .cloned()
+        .collect::<Vec<_>>();
+    stocks_ohlc::insert_batch(conn, &new)?;
+    summary.inserted = new.len();
+    summary.duplicates = bars.len() - new.len();
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_code::StockCode;
+
+    #[test]
+    fn test_import() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        stocks_ohlc::create_table(&conn).unwrap();
+        stocks_ohlc::insert(
+            &conn,
+            &OhlcPremium::new(
+                StockCode::new("7203").unwrap(),
+                "2019-01-04".to_owned(),
+                100.0,
+                110.0,
+                90.0,
+                105.0,
+                101.0,
+                102.0,
+            ),
+        )
+        .unwrap();
+
+        let csv = "code,date,open,high,low,close,morning_close,afternoon_open,vendor\n\
+            72030,2019-01-04,100,110,90,105,101,102,x\n\
+            7203,2019-01-07,105,115,95,110,106,107,x\n\
+            7203,2019-01-07,105,115,95,110,106,107,x\n\
+            7203,2019/01/08,105,115,95,110,106,107,x\n\
+            7203,2019-01-09,105,100,95,110,106,107,x\n\
+            ABCD,2019-01-10,105,115,95,110,106,107,x\n\
+            6758,2019-01-10,5000,5100,4900,5050,5010,5020,x\n";
+        let summary = import(&mut conn, csv.as_bytes()).unwrap();
+        assert_eq!(summary.get_inserted(), 2);
+        assert_eq!(summary.get_duplicates(), 2);
+        assert_eq!(summary.get_invalid(), 3);
+        assert_eq!(
+            summary.get_reported()[0],
+            "line 5: date 2019/01/08 is not YYYY-MM-DD"
+        );
+        assert!(summary.get_reported()[1].starts_with("line 6: prices outside"));
+        assert!(summary.get_reported()[2].starts_with("line 7: "));
+
+        let stored = stocks_ohlc::select_for_export(&conn, None, None, None).unwrap();
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored[0].get_code().as_str(), "6758");
+
+        let err = import(&mut conn, "code,date,open,high,low,close\n".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "missing columns morning_close, afternoon_open"
+        );
+    }
+}
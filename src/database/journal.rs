@@ -0,0 +1,463 @@
+use anyhow::anyhow;
+use chrono::Local;
+use clap::ValueEnum;
+use rusqlite::{Connection, OptionalExtension};
+use std::fmt::{Display, Formatter};
+
+use super::stocks_window;
+use crate::instrument::Instrument;
+use crate::my_error::MyError;
+use crate::signal::SignalId;
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+/// Positions taken from the reports and the trades opening and closing them
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS positions (
+            id INTEGER PRIMARY KEY,
+            code TEXT NOT NULL,
+            side TEXT NOT NULL,
+            quantity REAL NOT NULL,
+            entry_price REAL NOT NULL,
+            opened_on TEXT NOT NULL,
+            stop_loss REAL,
+            signal_on TEXT,
+            exit_price REAL,
+            closed_on TEXT,
+            note TEXT NOT NULL,
+            signal_id TEXT)",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS trades (
+            id INTEGER PRIMARY KEY,
+            position_id INTEGER NOT NULL REFERENCES positions (id),
+            action TEXT NOT NULL,
+            price REAL NOT NULL,
+            quantity REAL NOT NULL,
+            traded_on TEXT NOT NULL,
+            created_at TEXT NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Side {
+    Long,
+    Short,
+}
+
+impl Side {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Side::Long => "long",
+            Side::Short => "short",
+        }
+    }
+
+    fn parse(side: &str) -> Option<Self> {
+        match side {
+            "long" => Some(Side::Long),
+            "short" => Some(Side::Short),
+            _ => None,
+        }
+    }
+
+    pub fn sign(&self) -> f64 {
+        match self {
+            Side::Long => 1.0,
+            Side::Short => -1.0,
+        }
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Side::Long => write!(f, "Long"),
+            Side::Short => write!(f, "Short"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Position {
+    id: i64,
+    instrument: Instrument,
+    side: Side,
+    quantity: f64,
+    entry_price: f64,
+    /// "YYYY-MM-DD"
+    opened_on: String,
+    stop_loss: Option<f64>,
+    /// "YYYY-MM-DD", the analysis date of the report the entry came from
+    signal_on: Option<String>,
+    exit_price: Option<f64>,
+    /// "YYYY-MM-DD", None while open
+    closed_on: Option<String>,
+    note: String,
+    /// The ID column of the report the entry came from
+    signal_id: Option<SignalId>,
+}
+
+impl Position {
+    /// A position to `open`, the id is given by the table
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        instrument: Instrument,
+        side: Side,
+        quantity: f64,
+        entry_price: f64,
+        opened_on: &str,
+        stop_loss: Option<f64>,
+        signal_on: Option<&str>,
+        note: &str,
+    ) -> Self {
+        Position {
+            id: 0,
+            instrument,
+            side,
+            quantity,
+            entry_price,
+            opened_on: opened_on.to_owned(),
+            stop_loss,
+            signal_on: signal_on.map(|x| x.to_owned()),
+            exit_price: None,
+            closed_on: None,
+            note: note.to_owned(),
+            signal_id: None,
+        }
+    }
+
+    /// The entry came from the report row of `signal_id`, dated by it
+    pub fn with_signal(mut self, signal_id: SignalId) -> Self {
+        self.signal_on = Some(signal_id.date());
+        self.signal_id = Some(signal_id);
+        self
+    }
+
+    /// The signal is from before the entry and, when it is a stored window, of the
+    /// same code
+    pub fn check_signal(&self, windows: &Connection) -> Result<(), MyError> {
+        let Some(signal_id) = &self.signal_id else {
+            return Ok(());
+        };
+        if signal_id.date() > self.opened_on {
+            return Err(MyError::Anyhow(anyhow!(
+                "signal {} is of {}, after the entry on {}",
+                signal_id,
+                signal_id.date(),
+                self.opened_on
+            )));
+        }
+        match stocks_window::select_by_signal_id(windows, signal_id)? {
+            Some(window) if Instrument::Stock(window.get_code().clone()) != self.instrument => {
+                Err(MyError::Anyhow(anyhow!(
+                    "signal {} is of {}, not {}",
+                    signal_id,
+                    window.get_code(),
+                    self.instrument
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    //getters
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+    pub fn get_instrument(&self) -> &Instrument {
+        &self.instrument
+    }
+    pub fn get_side(&self) -> Side {
+        self.side
+    }
+    pub fn get_quantity(&self) -> f64 {
+        self.quantity
+    }
+    pub fn get_entry_price(&self) -> f64 {
+        self.entry_price
+    }
+    pub fn get_signal_on(&self) -> Option<&str> {
+        self.signal_on.as_deref()
+    }
+    pub fn get_signal_id(&self) -> Option<&SignalId> {
+        self.signal_id.as_ref()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.closed_on.is_none()
+    }
+
+    /// In the quote currency (yen for stocks), None while open
+    pub fn realized_pnl(&self) -> Option<f64> {
+        let exit_price = self.exit_price?;
+        Some((exit_price - self.entry_price) * self.quantity * self.side.sign())
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} x{} @ {}",
+            self.id, self.instrument, self.side, self.quantity, self.entry_price
+        )?;
+        if let Some(stop_loss) = self.stop_loss {
+            write!(f, " stop {}", stop_loss)?;
+        }
+        match (&self.closed_on, self.exit_price, self.realized_pnl()) {
+            (Some(closed_on), Some(exit_price), Some(pnl)) => write!(
+                f,
+                " -> {} ({}~{}) P&L {}",
+                exit_price, self.opened_on, closed_on, pnl
+            )?,
+            _ => write!(f, " ({}~)", self.opened_on)?,
+        }
+        if !self.note.is_empty() {
+            write!(f, " {}", self.note)?;
+        }
+        Ok(())
+    }
+}
+
+fn insert_trade(
+    conn: &Connection,
+    position_id: i64,
+    action: &str,
+    price: f64,
+    quantity: f64,
+    traded_on: &str,
+) -> Result<(), MyError> {
+    let created_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    conn.execute(
+        "INSERT INTO trades (position_id, action, price, quantity, traded_on, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        (position_id, action, price, quantity, traded_on, created_at),
+    )?;
+    Ok(())
+}
+
+/// Records the position and its opening trade, returns the id of the position
+pub fn open(conn: &mut Connection, position: &Position) -> Result<i64, MyError> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO positions
+        (code, side, quantity, entry_price, opened_on, stop_loss, signal_on, note, signal_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        (
+            position.instrument.code(),
+            position.side.as_str(),
+            position.quantity,
+            position.entry_price,
+            &position.opened_on,
+            position.stop_loss,
+            &position.signal_on,
+            &position.note,
+            &position.signal_id,
+        ),
+    )?;
+    let id = tx.last_insert_rowid();
+    insert_trade(
+        &tx,
+        id,
+        "open",
+        position.entry_price,
+        position.quantity,
+        &position.opened_on,
+    )?;
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Closes the whole position at `price` on `closed_on` ("YYYY-MM-DD") and records the trade
+pub fn close(
+    conn: &mut Connection,
+    id: i64,
+    price: f64,
+    closed_on: &str,
+) -> Result<Position, MyError> {
+    let tx = conn.transaction()?;
+    let position = match select_by_id(&tx, id)? {
+        Some(position) if position.is_open() => position,
+        Some(_) => return Err(MyError::Anyhow(anyhow!("position {} is closed", id))),
+        None => return Err(MyError::Anyhow(anyhow!("no position {}", id))),
+    };
+    tx.execute(
+        "UPDATE positions SET exit_price = ?1, closed_on = ?2 WHERE id = ?3",
+        (price, closed_on, id),
+    )?;
+    insert_trade(&tx, id, "close", price, position.quantity, closed_on)?;
+    tx.commit()?;
+    Ok(Position {
+        exit_price: Some(price),
+        closed_on: Some(closed_on.to_owned()),
+        ..position
+    })
+}
+
+const COLUMNS: &str = "id, code, side, quantity, entry_price, opened_on, stop_loss, signal_on,
+    exit_price, closed_on, note, signal_id";
+
+/// A row of an unknown code or side is an error, rather than a position dropped
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Position> {
+    let invalid = |index, e: MyError| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+    };
+    let code: String = row.get(1)?;
+    let instrument = Instrument::parse(&code).map_err(|e| invalid(1, e))?;
+    let side: String = row.get(2)?;
+    let side = Side::parse(&side).ok_or_else(|| {
+        invalid(
+            2,
+            MyError::Anyhow(anyhow!("invalid position side: {}", side)),
+        )
+    })?;
+    Ok(Position {
+        id: row.get(0)?,
+        instrument,
+        side,
+        quantity: row.get(3)?,
+        entry_price: row.get(4)?,
+        opened_on: row.get(5)?,
+        stop_loss: row.get(6)?,
+        signal_on: row.get(7)?,
+        exit_price: row.get(8)?,
+        closed_on: row.get(9)?,
+        note: row.get(10)?,
+        signal_id: row.get(11)?,
+    })
+}
+
+fn select_by_id(conn: &Connection, id: i64) -> Result<Option<Position>, MyError> {
+    let position = conn
+        .query_row(
+            &format!("SELECT {} FROM positions WHERE id = ?1", COLUMNS),
+            [id],
+            from_row,
+        )
+        .optional()?;
+    Ok(position)
+}
+
+/// Closed positions included, newest first
+pub fn select_all(conn: &Connection) -> Result<Vec<Position>, MyError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM positions ORDER BY opened_on DESC, id DESC",
+        COLUMNS
+    ))?;
+    let rows = stmt.query_map((), from_row)?;
+    let mut positions = Vec::new();
+    for row in rows {
+        positions.push(row?);
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_and_close() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let toyota = Position::new(
+            Instrument::parse("7203").unwrap(),
+            Side::Long,
+            100.0,
+            2500.0,
+            "2024-01-05",
+            Some(2450.0),
+            Some("2024-01-04"),
+            "",
+        );
+        let usd_jpy = Position::new(
+            Instrument::parse("USD_JPY").unwrap(),
+            Side::Short,
+            10000.0,
+            145.0,
+            "2024-01-08",
+            None,
+            None,
+            "breakout",
+        );
+        let toyota_id = open(&mut conn, &toyota).unwrap();
+        let usd_jpy_id = open(&mut conn, &usd_jpy).unwrap();
+
+        let closed = close(&mut conn, usd_jpy_id, 144.5, "2024-01-09").unwrap();
+        assert_eq!(closed.realized_pnl(), Some(5000.0));
+        assert!(close(&mut conn, usd_jpy_id, 144.0, "2024-01-10").is_err());
+        assert!(close(&mut conn, 99, 144.0, "2024-01-10").is_err());
+
+        let positions = select_all(&conn).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(
+            positions[0].to_string(),
+            format!(
+                "{} USD_JPY Short x10000 @ 145 -> 144.5 (2024-01-08~2024-01-09) P&L 5000 breakout",
+                usd_jpy_id
+            )
+        );
+        assert!(positions[1].is_open());
+        assert_eq!(positions[1].realized_pnl(), None);
+        assert_eq!(
+            positions[1].to_string(),
+            format!(
+                "{} 7203 Long x100 @ 2500 stop 2450 (2024-01-05~)",
+                toyota_id
+            )
+        );
+
+        let trades: i64 = conn
+            .query_row("SELECT COUNT(*) FROM trades", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(trades, 3);
+
+        conn.execute(
+            "UPDATE positions SET side = 'flat' WHERE id = ?1",
+            [toyota_id],
+        )
+        .unwrap();
+        assert!(select_all(&conn).is_err());
+    }
+
+    #[test]
+    fn test_signal() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+        stocks_window::create_table(&conn).unwrap();
+
+        let code = crate::stock_code::StockCode::new("7203").unwrap();
+        let signal_id = SignalId::new(crate::signal::WINDOW_SOURCE, &code, "2024-01-04").unwrap();
+        let position = |opened_on: &str| {
+            Position::new(
+                Instrument::Stock(code.clone()),
+                Side::Long,
+                100.0,
+                2500.0,
+                opened_on,
+                None,
+                None,
+                "",
+            )
+            .with_signal(signal_id.clone())
+        };
+        assert!(position("2024-01-03").check_signal(&conn).is_err());
+        let position = position("2024-01-05");
+        position.check_signal(&conn).unwrap();
+        assert_eq!(position.get_signal_on(), Some("2024-01-04"));
+
+        open(&mut conn, &position).unwrap();
+        let stored = select_all(&conn).unwrap();
+        assert_eq!(stored[0].get_signal_id(), Some(&signal_id));
+    }
+}
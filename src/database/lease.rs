@@ -0,0 +1,326 @@
+use chrono::{Duration, Local, NaiveDateTime};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration as StdDuration;
+
+use crate::my_error::MyError;
+use crate::my_file_io::get_lease_file_path;
+
+/// Name of this machine in the lease instead of HOSTNAME
+pub const HOST_ENV: &str = "TRADING23_HOST";
+/// A lease is taken over once it is this old, e.g. after a crash
+pub const LEASE_MINUTES: i64 = 180;
+
+const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The machine writing trading23.sqlite. Drive syncs the file but not its locks, so two
+/// machines writing it at once corrupt it; the lease is a file next to it checked before
+/// writing. Drive takes a while to sync the lease too, so runs starting together on two
+/// machines may both go on, `db merge` puts their bars back together
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Lease {
+    host: String,
+    /// the runs of the host sharing it, the last one removes the file
+    holders: Vec<Holder>,
+}
+
+impl Display for Lease {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let holders = self
+            .holders
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "{}: {}", self.host, holders)
+    }
+}
+
+impl Lease {
+    //getters
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+    pub fn get_holders(&self) -> &[Holder] {
+        &self.holders
+    }
+    /// The last expiry of its holders
+    pub fn get_expires_at(&self) -> &str {
+        self.holders
+            .iter()
+            .map(|x| x.expires_at.as_str())
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// A run holding the lease
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Holder {
+    pid: u32,
+    /// the command, e.g. "stocks"
+    job: String,
+    /// "YYYY-MM-DD HH:MM:SS"
+    acquired_at: String,
+    /// renewed while the run goes on
+    expires_at: String,
+}
+
+impl Display for Holder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (pid {}) until {}",
+            self.job, self.pid, self.expires_at
+        )
+    }
+}
+
+impl Holder {
+    //getters
+    pub fn get_job(&self) -> &str {
+        &self.job
+    }
+    pub fn get_expires_at(&self) -> &str {
+        &self.expires_at
+    }
+
+    /// The same run, whatever its renewals
+    fn is(&self, other: &Holder) -> bool {
+        self.pid == other.pid && self.job == other.job && self.acquired_at == other.acquired_at
+    }
+}
+
+/// TRADING23_HOST, HOSTNAME or /etc/hostname
+pub fn host() -> String {
+    std::env::var(HOST_ENV)
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// A run holding the lease of the file at `path`, released when dropped
+#[derive(Debug)]
+pub struct LeaseGuard {
+    path: PathBuf,
+    host: String,
+    holder: Holder,
+    /// stops the renewals when dropped
+    renewer: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl LeaseGuard {
+    //getters
+    pub fn get_host(&self) -> &str {
+        &self.host
+    }
+    pub fn get_holder(&self) -> &Holder {
+        &self.holder
+    }
+
+    /// Renews the lease every third of LEASE_MINUTES until dropped, for runs longer
+    /// than that
+    fn keep_renewed(&mut self) {
+        let (stop, stopped) = channel::<()>();
+        let (path, host, holder) = (self.path.clone(), self.host.clone(), self.holder.clone());
+        let handle = std::thread::spawn(move || {
+            let every = StdDuration::from_secs(LEASE_MINUTES as u64 * 60 / 3);
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(every) {
+                if let Err(e) = renew(&path, &host, &holder, Local::now().naive_local()) {
+                    warn!("renew {} failed: {}", path.display(), e);
+                }
+            }
+        });
+        self.renewer = Some((stop, handle));
+    }
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        if let Some((stop, handle)) = self.renewer.take() {
+            drop(stop);
+            let _ = handle.join();
+        }
+        if let Err(e) = release(&self.path, &self.host, &self.holder) {
+            warn!("release {} failed: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Option<Lease>, MyError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    match serde_json::from_str(&std::fs::read_to_string(path)?) {
+        Ok(lease) => Ok(Some(lease)),
+        Err(e) => {
+            warn!("{} unreadable, taken over: {}", path.display(), e);
+            Ok(None)
+        }
+    }
+}
+
+fn write(path: &Path, lease: &Lease) -> Result<(), MyError> {
+    Ok(std::fs::write(path, serde_json::to_string(lease)?)?)
+}
+
+/// Leases the database to `host` at `now`. Fails while another host holds an unexpired
+/// lease; runs on the same host share it as SQLite locks them locally, each added as a
+/// holder
+pub fn acquire(
+    path: &Path,
+    host: &str,
+    job: &str,
+    now: NaiveDateTime,
+) -> Result<LeaseGuard, MyError> {
+    let now_str = now.format(FORMAT).to_string();
+    let mut lease = match read(path)? {
+        Some(held) if held.host == host => held,
+        Some(held) if held.get_expires_at() > now_str.as_str() => {
+            return Err(MyError::Leased(held.to_string()))
+        }
+        Some(held) => {
+            warn!("lease of {} expired, taken over", held);
+            Lease {
+                host: host.to_owned(),
+                holders: Vec::new(),
+            }
+        }
+        None => Lease {
+            host: host.to_owned(),
+            holders: Vec::new(),
+        },
+    };
+    // runs of this host left by a crash
+    lease.holders.retain(|x| x.expires_at > now_str);
+    let holder = Holder {
+        pid: std::process::id(),
+        job: job.to_owned(),
+        acquired_at: now_str,
+        expires_at: (now + Duration::minutes(LEASE_MINUTES))
+            .format(FORMAT)
+            .to_string(),
+    };
+    lease.holders.push(holder.clone());
+    write(path, &lease)?;
+    Ok(LeaseGuard {
+        path: path.to_owned(),
+        host: host.to_owned(),
+        holder,
+        renewer: None,
+    })
+}
+
+/// Extends the lease of `holder` to LEASE_MINUTES from `now`, unless another host took
+/// it over
+fn renew(path: &Path, host: &str, holder: &Holder, now: NaiveDateTime) -> Result<(), MyError> {
+    let Some(mut lease) = read(path)?.filter(|x| x.host == host) else {
+        return Ok(());
+    };
+    if let Some(held) = lease.holders.iter_mut().find(|x| x.is(holder)) {
+        held.expires_at = (now + Duration::minutes(LEASE_MINUTES))
+            .format(FORMAT)
+            .to_string();
+        write(path, &lease)?;
+    }
+    Ok(())
+}
+
+/// Removes `holder` from the lease, and the file with the last holder
+fn release(path: &Path, host: &str, holder: &Holder) -> Result<(), MyError> {
+    let Some(mut lease) = read(path)?.filter(|x| x.host == host) else {
+        return Ok(());
+    };
+    let Some(position) = lease.holders.iter().position(|x| x.is(holder)) else {
+        return Ok(());
+    };
+    lease.holders.remove(position);
+    match lease.holders.is_empty() {
+        true => std::fs::remove_file(path)?,
+        false => write(path, &lease)?,
+    }
+    Ok(())
+}
+
+/// Leases trading23.sqlite on Drive for `job`, renewed until the run ends. None with
+/// TRADING23_DB (`--db`), a file of this machine only
+pub fn acquire_for(job: &str) -> Result<Option<LeaseGuard>, MyError> {
+    if std::env::var(super::DB_ENV).is_ok() {
+        return Ok(None);
+    }
+    let mut guard = acquire(
+        &get_lease_file_path()?,
+        &host(),
+        job,
+        Local::now().naive_local(),
+    )?;
+    guard.keep_renewed();
+    info!("lease: {} on {}", guard.holder, guard.host);
+    Ok(Some(guard))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire() {
+        let dir = std::env::temp_dir().join(format!("trading23_lease_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trading23.sqlite.lease");
+        let at = |x: &str| NaiveDateTime::parse_from_str(x, FORMAT).unwrap();
+
+        let guard = acquire(&path, "desktop", "stocks", at("2024-01-05 18:00:00")).unwrap();
+        assert_eq!(guard.get_holder().get_expires_at(), "2024-01-05 21:00:00");
+        let err = acquire(&path, "laptop", "db", at("2024-01-05 20:59:59")).unwrap_err();
+        assert!(matches!(err, MyError::Leased(_)));
+        assert!(err.to_string().contains("desktop: stocks"));
+        // the same machine shares it
+        let again = acquire(&path, "desktop", "db", at("2024-01-05 19:00:00")).unwrap();
+        assert_eq!(read(&path).unwrap().unwrap().get_holders().len(), 2);
+        // the shorter run doesn't release the lease of the longer one
+        drop(again);
+        let lease = read(&path).unwrap().unwrap();
+        assert_eq!(lease.get_holders().len(), 1);
+        assert_eq!(lease.get_holders()[0].get_job(), "stocks");
+
+        // renewed past LEASE_MINUTES
+        renew(
+            &path,
+            "desktop",
+            guard.get_holder(),
+            at("2024-01-05 20:00:00"),
+        )
+        .unwrap();
+        assert_eq!(
+            read(&path).unwrap().unwrap().get_expires_at(),
+            "2024-01-05 23:00:00"
+        );
+        assert!(acquire(&path, "laptop", "db", at("2024-01-05 22:00:00")).is_err());
+        drop(guard);
+        assert!(!path.exists());
+
+        // a lease left by a crash is taken over once expired
+        std::mem::forget(acquire(&path, "desktop", "stocks", at("2024-01-05 18:00:00")).unwrap());
+        let guard = acquire(&path, "laptop", "stocks", at("2024-01-05 21:00:01")).unwrap();
+        assert_eq!(guard.get_host(), "laptop");
+        drop(guard);
+        assert!(!path.exists());
+
+        // and a run left by a crash on the same machine doesn't keep it
+        std::mem::forget(acquire(&path, "desktop", "stocks", at("2024-01-05 18:00:00")).unwrap());
+        let guard = acquire(&path, "desktop", "db", at("2024-01-05 21:00:01")).unwrap();
+        assert_eq!(read(&path).unwrap().unwrap().get_holders().len(), 1);
+        drop(guard);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
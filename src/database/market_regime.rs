@@ -0,0 +1,124 @@
+use chrono::Local;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+use crate::analysis::live::{standardized_diff_and_trend, BullBear, Ohlc};
+use crate::my_error::MyError;
+
+use super::topix_ohlc;
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+/// The trend of TOPIX on each date, over the `rangePeriod` daily bars until it
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS market_regime (
+            date TEXT PRIMARY KEY,
+            regime TEXT NOT NULL,
+            standardized_diff REAL NOT NULL,
+            created_at TEXT NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// (date, standardized diff, regime) of the days with `period` bars until them,
+/// `topix` is oldest first
+pub fn classify(topix: &[Ohlc], period: usize) -> Vec<(String, f64, BullBear)> {
+    topix
+        .windows(period.max(1))
+        .map(|x| {
+            let (standardized_diff, regime) = standardized_diff_and_trend(x);
+            let date = x[x.len() - 1].get_date().to_owned();
+            (date, standardized_diff, regime)
+        })
+        .collect()
+}
+
+/// Classifies every day of topix_ohlc over `period` bars (`rangePeriod` of the
+/// analyses), replacing the days already stored. Returns the number of days stored
+pub fn update(conn: &mut Connection, period: usize) -> Result<usize, MyError> {
+    let topix = topix_ohlc::select_all(conn)?;
+    let regimes = classify(&topix, period);
+    let created_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO market_regime (date, regime, standardized_diff, created_at)
+            VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (date, standardized_diff, regime) in &regimes {
+            stmt.execute(rusqlite::params![
+                date,
+                regime.to_string(),
+                standardized_diff,
+                created_at
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(regimes.len())
+}
+
+/// date -> regime of the days between `from` and `to` ("YYYY-MM-DD", both included)
+pub fn select_by_date_range(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+) -> Result<HashMap<String, BullBear>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT date, regime FROM market_regime WHERE date BETWEEN ?1 AND ?2 ORDER BY date",
+    )?;
+    let rows = stmt
+        .query_map([from, to], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(date, regime)| regime.parse().ok().map(|x| (date, x)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_and_select_by_date_range() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        topix_ohlc::create_table(&conn).unwrap();
+        create_table(&conn).unwrap();
+
+        // a steady rise, each bar narrow against the range
+        let topix = (1..=70)
+            .map(|i| {
+                let close = 2000.0 + i as f64 * 10.0;
+                Ohlc::new(
+                    format!("2024-{:02}-{:02}", (i - 1) / 28 + 1, (i - 1) % 28 + 1),
+                    close - 5.0,
+                    close + 5.0,
+                    close - 10.0,
+                    close,
+                )
+            })
+            .collect::<Vec<_>>();
+        let regimes = classify(&topix, 60);
+        assert_eq!(regimes.len(), 11);
+        assert_eq!(regimes[0].0, "2024-03-04");
+        assert!(regimes.iter().all(|x| x.2 == BullBear::Bull));
+        assert!(classify(&topix[..59], 60).is_empty());
+
+        topix_ohlc::insert(&mut conn, &topix).unwrap();
+        assert_eq!(update(&mut conn, 60).unwrap(), 11);
+        let selected = select_by_date_range(&conn, "2024-03-10", "2024-03-14").unwrap();
+        assert_eq!(selected.len(), 5);
+        assert_eq!(selected.get("2024-03-14"), Some(&BullBear::Bull));
+        assert_eq!("NoTrend".parse::<BullBear>().unwrap(), BullBear::NoTrend);
+        assert!("bull".parse::<BullBear>().is_err());
+    }
+}
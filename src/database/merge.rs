@@ -0,0 +1,407 @@
+use rusqlite::Connection;
+use std::path::Path;
+
+use super::{blacklist, corporate_events, journal, stocks_ohlc};
+use crate::my_error::MyError;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeSummary {
+    inserted: usize,
+    /// (code, date) stored already with the same prices
+    duplicates: usize,
+    /// (code, date) stored already with other prices, the stored bar is kept
+    conflicts: usize,
+    /// positions added, or closed on the other machine only
+    positions: usize,
+    trades: usize,
+    /// codes not blacklisted yet, an entry of both keeps the stored one
+    blacklist: usize,
+    corporate_events: usize,
+}
+
+impl MergeSummary {
+    //getters
+    pub fn get_inserted(&self) -> usize {
+        self.inserted
+    }
+    pub fn get_duplicates(&self) -> usize {
+        self.duplicates
+    }
+    pub fn get_conflicts(&self) -> usize {
+        self.conflicts
+    }
+    pub fn get_positions(&self) -> usize {
+        self.positions
+    }
+    pub fn get_trades(&self) -> usize {
+        self.trades
+    }
+    pub fn get_blacklist(&self) -> usize {
+        self.blacklist
+    }
+    pub fn get_corporate_events(&self) -> usize {
+        self.corporate_events
+    }
+}
+
+/// Adds what another trading23.sqlite (e.g. the copy of a second machine, or
+/// "trading23 (1).sqlite" left by a Drive conflict) has and this one doesn't, in one
+/// transaction: the stocks_ohlc bars, the journal, the blacklist and the corporate events.
+/// The other tables are fetched again by the next run
+pub fn merge(conn: &mut Connection, path: &Path) -> Result<MergeSummary, MyError> {
+    stocks_ohlc::create_table(conn)?;
+    journal::create_table(conn)?;
+    blacklist::create_table(conn)?;
+    corporate_events::create_table(conn)?;
+    conn.execute(
+        "ATTACH DATABASE ?1 AS other",
+        [path.to_string_lossy().as_ref()],
+    )?;
+    let merged = merge_attached(conn);
+    conn.execute("DETACH DATABASE other", ())?;
+    merged
+}
+
+fn merge_attached(conn: &mut Connection) -> Result<MergeSummary, MyError> {
+    let tx = conn.transaction()?;
+    let (inserted, duplicates, conflicts) = match has_table(&tx, "stocks_ohlc")? {
+        true => merge_ohlc(&tx)?,
+        false => (0, 0, 0),
+    };
+    let (positions, trades) = match has_table(&tx, "positions")? {
+        true => merge_journal(&tx)?,
+        false => (0, 0),
+    };
+    let blacklist = match has_table(&tx, "blacklist")? {
+        true => tx.execute(
+            "INSERT OR IGNORE INTO main.blacklist (code, reason, expires_on, added_at)
+            SELECT code, reason, expires_on, added_at FROM other.blacklist",
+            (),
+        )?,
+        false => 0,
+    };
+    let corporate_events = match has_table(&tx, "corporate_events")? {
+        true => tx.execute(
+            "INSERT INTO main.corporate_events (code, kind, starts_on, ends_on, note)
+            SELECT code, kind, starts_on, ends_on, note FROM other.corporate_events o
+            WHERE NOT EXISTS (SELECT 1 FROM main.corporate_events m
+                WHERE m.code = o.code AND m.kind = o.kind AND m.starts_on = o.starts_on)",
+            (),
+        )?,
+        false => 0,
+    };
+    tx.commit()?;
+    Ok(MergeSummary {
+        inserted,
+        duplicates,
+        conflicts,
+        positions,
+        trades,
+        blacklist,
+        corporate_events,
+    })
+}
+
+/// Older files don't have every table
+fn has_table(conn: &Connection, table: &str) -> Result<bool, MyError> {
+    Ok(conn
+        .prepare("SELECT 1 FROM other.sqlite_master WHERE type = 'table' AND name = ?1")?
+        .exists([table])?)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool, MyError> {
+    Ok(conn
+        .prepare("SELECT 1 FROM pragma_table_info(?1, 'other') WHERE name = ?2")?
+        .exists([table, column])?)
+}
+
+/// (inserted, duplicates, conflicts) of the stocks_ohlc bars
+fn merge_ohlc(conn: &Connection) -> Result<(usize, usize, usize), MyError> {
+    let (duplicates, conflicts) = conn.query_row(
+        "SELECT
+            COUNT(*) FILTER (WHERE m.open = o.open AND m.high = o.high AND m.low = o.low
+                AND m.close = o.close),
+            COUNT(*) FILTER (WHERE NOT (m.open = o.open AND m.high = o.high AND m.low = o.low
+                AND m.close = o.close))
+        FROM other.stocks_ohlc o JOIN main.stocks_ohlc m ON m.code = o.code AND m.date = o.date",
+        [],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+    )?;
+    // files from before the volume and the turnover of the bars
+    let volume = match has_column(conn, "stocks_ohlc", "volume")? {
+        true => "volume",
+        false => "NULL",
+    };
+    let turnover = match has_column(conn, "stocks_ohlc", "turnover")? {
+        true => "turnover",
+        false => "NULL",
+    };
+    // the first of a (code, date) repeated in the other file
+    let inserted = conn.execute(
+        &format!(
+            "INSERT INTO main.stocks_ohlc (code, date, open, high, low, close, morning_close,
+                afternoon_open, created_at, volume, turnover)
+            SELECT code, date, open, high, low, close, morning_close, afternoon_open,
+                created_at, {}, {}
+            FROM other.stocks_ohlc o
+            WHERE o.id IN (SELECT MIN(id) FROM other.stocks_ohlc GROUP BY code, date)
+                AND NOT EXISTS (SELECT 1 FROM main.stocks_ohlc m
+                    WHERE m.code = o.code AND m.date = o.date)",
+            volume, turnover
+        ),
+        (),
+    )?;
+    Ok((inserted, duplicates as usize, conflicts as usize))
+}
+
+/// A position of both files is the same (code, side, quantity, entry price, opening
+/// day). The other's positions are added with new ids and their trades follow them,
+/// a position closed on the other machine only is closed here too
+fn merge_journal(conn: &Connection) -> Result<(usize, usize), MyError> {
+    let pairs = conn
+        .prepare(
+            "SELECT o.id, (SELECT m.id FROM main.positions m WHERE m.code = o.code
+                AND m.side = o.side AND m.quantity = o.quantity
+                AND m.entry_price = o.entry_price AND m.opened_on = o.opened_on)
+            FROM other.positions o ORDER BY o.id",
+        )?
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // files from before the signal IDs of the journal
+    let signal_id = match has_column(conn, "positions", "signal_id")? {
+        true => "signal_id",
+        false => "NULL",
+    };
+    let (mut positions, mut trades) = (0, 0);
+    for (other_id, main_id) in pairs {
+        let id = match main_id {
+            Some(id) => {
+                positions += conn.execute(
+                    "UPDATE main.positions SET (exit_price, closed_on) =
+                        (SELECT exit_price, closed_on FROM other.positions WHERE id = ?2)
+                    WHERE id = ?1 AND closed_on IS NULL
+                        AND (SELECT closed_on FROM other.positions WHERE id = ?2) IS NOT NULL",
+                    (id, other_id),
+                )?;
+                id
+            }
+            None => {
+                conn.execute(
+                    &format!(
+                        "INSERT INTO main.positions (code, side, quantity, entry_price,
+                            opened_on, stop_loss, signal_on, exit_price, closed_on, note,
+                            signal_id)
+                        SELECT code, side, quantity, entry_price, opened_on, stop_loss,
+                            signal_on, exit_price, closed_on, note, {}
+                        FROM other.positions WHERE id = ?1",
+                        signal_id
+                    ),
+                    [other_id],
+                )?;
+                positions += 1;
+                conn.last_insert_rowid()
+            }
+        };
+        trades += conn.execute(
+            "INSERT INTO main.trades (position_id, action, price, quantity, traded_on, created_at)
+            SELECT ?1, action, price, quantity, traded_on, created_at FROM other.trades o
+            WHERE o.position_id = ?2 AND NOT EXISTS (SELECT 1 FROM main.trades m
+                WHERE m.position_id = ?1 AND m.action = o.action AND m.price = o.price
+                    AND m.quantity = o.quantity AND m.traded_on = o.traded_on)",
+            (id, other_id),
+        )?;
+    }
+    Ok((positions, trades))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::live::OhlcPremium;
+    use crate::stock_code::StockCode;
+
+    fn bar(code: &str, date: &str, close: f64) -> OhlcPremium {
+        OhlcPremium::new(
+            StockCode::new(code).unwrap(),
+            date.to_owned(),
+            100.0,
+            110.0,
+            90.0,
+            close,
+            101.0,
+            102.0,
+        )
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = std::env::temp_dir().join(format!("trading23_merge_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("laptop.sqlite");
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        stocks_ohlc::create_table(&conn).unwrap();
+        stocks_ohlc::insert_batch(
+            &mut conn,
+            &[
+                bar("7203", "2024-01-04", 105.0),
+                bar("7203", "2024-01-05", 105.0),
+            ],
+        )
+        .unwrap();
+        let mut other = Connection::open(&path).unwrap();
+        stocks_ohlc::create_table(&other).unwrap();
+        stocks_ohlc::insert_batch(
+            &mut other,
+            &[
+                bar("7203", "2024-01-04", 105.0),
+                bar("7203", "2024-01-05", 106.0),
+                bar("7203", "2024-01-09", 107.0),
+                bar("7203", "2024-01-09", 107.0),
+                bar("6758", "2024-01-09", 108.0),
+            ],
+        )
+        .unwrap();
+        drop(other);
+
+        let summary = merge(&mut conn, &path).unwrap();
+        assert_eq!(summary.get_inserted(), 2);
+        assert_eq!(summary.get_duplicates(), 1);
+        assert_eq!(summary.get_conflicts(), 1);
+        let stored = stocks_ohlc::select_for_export(&conn, None, None, None).unwrap();
+        assert_eq!(stored.len(), 4);
+        assert_eq!(stored[2].get_close(), 105.0);
+        // nothing new the second time
+        assert_eq!(merge(&mut conn, &path).unwrap().get_inserted(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_journal() {
+        use super::super::blacklist::BlacklistEntry;
+        use super::super::corporate_events::{CorporateEvent, EventKind};
+        use super::super::journal::{Position, Side};
+        use crate::instrument::Instrument;
+
+        let dir =
+            std::env::temp_dir().join(format!("trading23_merge_journal_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("laptop.sqlite");
+        let position = |code: &str, opened_on: &str| {
+            Position::new(
+                Instrument::parse(code).unwrap(),
+                Side::Long,
+                100.0,
+                2500.0,
+                opened_on,
+                None,
+                None,
+                "",
+            )
+        };
+        let toyota = StockCode::new("7203").unwrap();
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        journal::create_table(&conn).unwrap();
+        blacklist::create_table(&conn).unwrap();
+        journal::open(&mut conn, &position("7203", "2024-01-05")).unwrap();
+        blacklist::add(&conn, &BlacklistEntry::new(toyota.clone(), "TOB", None)).unwrap();
+
+        // the copy taken before the laptop closed Toyota and opened Sony
+        let mut other = Connection::open(&path).unwrap();
+        journal::create_table(&other).unwrap();
+        blacklist::create_table(&other).unwrap();
+        corporate_events::create_table(&other).unwrap();
+        let id = journal::open(&mut other, &position("7203", "2024-01-05")).unwrap();
+        journal::close(&mut other, id, 2600.0, "2024-01-09").unwrap();
+        journal::open(&mut other, &position("6758", "2024-01-09")).unwrap();
+        blacklist::add(&other, &BlacklistEntry::new(toyota.clone(), "MBO", None)).unwrap();
+        blacklist::add(
+            &other,
+            &BlacklistEntry::new(StockCode::new("6758").unwrap(), "offering", None),
+        )
+        .unwrap();
+        corporate_events::insert(
+            &other,
+            &CorporateEvent::new(toyota, EventKind::Tob, "2024-01-09", None, ""),
+        )
+        .unwrap();
+        drop(other);
+
+        let summary = merge(&mut conn, &path).unwrap();
+        assert_eq!(summary.get_positions(), 2);
+        // the close of Toyota, the opening of Sony
+        assert_eq!(summary.get_trades(), 2);
+        assert_eq!(summary.get_blacklist(), 1);
+        assert_eq!(summary.get_corporate_events(), 1);
+        let positions = journal::select_all(&conn).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert!(positions
+            .iter()
+            .all(|x| x.get_instrument().code() != "7203" || !x.is_open()));
+        assert_eq!(
+            blacklist::select_all(&conn).unwrap()[1].to_string(),
+            BlacklistEntry::new(StockCode::new("7203").unwrap(), "TOB", None).to_string()
+        );
+
+        // nothing new the second time
+        let summary = merge(&mut conn, &path).unwrap();
+        assert_eq!(
+            (
+                summary.get_positions(),
+                summary.get_trades(),
+                summary.get_blacklist(),
+                summary.get_corporate_events()
+            ),
+            (0, 0, 0, 0)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_old_ohlc() {
+        let dir = std::env::temp_dir().join(format!("trading23_merge_old_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("old.sqlite");
+
+        // a file from before the volume and the turnover
+        let other = Connection::open(&path).unwrap();
+        other
+            .execute(
+                "CREATE TABLE stocks_ohlc (
+                    id INTEGER PRIMARY KEY,
+                    code TEXT NOT NULL,
+                    date TEXT NOT NULL,
+                    open REAL NOT NULL,
+                    high REAL NOT NULL,
+                    low REAL NOT NULL,
+                    close REAL NOT NULL,
+                    morning_close REAL NOT NULL,
+                    afternoon_open REAL NOT NULL,
+                    created_at TEXT NOT NULL)",
+                (),
+            )
+            .unwrap();
+        other
+            .execute(
+                "INSERT INTO stocks_ohlc (code, date, open, high, low, close, morning_close,
+                    afternoon_open, created_at)
+                VALUES ('7203', '2024-01-09', 100, 110, 90, 107, 101, 102, '2024-01-09')",
+                (),
+            )
+            .unwrap();
+        drop(other);
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        let summary = merge(&mut conn, &path).unwrap();
+        assert_eq!(summary.get_inserted(), 1);
+        assert_eq!(summary.get_positions(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
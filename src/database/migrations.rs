@@ -0,0 +1,246 @@
+use chrono::Local;
+use log::info;
+use rusqlite::{Connection, TransactionBehavior};
+
+use crate::my_error::MyError;
+use crate::signal::{SignalId, WINDOW_SOURCE};
+use crate::stock_code::StockCode;
+
+/// A schema change of trading23.sqlite. Tables are created by the `create_table` of
+/// their module with the latest schema, migrations bring older files up to it.
+/// Released migrations are never edited, a new change gets the next version.
+struct Migration {
+    version: i64,
+    name: &'static str,
+    apply: fn(&Connection) -> Result<(), MyError>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "sector33_code of stocks_master",
+        apply: add_sector33_code,
+    },
+    Migration {
+        version: 2,
+        name: "volume and turnover of stocks_ohlc",
+        apply: add_liquidity_columns,
+    },
+    Migration {
+        version: 3,
+        name: "signal_id of stocks_window",
+        apply: add_signal_id,
+    },
+    Migration {
+        version: 4,
+        name: "market_regime of stocks_window",
+        apply: add_market_regime,
+    },
+    Migration {
+        version: 5,
+        name: "one runs row per kind and date",
+        apply: dedup_runs,
+    },
+    Migration {
+        version: 6,
+        name: "signal_id of positions",
+        apply: add_position_signal_id,
+    },
+];
+
+fn add_sector33_code(conn: &Connection) -> Result<(), MyError> {
+    add_column(
+        conn,
+        "stocks_master",
+        "sector33_code",
+        "TEXT NOT NULL DEFAULT ''",
+    )
+}
+
+fn add_liquidity_columns(conn: &Connection) -> Result<(), MyError> {
+    add_column(conn, "stocks_ohlc", "volume", "REAL")?;
+    add_column(conn, "stocks_ohlc", "turnover", "REAL")
+}
+
+/// The windows stored before get the ids their reports would have shown
+fn add_signal_id(conn: &Connection) -> Result<(), MyError> {
+    add_column(conn, "stocks_window", "signal_id", "TEXT")?;
+    let table_exists = conn
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'stocks_window'")?
+        .exists([])?;
+    if !table_exists {
+        return Ok(());
+    }
+    let windows = conn
+        .prepare("SELECT code, analyzed_at FROM stocks_window WHERE signal_id IS NULL")?
+        .query_map([], |row| {
+            Ok((row.get::<_, StockCode>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut stmt = conn
+        .prepare("UPDATE stocks_window SET signal_id = ?1 WHERE code = ?2 AND analyzed_at = ?3")?;
+    for (code, analyzed_at) in windows {
+        // left without an ID when the date can't be read
+        let Ok(id) = SignalId::new(WINDOW_SOURCE, &code, &analyzed_at) else {
+            continue;
+        };
+        stmt.execute(rusqlite::params![id, code, analyzed_at])?;
+    }
+    Ok(())
+}
+
+/// The windows stored before stay untagged, the report reruns tag them
+fn add_market_regime(conn: &Connection) -> Result<(), MyError> {
+    add_column(conn, "stocks_window", "market_regime", "TEXT")
+}
+
+/// The latest coverage of a date is kept, `runs::create_table` adds the unique index
+fn dedup_runs(conn: &Connection) -> Result<(), MyError> {
+    let table_exists = conn
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'runs'")?
+        .exists([])?;
+    if table_exists {
+        conn.execute(
+            "DELETE FROM runs WHERE id NOT IN (SELECT MAX(id) FROM runs GROUP BY kind, date)",
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+/// The positions recorded before keep their signal date only
+fn add_position_signal_id(conn: &Connection) -> Result<(), MyError> {
+    add_column(conn, "positions", "signal_id", "TEXT")
+}
+
+/// Adds the column to an existing table, once. Tables created after the migration
+/// already have it, and files from before this runner may have it from an older build.
+fn add_column(conn: &Connection, table: &str, column: &str, ty: &str) -> Result<(), MyError> {
+    let table_exists = conn
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?
+        .exists([table])?;
+    let column_exists = conn
+        .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists([table, column])?;
+    if table_exists && !column_exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, ty),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// The latest migration applied, 0 for a new file
+pub fn current_version(conn: &Connection) -> Result<i64, MyError> {
+    create_table(conn)?;
+    Ok(conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// Applies the migrations newer than the file, each in its own transaction.
+/// Runs started at the same time wait for each other and apply a migration once.
+pub fn migrate(conn: &mut Connection) -> Result<i64, MyError> {
+    let from = current_version(conn)?;
+    let mut version = from;
+    for migration in MIGRATIONS.iter().filter(|x| x.version > from) {
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if current_version(&tx)? < migration.version {
+            (migration.apply)(&tx)?;
+            tx.execute(
+                "INSERT INTO schema_version (version, name, applied_at) VALUES (?1, ?2, ?3)",
+                (
+                    migration.version,
+                    migration.name,
+                    Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                ),
+            )?;
+            info!("schema version {}: {}", migration.version, migration.name);
+        }
+        tx.commit()?;
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        assert!(MIGRATIONS
+            .windows(2)
+            .all(|x| x[0].version + 1 == x[1].version));
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        // stocks_master from before sector33_code, no stocks_ohlc yet
+        conn.execute(
+            "CREATE TABLE stocks_master (code TEXT PRIMARY KEY, name TEXT NOT NULL)",
+            (),
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO stocks_master VALUES ('7203', 'トヨタ自動車')",
+            (),
+        )
+        .unwrap();
+
+        let latest = MIGRATIONS.last().unwrap().version;
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert_eq!(migrate(&mut conn).unwrap(), latest);
+        assert_eq!(migrate(&mut conn).unwrap(), latest);
+        assert_eq!(current_version(&conn).unwrap(), latest);
+
+        let sector33_code: String = conn
+            .query_row("SELECT sector33_code FROM stocks_master", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(sector33_code, "");
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        // stocks_window from before signal_id
+        conn.execute(
+            "CREATE TABLE stocks_window (code TEXT NOT NULL, analyzed_at TEXT NOT NULL)",
+            (),
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO stocks_window VALUES ('7203', '2024-01-05')",
+            (),
+        )
+        .unwrap();
+        migrate(&mut conn).unwrap();
+        let signal_id: SignalId = conn
+            .query_row("SELECT signal_id FROM stocks_window", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(
+            signal_id,
+            SignalId::new(
+                WINDOW_SOURCE,
+                &StockCode::new("7203").unwrap(),
+                "2024-01-05"
+            )
+            .unwrap()
+        );
+        let applied: i64 = conn
+            .query_row("SELECT count(*) FROM schema_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied, latest);
+    }
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, Local, NaiveDateTime};
+use rusqlite::Connection;
+
+use crate::jquants::fetcher::PricesAmInner;
+use crate::my_error::MyError;
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+/// The morning session of J-Quants `/prices/prices_am`, prices are NULL without
+/// a trade in the morning
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS prices_am (
+            code TEXT NOT NULL,
+            date TEXT NOT NULL,
+            open REAL,
+            high REAL,
+            low REAL,
+            close REAL,
+            volume REAL,
+            turnover REAL,
+            fetched_at TEXT NOT NULL,
+            PRIMARY KEY (code, date))",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Replaces the sessions already stored, a later fetch of the same day wins
+pub fn insert(
+    conn: &mut Connection,
+    prices_am: &[PricesAmInner],
+    fetched_at: DateTime<Local>,
+) -> Result<(), MyError> {
+    let fetched_at = fetched_at.format("%Y-%m-%d %H:%M:%S").to_string();
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO prices_am
+            (code, date, open, high, low, close, volume, turnover, fetched_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        for x in prices_am {
+            let ohlc = match x.has_ohlc() {
+                true => [
+                    Some(x.get_open()),
+                    Some(x.get_high()),
+                    Some(x.get_low()),
+                    Some(x.get_close()),
+                ],
+                false => [None; 4],
+            };
+            stmt.execute(rusqlite::params![
+                x.get_code(),
+                x.get_date(),
+                ohlc[0],
+                ohlc[1],
+                ohlc[2],
+                ohlc[3],
+                x.get_volume(),
+                x.get_turnover_value(),
+                fetched_at,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// The morning sessions recorded on `date` ("YYYY-MM-DD") ordered by code, with
+/// the latest fetch time among them
+pub fn select_by_date(
+    conn: &Connection,
+    date: &str,
+) -> Result<(Vec<PricesAmInner>, Option<DateTime<Local>>), MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT code, date, open, high, low, close, volume, turnover, fetched_at
+        FROM prices_am WHERE date = ?1 ORDER BY code",
+    )?;
+    let mut fetched_at: Option<String> = None;
+    let mut prices_am = Vec::new();
+    let mut rows = stmt.query([date])?;
+    while let Some(row) = rows.next()? {
+        prices_am.push(PricesAmInner::new(
+            row.get(1)?,
+            row.get(0)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+            row.get(7)?,
+        ));
+        let row_fetched_at: String = row.get(8)?;
+        if fetched_at.as_ref().is_none_or(|x| *x < row_fetched_at) {
+            fetched_at = Some(row_fetched_at);
+        }
+    }
+    let fetched_at = fetched_at.and_then(|x| {
+        NaiveDateTime::parse_from_str(&x, "%Y-%m-%d %H:%M:%S")
+            .ok()?
+            .and_local_timezone(Local)
+            .single()
+    });
+    Ok((prices_am, fetched_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stock_code::StockCode;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_insert_and_select_by_date() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let session = |code: &str, close: Option<f64>| {
+            PricesAmInner::new(
+                "2024-01-05".to_owned(),
+                StockCode::new(code).unwrap(),
+                close,
+                close,
+                close,
+                close,
+                Some(100.0),
+                close.map(|x| x * 100.0),
+            )
+        };
+        let first = Local.with_ymd_and_hms(2024, 1, 5, 11, 35, 0).unwrap();
+        insert(
+            &mut conn,
+            &[session("7203", Some(2500.0)), session("9984", None)],
+            first,
+        )
+        .unwrap();
+        let second = Local.with_ymd_and_hms(2024, 1, 5, 12, 10, 0).unwrap();
+        insert(&mut conn, &[session("7203", Some(2510.0))], second).unwrap();
+
+        let (prices_am, fetched_at) = select_by_date(&conn, "2024-01-05").unwrap();
+        assert_eq!(fetched_at, Some(second));
+        assert_eq!(prices_am.len(), 2);
+        assert_eq!(prices_am[0].get_close(), 2510.0);
+        assert_eq!(prices_am[0].get_turnover_value(), Some(251000.0));
+        assert!(!prices_am[1].has_ohlc());
+
+        let (prices_am, fetched_at) = select_by_date(&conn, "2024-01-09").unwrap();
+        assert!(prices_am.is_empty());
+        assert_eq!(fetched_at, None);
+    }
+}
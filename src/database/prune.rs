@@ -0,0 +1,221 @@
+use anyhow::anyhow;
+use clap::ValueEnum;
+use rusqlite::Connection;
+use std::path::Path;
+
+use super::{export, stocks_ohlc};
+use crate::analysis::stocks_window;
+use crate::config::GdriveJson;
+use crate::my_error::MyError;
+
+/// Where `prune` moves the old bars
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ArchiveFormat {
+    /// stocks_ohlc of another sqlite file, added to on each prune
+    Sqlite,
+    /// One zstd compressed file per prune
+    Parquet,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Sqlite => "sqlite",
+            ArchiveFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Bars of a window of the analyses
+const WINDOW_BARS: usize = 60;
+
+/// The fewest trading days `prune` keeps: the bars the analyses and the gap planner of
+/// the fetcher read back, their calendar days counted as trading days to be safe
+pub fn min_keep_days() -> Result<usize, MyError> {
+    let gap_lookback_days = GdriveJson::new()?.gap_lookback_days();
+    Ok([
+        WINDOW_BARS as i64,
+        stocks_window::lookback_days(),
+        gap_lookback_days,
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or_default() as usize)
+}
+
+/// The oldest of the latest `keep_days` dates of stocks_ohlc, the bars before it are
+/// pruned. None when no bar is older
+pub fn cutoff(conn: &Connection, keep_days: usize) -> Result<Option<String>, MyError> {
+    if keep_days == 0 {
+        return Err(MyError::Anyhow(anyhow!("keep at least 1 trading day")));
+    }
+    let dates = stocks_ohlc::select_latest_dates(conn, "9999-12-31", keep_days)?;
+    let Some(oldest) = dates.last().filter(|_| dates.len() == keep_days) else {
+        return Ok(None);
+    };
+    match stocks_ohlc::select_latest_dates(conn, oldest, 1)?.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(oldest.clone())),
+    }
+}
+
+/// Moves the bars dated before `before` ("YYYY-MM-DD") to the archive at `path`,
+/// returns the number of bars. They are deleted only once the archive is written, in one
+/// transaction
+pub fn prune(
+    conn: &mut Connection,
+    before: &str,
+    format: ArchiveFormat,
+    path: &Path,
+) -> Result<usize, MyError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match format {
+        ArchiveFormat::Sqlite => {
+            stocks_ohlc::create_table(&Connection::open(path)?)?;
+            conn.execute(
+                "ATTACH DATABASE ?1 AS archive",
+                [path.to_string_lossy().as_ref()],
+            )?;
+            let moved = move_to_archive(conn, before);
+            conn.execute("DETACH DATABASE archive", ())?;
+            moved
+        }
+        ArchiveFormat::Parquet => {
+            let tx = conn.transaction()?;
+            let bars = match stocks_ohlc::select_latest_dates(&tx, before, 1)?.pop() {
+                Some(last) => stocks_ohlc::select_for_export(&tx, None, None, Some(&last))?,
+                None => Vec::new(),
+            };
+            let mut file = std::fs::File::create(path)?;
+            export::write_parquet_compressed(&bars, &mut file)?;
+            file.sync_all()?;
+            let deleted = tx.execute("DELETE FROM stocks_ohlc WHERE date < ?1", [before])?;
+            if deleted != bars.len() {
+                return Err(MyError::Anyhow(anyhow!(
+                    "{} bars archived but {} deleted",
+                    bars.len(),
+                    deleted
+                )));
+            }
+            tx.commit()?;
+            Ok(deleted)
+        }
+    }
+}
+
+/// Copies and deletes in one transaction across both files
+fn move_to_archive(conn: &mut Connection, before: &str) -> Result<usize, MyError> {
+    let tx = conn.transaction()?;
+    let copied = tx.execute(
+        "INSERT INTO archive.stocks_ohlc (code, date, open, high, low, close, morning_close,
+            afternoon_open, created_at, volume, turnover)
+        SELECT code, date, open, high, low, close, morning_close, afternoon_open, created_at,
+            volume, turnover
+        FROM main.stocks_ohlc WHERE date < ?1",
+        [before],
+    )?;
+    let deleted = tx.execute("DELETE FROM main.stocks_ohlc WHERE date < ?1", [before])?;
+    if copied != deleted {
+        return Err(MyError::Anyhow(anyhow!(
+            "{} bars archived but {} deleted",
+            copied,
+            deleted
+        )));
+    }
+    tx.commit()?;
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::live::OhlcPremium;
+    use crate::stock_code::StockCode;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    fn bars(conn: &mut Connection) {
+        let bar = |code: &str, date: &str| {
+            OhlcPremium::new(
+                StockCode::new(code).unwrap(),
+                date.to_owned(),
+                100.0,
+                110.0,
+                90.0,
+                105.0,
+                101.0,
+                102.0,
+            )
+        };
+        stocks_ohlc::insert_batch(
+            conn,
+            &[
+                bar("7203", "2024-01-04"),
+                bar("6758", "2024-01-04"),
+                bar("7203", "2024-01-05"),
+                bar("7203", "2024-01-09"),
+                bar("6758", "2024-01-09"),
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        stocks_ohlc::create_table(&conn).unwrap();
+        bars(&mut conn);
+        assert_eq!(cutoff(&conn, 1).unwrap(), Some("2024-01-09".to_owned()));
+        assert_eq!(cutoff(&conn, 2).unwrap(), Some("2024-01-05".to_owned()));
+        assert_eq!(cutoff(&conn, 3).unwrap(), None);
+        assert_eq!(cutoff(&conn, 10).unwrap(), None);
+        assert!(cutoff(&conn, 0).is_err());
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = std::env::temp_dir().join(format!("trading23_prune_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        stocks_ohlc::create_table(&conn).unwrap();
+        bars(&mut conn);
+        let path = dir.join("archive.sqlite");
+        assert_eq!(
+            prune(&mut conn, "2024-01-05", ArchiveFormat::Sqlite, &path).unwrap(),
+            2
+        );
+        // a second prune adds to the same archive
+        assert_eq!(
+            prune(&mut conn, "2024-01-09", ArchiveFormat::Sqlite, &path).unwrap(),
+            1
+        );
+        let kept = stocks_ohlc::select_for_export(&conn, None, None, None).unwrap();
+        assert_eq!(kept.len(), 2);
+        assert!(kept.iter().all(|x| x.get_date() == "2024-01-09"));
+        let archived =
+            stocks_ohlc::select_for_export(&Connection::open(&path).unwrap(), None, None, None)
+                .unwrap();
+        assert_eq!(archived.len(), 3);
+        assert_eq!(archived[0].get_code().as_str(), "6758");
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        stocks_ohlc::create_table(&conn).unwrap();
+        bars(&mut conn);
+        let path = dir.join("archive.parquet");
+        assert_eq!(
+            prune(&mut conn, "2024-01-09", ArchiveFormat::Parquet, &path).unwrap(),
+            3
+        );
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+        assert_eq!(
+            stocks_ohlc::select_for_export(&conn, None, None, None)
+                .unwrap()
+                .len(),
+            2
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use chrono::{Duration, NaiveDate};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+use super::{prices_am, runs, stocks_ohlc, stocks_window};
+use crate::my_error::MyError;
+
+/// `retention` of config.json, days each table keeps by its date column, forever when
+/// null. stocks_ohlc is kept forever, `db prune` archives it instead
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Retention {
+    /// The morning sessions of the afternoon job
+    #[serde(default = "default_prices_am_days")]
+    prices_am: Option<i64>,
+    /// The coverage log of the fetches
+    #[serde(default = "default_runs_days")]
+    runs: Option<i64>,
+    /// The analyzed windows, their outcomes are queried later
+    #[serde(default)]
+    stocks_window: Option<i64>,
+}
+
+fn default_prices_am_days() -> Option<i64> {
+    Some(90)
+}
+
+fn default_runs_days() -> Option<i64> {
+    Some(30)
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            prices_am: default_prices_am_days(),
+            runs: default_runs_days(),
+            stocks_window: None,
+        }
+    }
+}
+
+impl Retention {
+    /// (table, its date column, days kept) of the tables under the policy
+    fn tables(&self) -> [(&'static str, &'static str, Option<i64>); 4] {
+        [
+            ("stocks_ohlc", "date", None),
+            ("prices_am", "date", self.prices_am),
+            ("runs", "date", self.runs),
+            ("stocks_window", "analyzed_at", self.stocks_window),
+        ]
+    }
+}
+
+/// Rows of a table and their dates, for `db status`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatus {
+    table: &'static str,
+    rows: i64,
+    oldest: Option<String>,
+    newest: Option<String>,
+    days: Option<i64>,
+}
+
+impl TableStatus {
+    //getters
+    pub fn get_table(&self) -> &str {
+        self.table
+    }
+    pub fn get_rows(&self) -> i64 {
+        self.rows
+    }
+    pub fn get_oldest(&self) -> Option<&str> {
+        self.oldest.as_deref()
+    }
+}
+
+impl Display for TableStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.oldest, &self.newest) {
+            (Some(oldest), Some(newest)) => write!(
+                f,
+                "{}: {} rows, {} - {}, ",
+                self.table, self.rows, oldest, newest
+            )?,
+            _ => write!(f, "{}: empty, ", self.table)?,
+        }
+        match self.days {
+            Some(days) => write!(f, "kept {} days", days),
+            None => write!(f, "kept forever"),
+        }
+    }
+}
+
+/// Rows deleted from a table by `apply`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pruned {
+    table: &'static str,
+    before: String,
+    deleted: usize,
+}
+
+impl Display for Pruned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} rows before {} deleted",
+            self.table, self.deleted, self.before
+        )
+    }
+}
+
+fn create_tables(conn: &Connection) -> Result<(), MyError> {
+    stocks_ohlc::create_table(conn)?;
+    prices_am::create_table(conn)?;
+    runs::create_table(conn)?;
+    stocks_window::create_table(conn)?;
+    Ok(())
+}
+
+/// Deletes the rows dated over the retention before `today` ("YYYY-MM-DD"), in one
+/// transaction. The file shrinks with `database::optimize` afterwards
+pub fn apply(
+    conn: &mut Connection,
+    retention: &Retention,
+    today: NaiveDate,
+) -> Result<Vec<Pruned>, MyError> {
+    create_tables(conn)?;
+    let tx = conn.transaction()?;
+    let mut pruned = Vec::new();
+    for (table, column, days) in retention.tables() {
+        let Some(days) = days else {
+            continue;
+        };
+        let before = (today - Duration::days(days))
+            .format("%Y-%m-%d")
+            .to_string();
+        let deleted = tx.execute(
+            &format!("DELETE FROM {} WHERE {} < ?1", table, column),
+            [&before],
+        )?;
+        pruned.push(Pruned {
+            table,
+            before,
+            deleted,
+        });
+    }
+    tx.commit()?;
+    Ok(pruned)
+}
+
+pub fn status(conn: &Connection, retention: &Retention) -> Result<Vec<TableStatus>, MyError> {
+    create_tables(conn)?;
+    retention
+        .tables()
+        .into_iter()
+        .map(|(table, column, days)| {
+            let (rows, oldest, newest) = conn.query_row(
+                &format!(
+                    "SELECT COUNT(*), MIN({}), MAX({}) FROM {}",
+                    column, column, table
+                ),
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?;
+            Ok(TableStatus {
+                table,
+                rows,
+                oldest,
+                newest,
+                days,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_tables(&conn).unwrap();
+        for date in ["2024-01-04", "2024-03-01", "2024-04-03"] {
+            conn.execute(
+                "INSERT INTO prices_am (code, date, fetched_at) VALUES ('7203', ?1, ?1)",
+                [date],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO runs (kind, date, expected, fetched, missing, created_at)
+                VALUES ('fetch_nikkei225', ?1, 225, 225, '', ?1)",
+                [date],
+            )
+            .unwrap();
+        }
+
+        let retention: Retention = serde_json::from_str(r#"{"runs": null}"#).unwrap();
+        assert_eq!(retention.prices_am, Some(90));
+        assert_eq!(retention.runs, None);
+        let today = NaiveDate::from_ymd_opt(2024, 4, 5).unwrap();
+        let pruned = apply(&mut conn, &retention, today).unwrap();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(
+            pruned[0].to_string(),
+            "prices_am: 1 rows before 2024-01-06 deleted"
+        );
+
+        let status = status(&conn, &Retention::default()).unwrap();
+        assert_eq!(
+            status.iter().map(|x| x.get_table()).collect::<Vec<_>>(),
+            vec!["stocks_ohlc", "prices_am", "runs", "stocks_window"]
+        );
+        assert_eq!(status[1].get_rows(), 2);
+        assert_eq!(status[1].get_oldest(), Some("2024-03-01"));
+        assert_eq!(
+            status[2].to_string(),
+            "runs: 3 rows, 2024-01-04 - 2024-04-03, kept 30 days"
+        );
+        assert_eq!(status[0].to_string(), "stocks_ohlc: empty, kept forever");
+    }
+}
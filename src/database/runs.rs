@@ -0,0 +1,252 @@
+use std::fmt::{Display, Formatter};
+
+use chrono::Local;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::{my_error::MyError, stock_code::StockCode};
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS runs (
+            id INTEGER PRIMARY KEY,
+            kind TEXT NOT NULL,
+            date TEXT NOT NULL,
+            expected INTEGER NOT NULL,
+            fetched INTEGER NOT NULL,
+            missing TEXT NOT NULL,
+            created_at TEXT NOT NULL)",
+        (),
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS runs_kind_date ON runs (kind, date)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// How much of the universe was stored for one date
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Coverage {
+    date: String,
+    expected: usize,
+    fetched: usize,
+    missing: Vec<StockCode>,
+}
+
+impl Coverage {
+    pub fn new(date: &str, expected: usize) -> Self {
+        Self {
+            date: date.to_owned(),
+            expected,
+            fetched: 0,
+            missing: Vec::new(),
+        }
+    }
+
+    pub fn add_fetched(&mut self) {
+        self.fetched += 1;
+    }
+    pub fn add_missing(&mut self, code: &StockCode) {
+        self.missing.push(code.clone());
+    }
+
+    //getters
+    pub fn get_date(&self) -> &str {
+        &self.date
+    }
+    pub fn get_missing(&self) -> &[StockCode] {
+        &self.missing
+    }
+    /// 0.0..=1.0, 1.0 for an empty universe
+    pub fn ratio(&self) -> f64 {
+        match self.expected {
+            0 => 1.0,
+            expected => self.fetched as f64 / expected as f64,
+        }
+    }
+
+    /// `min_coverage` in percent, see `GdriveJson::min_coverage`
+    pub fn check(&self, min_coverage: f64) -> Result<(), MyError> {
+        let percent = self.ratio() * 100.0;
+        match percent < min_coverage {
+            true => Err(MyError::InsufficientCoverage {
+                date: self.date.clone(),
+                percent,
+                min_coverage,
+            }),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Display for Coverage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}/{}", self.date, self.fetched, self.expected)?;
+        if !self.missing.is_empty() {
+            let missing = self.missing.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+            write!(f, " (missing: {})", missing.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// `kind` names the job, e.g. "fetch_nikkei225". Replaces the coverage stored for the date
+pub fn upsert(conn: &Connection, kind: &str, coverage: &Coverage) -> Result<(), MyError> {
+    let created_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let missing = coverage
+        .missing
+        .iter()
+        .map(|x| x.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    conn.execute(
+        "INSERT INTO runs (kind, date, expected, fetched, missing, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT (kind, date) DO UPDATE SET expected = excluded.expected,
+            fetched = excluded.fetched, missing = excluded.missing,
+            created_at = excluded.created_at",
+        (
+            kind,
+            &coverage.date,
+            coverage.expected as i64,
+            coverage.fetched as i64,
+            missing,
+            created_at,
+        ),
+    )?;
+    Ok(())
+}
+
+/// Latest coverage of `kind` for `date`
+pub fn select_latest(
+    conn: &Connection,
+    kind: &str,
+    date: &str,
+) -> Result<Option<Coverage>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT date, expected, fetched, missing FROM runs
+        WHERE kind = ?1 AND date = ?2 ORDER BY id DESC LIMIT 1",
+    )?;
+    let mut rows = stmt.query([kind, date])?;
+    let row = match rows.next()? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let missing: String = row.get(3)?;
+    let missing = missing
+        .split(',')
+        .filter(|x| !x.is_empty())
+        .map(StockCode::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(Coverage {
+        date: row.get(0)?,
+        expected: row.get::<_, i64>(1)? as usize,
+        fetched: row.get::<_, i64>(2)? as usize,
+        missing,
+    }))
+}
+
+/// (code, date) J-Quants had no bar for in the runs of `kind` between `from` and `to`
+/// ("YYYY-MM-DD", both included). A date nothing was fetched for is left out, its
+/// quotes may not have been out yet
+pub fn select_missing(
+    conn: &Connection,
+    kind: &str,
+    from: &str,
+    to: &str,
+) -> Result<Vec<(StockCode, String)>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT date, missing FROM runs
+        WHERE kind = ?1 AND date BETWEEN ?2 AND ?3 AND fetched > 0 AND missing != ''",
+    )?;
+    let rows = stmt
+        .query_map([kind, from, to], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut cells = Vec::new();
+    for (date, missing) in rows {
+        for code in missing.split(',') {
+            cells.push((StockCode::new(code)?, date.clone()));
+        }
+    }
+    Ok(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_select() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let mut coverage = Coverage::new("2024-01-04", 3);
+        coverage.add_fetched();
+        coverage.add_fetched();
+        coverage.add_missing(&StockCode::new("1301").unwrap());
+        upsert(&conn, "fetch_nikkei225", &coverage).unwrap();
+
+        let selected = select_latest(&conn, "fetch_nikkei225", "2024-01-04")
+            .unwrap()
+            .unwrap();
+        assert_eq!(selected, coverage);
+        assert_eq!(selected.to_string(), "2024-01-04: 2/3 (missing: 1301)");
+        assert!(select_latest(&conn, "fetch_nikkei225", "2024-01-05")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_upsert_and_select_missing() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let mut coverage = Coverage::new("2024-01-04", 3);
+        coverage.add_missing(&StockCode::new("1301").unwrap());
+        upsert(&conn, "fetch_nikkei225", &coverage).unwrap();
+        // nothing fetched, the date may not have been out
+        assert!(
+            select_missing(&conn, "fetch_nikkei225", "2024-01-01", "2024-01-31")
+                .unwrap()
+                .is_empty()
+        );
+
+        coverage.add_fetched();
+        coverage.add_fetched();
+        upsert(&conn, "fetch_nikkei225", &coverage).unwrap();
+        let runs: i64 = conn
+            .query_row("SELECT count(*) FROM runs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(runs, 1);
+        assert_eq!(
+            select_missing(&conn, "fetch_nikkei225", "2024-01-01", "2024-01-31").unwrap(),
+            vec![(StockCode::new("1301").unwrap(), "2024-01-04".to_owned())]
+        );
+        assert!(
+            select_missing(&conn, "fetch_nikkei225", "2024-01-05", "2024-01-31")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let mut coverage = Coverage::new("2024-01-04", 4);
+        for _ in 0..3 {
+            coverage.add_fetched();
+        }
+        assert!(coverage.check(75.0).is_ok());
+        let e = coverage.check(90.0).unwrap_err();
+        assert_eq!(e.to_string(), "coverage of 2024-01-04 is 75.0%, below 90%");
+    }
+}
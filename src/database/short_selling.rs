@@ -0,0 +1,160 @@
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+use crate::{my_error::MyError, stock_code::StockCode};
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    crate::database::stocks_master::create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS short_selling (
+            date TEXT NOT NULL,
+            sector33_code TEXT NOT NULL,
+            selling REAL NOT NULL,
+            short_selling_with_restrictions REAL NOT NULL,
+            short_selling_without_restrictions REAL NOT NULL,
+            PRIMARY KEY (date, sector33_code))",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Turnover value of a sector on a day from J-Quants `/markets/short_selling`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShortSelling {
+    /// "YYYY-MM-DD"
+    date: String,
+    sector33_code: String,
+    /// selling excluding short selling
+    selling: f64,
+    /// short selling with price restrictions
+    with_restrictions: f64,
+    without_restrictions: f64,
+}
+
+impl ShortSelling {
+    pub fn new(
+        date: &str,
+        sector33_code: &str,
+        selling: f64,
+        with_restrictions: f64,
+        without_restrictions: f64,
+    ) -> Self {
+        ShortSelling {
+            date: date.to_owned(),
+            sector33_code: sector33_code.to_owned(),
+            selling,
+            with_restrictions,
+            without_restrictions,
+        }
+    }
+
+    /// Short selling in % of all selling, None without any selling
+    pub fn ratio(&self) -> Option<f64> {
+        let short = self.with_restrictions + self.without_restrictions;
+        let total = self.selling + short;
+        match total > 0.0 {
+            true => Some(short / total * 100.0),
+            false => None,
+        }
+    }
+}
+
+/// Replaces the days already stored
+pub fn insert(conn: &mut Connection, short_selling: &[ShortSelling]) -> Result<(), MyError> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO short_selling
+            (date, sector33_code, selling, short_selling_with_restrictions,
+            short_selling_without_restrictions)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for x in short_selling {
+            stmt.execute((
+                &x.date,
+                &x.sector33_code,
+                x.selling,
+                x.with_restrictions,
+                x.without_restrictions,
+            ))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// (date, code) -> short ratio (%) of the code's sector between `from` and `to`.
+/// J-Quants has it by sector only, so every code of a sector gets the same ratio.
+pub fn select_ratios(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+) -> Result<HashMap<(String, StockCode), f64>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT s.date, m.code, s.selling, s.short_selling_with_restrictions,
+        s.short_selling_without_restrictions
+        FROM short_selling s JOIN stocks_master m ON m.sector33_code = s.sector33_code
+        WHERE s.date BETWEEN ?1 AND ?2",
+    )?;
+    let rows = stmt.query_map([from, to], |row| {
+        let date: String = row.get(0)?;
+        let code: StockCode = row.get(1)?;
+        Ok((
+            code,
+            ShortSelling::new(&date, "", row.get(2)?, row.get(3)?, row.get(4)?),
+        ))
+    })?;
+    let mut ratios = HashMap::new();
+    for row in rows {
+        let (code, short_selling) = row?;
+        if let Some(ratio) = short_selling.ratio() {
+            ratios.insert((short_selling.date, code), ratio);
+        }
+    }
+    Ok(ratios)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::stocks_master::{self, StockMaster};
+
+    #[test]
+    fn test_select_ratios() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+        stocks_master::create_table(&conn).unwrap();
+
+        let toyota = StockCode::new("7203").unwrap();
+        let honda = StockCode::new("7267").unwrap();
+        stocks_master::upsert(
+            &mut conn,
+            &[
+                StockMaster::new(toyota.clone(), "トヨタ自動車", "輸送用機器", "3700", "P"),
+                StockMaster::new(honda.clone(), "本田技研工業", "輸送用機器", "3700", "P"),
+            ],
+        )
+        .unwrap();
+        insert(
+            &mut conn,
+            &[
+                ShortSelling::new("2024-01-04", "3700", 600.0, 300.0, 100.0),
+                ShortSelling::new("2024-01-05", "3700", 0.0, 0.0, 0.0),
+                ShortSelling::new("2024-01-05", "3650", 500.0, 500.0, 0.0),
+            ],
+        )
+        .unwrap();
+
+        let ratios = select_ratios(&conn, "2024-01-01", "2024-01-31").unwrap();
+        assert_eq!(ratios.len(), 2);
+        assert_eq!(ratios[&("2024-01-04".to_owned(), toyota)], 40.0);
+        assert_eq!(ratios[&("2024-01-04".to_owned(), honda.clone())], 40.0);
+        assert!(!ratios.contains_key(&("2024-01-05".to_owned(), honda)));
+    }
+}
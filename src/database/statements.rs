@@ -0,0 +1,345 @@
+use chrono::{Months, NaiveDate};
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+use crate::{my_error::MyError, stock_code::StockCode};
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS statements (
+            id INTEGER PRIMARY KEY,
+            code TEXT NOT NULL,
+            disclosed_date TEXT NOT NULL,
+            type_of_document TEXT NOT NULL,
+            fiscal_year_end TEXT NOT NULL,
+            eps REAL,
+            forecast_eps REAL,
+            UNIQUE(code, disclosed_date, type_of_document))",
+        (),
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS announcements (
+            code TEXT PRIMARY KEY,
+            date TEXT NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// EPS and the forecast of a disclosure from J-Quants `/fins/statements`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    code: StockCode,
+    /// "YYYY-MM-DD"
+    disclosed_date: String,
+    /// e.g. "3QFinancialStatements_Consolidated_JP", "EarnForecastRevision"
+    type_of_document: String,
+    /// "YYYY-MM-DD", forecasts are compared within a fiscal year
+    fiscal_year_end: String,
+    eps: Option<f64>,
+    /// EPS forecast for the fiscal year
+    forecast_eps: Option<f64>,
+}
+
+impl Statement {
+    pub fn new(
+        code: StockCode,
+        disclosed_date: &str,
+        type_of_document: &str,
+        fiscal_year_end: &str,
+        eps: Option<f64>,
+        forecast_eps: Option<f64>,
+    ) -> Self {
+        Statement {
+            code,
+            disclosed_date: disclosed_date.to_owned(),
+            type_of_document: type_of_document.to_owned(),
+            fiscal_year_end: fiscal_year_end.to_owned(),
+            eps,
+            forecast_eps,
+        }
+    }
+
+    //getters
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
+    pub fn get_disclosed_date(&self) -> &str {
+        &self.disclosed_date
+    }
+    pub fn get_eps(&self) -> Option<f64> {
+        self.eps
+    }
+    pub fn get_forecast_eps(&self) -> Option<f64> {
+        self.forecast_eps
+    }
+}
+
+/// Change of the EPS forecast between two disclosures of the same fiscal year
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForecastRevision {
+    disclosed_date: String,
+    from: f64,
+    to: f64,
+}
+
+impl ForecastRevision {
+    pub fn get_disclosed_date(&self) -> &str {
+        &self.disclosed_date
+    }
+    /// to / from - 1, e.g. 0.1 for an upward revision of 10%
+    pub fn ratio(&self) -> f64 {
+        self.to / self.from - 1.0
+    }
+}
+
+/// Replaces statements already stored (J-Quants corrects them in place)
+pub fn insert(conn: &mut Connection, statements: &[Statement]) -> Result<(), MyError> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO statements
+            (code, disclosed_date, type_of_document, fiscal_year_end, eps, forecast_eps)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for statement in statements {
+            stmt.execute((
+                &statement.code,
+                &statement.disclosed_date,
+                &statement.type_of_document,
+                &statement.fiscal_year_end,
+                statement.eps,
+                statement.forecast_eps,
+            ))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Oldest first
+pub fn select_by_code(conn: &Connection, code: &StockCode) -> Result<Vec<Statement>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT code, disclosed_date, type_of_document, fiscal_year_end, eps, forecast_eps
+        FROM statements WHERE code = ?1 ORDER BY disclosed_date",
+    )?;
+    let rows = stmt.query_map([code], |row| {
+        Ok(Statement {
+            code: row.get(0)?,
+            disclosed_date: row.get(1)?,
+            type_of_document: row.get(2)?,
+            fiscal_year_end: row.get(3)?,
+            eps: row.get(4)?,
+            forecast_eps: row.get(5)?,
+        })
+    })?;
+    let mut statements = Vec::new();
+    for row in rows {
+        statements.push(row?);
+    }
+    Ok(statements)
+}
+
+/// Last quarterly or annual results of each code, forecast revisions are left out
+pub fn select_last_results(conn: &Connection) -> Result<HashMap<StockCode, NaiveDate>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT code, MAX(disclosed_date) FROM statements
+        WHERE type_of_document LIKE '%FinancialStatements%' GROUP BY code",
+    )?;
+    let rows = stmt.query_map((), |row| {
+        let code: StockCode = row.get(0)?;
+        let date: String = row.get(1)?;
+        Ok((code, date))
+    })?;
+    let mut last_results = HashMap::new();
+    for row in rows {
+        let (code, date) = row?;
+        if let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            last_results.insert(code, date);
+        }
+    }
+    Ok(last_results)
+}
+
+/// Replaces the scheduled date of the next results of each code, from J-Quants
+/// `/fins/announcement`
+pub fn insert_announcements(
+    conn: &mut Connection,
+    announcements: &[(StockCode, String)],
+) -> Result<(), MyError> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt =
+            tx.prepare("INSERT OR REPLACE INTO announcements (code, date) VALUES (?1, ?2)")?;
+        for (code, date) in announcements {
+            stmt.execute((code, date))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Scheduled date of the next results of each code
+pub fn select_announcements(conn: &Connection) -> Result<HashMap<StockCode, NaiveDate>, MyError> {
+    let mut stmt = conn.prepare("SELECT code, date FROM announcements")?;
+    let rows = stmt.query_map((), |row| {
+        let code: StockCode = row.get(0)?;
+        let date: String = row.get(1)?;
+        Ok((code, date))
+    })?;
+    let mut announcements = HashMap::new();
+    for row in rows {
+        let (code, date) = row?;
+        if let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+            announcements.insert(code, date);
+        }
+    }
+    Ok(announcements)
+}
+
+/// Estimate when the schedule isn't announced yet: results come out every quarter,
+/// around the same time after each period end
+pub fn next_results(last_results: NaiveDate) -> NaiveDate {
+    last_results + Months::new(3)
+}
+
+/// The last results and the announcement schedule of every code
+#[derive(Debug, Default)]
+pub struct ResultsCalendar {
+    last_results: HashMap<StockCode, NaiveDate>,
+    scheduled: HashMap<StockCode, NaiveDate>,
+}
+
+impl ResultsCalendar {
+    pub fn select(conn: &Connection) -> Result<Self, MyError> {
+        Ok(ResultsCalendar {
+            last_results: select_last_results(conn)?,
+            scheduled: select_announcements(conn)?,
+        })
+    }
+
+    /// The next results of `code` on or after `date`: the scheduled date when it is
+    /// announced, estimated from the last results otherwise
+    pub fn next_results(&self, code: &StockCode, date: NaiveDate) -> Option<NaiveDate> {
+        match self.scheduled.get(code).filter(|x| **x >= date) {
+            Some(scheduled) => Some(*scheduled),
+            None => self.last_results.get(code).map(|x| next_results(*x)),
+        }
+    }
+
+    /// The next results of `code` when they fall within `days` after `date`
+    pub fn reports_within(
+        &self,
+        code: &StockCode,
+        date: NaiveDate,
+        days: i64,
+    ) -> Option<NaiveDate> {
+        self.next_results(code, date)
+            .filter(|x| (0..=days).contains(&(*x - date).num_days()))
+    }
+}
+
+/// Revisions of the EPS forecast, oldest first. `statements` must be sorted by date.
+pub fn forecast_revisions(statements: &[Statement]) -> Vec<ForecastRevision> {
+    let mut revisions = Vec::new();
+    let mut last: Option<(&str, f64)> = None;
+    for statement in statements {
+        let Some(forecast) = statement.forecast_eps else {
+            continue;
+        };
+        if let Some((fiscal_year_end, from)) = last {
+            if fiscal_year_end == statement.fiscal_year_end && from != forecast && from != 0.0 {
+                revisions.push(ForecastRevision {
+                    disclosed_date: statement.disclosed_date.clone(),
+                    from,
+                    to: forecast,
+                });
+            }
+        }
+        last = Some((&statement.fiscal_year_end, forecast));
+    }
+    revisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_revisions() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let code = StockCode::new("7203").unwrap();
+        let statement = |date: &str, document: &str, fiscal_year_end: &str, forecast: f64| {
+            Statement::new(
+                code.clone(),
+                date,
+                document,
+                fiscal_year_end,
+                Some(50.0),
+                Some(forecast),
+            )
+        };
+        insert(
+            &mut conn,
+            &[
+                statement(
+                    "2023-08-01",
+                    "1QFinancialStatements_Consolidated_IFRS",
+                    "2024-03-31",
+                    200.0,
+                ),
+                statement(
+                    "2023-11-01",
+                    "2QFinancialStatements_Consolidated_IFRS",
+                    "2024-03-31",
+                    220.0,
+                ),
+                statement("2023-12-20", "EarnForecastRevision", "2024-03-31", 220.0),
+                statement(
+                    "2024-05-08",
+                    "FYFinancialStatements_Consolidated_IFRS",
+                    "2025-03-31",
+                    230.0,
+                ),
+            ],
+        )
+        .unwrap();
+
+        let statements = select_by_code(&conn, &code).unwrap();
+        assert_eq!(statements.len(), 4);
+        let revisions = forecast_revisions(&statements);
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].get_disclosed_date(), "2023-11-01");
+        assert!((revisions[0].ratio() - 0.1).abs() < 1e-9);
+
+        let last_results = select_last_results(&conn).unwrap();
+        let last = NaiveDate::from_ymd_opt(2024, 5, 8).unwrap();
+        assert_eq!(last_results[&code], last);
+        let estimated = NaiveDate::from_ymd_opt(2024, 8, 8).unwrap();
+        assert_eq!(next_results(last), estimated);
+
+        let calendar = ResultsCalendar::select(&conn).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 8, 5).unwrap();
+        assert_eq!(calendar.reports_within(&code, date, 3), Some(estimated));
+        assert_eq!(calendar.reports_within(&code, date, 2), None);
+        let later = date + chrono::Duration::days(4);
+        assert_eq!(calendar.reports_within(&code, later, 5), None);
+
+        // the schedule wins over the estimate until it has passed
+        insert_announcements(&mut conn, &[(code.clone(), "2024-08-01".to_owned())]).unwrap();
+        let scheduled = NaiveDate::from_ymd_opt(2024, 8, 1).unwrap();
+        let calendar = ResultsCalendar::select(&conn).unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 7, 30).unwrap();
+        assert_eq!(calendar.reports_within(&code, date, 3), Some(scheduled));
+        let date = NaiveDate::from_ymd_opt(2024, 8, 5).unwrap();
+        assert_eq!(calendar.reports_within(&code, date, 3), Some(estimated));
+    }
+}
@@ -1,17 +1,15 @@
-use crate::{analysis::live::OhlcAnalyzer, my_error::MyError};
+use crate::{
+    analysis::live::OhlcAnalyzer, my_error::MyError, rounding::round_dp, stock_code::StockCode,
+};
 use anyhow::Result;
 use chrono::{Local, TimeZone};
 use log::info;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
-use std::{env, fmt::Write, path::Path};
+use std::fmt::Write;
 
 pub fn open_db() -> Result<Connection, MyError> {
-    let gdrive_path = env::var("GDRIVE_PATH")?;
-    let sqlite_path = Path::new(&gdrive_path)
-        .join("trading23")
-        .join("trading23.sqlite");
-    let conn = Connection::open(sqlite_path)?;
+    let conn = super::open()?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS stocks (
@@ -33,13 +31,13 @@ pub fn open_db() -> Result<Connection, MyError> {
 }
 
 pub struct NewStock {
-    code: i32,
+    code: StockCode,
     name: String,
     ohlc_analyzer: OhlcAnalyzer,
 }
 
 impl NewStock {
-    pub fn new(code: i32, name: &str, ohlc_analyzer: OhlcAnalyzer) -> Self {
+    pub fn new(code: StockCode, name: &str, ohlc_analyzer: OhlcAnalyzer) -> Self {
         let name = name.to_string();
 
         Self {
@@ -87,7 +85,7 @@ impl NewStock {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Stock {
     id: i32,
-    code: i32,
+    code: StockCode,
     name: String,
     break_or_not: String,
     long_or_short: String,
@@ -106,7 +104,7 @@ impl Stock {
     }
     fn output_stock_data(&self, mut buffer: String) -> String {
         let required_amount = self.stop_loss_order.unwrap() * self.units.unwrap() as f64;
-        let required_amount_rounded: i32 = (required_amount * 10.0).round() as i32 / 10;
+        let required_amount_rounded = round_dp(required_amount, 0) as i32;
 
         let stop_loss_order_rounded: i32 = self.stop_loss_order.unwrap().round() as i32;
         let stop_loss_order_str = stop_loss_order_rounded.to_string() + "円";
@@ -182,6 +180,9 @@ impl StockList {
     pub fn len(&self) -> usize {
         self.stocks.len()
     }
+    pub fn is_empty(&self) -> bool {
+        self.stocks.is_empty()
+    }
 }
 
 struct DateAndLongShortCount {
@@ -0,0 +1,201 @@
+use chrono::Local;
+use log::warn;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+use crate::{my_error::MyError, stock_code::StockCode};
+
+/// code -> 33-sector name, for the report tables
+pub type Sectors = HashMap<StockCode, String>;
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS stocks_master (
+            code TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            sector33 TEXT NOT NULL,
+            sector33_code TEXT NOT NULL DEFAULT '',
+            market TEXT NOT NULL,
+            updated_at TEXT NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// A listed stock from J-Quants `/listed/info`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StockMaster {
+    code: StockCode,
+    name: String,
+    /// 33-sector name, e.g. "輸送用機器"
+    sector33: String,
+    /// e.g. "3700", the key of sector statistics such as `/markets/short_selling`
+    sector33_code: String,
+    /// market segment, e.g. "プライム"
+    market: String,
+}
+
+impl StockMaster {
+    pub fn new(
+        code: StockCode,
+        name: &str,
+        sector33: &str,
+        sector33_code: &str,
+        market: &str,
+    ) -> Self {
+        StockMaster {
+            code,
+            name: name.to_owned(),
+            sector33: sector33.to_owned(),
+            sector33_code: sector33_code.to_owned(),
+            market: market.to_owned(),
+        }
+    }
+
+    //getters
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_sector33(&self) -> &str {
+        &self.sector33
+    }
+    pub fn get_sector33_code(&self) -> &str {
+        &self.sector33_code
+    }
+    pub fn get_market(&self) -> &str {
+        &self.market
+    }
+}
+
+/// Replaces the rows of the given codes, delisted codes are left as they were
+pub fn upsert(conn: &mut Connection, masters: &[StockMaster]) -> Result<(), MyError> {
+    let updated_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO stocks_master
+            (code, name, sector33, sector33_code, market, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for master in masters {
+            stmt.execute((
+                &master.code,
+                &master.name,
+                &master.sector33,
+                &master.sector33_code,
+                &master.market,
+                &updated_at,
+            ))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn select_all(conn: &Connection) -> Result<Vec<StockMaster>, MyError> {
+    let mut stmt =
+        conn.prepare("SELECT code, name, sector33, sector33_code, market FROM stocks_master")?;
+    let masters = stmt
+        .query_map((), |row| {
+            Ok(StockMaster {
+                code: row.get(0)?,
+                name: row.get(1)?,
+                sector33: row.get(2)?,
+                sector33_code: row.get(3)?,
+                market: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(masters)
+}
+
+pub fn select_sectors(conn: &Connection) -> Result<Sectors, MyError> {
+    let mut stmt = conn.prepare("SELECT code, sector33 FROM stocks_master")?;
+    let rows = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let mut sectors = Sectors::new();
+    for row in rows {
+        let (code, sector33) = row?;
+        sectors.insert(code, sector33);
+    }
+    Ok(sectors)
+}
+
+/// Sectors for the reports, empty (shown as "-") until `trading23 master` has run
+pub fn load_sectors() -> Sectors {
+    match open_db().and_then(|conn| select_sectors(&conn)) {
+        Ok(sectors) => sectors,
+        Err(e) => {
+            warn!("stocks_master unavailable, sectors are left out: {}", e);
+            Sectors::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upsert_and_select_sectors() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let toyota = StockCode::new("7203").unwrap();
+        upsert(
+            &mut conn,
+            &[StockMaster::new(
+                toyota.clone(),
+                "トヨタ自動車",
+                "輸送用機器",
+                "3700",
+                "プライム",
+            )],
+        )
+        .unwrap();
+        upsert(
+            &mut conn,
+            &[
+                StockMaster::new(
+                    toyota.clone(),
+                    "トヨタ自動車",
+                    "輸送用機器",
+                    "3700",
+                    "プライム",
+                ),
+                StockMaster::new(
+                    StockCode::new("6758").unwrap(),
+                    "ソニーグループ",
+                    "電気機器",
+                    "3650",
+                    "プライム",
+                ),
+            ],
+        )
+        .unwrap();
+
+        let sectors = select_sectors(&conn).unwrap();
+        assert_eq!(sectors.len(), 2);
+        assert_eq!(sectors[&toyota], "輸送用機器");
+
+        // a table from before sector33_code gets the column
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE stocks_master (code TEXT PRIMARY KEY, name TEXT NOT NULL,
+            sector33 TEXT NOT NULL, market TEXT NOT NULL, updated_at TEXT NOT NULL)",
+            (),
+        )
+        .unwrap();
+        create_table(&conn).unwrap();
+        create_table(&conn).unwrap();
+        assert!(select_sectors(&conn).unwrap().is_empty());
+    }
+}
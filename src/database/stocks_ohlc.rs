@@ -1,8 +1,12 @@
-use std::{env, path::Path};
+use std::collections::HashSet;
 
-use crate::{analysis::live::OhlcPremium, my_error::MyError};
+use crate::{
+    analysis::live::OhlcPremium,
+    my_error::MyError,
+    profile::{self, Stage},
+    stock_code::StockCode,
+};
 use chrono::Local;
-use log::debug;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 
@@ -20,12 +24,12 @@ impl StocksOhlc {
 }
 
 pub fn open_db() -> Result<Connection, MyError> {
-    let gdrive_path = env::var("GDRIVE_PATH")?;
-    let sqlite_path = Path::new(&gdrive_path)
-        .join("trading23")
-        .join("trading23.sqlite");
-    let conn = Connection::open(sqlite_path)?;
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
 
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS stocks_ohlc (
             id INTEGER PRIMARY KEY,
@@ -37,61 +41,174 @@ pub fn open_db() -> Result<Connection, MyError> {
             close REAL NOT NULL,
             morning_close REAL NOT NULL,
             afternoon_open REAL NOT NULL,
-            created_at TEXT NOT NULL)",
+            created_at TEXT NOT NULL,
+            volume REAL,
+            turnover REAL)",
         (),
     )?;
-    Ok(conn)
+    // select_by_code and select_by_date scan the whole table without them
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS stocks_ohlc_code ON stocks_ohlc (code)",
+        (),
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS stocks_ohlc_date ON stocks_ohlc (date)",
+        (),
+    )?;
+    Ok(())
 }
 
-pub fn select_by_code(conn: &Connection, code: &str) -> Result<Vec<StocksOhlc>, MyError> {
-    let mut stmt = conn.prepare("SELECT * FROM stocks_ohlc WHERE code = ?1")?;
-    let mut rows = stmt.query([&code])?;
-    let mut ohlcs = Vec::new();
-    while let Some(row) = rows.next()? {
-        let inner = OhlcPremium::new(
-            row.get(1)?,
-            row.get(2)?,
-            row.get(3)?,
-            row.get(4)?,
-            row.get(5)?,
-            row.get(6)?,
-            row.get(7)?,
-            row.get(8)?,
-        );
-        ohlcs.push(StocksOhlc {
-            id: row.get(0)?,
-            created_at: row.get(9)?,
-            inner,
-        });
-    }
-    // debug!("{:?}", ohlcs);
+const COLUMNS: &str = "id, code, date, open, high, low, close, morning_close, afternoon_open,
+    created_at, volume, turnover";
+
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<StocksOhlc> {
+    let inner = OhlcPremium::new(
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+        row.get(6)?,
+        row.get(7)?,
+        row.get(8)?,
+    )
+    .with_liquidity(row.get(10)?, row.get(11)?);
+    Ok(StocksOhlc {
+        id: row.get(0)?,
+        created_at: row.get(9)?,
+        inner,
+    })
+}
+
+pub fn select_by_code(conn: &Connection, code: &StockCode) -> Result<Vec<StocksOhlc>, MyError> {
+    let _span = profile::span(Stage::DbRead);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM stocks_ohlc WHERE code = ?1",
+        COLUMNS
+    ))?;
+    let ohlcs = stmt
+        .query_map([code], from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ohlcs)
+}
+
+/// Bars of the code between `from` and `to` ("YYYY-MM-DD", both included), ordered by date
+pub fn select_by_code_and_range(
+    conn: &Connection,
+    code: &StockCode,
+    from: &str,
+    to: &str,
+) -> Result<Vec<StocksOhlc>, MyError> {
+    select_by_code_and_range_known_at(conn, code, from, to, None)
+}
+
+/// `select_by_code_and_range` without the bars stored after `known_at` ("YYYY-MM-DD
+/// HH:MM:SS" of created_at), the bars a run at that time read. All of them when None
+pub fn select_by_code_and_range_known_at(
+    conn: &Connection,
+    code: &StockCode,
+    from: &str,
+    to: &str,
+    known_at: Option<&str>,
+) -> Result<Vec<StocksOhlc>, MyError> {
+    let _span = profile::span(Stage::DbRead);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM stocks_ohlc WHERE code = ?1 AND date BETWEEN ?2 AND ?3
+        AND (?4 IS NULL OR created_at <= ?4) ORDER BY date",
+        COLUMNS
+    ))?;
+    let ohlcs = stmt
+        .query_map(rusqlite::params![code, from, to, known_at], from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ohlcs)
+}
+
+/// Bars of `code` (all codes when None) between `from` and `to` ("YYYY-MM-DD", both
+/// included, open ended when None), ordered by code and date
+pub fn select_for_export(
+    conn: &Connection,
+    code: Option<&StockCode>,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<OhlcPremium>, MyError> {
+    let _span = profile::span(Stage::DbRead);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM stocks_ohlc
+        WHERE (?1 IS NULL OR code = ?1) AND (?2 IS NULL OR date >= ?2) AND (?3 IS NULL OR date <= ?3)
+        ORDER BY code, date",
+        COLUMNS
+    ))?;
+    let ohlcs = stmt
+        .query_map(rusqlite::params![code, from, to], |row| {
+            from_row(row).map(|x| x.inner)
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
     Ok(ohlcs)
 }
 
 pub fn select_by_date(conn: &Connection, date: &str) -> Result<Vec<StocksOhlc>, MyError> {
-    let mut stmt = conn.prepare("SELECT * FROM stocks_ohlc WHERE date = ?1")?;
-    let mut rows = stmt.query([&date])?;
-    let mut ohlcs = Vec::new();
-    while let Some(row) = rows.next()? {
-        let inner = OhlcPremium::new(
-            row.get(1)?,
-            row.get(2)?,
-            row.get(3)?,
-            row.get(4)?,
-            row.get(5)?,
-            row.get(6)?,
-            row.get(7)?,
-            row.get(8)?,
-        );
-        ohlcs.push(StocksOhlc {
-            id: row.get(0)?,
-            created_at: row.get(9)?,
-            inner,
-        });
-    }
+    let _span = profile::span(Stage::DbRead);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM stocks_ohlc WHERE date = ?1",
+        COLUMNS
+    ))?;
+    let ohlcs = stmt
+        .query_map([&date], from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
     Ok(ohlcs)
 }
 
+/// Dates with any bar between `from` and `to` ("YYYY-MM-DD", both included)
+pub fn select_dates(conn: &Connection, from: &str, to: &str) -> Result<HashSet<String>, MyError> {
+    select_dates_known_at(conn, from, to, None)
+}
+
+/// `select_dates` of the bars stored by `known_at`, see `select_by_code_and_range_known_at`
+pub fn select_dates_known_at(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+    known_at: Option<&str>,
+) -> Result<HashSet<String>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT date FROM stocks_ohlc WHERE date BETWEEN ?1 AND ?2
+        AND (?3 IS NULL OR created_at <= ?3)",
+    )?;
+    let dates = stmt
+        .query_map(rusqlite::params![from, to, known_at], |row| row.get(0))?
+        .collect::<Result<HashSet<String>, _>>()?;
+    Ok(dates)
+}
+
+/// (code, date) of every bar between `from` and `to` ("YYYY-MM-DD", both included)
+pub fn select_cells(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+) -> Result<HashSet<(StockCode, String)>, MyError> {
+    let mut stmt =
+        conn.prepare("SELECT code, date FROM stocks_ohlc WHERE date BETWEEN ?1 AND ?2")?;
+    let cells = stmt
+        .query_map([from, to], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<HashSet<(StockCode, String)>, _>>()?;
+    Ok(cells)
+}
+
+/// Latest `limit` distinct dates before `before` ("YYYY-MM-DD"), newest first
+pub fn select_latest_dates(
+    conn: &Connection,
+    before: &str,
+    limit: usize,
+) -> Result<Vec<String>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT date FROM stocks_ohlc WHERE date < ?1 ORDER BY date DESC LIMIT ?2",
+    )?;
+    let dates = stmt
+        .query_map(rusqlite::params![before, limit as i64], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(dates)
+}
+
 // pub fn select_by_code_and_date(
 //     conn: &Connection,
 //     code: i32,
@@ -120,24 +237,48 @@ pub fn select_by_date(conn: &Connection, date: &str) -> Result<Vec<StocksOhlc>,
 //     Ok(ohlcs)
 // }
 
+const INSERT: &str = "INSERT INTO stocks_ohlc (code, date, open, high, low, close, morning_close, afternoon_open, created_at, volume, turnover)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)";
+
+fn execute_insert(
+    stmt: &mut rusqlite::Statement,
+    ohlc: &OhlcPremium,
+    created_at: &str,
+) -> Result<(), MyError> {
+    stmt.execute(rusqlite::params![
+        ohlc.get_code().to_string(),
+        ohlc.get_date(),
+        ohlc.get_open(),
+        ohlc.get_high(),
+        ohlc.get_low(),
+        ohlc.get_close(),
+        ohlc.get_morning_close(),
+        ohlc.get_afternoon_open(),
+        created_at,
+        ohlc.get_volume(),
+        ohlc.get_turnover(),
+    ])?;
+    Ok(())
+}
+
 pub fn insert(conn: &Connection, ohlc: &OhlcPremium) -> Result<(), MyError> {
     let created_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    let code = ohlc.get_code();
-    conn.execute(
-        "INSERT INTO stocks_ohlc (code, date, open, high, low, close, morning_close, afternoon_open, created_at)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        [
-            code.to_string(),
-            ohlc.get_date().to_string(),
-            ohlc.get_open().to_string(),
-            ohlc.get_high().to_string(),
-            ohlc.get_low().to_string(),
-            ohlc.get_close().to_string(),
-            ohlc.get_morning_close().to_string(),
-            ohlc.get_afternoon_open().to_string(),
-            created_at,
-        ],
-    )?;
+    let mut stmt = conn.prepare_cached(INSERT)?;
+    execute_insert(&mut stmt, ohlc, &created_at)
+}
+
+/// Inserts all the bars in one transaction, none of them when one fails.
+/// Each commit is a sync of the file, which is slow on the Google Drive mount
+pub fn insert_batch(conn: &mut Connection, ohlcs: &[OhlcPremium]) -> Result<(), MyError> {
+    let created_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(INSERT)?;
+        for ohlc in ohlcs {
+            execute_insert(&mut stmt, ohlc, &created_at)?;
+        }
+    }
+    tx.commit()?;
     Ok(())
 }
 
@@ -145,3 +286,175 @@ pub fn insert(conn: &Connection, ohlc: &OhlcPremium) -> Result<(), MyError> {
 //     conn.execute("DELETE FROM stocks_ohlc WHERE code = ?1", [&code])?;
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_liquidity_columns() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // a table from before volume and turnover were kept
+        conn.execute(
+            "CREATE TABLE stocks_ohlc (
+                id INTEGER PRIMARY KEY,
+                code TEXT NOT NULL,
+                date TEXT NOT NULL,
+                open REAL NOT NULL,
+                high REAL NOT NULL,
+                low REAL NOT NULL,
+                close REAL NOT NULL,
+                morning_close REAL NOT NULL,
+                afternoon_open REAL NOT NULL,
+                created_at TEXT NOT NULL)",
+            (),
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO stocks_ohlc (code, date, open, high, low, close, morning_close, afternoon_open, created_at)
+            VALUES ('7203', '2024-01-04', 1, 2, 1, 2, 2, 2, '2024-01-04 18:00:00')",
+            (),
+        )
+        .unwrap();
+        crate::database::migrations::migrate(&mut conn).unwrap();
+        create_table(&conn).unwrap();
+
+        let code = StockCode::new("7203").unwrap();
+        let ohlc = OhlcPremium::new(
+            code.clone(),
+            "2024-01-05".to_owned(),
+            2.0,
+            3.0,
+            1.5,
+            2.5,
+            2.0,
+            2.1,
+        )
+        .with_liquidity(Some(1000.0), Some(2500.0));
+        insert(&conn, &ohlc).unwrap();
+
+        let ohlcs = select_by_code(&conn, &code)
+            .unwrap()
+            .into_iter()
+            .map(|x| x.get_inner())
+            .collect::<Vec<_>>();
+        assert_eq!(ohlcs.len(), 2);
+        assert_eq!(ohlcs[0].get_turnover(), None);
+        assert_eq!(ohlcs[1].get_volume(), Some(1000.0));
+        assert_eq!(ohlcs[1].get_turnover(), Some(2500.0));
+        assert_eq!(ohlcs[1].get_close(), 2.5);
+    }
+
+    #[test]
+    fn test_insert_batch() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+        let ohlc = |code: &str, date: &str| {
+            OhlcPremium::new(
+                StockCode::new(code).unwrap(),
+                date.to_owned(),
+                2.0,
+                3.0,
+                1.5,
+                2.5,
+                2.0,
+                2.1,
+            )
+        };
+        insert_batch(
+            &mut conn,
+            &[ohlc("7203", "2024-01-05"), ohlc("6758", "2024-01-05")],
+        )
+        .unwrap();
+        assert_eq!(select_by_date(&conn, "2024-01-05").unwrap().len(), 2);
+
+        // a failing row rolls back the rows before it
+        conn.execute(
+            "CREATE TRIGGER reject BEFORE INSERT ON stocks_ohlc WHEN NEW.code = '9984'
+            BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+            (),
+        )
+        .unwrap();
+        assert!(insert_batch(
+            &mut conn,
+            &[ohlc("7203", "2024-01-09"), ohlc("9984", "2024-01-09")],
+        )
+        .is_err());
+        assert!(select_by_date(&conn, "2024-01-09").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_select_by_code_and_range() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+        let ohlc = |code: &str, date: &str| {
+            OhlcPremium::new(
+                StockCode::new(code).unwrap(),
+                date.to_owned(),
+                2.0,
+                3.0,
+                1.5,
+                2.5,
+                2.0,
+                2.1,
+            )
+        };
+        insert_batch(
+            &mut conn,
+            &[
+                ohlc("7203", "2024-01-09"),
+                ohlc("7203", "2024-01-04"),
+                ohlc("7203", "2024-01-05"),
+                ohlc("6758", "2024-01-05"),
+                ohlc("7203", "2024-01-10"),
+            ],
+        )
+        .unwrap();
+
+        let code = StockCode::new("7203").unwrap();
+        let dates = select_by_code_and_range(&conn, &code, "2024-01-05", "2024-01-09")
+            .unwrap()
+            .into_iter()
+            .map(|x| x.get_inner().get_date().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(dates, vec!["2024-01-05", "2024-01-09"]);
+
+        // the bars from 01-09 on fetched after the cutoff
+        conn.execute(
+            "UPDATE stocks_ohlc SET created_at = CASE WHEN date >= '2024-01-09'
+            THEN '2024-01-10 18:00:00' ELSE '2024-01-08 18:00:00' END",
+            (),
+        )
+        .unwrap();
+        let known_at = Some("2024-01-09 23:59:59");
+        let dates =
+            select_by_code_and_range_known_at(&conn, &code, "2024-01-05", "2024-01-09", known_at)
+                .unwrap();
+        assert_eq!(dates.len(), 1);
+        assert_eq!(
+            select_dates_known_at(&conn, "2024-01-01", "2024-01-31", known_at).unwrap(),
+            HashSet::from(["2024-01-04".to_owned(), "2024-01-05".to_owned()])
+        );
+        assert_eq!(
+            select_dates(&conn, "2024-01-01", "2024-01-31")
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_indexes() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+        let plan = |sql: &str| {
+            conn.query_row(&format!("EXPLAIN QUERY PLAN {}", sql), [], |row| {
+                row.get::<_, String>(3)
+            })
+            .unwrap()
+        };
+        assert!(plan("SELECT * FROM stocks_ohlc WHERE code = '7203'").contains("stocks_ohlc_code"));
+        assert!(plan("SELECT * FROM stocks_ohlc WHERE date = '2024-01-05'")
+            .contains("stocks_ohlc_date"));
+    }
+}
@@ -0,0 +1,308 @@
+use chrono::Local;
+use rusqlite::{Connection, OptionalExtension};
+use std::sync::Arc;
+
+use crate::analysis::stocks_window::StocksWindow;
+use crate::my_error::MyError;
+use crate::signal::SignalId;
+use crate::stock_code::StockCode;
+use crate::units::AtrUnits;
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+/// The windows analysis of each (code, date) with its results on the next day,
+/// results are NULL until the next day's bar is stored
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS stocks_window (
+            code TEXT NOT NULL,
+            analyzed_at TEXT NOT NULL,
+            name TEXT NOT NULL,
+            current_price REAL NOT NULL,
+            atr REAL NOT NULL,
+            unit INTEGER NOT NULL,
+            standardized_diff REAL NOT NULL,
+            latest_move REAL NOT NULL,
+            resistance_candles INTEGER NOT NULL,
+            support_candles INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            regime TEXT NOT NULL,
+            turnover_20 REAL,
+            result_morning REAL,
+            result_afternoon REAL,
+            result_allday REAL,
+            result_at TEXT,
+            created_at TEXT NOT NULL,
+            signal_id TEXT,
+            market_regime TEXT,
+            PRIMARY KEY (code, analyzed_at))",
+        (),
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS stocks_window_signal_id ON stocks_window (signal_id)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Replaces the windows already stored, so a rerun on the next day fills the results.
+/// Stale windows (no bar on the date) aren't stored, returns the number stored
+pub fn insert(conn: &mut Connection, windows: &[Arc<StocksWindow>]) -> Result<usize, MyError> {
+    let created_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let tx = conn.transaction()?;
+    let mut stored = 0;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO stocks_window
+            (code, analyzed_at, name, current_price, atr, unit, standardized_diff, latest_move,
+            resistance_candles, support_candles, status, regime, turnover_20,
+            result_morning, result_afternoon, result_allday, result_at, created_at, signal_id,
+            market_regime)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18,
+            ?19, ?20)",
+        )?;
+        for x in windows.iter().filter(|x| !x.is_stale()) {
+            let [morning, afternoon, allday] = x.get_results().map(|x| x.map(|x| x.0));
+            stmt.execute(rusqlite::params![
+                x.get_code(),
+                x.get_analyzed_at(),
+                x.get_name(),
+                x.get_current_price(),
+                x.get_atr(),
+                x.get_unit(),
+                x.get_standardized_diff(),
+                x.get_latest_move(),
+                x.get_resistance_candles(),
+                x.get_support_candles(),
+                x.get_status(),
+                x.get_regime().to_string(),
+                x.get_turnover_20(),
+                morning,
+                afternoon,
+                allday,
+                x.get_result_at(),
+                created_at,
+                x.signal_id()?,
+                x.get_market_regime().map(|x| x.to_string()),
+            ])?;
+            stored += 1;
+        }
+    }
+    tx.commit()?;
+    Ok(stored)
+}
+
+/// A stored window, the signal and how it turned out
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredWindow {
+    signal_id: SignalId,
+    code: StockCode,
+    /// "YYYY-MM-DD"
+    analyzed_at: String,
+    status: String,
+    regime: String,
+    /// of TOPIX on `analyzed_at`, None for windows stored before the market_regime table
+    market_regime: Option<String>,
+    atr: f64,
+    standardized_diff: f64,
+    resistance_candles: usize,
+    support_candles: usize,
+    /// morning, afternoon and all day of the next day, in ATR
+    results: [Option<AtrUnits>; 3],
+}
+
+impl StoredWindow {
+    //getters
+    pub fn get_signal_id(&self) -> &SignalId {
+        &self.signal_id
+    }
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
+    pub fn get_analyzed_at(&self) -> &str {
+        &self.analyzed_at
+    }
+    pub fn get_status(&self) -> &str {
+        &self.status
+    }
+    pub fn get_regime(&self) -> &str {
+        &self.regime
+    }
+    pub fn get_market_regime(&self) -> Option<&str> {
+        self.market_regime.as_deref()
+    }
+    pub fn get_atr(&self) -> f64 {
+        self.atr
+    }
+    pub fn get_standardized_diff(&self) -> f64 {
+        self.standardized_diff
+    }
+    pub fn get_resistance_candles(&self) -> usize {
+        self.resistance_candles
+    }
+    pub fn get_support_candles(&self) -> usize {
+        self.support_candles
+    }
+    pub fn get_results(&self) -> [Option<AtrUnits>; 3] {
+        self.results
+    }
+}
+
+const COLUMNS: &str = "code, analyzed_at, status, regime, atr, standardized_diff,
+    resistance_candles, support_candles, result_morning, result_afternoon, result_allday,
+    signal_id, market_regime";
+
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<StoredWindow> {
+    let result = |i: usize| row.get::<_, Option<f64>>(i).map(|x| x.map(AtrUnits));
+    Ok(StoredWindow {
+        code: row.get(0)?,
+        analyzed_at: row.get(1)?,
+        status: row.get(2)?,
+        regime: row.get(3)?,
+        atr: row.get(4)?,
+        standardized_diff: row.get(5)?,
+        resistance_candles: row.get(6)?,
+        support_candles: row.get(7)?,
+        results: [result(8)?, result(9)?, result(10)?],
+        signal_id: row.get(11)?,
+        market_regime: row.get(12)?,
+    })
+}
+
+/// The window of `code` analyzed on `analyzed_at` ("YYYY-MM-DD")
+pub fn select_one(
+    conn: &Connection,
+    code: &StockCode,
+    analyzed_at: &str,
+) -> Result<Option<StoredWindow>, MyError> {
+    let window = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM stocks_window WHERE code = ?1 AND analyzed_at = ?2",
+                COLUMNS
+            ),
+            rusqlite::params![code, analyzed_at],
+            from_row,
+        )
+        .optional()?;
+    Ok(window)
+}
+
+/// The window a report or notification refers to by its ID
+pub fn select_by_signal_id(
+    conn: &Connection,
+    signal_id: &SignalId,
+) -> Result<Option<StoredWindow>, MyError> {
+    let window = conn
+        .query_row(
+            &format!("SELECT {} FROM stocks_window WHERE signal_id = ?1", COLUMNS),
+            [signal_id],
+            from_row,
+        )
+        .optional()?;
+    Ok(window)
+}
+
+/// Windows analyzed between `from` and `to` ("YYYY-MM-DD", both included),
+/// ordered by date and code
+pub fn select_between(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+) -> Result<Vec<StoredWindow>, MyError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM stocks_window WHERE analyzed_at BETWEEN ?1 AND ?2
+        ORDER BY analyzed_at, code",
+        COLUMNS
+    ))?;
+    let windows = stmt
+        .query_map([from, to], from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::indicators::AnalysisParams;
+    use crate::analysis::live::OhlcPremium;
+    use chrono::{Duration, NaiveDate};
+
+    #[test]
+    fn test_insert_and_select_between() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let code = StockCode::new("7203").unwrap();
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let ohlc_vec = (0..61)
+            .map(|i| {
+                let date = (start + Duration::days(i)).format("%Y-%m-%d").to_string();
+                let base = 1000.0 + (i % 7) as f64 * 10.0;
+                OhlcPremium::new(
+                    code.clone(),
+                    date,
+                    base,
+                    base + 20.0,
+                    base - 20.0,
+                    base + 5.0,
+                    base + 2.0,
+                    base + 3.0,
+                )
+            })
+            .collect::<Vec<_>>();
+        let window = |bars: usize, date: &str| {
+            Arc::new(
+                StocksWindow::from_vec(
+                    &ohlc_vec[..bars],
+                    &code,
+                    "Toyota",
+                    100.0,
+                    date,
+                    &AnalysisParams::default(),
+                )
+                .unwrap(),
+            )
+        };
+
+        // analyzed before the next day's bar, then again with it
+        assert_eq!(insert(&mut conn, &[window(60, "2024-02-29")]).unwrap(), 1);
+        let stored = select_between(&conn, "2024-02-29", "2024-02-29").unwrap();
+        assert_eq!(stored[0].get_results(), [None; 3]);
+
+        insert(
+            &mut conn,
+            &[window(61, "2024-02-29"), window(61, "2024-03-01")],
+        )
+        .unwrap();
+        let stored = select_between(&conn, "2024-02-01", "2024-03-31").unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].get_analyzed_at(), "2024-02-29");
+        assert_eq!(stored[0].get_code(), &code);
+        assert!(stored[0].get_results().iter().all(|x| x.is_some()));
+        assert_eq!(stored[1].get_results(), [None; 3]);
+        assert_eq!(stored[0].get_market_regime(), None);
+        assert_eq!(
+            select_one(&conn, &code, "2024-03-01").unwrap().as_ref(),
+            stored.get(1)
+        );
+        assert!(select_one(&conn, &code, "2024-03-02").unwrap().is_none());
+
+        // the ID stays the same across reruns
+        assert_eq!(
+            stored[0].get_signal_id(),
+            &window(60, "2024-02-29").signal_id().unwrap()
+        );
+        assert_ne!(stored[0].get_signal_id(), stored[1].get_signal_id());
+        assert_eq!(
+            select_by_signal_id(&conn, stored[1].get_signal_id())
+                .unwrap()
+                .as_ref(),
+            stored.get(1)
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use rusqlite::Connection;
+
+use crate::{analysis::live::Ohlc, my_error::MyError};
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS topix_ohlc (
+            date TEXT PRIMARY KEY,
+            open REAL NOT NULL,
+            high REAL NOT NULL,
+            low REAL NOT NULL,
+            close REAL NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Replaces the days already stored
+pub fn insert(conn: &mut Connection, ohlc_vec: &[Ohlc]) -> Result<(), MyError> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO topix_ohlc (date, open, high, low, close)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for ohlc in ohlc_vec {
+            stmt.execute((
+                ohlc.get_date(),
+                ohlc.get_open(),
+                ohlc.get_high(),
+                ohlc.get_low(),
+                ohlc.get_close(),
+            ))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Days between `from` and `to` ("YYYY-MM-DD", both included), oldest first
+pub fn select_by_date_range(conn: &Connection, from: &str, to: &str) -> Result<Vec<Ohlc>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT date, open, high, low, close FROM topix_ohlc
+        WHERE date BETWEEN ?1 AND ?2 ORDER BY date",
+    )?;
+    let rows = stmt.query_map([from, to], |row| {
+        Ok(Ohlc::new(
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+        ))
+    })?;
+    let mut ohlc_vec = Vec::new();
+    for row in rows {
+        ohlc_vec.push(row?);
+    }
+    Ok(ohlc_vec)
+}
+
+/// Every day stored, oldest first
+pub fn select_all(conn: &Connection) -> Result<Vec<Ohlc>, MyError> {
+    select_by_date_range(conn, "0000-00-00", "9999-12-31")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_select_by_date_range() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        let ohlc =
+            |date: &str, close: f64| Ohlc::new(date.to_owned(), 2400.0, 2420.0, 2390.0, close);
+        insert(
+            &mut conn,
+            &[
+                ohlc("2024-01-05", 2410.0),
+                ohlc("2024-01-04", 2400.0),
+                ohlc("2024-01-09", 2430.0),
+            ],
+        )
+        .unwrap();
+        insert(&mut conn, &[ohlc("2024-01-05", 2415.0)]).unwrap();
+
+        let selected = select_by_date_range(&conn, "2024-01-04", "2024-01-05").unwrap();
+        assert_eq!(
+            selected
+                .iter()
+                .map(|x| (x.get_date(), x.get_close()))
+                .collect::<Vec<_>>(),
+            vec![("2024-01-04", 2400.0), ("2024-01-05", 2415.0)]
+        );
+        assert_eq!(select_all(&conn).unwrap().len(), 3);
+    }
+}
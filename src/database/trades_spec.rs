@@ -0,0 +1,211 @@
+use log::warn;
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::i18n::{Lang, Msg};
+use crate::my_error::MyError;
+
+/// Market of the weekly flows shown in the reports
+pub const SECTION: &str = "TSEPrime";
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS trades_spec (
+            section TEXT NOT NULL,
+            start_date TEXT NOT NULL,
+            end_date TEXT NOT NULL,
+            published_date TEXT NOT NULL,
+            foreigners_balance REAL NOT NULL,
+            individuals_balance REAL NOT NULL,
+            PRIMARY KEY (section, start_date))",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Net purchases of a week by investor type from J-Quants `/markets/trades_spec`,
+/// in thousands of yen
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradesSpec {
+    /// e.g. "TSEPrime"
+    section: String,
+    /// "YYYY-MM-DD", the week traded
+    start_date: String,
+    end_date: String,
+    published_date: String,
+    foreigners_balance: f64,
+    individuals_balance: f64,
+}
+
+impl TradesSpec {
+    pub fn new(
+        section: &str,
+        start_date: &str,
+        end_date: &str,
+        published_date: &str,
+        foreigners_balance: f64,
+        individuals_balance: f64,
+    ) -> Self {
+        TradesSpec {
+            section: section.to_owned(),
+            start_date: start_date.to_owned(),
+            end_date: end_date.to_owned(),
+            published_date: published_date.to_owned(),
+            foreigners_balance,
+            individuals_balance,
+        }
+    }
+
+    //getters
+    pub fn get_foreigners_balance(&self) -> f64 {
+        self.foreigners_balance
+    }
+    pub fn get_individuals_balance(&self) -> f64 {
+        self.individuals_balance
+    }
+
+    /// Lines for the report, e.g. "海外投資家: 買い越し +1234.6億円"
+    pub fn lines(&self, lang: Lang) -> Vec<String> {
+        let line = |investor: Msg, balance: f64| {
+            let side = match balance >= 0.0 {
+                true => Msg::NetBuyers,
+                false => Msg::NetSellers,
+            };
+            // thousands of yen
+            let amount = match lang {
+                Lang::Ja => format!("{:+.1}億円", balance / 100_000.0),
+                Lang::En => format!("¥{:+.1}bn", balance / 1_000_000.0),
+            };
+            format!("{}: {} {}", investor.text(lang), side.text(lang), amount)
+        };
+        vec![
+            format!("{} - {}", self.start_date, self.end_date),
+            line(Msg::Foreigners, self.foreigners_balance),
+            line(Msg::Individuals, self.individuals_balance),
+        ]
+    }
+}
+
+/// Replaces the weeks already stored, J-Quants republishes corrected weeks
+pub fn insert(conn: &mut Connection, trades_spec: &[TradesSpec]) -> Result<(), MyError> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO trades_spec
+            (section, start_date, end_date, published_date, foreigners_balance,
+            individuals_balance)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for x in trades_spec {
+            stmt.execute((
+                &x.section,
+                &x.start_date,
+                &x.end_date,
+                &x.published_date,
+                x.foreigners_balance,
+                x.individuals_balance,
+            ))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn select_latest(conn: &Connection, section: &str) -> Result<Option<TradesSpec>, MyError> {
+    let trades_spec = conn
+        .query_row(
+            "SELECT section, start_date, end_date, published_date, foreigners_balance,
+            individuals_balance
+            FROM trades_spec WHERE section = ?1 ORDER BY start_date DESC LIMIT 1",
+            [section],
+            |row| {
+                Ok(TradesSpec {
+                    section: row.get(0)?,
+                    start_date: row.get(1)?,
+                    end_date: row.get(2)?,
+                    published_date: row.get(3)?,
+                    foreigners_balance: row.get(4)?,
+                    individuals_balance: row.get(5)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(trades_spec)
+}
+
+/// The latest week of `SECTION` for the reports, None until `trades_spec` is fetched
+pub fn load_latest() -> Option<TradesSpec> {
+    match open_db().and_then(|conn| select_latest(&conn, SECTION)) {
+        Ok(trades_spec) => trades_spec,
+        Err(e) => {
+            warn!(
+                "trades_spec unavailable, investor flows are left out: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+        assert_eq!(select_latest(&conn, SECTION).unwrap(), None);
+
+        insert(
+            &mut conn,
+            &[
+                TradesSpec::new(
+                    SECTION,
+                    "2024-01-09",
+                    "2024-01-12",
+                    "2024-01-18",
+                    123_460_000.0,
+                    -98_760_000.0,
+                ),
+                TradesSpec::new(
+                    SECTION,
+                    "2024-01-04",
+                    "2024-01-05",
+                    "2024-01-11",
+                    -1_000.0,
+                    2_000.0,
+                ),
+                TradesSpec::new(
+                    "TSEStandard",
+                    "2024-01-15",
+                    "2024-01-19",
+                    "2024-01-25",
+                    0.0,
+                    0.0,
+                ),
+            ],
+        )
+        .unwrap();
+
+        let latest = select_latest(&conn, SECTION).unwrap().unwrap();
+        assert_eq!(latest.get_foreigners_balance(), 123_460_000.0);
+        assert_eq!(
+            latest.lines(Lang::Ja),
+            vec![
+                "2024-01-09 - 2024-01-12",
+                "海外投資家: 買い越し +1234.6億円",
+                "個人: 売り越し -987.6億円",
+            ]
+        );
+        assert_eq!(
+            latest.lines(Lang::En)[1],
+            "Foreigners: net buyers ¥+123.5bn"
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use rusqlite::Connection;
+
+use crate::my_error::MyError;
+
+pub fn open_db() -> Result<Connection, MyError> {
+    let conn = super::open()?;
+    create_table(&conn)?;
+    Ok(conn)
+}
+
+pub fn create_table(conn: &Connection) -> Result<(), MyError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS trading_calendar (
+            date TEXT PRIMARY KEY,
+            holiday_division TEXT NOT NULL)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// (date, holiday division) pairs of J-Quants `/markets/trading_calendar`, "1" is a
+/// trading day. Replaces the days already stored.
+pub fn insert(conn: &mut Connection, days: &[(&str, &str)]) -> Result<(), MyError> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO trading_calendar (date, holiday_division) VALUES (?1, ?2)",
+        )?;
+        for day in days {
+            stmt.execute(*day)?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Days between `from` and `to` ("YYYY-MM-DD", both included), oldest first.
+/// The calendar has every day, so a full range has one row per day.
+pub fn select_by_date_range(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+) -> Result<Vec<(String, String)>, MyError> {
+    let mut stmt = conn.prepare(
+        "SELECT date, holiday_division FROM trading_calendar
+        WHERE date BETWEEN ?1 AND ?2 ORDER BY date",
+    )?;
+    let days = stmt
+        .query_map([from, to], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_select_by_date_range() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_table(&conn).unwrap();
+
+        insert(
+            &mut conn,
+            &[
+                ("2024-01-05", "1"),
+                ("2024-01-06", "0"),
+                ("2024-01-07", "0"),
+            ],
+        )
+        .unwrap();
+        insert(&mut conn, &[("2024-01-08", "3"), ("2024-01-05", "1")]).unwrap();
+
+        let days = select_by_date_range(&conn, "2024-01-06", "2024-01-09").unwrap();
+        assert_eq!(
+            days,
+            vec![
+                ("2024-01-06".to_owned(), "0".to_owned()),
+                ("2024-01-07".to_owned(), "0".to_owned()),
+                ("2024-01-08".to_owned(), "3".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::feed::{self, FeedItem};
+use crate::i18n::{Lang, Msg};
+use crate::markdown::{Markdown, ReportFormat};
+use crate::my_error::MyError;
+use crate::my_file_io::{get_draft_dir, get_drafts_root};
+use crate::output_sink::Report;
+use crate::report_kind;
+use crate::signal::Signal;
+
+/// A report kept in `trading23/drafts/{date}` until it's approved with
+/// `trading23 publish --date {date}`.
+/// The report and `{feed_title}.json` (this struct) are stored side by side.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Draft {
+    date: String,
+    /// final path of the report, with extension
+    target: PathBuf,
+    feed_dir: PathBuf,
+    feed_title: String,
+    item: FeedItem,
+    /// name of the `ReportKind`, empty for drafts saved before it was kept
+    #[serde(default)]
+    kind: String,
+    /// markdown source and signals of the report, for the sinks the draft held back
+    #[serde(default)]
+    markdown: String,
+    #[serde(default)]
+    signals: Vec<Signal>,
+}
+
+impl Draft {
+    fn new(
+        report: &Report,
+        target: PathBuf,
+        feed_dir: PathBuf,
+        feed_title: &str,
+        item: FeedItem,
+    ) -> Self {
+        Draft {
+            date: report.get_date().to_owned(),
+            target,
+            feed_dir,
+            feed_title: feed_title.to_owned(),
+            item,
+            kind: report.get_kind().get_name().to_owned(),
+            markdown: report.get_markdown().buffer().to_owned(),
+            signals: report.get_signals().to_vec(),
+        }
+    }
+
+    //getters
+    pub fn get_date(&self) -> &str {
+        &self.date
+    }
+    pub fn get_target(&self) -> &Path {
+        &self.target
+    }
+
+    /// The report again, None when its kind is unknown
+    pub fn to_report(&self) -> Option<Report> {
+        let kind = report_kind::find(&self.kind)?;
+        let mut markdown = Markdown::new();
+        markdown.write_str(&self.markdown).ok()?;
+        let report = Report::new(
+            kind,
+            &self.date,
+            self.item.get_title(),
+            self.item.get_description().to_vec(),
+            markdown,
+        );
+        Some(report.with_signals(self.signals.clone()))
+    }
+
+    fn report_path(&self, dir: &Path) -> PathBuf {
+        let path = dir.join(&self.feed_title);
+        match self.target.extension() {
+            Some(extension) => path.with_extension(extension),
+            None => path,
+        }
+    }
+
+    fn save_to(
+        &self,
+        dir: &Path,
+        markdown: &Markdown,
+        format: ReportFormat,
+    ) -> Result<(), MyError> {
+        markdown.write(&dir.join(&self.feed_title), format)?;
+        let json_path = dir.join(&self.feed_title).with_extension("json");
+        serde_json::to_writer_pretty(File::create(json_path)?, self)?;
+        Ok(())
+    }
+
+    /// Moves the report to its final folder and adds it to the feed
+    fn publish_from(&self, dir: &Path) -> Result<(), MyError> {
+        if let Some(parent) = self.target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let report_path = self.report_path(dir);
+        if std::fs::rename(&report_path, &self.target).is_err() {
+            // across file systems
+            std::fs::copy(&report_path, &self.target)?;
+            std::fs::remove_file(&report_path)?;
+        }
+        feed::publish(&self.feed_dir, &self.feed_title, self.item.clone())?;
+        std::fs::remove_file(dir.join(&self.feed_title).with_extension("json"))?;
+        Ok(())
+    }
+}
+
+/// Writes the report to its folder and adds it to the feed.
+/// With `draft`, both are held back until `publish`.
+pub fn write_report(
+    report: &Report,
+    format: ReportFormat,
+    draft: bool,
+) -> Result<PathBuf, MyError> {
+    let (kind, date) = (report.get_kind(), report.get_date());
+    let path = kind.path(date)?;
+    let target = path.with_extension(format.extension());
+    let feed_dir = kind.dir()?;
+    let feed_title = kind.dir_name();
+    let item = FeedItem::new(
+        date,
+        report.get_title(),
+        report.get_summary().to_vec(),
+        &feed::link(&feed_dir, &feed_title, &target),
+    );
+
+    match draft {
+        true => {
+            let draft = Draft::new(report, target, feed_dir, &feed_title, item);
+            draft.save_to(&get_draft_dir(date)?, report.get_markdown(), format)?;
+        }
+        false => {
+            report.get_markdown().write(&path, format)?;
+            feed::publish(&feed_dir, &feed_title, item)?;
+        }
+    }
+    Ok(path)
+}
+
+fn load_from(dir: &Path) -> Result<Vec<Draft>, MyError> {
+    let mut drafts = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|x| x == "json") {
+            drafts.push(serde_json::from_reader(File::open(path)?)?);
+        }
+    }
+    drafts.sort_by(|a: &Draft, b| a.feed_title.cmp(&b.feed_title));
+    Ok(drafts)
+}
+
+fn publish_from(dir: &Path, date: &str) -> Result<Vec<Draft>, MyError> {
+    let drafts = match dir.exists() {
+        true => load_from(dir)?,
+        false => Vec::new(),
+    };
+    if drafts.is_empty() {
+        return Err(MyError::Anyhow(anyhow!("no draft for {}", date)));
+    }
+
+    for draft in &drafts {
+        draft.publish_from(dir)?;
+    }
+    if std::fs::read_dir(dir)?.next().is_none() {
+        std::fs::remove_dir(dir)?;
+    }
+    Ok(drafts)
+}
+
+/// Publishes every draft of `date` ("YYYY-MM-DD") to its folder and feed, the other
+/// sinks are for the caller with `Draft::to_report`
+pub fn publish(date: &str) -> Result<Vec<Draft>, MyError> {
+    publish_from(&get_draft_dir(date)?, date)
+}
+
+/// Drafts waiting for approval, by date
+pub fn pending() -> Result<BTreeMap<String, Vec<Draft>>, MyError> {
+    let root = get_drafts_root()?;
+    let mut pending = BTreeMap::new();
+    if !root.exists() {
+        return Ok(pending);
+    }
+    for entry in std::fs::read_dir(root)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let drafts = load_from(&path)?;
+        if let Some(draft) = drafts.first() {
+            pending.insert(draft.date.clone(), drafts);
+        }
+    }
+    Ok(pending)
+}
+
+/// Notification asking for review, ends with the approval command
+pub fn summary(date: &str, drafts: &[Draft], lang: Lang) -> Result<String, MyError> {
+    let mut buffer = String::new();
+    writeln!(buffer, "{} {}", date, Msg::DraftReady.text(lang))?;
+    for draft in drafts {
+        writeln!(buffer, "- {}", draft.item.get_title())?;
+        for line in draft.item.get_description() {
+            writeln!(buffer, "  {}", line)?;
+        }
+    }
+    write!(buffer, "trading23 publish --date {}", date)?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_publish() {
+        let root = std::env::temp_dir().join(format!("trading23_draft_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let dir = root.join("drafts").join("2024-01-05");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let target = root
+            .join("jquants_resistance")
+            .join("2024-1")
+            .join("5.html");
+        let item = FeedItem::new(
+            "2024-01-05",
+            "2024-01-05 Nextday",
+            vec!["Number of Stocks: 20".to_owned()],
+            "2024-1/5.html",
+        );
+        let mut markdown = Markdown::new();
+        markdown.h1("2024-01-05").unwrap();
+        let report = Report::new(
+            crate::analysis::stocks_window::RESISTANCE,
+            "2024-01-05",
+            "2024-01-05 Nextday",
+            vec!["Number of Stocks: 20".to_owned()],
+            markdown,
+        );
+        let draft = Draft::new(
+            &report,
+            target.clone(),
+            root.join("jquants_resistance"),
+            "jquants_resistance",
+            item,
+        );
+        draft
+            .save_to(&dir, report.get_markdown(), ReportFormat::Html)
+            .unwrap();
+
+        assert!(dir.join("jquants_resistance.html").exists());
+        assert!(!target.exists());
+        assert_eq!(load_from(&dir).unwrap(), vec![draft.clone()]);
+        // the other sinks get the same report on publish
+        let again = draft.to_report().unwrap();
+        assert_eq!(again.message(), report.message());
+        assert_eq!(
+            again.get_markdown().buffer(),
+            report.get_markdown().buffer()
+        );
+        assert_eq!(
+            summary("2024-01-05", std::slice::from_ref(&draft), Lang::En).unwrap(),
+            "2024-01-05 draft ready for review\n\
+            - 2024-01-05 Nextday\n  Number of Stocks: 20\n\
+            trading23 publish --date 2024-01-05"
+        );
+
+        let published = publish_from(&dir, "2024-01-05").unwrap();
+        assert_eq!(published, vec![draft]);
+        assert!(target.exists());
+        assert!(root.join("jquants_resistance").join("feed.xml").exists());
+        assert!(!dir.exists());
+        assert!(publish_from(&dir, "2024-01-05").is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use log::info;
+use reqwest::Client;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+
+use crate::my_error::MyError;
+
+/// This week's events, published by Forex Factory
+const URL: &str = "https://nfs.faireconomy.media/ff_calendar_thisweek.json";
+
+#[derive(Deserialize, Debug)]
+struct CalendarItem {
+    title: String,
+    /// currency, e.g. "USD"
+    country: String,
+    /// "2024-01-12T08:30:00-05:00"
+    date: String,
+    /// "High", "Medium", "Low" or "Holiday"
+    impact: String,
+}
+
+/// High-impact release of a currency
+#[derive(Debug, Clone, PartialEq)]
+pub struct EconomicEvent {
+    title: String,
+    currency: String,
+    at: DateTime<Utc>,
+}
+
+impl EconomicEvent {
+    pub fn new(title: &str, currency: &str, at: DateTime<Utc>) -> Self {
+        EconomicEvent {
+            title: title.to_owned(),
+            currency: currency.to_owned(),
+            at,
+        }
+    }
+
+    //getters
+    pub fn get_title(&self) -> &str {
+        &self.title
+    }
+    pub fn get_currency(&self) -> &str {
+        &self.currency
+    }
+    pub fn get_at(&self) -> DateTime<Utc> {
+        self.at
+    }
+}
+
+impl Display for EconomicEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        write!(
+            f,
+            "{} {} {}",
+            self.at.with_timezone(&jst).format("%m-%d %H:%M"),
+            self.currency,
+            self.title
+        )
+    }
+}
+
+fn parse_high_impact(json: &str) -> Result<Vec<EconomicEvent>, MyError> {
+    let items: Vec<CalendarItem> = serde_json::from_str(json)?;
+    let mut events = Vec::new();
+    for item in items.into_iter().filter(|x| x.impact == "High") {
+        // all-day items ("Tentative" releases) have no time and are skipped
+        if let Ok(at) = DateTime::parse_from_rfc3339(&item.date) {
+            events.push(EconomicEvent::new(
+                &item.title,
+                &item.country,
+                at.with_timezone(&Utc),
+            ));
+        }
+    }
+    Ok(events)
+}
+
+/// High-impact events of this week
+pub async fn fetch_high_impact(client: &Client) -> Result<Vec<EconomicEvent>, MyError> {
+    let res = client.get(URL).send().await?.error_for_status()?;
+    let events = parse_high_impact(&res.text().await?)?;
+    info!("economic calendar: {} high-impact events", events.len());
+    Ok(events)
+}
+
+/// Events of `currencies` within `window` before or after `at`
+pub fn events_near<'a>(
+    events: &'a [EconomicEvent],
+    currencies: &[&str],
+    at: DateTime<Utc>,
+    window: Duration,
+) -> Vec<&'a EconomicEvent> {
+    events
+        .iter()
+        .filter(|x| currencies.contains(&x.currency.as_str()))
+        .filter(|x| (x.at - at).abs() <= window)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_events_near() {
+        let json = r#"[
+            {"title":"Non-Farm Employment Change","country":"USD","date":"2024-01-05T08:30:00-05:00","impact":"High","forecast":"170K","previous":"199K"},
+            {"title":"Unemployment Rate","country":"USD","date":"2024-01-05T08:30:00-05:00","impact":"Medium","forecast":"3.8%","previous":"3.7%"},
+            {"title":"BOJ Policy Rate","country":"JPY","date":"2024-01-23T03:00:00+00:00","impact":"High","forecast":"","previous":""},
+            {"title":"Bank Holiday","country":"CHF","date":"2024-01-02T00:00:00+00:00","impact":"Holiday","forecast":"","previous":""}
+        ]"#;
+        let events = parse_high_impact(json).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].to_string(),
+            "01-05 22:30 USD Non-Farm Employment Change"
+        );
+
+        let at = DateTime::parse_from_rfc3339("2024-01-05T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let near = events_near(&events, &["USD", "JPY"], at, Duration::hours(2));
+        assert_eq!(near, vec![&events[0]]);
+        assert!(events_near(&events, &["EUR", "GBP"], at, Duration::hours(2)).is_empty());
+        assert!(events_near(&events, &["USD"], at, Duration::hours(1)).is_empty());
+    }
+}
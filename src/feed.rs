@@ -0,0 +1,231 @@
+use chrono::{FixedOffset, NaiveDate, TimeZone};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs::File;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::config::GdriveJson;
+use crate::my_error::MyError;
+
+const MAX_ITEMS: usize = 100;
+
+/// One trading day of a report
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    date: String,
+    title: String,
+    description: Vec<String>,
+    link: String,
+}
+
+impl FeedItem {
+    /// `link` to the report, see `link`
+    pub fn new(date: &str, title: &str, description: Vec<String>, link: &str) -> Self {
+        FeedItem {
+            date: date.to_owned(),
+            title: title.to_owned(),
+            description,
+            link: link.to_owned(),
+        }
+    }
+
+    //getters
+    pub fn get_title(&self) -> &str {
+        &self.title
+    }
+    pub fn get_description(&self) -> &[String] {
+        &self.description
+    }
+}
+
+/// `reportsUrl` of config.json without the trailing "/", read once per run
+fn reports_url() -> Option<&'static str> {
+    static REPORTS_URL: OnceLock<Option<String>> = OnceLock::new();
+    REPORTS_URL
+        .get_or_init(|| {
+            let reports_url = GdriveJson::new()
+                .ok()
+                .and_then(|x| x.reports_url().map(|x| x.trim_end_matches('/').to_owned()));
+            if reports_url.is_none() {
+                warn!("reportsUrl not set in config.json, the feeds link to file:// URLs");
+            }
+            reports_url
+        })
+        .as_deref()
+}
+
+/// The absolute file:// URL of `path`, RSS readers don't take relative links
+fn file_url(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    let encoded = path
+        .to_string_lossy()
+        .bytes()
+        .map(|x| match x {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (x as char).to_string()
+            }
+            _ => format!("%{:02X}", x),
+        })
+        .collect::<String>();
+    format!("file://{}", encoded)
+}
+
+/// `report_path` in the feed of `feed_dir` (trading23/{dir_name}) as published under
+/// `base_url`, or its file:// URL without it
+fn link_under(
+    base_url: Option<&str>,
+    feed_dir: &Path,
+    dir_name: &str,
+    report_path: &Path,
+) -> String {
+    let relative = report_path
+        .strip_prefix(feed_dir)
+        .unwrap_or(report_path)
+        .iter()
+        .map(|x| x.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    match base_url {
+        Some(base_url) => format!("{}/{}/{}", base_url, dir_name, relative),
+        None => file_url(report_path),
+    }
+}
+
+/// The link of a report in its feed, under `reportsUrl` when it's set
+pub fn link(feed_dir: &Path, dir_name: &str, report_path: &Path) -> String {
+    link_under(reports_url(), feed_dir, dir_name, report_path)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// "YYYY-MM-DD" -> RFC 2822 at 00:00 JST
+fn pub_date(date: &str) -> Option<String> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let jst = FixedOffset::east_opt(9 * 3600)?;
+    let datetime = jst
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .single()?;
+    Some(datetime.to_rfc2822())
+}
+
+fn to_rss(channel_title: &str, channel_link: &str, items: &[FeedItem]) -> Result<String, MyError> {
+    let mut buffer = String::new();
+    writeln!(buffer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(buffer, r#"<rss version="2.0">"#)?;
+    writeln!(buffer, "<channel>")?;
+    writeln!(buffer, "<title>{}</title>", escape(channel_title))?;
+    writeln!(buffer, "<link>{}</link>", escape(channel_link))?;
+    writeln!(
+        buffer,
+        "<description>{}</description>",
+        escape(channel_title)
+    )?;
+    for item in items {
+        writeln!(buffer, "<item>")?;
+        writeln!(buffer, "<title>{}</title>", escape(&item.title))?;
+        writeln!(buffer, "<link>{}</link>", escape(&item.link))?;
+        writeln!(
+            buffer,
+            r#"<guid isPermaLink="false">{}-{}</guid>"#,
+            escape(channel_title),
+            item.date
+        )?;
+        if let Some(pub_date) = pub_date(&item.date) {
+            writeln!(buffer, "<pubDate>{}</pubDate>", pub_date)?;
+        }
+        writeln!(
+            buffer,
+            "<description>{}</description>",
+            escape(&item.description.join("<br>"))
+        )?;
+        writeln!(buffer, "</item>")?;
+    }
+    writeln!(buffer, "</channel>")?;
+    writeln!(buffer, "</rss>")?;
+    Ok(buffer)
+}
+
+/// Adds (or replaces, for the same date) an item and rewrites `feed.xml`.
+/// Items are kept in `feed.json` next to it. `channel_title` is the directory name
+pub fn publish(dir: &Path, channel_title: &str, item: FeedItem) -> Result<(), MyError> {
+    if !dir.exists() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let json_path = dir.join("feed.json");
+    let mut items: Vec<FeedItem> = match json_path.exists() {
+        true => serde_json::from_reader(File::open(&json_path)?)?,
+        false => Vec::new(),
+    };
+    items.retain(|x| x.date != item.date);
+    items.push(item);
+    items.sort_by(|a, b| b.date.cmp(&a.date));
+    items.truncate(MAX_ITEMS);
+
+    serde_json::to_writer_pretty(File::create(&json_path)?, &items)?;
+    let channel_link = match reports_url() {
+        Some(base_url) => format!("{}/{}/", base_url, channel_title),
+        None => file_url(dir),
+    };
+    std::fs::write(
+        dir.join("feed.xml"),
+        to_rss(channel_title, &channel_link, &items)?,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rss() {
+        let item = FeedItem::new(
+            "2024-01-05",
+            "2024-01-05 Nextday",
+            vec!["Number of Stocks: 225".to_owned(), "A & B".to_owned()],
+            "https://example.com/trading23/jquants_resistance/2024-1/5.html",
+        );
+        let rss = to_rss(
+            "jquants_resistance",
+            "https://example.com/trading23/jquants_resistance/",
+            &[item],
+        )
+        .unwrap();
+        assert!(rss.contains("<title>2024-01-05 Nextday</title>"));
+        assert!(rss.contains("<pubDate>Fri, 5 Jan 2024 00:00:00 +0900</pubDate>"));
+        assert!(rss.contains("Number of Stocks: 225&lt;br&gt;A &amp; B"));
+        assert!(!rss.contains("file://"));
+    }
+
+    #[test]
+    fn test_link() {
+        let feed_dir = Path::new("/gdrive/trading23/jquants_resistance");
+        let report_path = feed_dir.join("2024-1").join("5.html");
+        assert_eq!(
+            link_under(
+                Some("https://example.com/trading23"),
+                feed_dir,
+                "jquants_resistance",
+                &report_path
+            ),
+            "https://example.com/trading23/jquants_resistance/2024-1/5.html"
+        );
+        assert_eq!(
+            link_under(None, feed_dir, "jquants_resistance", &report_path),
+            "file:///gdrive/trading23/jquants_resistance/2024-1/5.html"
+        );
+        assert_eq!(
+            file_url(Path::new("/My Drive/マイ")),
+            "file:///My%20Drive/%E3%83%9E%E3%82%A4"
+        );
+    }
+}
@@ -1,3 +1,5 @@
 pub mod backtesting;
+pub mod exposure;
 pub mod fx_private;
 pub mod fx_public;
+pub mod series;
@@ -0,0 +1,170 @@
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use super::fx_private::PositionSummary;
+use crate::analysis::live::LongOrShort;
+use crate::my_error::MyError;
+
+/// A position's share of a currency, e.g. ("USD_JPY Long", 10000.0)
+#[derive(Debug, Clone, PartialEq)]
+struct Leg {
+    position: String,
+    amount: f64,
+}
+
+/// Net amount of a currency over all positions holding it
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CurrencyExposure {
+    net: f64,
+    legs: Vec<Leg>,
+}
+
+impl CurrencyExposure {
+    //getters
+    pub fn get_net(&self) -> f64 {
+        self.net
+    }
+
+    /// More than one position adds to the same side of the currency
+    pub fn is_doubled(&self) -> bool {
+        let same_side = self
+            .legs
+            .iter()
+            .filter(|x| x.amount.signum() == self.net.signum())
+            .count();
+        same_side > 1
+    }
+}
+
+impl Display for CurrencyExposure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let legs = self
+            .legs
+            .iter()
+            .map(|x| x.position.as_str())
+            .collect::<Vec<_>>();
+        write!(f, "{:+.0} ({})", self.net, legs.join(", "))?;
+        if self.is_doubled() {
+            write!(f, " doubled")?;
+        }
+        Ok(())
+    }
+}
+
+/// Net currency exposure of the open FX positions.
+/// A long USD_JPY of 10000 at 140 is +10000 USD and -1400000 JPY,
+/// so long USD_JPY and short EUR_USD both add to USD.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Exposure {
+    currencies: BTreeMap<String, CurrencyExposure>,
+}
+
+impl Exposure {
+    pub fn from_positions(positions: &[PositionSummary]) -> Result<Self, MyError> {
+        let mut exposure = Exposure::default();
+        for position in positions {
+            let symbol = position.get_symbol();
+            let (base, quote) = symbol
+                .split_once('_')
+                .ok_or_else(|| MyError::Anyhow(anyhow!("invalid symbol: {}", symbol)))?;
+            let side = position.side()?;
+            let sign = match side {
+                LongOrShort::Long => 1.0,
+                LongOrShort::Short => -1.0,
+            };
+            let quantity = position.quantity()?;
+            let name = format!("{} {}", symbol, side);
+            exposure.add(base, &name, sign * quantity);
+            exposure.add(quote, &name, -sign * quantity * position.average_rate()?);
+        }
+        Ok(exposure)
+    }
+
+    fn add(&mut self, currency: &str, position: &str, amount: f64) {
+        let entry = self.currencies.entry(currency.to_owned()).or_default();
+        entry.net += amount;
+        entry.legs.push(Leg {
+            position: position.to_owned(),
+            amount,
+        });
+    }
+
+    pub fn get(&self, currency: &str) -> Option<&CurrencyExposure> {
+        self.currencies.get(currency)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.currencies.is_empty()
+    }
+
+    /// Currencies more than one position adds to, see `CurrencyExposure::is_doubled`
+    pub fn doubled(&self) -> Vec<(&str, &CurrencyExposure)> {
+        self.currencies
+            .iter()
+            .filter(|(_, exposure)| exposure.is_doubled())
+            .map(|(currency, exposure)| (currency.as_str(), exposure))
+            .collect()
+    }
+
+    /// Exposure of `currencies` only, for the notes of a symbol
+    pub fn describe(&self, currencies: &[&str]) -> Option<String> {
+        let described = currencies
+            .iter()
+            .filter_map(|x| self.get(x).map(|exposure| format!("{} {}", x, exposure)))
+            .collect::<Vec<_>>();
+        match described.is_empty() {
+            true => None,
+            false => Some(format!("exposure: {}", described.join(" / "))),
+        }
+    }
+}
+
+impl Display for Exposure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no open positions");
+        }
+        let currencies = self
+            .currencies
+            .iter()
+            .map(|(currency, exposure)| format!("{} {}", currency, exposure))
+            .collect::<Vec<_>>();
+        write!(f, "{}", currencies.join(" / "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposure() {
+        let positions = vec![
+            PositionSummary::new("USD_JPY", "BUY", 10000.0, 140.0),
+            PositionSummary::new("EUR_USD", "SELL", 10000.0, 1.1),
+        ];
+        let exposure = Exposure::from_positions(&positions).unwrap();
+
+        let usd = exposure.get("USD").unwrap();
+        assert_eq!(usd.get_net(), 21000.0);
+        assert!(usd.is_doubled());
+        assert_eq!(exposure.get("JPY").unwrap().get_net(), -1400000.0);
+        assert_eq!(exposure.get("EUR").unwrap().get_net(), -10000.0);
+        assert!(!exposure.get("EUR").unwrap().is_doubled());
+        assert_eq!(
+            exposure.describe(&["EUR", "USD"]).unwrap(),
+            "exposure: EUR -10000 (EUR_USD Short) / USD +21000 (USD_JPY Long, EUR_USD Short) doubled"
+        );
+        assert_eq!(exposure.describe(&["GBP", "AUD"]), None);
+
+        // both cross yen longs are short JPY
+        let crosses = Exposure::from_positions(&[
+            PositionSummary::new("USD_JPY", "BUY", 10000.0, 140.0),
+            PositionSummary::new("EUR_JPY", "BUY", 10000.0, 150.0),
+        ])
+        .unwrap();
+        assert!(crosses.get("JPY").unwrap().is_doubled());
+        assert_eq!(Exposure::default().to_string(), "no open positions");
+    }
+}
@@ -1,11 +1,21 @@
+use anyhow::anyhow;
 use chrono::Local;
 use hex::encode as hex_encode;
 use log::info;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use ring::hmac::{sign, Key, HMAC_SHA256};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::env;
 
+use super::fx_public::ApiMessage;
+use crate::analysis::live::LongOrShort;
+use crate::config::GdriveJson;
+use crate::my_error::MyError;
+use crate::rate_limit::Provider;
+
+const ENDPOINT: &str = "https://forex-api.coin.z.com/private";
+
 pub async fn _get_assets() {
     let client = Client::new();
 
@@ -20,6 +30,7 @@ pub async fn _get_assets() {
     let signed_key = Key::new(HMAC_SHA256, secret_key.as_bytes());
     let sign = hex_encode(sign(&signed_key, text.as_bytes()).as_ref());
 
+    let _permit = Provider::Gmo.limiter().acquire().await;
     let res = client
         .get(&(endpoint.to_string() + path))
         .header("API-KEY", api_key)
@@ -53,6 +64,7 @@ pub async fn _speed_order() {
     let signed_key = Key::new(HMAC_SHA256, secret_key.as_bytes());
     let sign = hex_encode(sign(&signed_key, text.as_bytes()).as_ref());
 
+    let _permit = Provider::Gmo.limiter().acquire().await;
     let res = client
         .post(&(endpoint.to_string() + path))
         .header("content-type", "application/json")
@@ -67,3 +79,158 @@ pub async fn _speed_order() {
     info!("Status: {}", res.status());
     info!("body: {}", res.text().await.unwrap())
 }
+
+#[derive(Deserialize, Serialize, Debug)]
+struct PositionSummaryResponse {
+    status: i32,
+    #[serde(default)]
+    data: Option<PositionSummaryList>,
+    #[serde(default)]
+    messages: Vec<ApiMessage>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct PositionSummaryList {
+    #[serde(default)]
+    list: Vec<PositionSummary>,
+}
+
+/// Open positions of a symbol and side, summed
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PositionSummary {
+    symbol: String,
+    /// "BUY" or "SELL"
+    side: String,
+    #[serde(rename = "sumPositionQuantity")]
+    sum_position_quantity: String,
+    #[serde(rename = "averagePositionRate")]
+    average_position_rate: String,
+}
+
+fn parse_number(field: &str, value: &str) -> Result<f64, MyError> {
+    value
+        .parse()
+        .map_err(|_| MyError::Anyhow(anyhow!("invalid position {}: {}", field, value)))
+}
+
+impl PositionSummary {
+    pub fn new(symbol: &str, side: &str, quantity: f64, rate: f64) -> Self {
+        PositionSummary {
+            symbol: symbol.to_owned(),
+            side: side.to_owned(),
+            sum_position_quantity: quantity.to_string(),
+            average_position_rate: rate.to_string(),
+        }
+    }
+
+    //getters
+    pub fn get_symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn side(&self) -> Result<LongOrShort, MyError> {
+        match self.side.as_str() {
+            "BUY" => Ok(LongOrShort::Long),
+            "SELL" => Ok(LongOrShort::Short),
+            side => Err(MyError::Anyhow(anyhow!("invalid position side: {}", side))),
+        }
+    }
+    /// in units of the base currency
+    pub fn quantity(&self) -> Result<f64, MyError> {
+        parse_number("sumPositionQuantity", &self.sum_position_quantity)
+    }
+    pub fn average_rate(&self) -> Result<f64, MyError> {
+        parse_number("averagePositionRate", &self.average_position_rate)
+    }
+}
+
+/// Side of the net open quantity of `symbol`, None when flat. A symbol held both ways
+/// has a summary per side
+pub fn net_side(
+    positions: &[PositionSummary],
+    symbol: &str,
+) -> Result<Option<LongOrShort>, MyError> {
+    let mut net = 0.0;
+    for position in positions.iter().filter(|x| x.symbol == symbol) {
+        net += match position.side()? {
+            LongOrShort::Long => position.quantity()?,
+            LongOrShort::Short => -position.quantity()?,
+        };
+    }
+    Ok(match net {
+        x if x > 0.0 => Some(LongOrShort::Long),
+        x if x < 0.0 => Some(LongOrShort::Short),
+        _ => None,
+    })
+}
+
+/// Open positions of all symbols, signed with the API key in config.json
+pub async fn fetch_position_summary(client: &Client) -> Result<Vec<PositionSummary>, MyError> {
+    let config = GdriveJson::new()?;
+    let timestamp = Local::now().timestamp_millis();
+    let path = "/v1/positionSummary";
+
+    let text = format!("{}{}{}", timestamp, "GET", path);
+    let signed_key = Key::new(HMAC_SHA256, config.gmo_coin_fx_api_secret().as_bytes());
+    let sign = hex_encode(sign(&signed_key, text.as_bytes()).as_ref());
+
+    let _permit = Provider::Gmo.limiter().acquire().await;
+    let res = client
+        .get(ENDPOINT.to_string() + path)
+        .header("API-KEY", config.gmo_coin_fx_api_key())
+        .header("API-TIMESTAMP", timestamp)
+        .header("API-SIGN", sign)
+        .send()
+        .await?;
+    let status = res.status();
+    if status != StatusCode::OK {
+        return Err(MyError::GmoApi {
+            status: status.as_u16(),
+            message: res.text().await?,
+        });
+    }
+
+    let res = res.json::<PositionSummaryResponse>().await?;
+    match res.status {
+        0 => Ok(res.data.map(|x| x.list).unwrap_or_default()),
+        _ => Err(MyError::GmoApi {
+            status: status.as_u16(),
+            message: format!("status {}, {:?}", res.status, res.messages),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_summary_response() {
+        let json = r#"{"status":0,"data":{"list":[{"averagePositionRate":"140.043","positionLossGain":"2322204","side":"BUY","sumOrderQuantity":"0","sumPositionQuantity":"10000","symbol":"USD_JPY"}]},"responsetime":"2024-01-05T12:00:00.000Z"}"#;
+        let res: PositionSummaryResponse = serde_json::from_str(json).unwrap();
+        let list = res.data.unwrap().list;
+        assert_eq!(list[0].get_symbol(), "USD_JPY");
+        assert!(matches!(list[0].side().unwrap(), LongOrShort::Long));
+        assert_eq!(list[0].quantity().unwrap(), 10000.0);
+        assert_eq!(list[0].average_rate().unwrap(), 140.043);
+    }
+
+    #[test]
+    fn test_net_side() {
+        let positions = [
+            PositionSummary::new("USD_JPY", "BUY", 10000.0, 140.0),
+            PositionSummary::new("USD_JPY", "SELL", 30000.0, 142.0),
+            PositionSummary::new("EUR_USD", "BUY", 5000.0, 1.1),
+            PositionSummary::new("EUR_USD", "SELL", 5000.0, 1.1),
+        ];
+        let side = |symbol: &str| net_side(&positions, symbol).unwrap();
+        assert!(matches!(side("USD_JPY"), Some(LongOrShort::Short)));
+        assert!(side("EUR_USD").is_none());
+        assert!(side("GBP_JPY").is_none());
+        assert!(net_side(
+            &[PositionSummary::new("USD_JPY", "FLAT", 1.0, 140.0)],
+            "USD_JPY"
+        )
+        .is_err());
+    }
+}
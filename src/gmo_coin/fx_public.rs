@@ -1,34 +1,63 @@
-use crate::analysis::live::LongOrShort;
+use super::exposure::Exposure;
+use super::fx_private::{self, PositionSummary};
+use super::series;
+use crate::database::economic_events;
+use crate::economic_calendar::{self, EconomicEvent};
+use crate::i18n::{Lang, Msg};
+use crate::rate_limit::{Provider, RateLimiter};
 use crate::{
     analysis::live::{Ohlc, OhlcAnalyzer},
     my_error::MyError,
+    rounding::round_dp,
 };
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Datelike, Duration, Local, Utc};
-use log::{debug, info};
+use futures::future::join_all;
+use log::{debug, error, info, warn};
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::OnceLock;
 use std::time::Duration as StdDuration;
-use std::{
-    fmt::{Display, Formatter},
-    thread,
-};
+
+/// Tries per request, waiting `RETRY_WAIT_SECS * 2^n` in between
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_WAIT_SECS: u64 = 2;
 
 #[derive(Deserialize, Serialize, Debug)]
 struct KLinesResponse {
     status: i32,
+    #[serde(default)]
     data: Vec<KLine>,
+    /// set instead of `data` when `status` != 0
+    #[serde(default)]
+    messages: Vec<ApiMessage>,
     #[serde(rename = "responsetime")]
     response_time: String,
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+pub(super) struct ApiMessage {
+    message_code: String,
+    message_string: String,
+}
+
 impl KLinesResponse {
-    fn to_ohlc_vec(&self) -> Vec<Ohlc> {
-        let mut ohlc_vec = Vec::new();
-        for kline in &self.data {
-            ohlc_vec.push(kline.to_ohlc());
+    fn into_ohlc_vec(self, http_status: StatusCode) -> Result<Vec<Ohlc>, MyError> {
+        if self.status != 0 {
+            let message = self
+                .messages
+                .iter()
+                .map(|x| format!("{} {}", x.message_code, x.message_string))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(MyError::GmoApi {
+                status: http_status.as_u16(),
+                message: format!("status {}, {}", self.status, message),
+            });
         }
-        ohlc_vec
+        self.data.iter().map(|kline| kline.to_ohlc()).collect()
     }
 }
 
@@ -42,35 +71,35 @@ struct KLine {
     close: String,
 }
 
+fn parse_price(field: &'static str, value: &str) -> Result<f64, MyError> {
+    value.parse().map_err(|_| MyError::InvalidKline {
+        field,
+        value: value.to_owned(),
+    })
+}
+
 impl KLine {
     //getters
-    pub fn get_open_time(&self) -> String {
-        let timestamp_secs: i64 = self.open_time.parse::<i64>().unwrap() / 1000;
-        let datetime: DateTime<Utc> = DateTime::from_timestamp(timestamp_secs, 0).unwrap();
+    pub fn get_open_time(&self) -> Result<String, MyError> {
+        let invalid = || MyError::InvalidKline {
+            field: "openTime",
+            value: self.open_time.clone(),
+        };
+        let timestamp_secs: i64 = self.open_time.parse::<i64>().map_err(|_| invalid())? / 1000;
+        let datetime: DateTime<Utc> =
+            DateTime::from_timestamp(timestamp_secs, 0).ok_or_else(invalid)?;
         let datetime_local: DateTime<Local> = datetime.with_timezone(&Local);
 
-        datetime_local.format("%Y-%m-%d %H:%M:%S").to_string()
-    }
-    pub fn get_open(&self) -> f64 {
-        self.open.parse().unwrap()
-    }
-    pub fn get_high(&self) -> f64 {
-        self.high.parse().unwrap()
-    }
-    pub fn get_low(&self) -> f64 {
-        self.low.parse().unwrap()
+        Ok(datetime_local.format("%Y-%m-%d %H:%M:%S").to_string())
     }
-    pub fn get_close(&self) -> f64 {
-        self.close.parse().unwrap()
-    }
-    fn to_ohlc(&self) -> Ohlc {
-        Ohlc::new(
-            self.get_open_time(),
-            self.get_open(),
-            self.get_high(),
-            self.get_low(),
-            self.get_close(),
-        )
+    fn to_ohlc(&self) -> Result<Ohlc, MyError> {
+        Ok(Ohlc::new(
+            self.get_open_time()?,
+            parse_price("open", &self.open)?,
+            parse_price("high", &self.high)?,
+            parse_price("low", &self.low)?,
+            parse_price("close", &self.close)?,
+        ))
     }
 }
 
@@ -101,11 +130,11 @@ impl KLineQueryParams {
     }
 
     pub fn get_date_with_delta(&self, delta: i64) -> String {
-        match self.interval {
-            Interval::H1 | Interval::M30 => (self.date - Duration::days(delta))
+        match self.interval.fetched_by_day() {
+            true => (self.date - Duration::days(delta))
                 .format("%Y%m%d")
                 .to_string(),
-            Interval::D1 => (self.date - Duration::days(delta * 365))
+            false => (self.date - Duration::days(delta * 365))
                 .format("%Y")
                 .to_string(),
         }
@@ -121,10 +150,31 @@ impl KLineQueryParams {
         client: &Client,
         delta: i64,
     ) -> Result<Vec<Ohlc>, MyError> {
-        if self.date_with_delta_is_holiday(delta) && (self.interval != Interval::D1) {
+        if self.date_with_delta_is_holiday(delta) && self.interval.fetched_by_day() {
             return Err(MyError::Holiday);
         }
 
+        let mut attempt = 1;
+        loop {
+            match self.fetch_klines_once(client, delta).await {
+                Err(e) if attempt < MAX_ATTEMPTS && e.is_retryable() => {
+                    let wait = RETRY_WAIT_SECS * 2u64.pow(attempt - 1);
+                    warn!(
+                        "{} {}: {}, retrying in {}s",
+                        self.symbol,
+                        self.get_date_with_delta(delta),
+                        e,
+                        wait
+                    );
+                    tokio::time::sleep(StdDuration::from_secs(wait)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn fetch_klines_once(&self, client: &Client, delta: i64) -> Result<Vec<Ohlc>, MyError> {
         let url = "https://forex-api.coin.z.com/public/v1/klines";
         let date = self.get_date_with_delta(delta);
 
@@ -137,27 +187,25 @@ impl KLineQueryParams {
                 ("date", date),
             ])
             .send()
-            .await
-            .unwrap();
+            .await?;
 
-        match res.status() {
+        let status = res.status();
+        match status {
             StatusCode::OK => {
-                info!("Status: {}", res.status());
-                let json = res.json::<KLinesResponse>().await.unwrap();
-                let ohlc_vec = json.to_ohlc_vec();
+                info!("Status: {}", status);
+                let ohlc_vec = res.json::<KLinesResponse>().await?.into_ohlc_vec(status)?;
                 debug!("{:?}", ohlc_vec);
                 Ok(ohlc_vec)
             }
-            _ => Err(MyError::Anyhow(anyhow!(
-                "Status code: {}, {}",
-                res.status(),
-                res.text().await?
-            ))),
+            _ => Err(MyError::GmoApi {
+                status: status.as_u16(),
+                message: res.text().await?,
+            }),
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Symbol {
     UsdJpy,
     EurJpy,
@@ -198,6 +246,17 @@ impl From<&str> for Symbol {
 }
 
 impl Symbol {
+    /// Every symbol, in the order they are scanned
+    pub const ALL: [Symbol; 7] = [
+        Symbol::UsdJpy,
+        Symbol::EurJpy,
+        Symbol::GbpJpy,
+        Symbol::AudJpy,
+        Symbol::EurUsd,
+        Symbol::GbpUsd,
+        Symbol::AudUsd,
+    ];
+
     pub fn pips(&self) -> f64 {
         match self {
             Symbol::UsdJpy => 0.01,
@@ -209,6 +268,35 @@ impl Symbol {
             Symbol::AudUsd => 0.0001,
         }
     }
+
+    /// Base and quote currency
+    pub fn currencies(&self) -> [&'static str; 2] {
+        match self {
+            Symbol::UsdJpy => ["USD", "JPY"],
+            Symbol::EurJpy => ["EUR", "JPY"],
+            Symbol::GbpJpy => ["GBP", "JPY"],
+            Symbol::AudJpy => ["AUD", "JPY"],
+            Symbol::EurUsd => ["EUR", "USD"],
+            Symbol::GbpUsd => ["GBP", "USD"],
+            Symbol::AudUsd => ["AUD", "USD"],
+        }
+    }
+
+    /// `normalSpreadPips` of config.json, read once per run, or the GMO Coin FX price list
+    pub fn normal_spread_pips(&self) -> Option<f64> {
+        static NORMAL_SPREAD_PIPS: OnceLock<HashMap<String, f64>> = OnceLock::new();
+        NORMAL_SPREAD_PIPS
+            .get_or_init(|| match crate::config::GdriveJson::new() {
+                Ok(config) => config.normal_spread_pips().clone(),
+                Err(_) => crate::config::default_normal_spread_pips(),
+            })
+            .get(&self.to_string())
+            .copied()
+    }
+
+    pub fn round_pips(&self, price: f64) -> f64 {
+        crate::rounding::round_to_step(price, self.pips())
+    }
 }
 
 pub enum PriceType {
@@ -225,104 +313,621 @@ impl Display for PriceType {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
 pub enum Interval {
+    #[serde(rename = "30min")]
     M30,
-    #[allow(dead_code)]
+    #[serde(rename = "1hour")]
     H1,
+    #[serde(rename = "4hour")]
+    H4,
+    #[serde(rename = "1day")]
     D1,
+    #[serde(rename = "1week")]
+    W1,
+}
+impl Interval {
+    /// Length of a bar
+    pub fn step(&self) -> Duration {
+        match self {
+            Interval::M30 => Duration::minutes(30),
+            Interval::H1 => Duration::hours(1),
+            Interval::H4 => Duration::hours(4),
+            Interval::D1 => Duration::days(1),
+            Interval::W1 => Duration::weeks(1),
+        }
+    }
+
+    /// The API takes a day (YYYYMMDD) up to 1hour and a year (YYYY) above
+    fn fetched_by_day(&self) -> bool {
+        matches!(self, Interval::M30 | Interval::H1)
+    }
 }
 impl Display for Interval {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Interval::M30 => write!(f, "30min"),
             Interval::H1 => write!(f, "1hour"),
+            Interval::H4 => write!(f, "4hour"),
             Interval::D1 => write!(f, "1day"),
+            Interval::W1 => write!(f, "1week"),
+        }
+    }
+}
+
+/// Bars and intervals of a symbol, set per symbol with `fxSymbols` in config.json:
+/// `{"AUD_USD": {"bars": 40, "shorter": "1hour", "longer": "1week"}}`.
+/// Missing keys fall back to 60 bars of 30min and 1day.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct FxSettings {
+    #[serde(default = "default_bars")]
+    bars: usize,
+    #[serde(default = "default_shorter")]
+    shorter: Interval,
+    #[serde(default = "default_longer")]
+    longer: Interval,
+    /// signals are vetoed while the spread is wider than this many times the normal one
+    #[serde(rename = "maxSpreadMultiple", default = "default_max_spread_multiple")]
+    max_spread_multiple: f64,
+    /// hours before and after a high-impact event of either currency
+    #[serde(rename = "eventWindowHours", default = "default_event_window_hours")]
+    event_window_hours: i64,
+    #[serde(rename = "eventAction", default)]
+    event_action: EventAction,
+}
+
+/// What happens to a signal near an economic event
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventAction {
+    /// logged with the events
+    Warn,
+    /// skipped like a spread veto
+    #[default]
+    Suppress,
+}
+
+fn default_bars() -> usize {
+    60
+}
+fn default_shorter() -> Interval {
+    Interval::M30
+}
+fn default_longer() -> Interval {
+    Interval::D1
+}
+fn default_max_spread_multiple() -> f64 {
+    3.0
+}
+fn default_event_window_hours() -> i64 {
+    2
+}
+
+impl Default for FxSettings {
+    fn default() -> Self {
+        FxSettings {
+            bars: default_bars(),
+            shorter: default_shorter(),
+            longer: default_longer(),
+            max_spread_multiple: default_max_spread_multiple(),
+            event_window_hours: default_event_window_hours(),
+            event_action: EventAction::default(),
         }
     }
 }
 
+impl FxSettings {
+    /// Falls back to the defaults when config.json can't be read
+    pub fn from_config(symbol: &Symbol) -> Self {
+        crate::config::GdriveJson::new()
+            .ok()
+            .and_then(|config| config.fx_settings(&symbol.to_string()).cloned())
+            .unwrap_or_default()
+    }
+
+    //getters
+    /// at least 20, the last 20 bars are analyzed
+    pub fn get_bars(&self) -> usize {
+        self.bars.max(20)
+    }
+    pub fn get_shorter(&self) -> Interval {
+        self.shorter
+    }
+    pub fn get_longer(&self) -> Interval {
+        self.longer
+    }
+
+    pub fn get_event_window(&self) -> Duration {
+        Duration::hours(self.event_window_hours)
+    }
+    pub fn get_event_action(&self) -> EventAction {
+        self.event_action
+    }
+
+    /// Errs for a symbol without `normalSpreadPips`
+    pub fn spread(&self, symbol: &Symbol, ticker: &Ticker) -> Result<Spread, MyError> {
+        let normal = symbol
+            .normal_spread_pips()
+            .ok_or_else(|| MyError::Config(format!("normalSpreadPips has no {}", symbol)))?;
+        Ok(Spread {
+            pips: ticker.spread_pips(symbol)?,
+            normal,
+            max_multiple: self.max_spread_multiple,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TickerResponse {
+    status: i32,
+    #[serde(default)]
+    data: Vec<Ticker>,
+    #[serde(default)]
+    messages: Vec<ApiMessage>,
+}
+
+/// Current bid/ask of a symbol
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Ticker {
+    symbol: String,
+    ask: String,
+    bid: String,
+    timestamp: String,
+    /// "OPEN" or "CLOSE"
+    status: String,
+}
+
+impl Ticker {
+    pub fn get_symbol(&self) -> &str {
+        &self.symbol
+    }
+    pub fn spread_pips(&self, symbol: &Symbol) -> Result<f64, MyError> {
+        let ask = parse_price("ask", &self.ask)?;
+        let bid = parse_price("bid", &self.bid)?;
+        Ok(round_dp((ask - bid) / symbol.pips(), 1))
+    }
+}
+
+/// Tickers of all symbols in one request
+pub async fn fetch_tickers(client: &Client) -> Result<Vec<Ticker>, MyError> {
+    let url = "https://forex-api.coin.z.com/public/v1/ticker";
+    let res = {
+        let _permit = Provider::Gmo.limiter().acquire().await;
+        client.get(url).send().await?
+    };
+    let status = res.status();
+    if status != StatusCode::OK {
+        return Err(MyError::GmoApi {
+            status: status.as_u16(),
+            message: res.text().await?,
+        });
+    }
+
+    let res = res.json::<TickerResponse>().await?;
+    match res.status {
+        0 => Ok(res.data),
+        _ => Err(MyError::GmoApi {
+            status: status.as_u16(),
+            message: format!("status {}, {:?}", res.status, res.messages),
+        }),
+    }
+}
+
+/// Spread at the time of the scan against its normal value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spread {
+    pips: f64,
+    normal: f64,
+    max_multiple: f64,
+}
+
+impl Spread {
+    /// news, rollover and thin markets widen the spread
+    pub fn is_vetoed(&self) -> bool {
+        self.pips > self.normal * self.max_multiple
+    }
+}
+
+impl Display for Spread {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "spread {} pips (normal {}, max x{})",
+            self.pips, self.normal, self.max_multiple
+        )
+    }
+}
+
+/// Requests per series, holidays included
+const MAX_FETCHES: i64 = 20;
+/// Bars `OhlcAnalyzer::analyze_last20` needs
+const MIN_BARS: usize = 20;
+
+/// The latest `bars` bars (or fewer when the series has a gap), an error for fewer
+/// than `MIN_BARS`
 pub async fn fetch_ohlc(
     client: &Client,
+    limiter: &RateLimiter,
     symbol: Symbol,
     interval: Interval,
+    bars: usize,
 ) -> Result<Vec<Ohlc>, MyError> {
     let params = KLineQueryParams::new(symbol, PriceType::Bid, interval, Local::now());
 
     let mut ohlc_vec: Vec<Ohlc> = Vec::new();
 
-    for delta in 0..10 {
-        if ohlc_vec.len() >= 60 {
+    for delta in 0..MAX_FETCHES {
+        // one more for the bar still forming
+        if ohlc_vec.len() > bars {
             break;
         }
 
-        thread::sleep(StdDuration::from_secs(2));
-
-        match params.fetch_klines_with_delta(client, delta).await {
-            Ok(ohlc_vec_delta) => {
-                let ohlc_vec_delta = ohlc_vec_delta.into_iter().rev().collect::<Vec<Ohlc>>();
-                ohlc_vec.extend(ohlc_vec_delta)
-            }
+        let fetched = {
+            let _permit = limiter.acquire().await;
+            params.fetch_klines_with_delta(client, delta).await
+        };
+        match fetched {
+            Ok(ohlc_vec_delta) => ohlc_vec.extend(ohlc_vec_delta),
             Err(e) => match e {
                 MyError::Holiday => {
                     info!("Holiday");
                     continue;
                 }
-                _ => return Err(e),
+                // one bad day leaves a gap, the bars of the other days are still usable
+                _ => warn!(
+                    "{} {}: skipped, {}",
+                    params.get_symbol(),
+                    params.get_date_with_delta(delta),
+                    e
+                ),
             },
         }
     }
 
-    ohlc_vec.remove(0);
-    ohlc_vec.reverse();
-    Ok(ohlc_vec)
+    let series = series::assemble(ohlc_vec, &params.interval, Local::now().naive_local())?;
+    if series.get_bars().len() < MIN_BARS {
+        return Err(MyError::Anyhow(anyhow!(
+            "{} {}: {} bars since the last gap, {} needed",
+            params.get_symbol(),
+            params.interval,
+            series.get_bars().len(),
+            MIN_BARS
+        )));
+    }
+    let bars_vec = series.into_bars();
+    let skip = bars_vec.len().saturating_sub(bars);
+    Ok(bars_vec.into_iter().skip(skip).collect())
 }
 
-pub async fn fetch_gmo_coin_fx() {
+/// What the scan found for a symbol, in the order the setups are ranked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum SetupKind {
+    /// breakout to act on
+    Breakout,
+    /// position held, the stop loss to follow
+    Held,
+    /// breakout vetoed by the spread or an economic event
+    Vetoed,
+    #[default]
+    NoBreakout,
+}
+
+/// Signal of one symbol in the scan
+#[derive(Debug, Clone)]
+pub struct FxSetup {
+    symbol: Symbol,
+    /// breakout and stop loss, or the stop of the held position
+    signal: String,
+    /// vetoed by the spread or an economic event
+    skipped: bool,
+    notes: Vec<String>,
+    kind: SetupKind,
+}
+
+impl FxSetup {
+    pub fn new(symbol: Symbol, signal: &str, skipped: bool, notes: Vec<String>) -> Self {
+        FxSetup {
+            symbol,
+            signal: signal.to_owned(),
+            skipped,
+            notes,
+            kind: SetupKind::default(),
+        }
+    }
+
+    pub fn with_kind(mut self, kind: SetupKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    //getters
+    pub fn get_symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+    pub fn get_signal(&self) -> &str {
+        &self.signal
+    }
+    pub fn is_skipped(&self) -> bool {
+        self.skipped
+    }
+    pub fn get_notes(&self) -> &[String] {
+        &self.notes
+    }
+    pub fn get_kind(&self) -> SetupKind {
+        self.kind
+    }
+
+    /// The signal, marked when the spread or an event vetoed it
+    pub fn signal_text(&self, lang: Lang) -> String {
+        match self.skipped {
+            true => format!("{} ({})", self.signal, Msg::Skipped.text(lang)),
+            false => self.signal.clone(),
+        }
+    }
+}
+
+/// Orders the setups by kind, keeping the scan order within a kind
+pub fn rank_setups(setups: &mut [FxSetup]) {
+    setups.sort_by_key(|x| x.get_kind());
+}
+
+/// Everything one FX scan found
+#[derive(Debug, Default)]
+pub struct FxScan {
+    setups: Vec<FxSetup>,
+    /// None when the positions couldn't be read
+    exposure: Option<Exposure>,
+    /// high-impact events from a day before to a day after the scan
+    events: Vec<EconomicEvent>,
+}
+
+impl FxScan {
+    pub fn new(
+        setups: Vec<FxSetup>,
+        exposure: Option<Exposure>,
+        events: Vec<EconomicEvent>,
+    ) -> Self {
+        FxScan {
+            setups,
+            exposure,
+            events,
+        }
+    }
+
+    //getters
+    pub fn get_setups(&self) -> &[FxSetup] {
+        &self.setups
+    }
+    pub fn get_exposure(&self) -> Option<&Exposure> {
+        self.exposure.as_ref()
+    }
+    pub fn get_events(&self) -> &[EconomicEvent] {
+        &self.events
+    }
+}
+
+pub async fn fetch_gmo_coin_fx() -> FxScan {
     let client = Client::new();
-    let symbols = vec![
-        Symbol::UsdJpy,
-        Symbol::EurJpy,
-        Symbol::GbpJpy,
-        Symbol::AudJpy,
-        Symbol::EurUsd,
-        Symbol::GbpUsd,
-        Symbol::AudUsd,
-    ];
-    for symbol in symbols {
-        info!("symbol: {}", symbol);
-        let position: Option<LongOrShort> = match symbol {
-            Symbol::UsdJpy => None,
-            Symbol::EurJpy => None,
-            Symbol::GbpJpy => None,
-            Symbol::AudJpy => None,
-            Symbol::EurUsd => None,
-            Symbol::GbpUsd => None,
-            Symbol::AudUsd => None,
-        };
+    // spreads are checked against the ticker at the start of the scan
+    let tickers = match fetch_tickers(&client).await {
+        Ok(tickers) => tickers,
+        Err(e) => {
+            warn!(
+                "fetch_tickers failed, signals aren't checked for spread: {}",
+                e
+            );
+            Vec::new()
+        }
+    };
 
-        let ohlc_vec_m30 = fetch_ohlc(&client, symbol.clone(), Interval::M30)
-            .await
-            .unwrap();
-        let ohlc_vec_d1 = fetch_ohlc(&client, symbol.clone(), Interval::D1)
-            .await
-            .unwrap();
+    let economic_events = load_economic_events(&client).await;
 
-        let ohlc_analyzer =
-            OhlcAnalyzer::from_gmo_coin_fx(symbol, ohlc_vec_m30, ohlc_vec_d1, position);
+    let positions = match fx_private::fetch_position_summary(&client).await {
+        Ok(positions) => Some(positions),
+        Err(e) => {
+            warn!("fetch_position_summary failed, exposure unknown: {}", e);
+            None
+        }
+    };
+    let exposure = positions
+        .as_deref()
+        .and_then(|x| match Exposure::from_positions(x) {
+            Ok(exposure) => {
+                info!("exposure: {}", exposure);
+                Some(exposure)
+            }
+            Err(e) => {
+                warn!("exposure unknown: {}", e);
+                None
+            }
+        });
+    let positions = positions.unwrap_or_default();
 
-        info!(
-            "M30 standardized diff: {}",
-            ohlc_analyzer.get_shorter_ohlc_standardized_diff()
-        );
-        info!(
-            "D1 trend: {:?}",
-            ohlc_analyzer.get_longer_ohlc_standardized_diff_and_trend()
-        );
+    // symbols are scanned concurrently, their klines requests share the GMO limiter
+    let limiter = Provider::Gmo.limiter();
+    let mut setups = join_all(Symbol::ALL.into_iter().map(|symbol| {
+        scan_symbol(
+            &client,
+            limiter,
+            symbol,
+            &positions,
+            &tickers,
+            &economic_events,
+            exposure.as_ref(),
+        )
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    rank_setups(&mut setups);
+
+    FxScan {
+        setups,
+        exposure,
+        events: economic_events,
+    }
+}
 
-        match ohlc_analyzer.get_position() {
-            Some(_) => info!("stop loss order: {:?}", ohlc_analyzer.position_follow()),
-            None => info!("{:?}", ohlc_analyzer.analyze_last20(None)),
+/// The setup of one symbol, None when its klines can't be fetched
+async fn scan_symbol(
+    client: &Client,
+    limiter: &RateLimiter,
+    symbol: Symbol,
+    positions: &[PositionSummary],
+    tickers: &[Ticker],
+    economic_events: &[EconomicEvent],
+    exposure: Option<&Exposure>,
+) -> Option<FxSetup> {
+    info!("symbol: {}", symbol);
+    let position = match fx_private::net_side(positions, &symbol.to_string()) {
+        Ok(position) => position,
+        Err(e) => {
+            error!("{} position unknown: {}", symbol, e);
+            return None;
+        }
+    };
+
+    let settings = FxSettings::from_config(&symbol);
+    let (shorter, longer) = (settings.get_shorter(), settings.get_longer());
+
+    let (ohlc_vec_shorter, ohlc_vec_longer) = tokio::join!(
+        fetch_ohlc(
+            client,
+            limiter,
+            symbol.clone(),
+            shorter,
+            settings.get_bars()
+        ),
+        fetch_ohlc(client, limiter, symbol.clone(), longer, settings.get_bars()),
+    );
+    let ohlc_vec_shorter = match ohlc_vec_shorter {
+        Ok(ohlc_vec) => ohlc_vec,
+        Err(e) => {
+            error!("{} {} failed: {}", symbol, shorter, e);
+            return None;
+        }
+    };
+    let ohlc_vec_longer = match ohlc_vec_longer {
+        Ok(ohlc_vec) => ohlc_vec,
+        Err(e) => {
+            error!("{} {} failed: {}", symbol, longer, e);
+            return None;
+        }
+    };
+
+    let spread = tickers
+        .iter()
+        .find(|x| x.get_symbol() == symbol.to_string())
+        .map(|ticker| settings.spread(&symbol, ticker));
+
+    let symbol_currencies = symbol.currencies();
+    let near_events = economic_calendar::events_near(
+        economic_events,
+        &symbol_currencies,
+        Utc::now(),
+        settings.get_event_window(),
+    );
+
+    let ohlc_analyzer =
+        OhlcAnalyzer::from_gmo_coin_fx(symbol.clone(), ohlc_vec_shorter, ohlc_vec_longer, position);
+
+    info!(
+        "{} standardized diff: {}",
+        shorter,
+        ohlc_analyzer.get_shorter_ohlc_standardized_diff()
+    );
+    info!(
+        "{} trend: {:?}",
+        longer,
+        ohlc_analyzer.get_longer_ohlc_standardized_diff_and_trend()
+    );
+
+    let setup = match ohlc_analyzer.get_position() {
+        Some(position) => {
+            let stop_loss_order = ohlc_analyzer.position_follow();
+            info!("stop loss order: {:?}", stop_loss_order);
+            FxSetup::new(
+                symbol,
+                &format!("{} held, stop loss order {}", position, stop_loss_order),
+                false,
+                Vec::new(),
+            )
+            .with_kind(SetupKind::Held)
+        }
+        None => {
+            let analysis = ohlc_analyzer.analyze_last20(None);
+            let breakout = analysis.get_break_or_not();
+            let mut skipped = false;
+            let mut notes = Vec::new();
+            match spread {
+                Some(Ok(spread)) => {
+                    skipped |= breakout && spread.is_vetoed();
+                    notes.push(spread.to_string());
+                }
+                Some(Err(e)) => notes.push(format!("spread unknown, {}", e)),
+                None => notes.push("spread unknown".to_owned()),
+            }
+            if !near_events.is_empty() {
+                skipped |= breakout && settings.get_event_action() == EventAction::Suppress;
+                let near_events = near_events
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>();
+                notes.push(format!("events: {}", near_events.join(" / ")));
+            }
+            if let Some(exposure) = exposure.and_then(|x| x.describe(&symbol_currencies)) {
+                notes.push(exposure);
+            }
+            match skipped {
+                true => warn!("{:?} skipped, {}", analysis, notes.join(", ")),
+                false => info!("{:?} {}", analysis, notes.join(", ")),
+            }
+            let signal = match breakout {
+                true => format!(
+                    "{} {} units, stop loss order {}",
+                    analysis.get_long_or_short(),
+                    analysis.get_units().unwrap_or_default(),
+                    analysis.get_stop_loss_order()
+                ),
+                false => "no breakout".to_owned(),
+            };
+            let kind = match (breakout, skipped) {
+                (true, false) => SetupKind::Breakout,
+                (true, true) => SetupKind::Vetoed,
+                (false, _) => SetupKind::NoBreakout,
+            };
+            FxSetup::new(symbol, &signal, skipped, notes).with_kind(kind)
+        }
+    };
+    Some(setup)
+}
+
+/// Fetches this week's calendar into the DB and reads the events around now,
+/// so a failed fetch still leaves the events stored earlier
+async fn load_economic_events(client: &Client) -> Vec<EconomicEvent> {
+    let conn = match economic_events::open_db() {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!("economic_events open_db failed: {}", e);
+            return Vec::new();
+        }
+    };
+    match economic_calendar::fetch_high_impact(client).await {
+        Ok(events) => {
+            if let Err(e) = economic_events::insert(&conn, &events) {
+                warn!("economic_events insert failed: {}", e);
+            }
+        }
+        Err(e) => warn!("economic calendar fetch failed: {}", e),
+    }
+
+    let now = Utc::now();
+    match economic_events::select_between(&conn, now - Duration::days(1), now + Duration::days(1)) {
+        Ok(events) => events,
+        Err(e) => {
+            warn!("economic_events select failed: {}", e);
+            Vec::new()
         }
     }
 }
@@ -339,6 +944,100 @@ mod tests {
         assert_eq!(symbol.to_string(), str);
     }
 
+    #[test]
+    fn test_klines_response() {
+        let ok = r#"{"status":0,"data":[{"openTime":"1618588800000","open":"108.8","high":"108.9","low":"108.7","close":"108.85"}],"responsetime":"2021-04-17T00:00:00.000Z"}"#;
+        let res: KLinesResponse = serde_json::from_str(ok).unwrap();
+        let ohlc_vec = res.into_ohlc_vec(StatusCode::OK).unwrap();
+        assert_eq!(ohlc_vec.len(), 1);
+
+        let maintenance = r#"{"status":5,"messages":[{"message_code":"ERR-5201","message_string":"MAINTENANCE"}],"responsetime":"2021-04-17T00:00:00.000Z"}"#;
+        let res: KLinesResponse = serde_json::from_str(maintenance).unwrap();
+        let e = res.into_ohlc_vec(StatusCode::OK).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "GMO Coin API error, status 200: status 5, ERR-5201 MAINTENANCE"
+        );
+        assert!(e.is_retryable());
+
+        let bad_price = r#"{"status":0,"data":[{"openTime":"1618588800000","open":"-","high":"108.9","low":"108.7","close":"108.85"}],"responsetime":"2021-04-17T00:00:00.000Z"}"#;
+        let res: KLinesResponse = serde_json::from_str(bad_price).unwrap();
+        let e = res.into_ohlc_vec(StatusCode::OK).unwrap_err();
+        assert_eq!(e.to_string(), "invalid kline open: -");
+        assert!(!e.is_retryable());
+    }
+
+    #[test]
+    fn test_rank_setups() {
+        let setup = |symbol: Symbol, kind: SetupKind| {
+            FxSetup::new(symbol, "", kind == SetupKind::Vetoed, Vec::new()).with_kind(kind)
+        };
+        let mut setups = vec![
+            setup(Symbol::UsdJpy, SetupKind::NoBreakout),
+            setup(Symbol::EurJpy, SetupKind::Vetoed),
+            setup(Symbol::GbpJpy, SetupKind::Breakout),
+            setup(Symbol::AudJpy, SetupKind::Held),
+            setup(Symbol::EurUsd, SetupKind::Breakout),
+        ];
+        rank_setups(&mut setups);
+        let ranked = setups
+            .iter()
+            .map(|x| x.get_symbol().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranked,
+            vec!["GBP_JPY", "EUR_USD", "AUD_JPY", "EUR_JPY", "USD_JPY"]
+        );
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let api_error = |status: u16| MyError::GmoApi {
+            status,
+            message: String::new(),
+        };
+        assert!(api_error(429).is_retryable());
+        assert!(api_error(503).is_retryable());
+        assert!(!api_error(400).is_retryable());
+        assert!(!MyError::Holiday.is_retryable());
+    }
+
+    #[test]
+    fn test_fx_settings() {
+        let settings: FxSettings =
+            serde_json::from_str(r#"{"shorter": "1hour", "longer": "1week"}"#).unwrap();
+        assert_eq!(settings.get_bars(), 60);
+        assert_eq!(settings.get_shorter(), Interval::H1);
+        assert_eq!(settings.get_longer(), Interval::W1);
+
+        assert_eq!(settings.get_event_window(), Duration::hours(2));
+        assert_eq!(settings.get_event_action(), EventAction::Suppress);
+
+        let settings: FxSettings =
+            serde_json::from_str(r#"{"bars": 5, "eventAction": "warn"}"#).unwrap();
+        assert_eq!(settings.get_event_action(), EventAction::Warn);
+        assert_eq!(settings.get_bars(), 20);
+        assert_eq!(settings.get_shorter(), Interval::M30);
+        assert!(serde_json::from_str::<FxSettings>(r#"{"shorter": "2hour"}"#).is_err());
+    }
+
+    #[test]
+    fn test_spread_veto() {
+        let ticker: Ticker = serde_json::from_str(
+            r#"{"ask":"137.654","bid":"137.632","symbol":"USD_JPY","timestamp":"2018-03-30T12:34:56.789671Z","status":"OPEN"}"#,
+        )
+        .unwrap();
+        let symbol = Symbol::UsdJpy;
+        assert_eq!(ticker.spread_pips(&symbol).unwrap(), 2.2);
+
+        let spread = FxSettings::default().spread(&symbol, &ticker).unwrap();
+        assert!(spread.is_vetoed());
+        assert_eq!(spread.to_string(), "spread 2.2 pips (normal 0.2, max x3)");
+
+        let settings: FxSettings = serde_json::from_str(r#"{"maxSpreadMultiple": 12.0}"#).unwrap();
+        assert!(!settings.spread(&symbol, &ticker).unwrap().is_vetoed());
+    }
+
     #[test]
     fn test_weekday() {
         use chrono::TimeZone;
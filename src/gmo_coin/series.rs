@@ -0,0 +1,230 @@
+use anyhow::anyhow;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Weekday};
+use log::warn;
+
+use super::fx_public::Interval;
+use crate::analysis::live::Ohlc;
+use crate::my_error::MyError;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Missing bars between two bars while the market was open
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gap {
+    after: String,
+    before: String,
+}
+
+/// Continuous bars, oldest first
+#[derive(Debug)]
+pub struct Series {
+    bars: Vec<Ohlc>,
+    gaps: Vec<Gap>,
+}
+
+impl Series {
+    //getters
+    pub fn get_bars(&self) -> &[Ohlc] {
+        &self.bars
+    }
+    /// all gaps found, the bars before the last one are dropped
+    pub fn get_gaps(&self) -> &[Gap] {
+        &self.gaps
+    }
+    pub fn into_bars(self) -> Vec<Ohlc> {
+        self.bars
+    }
+}
+
+fn open_time(ohlc: &Ohlc) -> Result<NaiveDateTime, MyError> {
+    NaiveDateTime::parse_from_str(ohlc.get_date(), DATE_FORMAT)
+        .map_err(|_| MyError::Anyhow(anyhow!("invalid open time {}", ohlc.get_date())))
+}
+
+/// (month, day) the market doesn't open, Christmas and New Year's Day
+const HOLIDAYS: [(u32, u32); 2] = [(12, 25), (1, 1)];
+/// Hours (JST) of the daily maintenance around the New York close, two for DST
+const MAINTENANCE_HOURS: std::ops::Range<u32> = 5..8;
+
+fn is_holiday(date: NaiveDate) -> bool {
+    HOLIDAYS.contains(&(date.month(), date.day()))
+}
+
+/// FX is closed from Saturday morning to Monday morning (JST), with an hour of slack for DST,
+/// on the holidays and during the daily maintenance
+fn is_market_closed(time: NaiveDateTime) -> bool {
+    let weekend = match time.weekday() {
+        Weekday::Sat => time.hour() >= 6,
+        Weekday::Sun => true,
+        Weekday::Mon => time.hour() < 8,
+        _ => false,
+    };
+    weekend || is_holiday(time.date()) || MAINTENANCE_HOURS.contains(&time.hour())
+}
+
+/// Whether a bar is missing between `prev` and `next`
+fn has_gap(prev: NaiveDateTime, next: NaiveDateTime, interval: &Interval) -> bool {
+    match interval {
+        // one bar per weekday but the holidays
+        Interval::D1 => {
+            let mut date = prev.date() + Duration::days(1);
+            while date < next.date() {
+                if !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !is_holiday(date) {
+                    return true;
+                }
+                date += Duration::days(1);
+            }
+            false
+        }
+        _ => {
+            let mut time = prev + interval.step();
+            while time < next {
+                if !is_market_closed(time) {
+                    return true;
+                }
+                time += interval.step();
+            }
+            false
+        }
+    }
+}
+
+/// Joins klines fetched day by day (year by year for D1) into one series.
+/// Bars are sorted by open time, duplicates across fetches are dropped (the later
+/// fetch wins) and so is the bar still forming at `now`.
+/// Only the bars after the last gap are kept, so indicators never span missing data.
+pub fn assemble(
+    bars: Vec<Ohlc>,
+    interval: &Interval,
+    now: NaiveDateTime,
+) -> Result<Series, MyError> {
+    let mut timed = bars
+        .into_iter()
+        .map(|x| Ok((open_time(&x)?, x)))
+        .collect::<Result<Vec<_>, MyError>>()?;
+    // stable, so the later of two bars with the same open time stays last
+    timed.sort_by_key(|(time, _)| *time);
+    let mut deduped: Vec<(NaiveDateTime, Ohlc)> = Vec::with_capacity(timed.len());
+    for (time, ohlc) in timed {
+        match deduped.last_mut() {
+            Some(last) if last.0 == time => *last = (time, ohlc),
+            _ => deduped.push((time, ohlc)),
+        }
+    }
+    deduped.retain(|(time, _)| *time + interval.step() <= now);
+
+    let mut gaps = Vec::new();
+    let mut start = 0;
+    for i in 1..deduped.len() {
+        if has_gap(deduped[i - 1].0, deduped[i].0, interval) {
+            let gap = Gap {
+                after: deduped[i - 1].1.get_date().to_owned(),
+                before: deduped[i].1.get_date().to_owned(),
+            };
+            warn!("{}: gap between {} and {}", interval, gap.after, gap.before);
+            gaps.push(gap);
+            start = i;
+        }
+    }
+
+    Ok(Series {
+        bars: deduped.into_iter().skip(start).map(|(_, x)| x).collect(),
+        gaps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(date: &str, close: f64) -> Ohlc {
+        Ohlc::new(date.to_owned(), close, close, close, close)
+    }
+
+    fn time(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, DATE_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn test_assemble_dedup_and_forming_bar() {
+        // 2024-01-10 is a Wednesday
+        let bars = vec![
+            bar("2024-01-10 10:30:00", 3.0),
+            bar("2024-01-10 10:00:00", 2.0),
+            bar("2024-01-10 09:30:00", 1.0),
+            bar("2024-01-10 10:00:00", 2.5),
+            bar("2024-01-10 11:00:00", 4.0),
+        ];
+        let series = assemble(bars, &Interval::M30, time("2024-01-10 11:10:00")).unwrap();
+        let closes = series
+            .get_bars()
+            .iter()
+            .map(|x| x.get_close())
+            .collect::<Vec<_>>();
+        assert_eq!(closes, vec![1.0, 2.5, 3.0]);
+        assert!(series.get_gaps().is_empty());
+    }
+
+    #[test]
+    fn test_assemble_gap() {
+        let bars = vec![
+            bar("2024-01-10 09:00:00", 1.0),
+            bar("2024-01-10 09:30:00", 2.0),
+            bar("2024-01-10 11:00:00", 3.0),
+            bar("2024-01-10 11:30:00", 4.0),
+        ];
+        let series = assemble(bars, &Interval::M30, time("2024-01-11 00:00:00")).unwrap();
+        assert_eq!(
+            series.get_gaps(),
+            &[Gap {
+                after: "2024-01-10 09:30:00".to_owned(),
+                before: "2024-01-10 11:00:00".to_owned(),
+            }]
+        );
+        assert_eq!(series.get_bars().len(), 2);
+        assert_eq!(series.get_bars()[0].get_close(), 3.0);
+    }
+
+    #[test]
+    fn test_assemble_weekend() {
+        // Saturday 05:30 to Monday 07:00 is the weekend close, not a gap
+        let bars = vec![
+            bar("2024-01-13 05:00:00", 1.0),
+            bar("2024-01-13 05:30:00", 2.0),
+            bar("2024-01-15 07:00:00", 3.0),
+        ];
+        let series = assemble(bars, &Interval::M30, time("2024-01-16 00:00:00")).unwrap();
+        assert!(series.get_gaps().is_empty());
+        assert_eq!(series.get_bars().len(), 3);
+
+        let daily = vec![
+            bar("2024-01-11 07:00:00", 1.0),
+            bar("2024-01-12 07:00:00", 2.0),
+            bar("2024-01-15 07:00:00", 3.0),
+            bar("2024-01-17 07:00:00", 4.0),
+        ];
+        let series = assemble(daily, &Interval::D1, time("2024-01-20 00:00:00")).unwrap();
+        assert_eq!(series.get_gaps().len(), 1);
+        assert_eq!(series.get_bars().len(), 1);
+    }
+
+    #[test]
+    fn test_assemble_holiday_and_maintenance() {
+        // the daily maintenance of a Wednesday
+        let bars = vec![
+            bar("2024-01-10 04:30:00", 1.0),
+            bar("2024-01-10 07:00:00", 2.0),
+        ];
+        let series = assemble(bars, &Interval::M30, time("2024-01-11 00:00:00")).unwrap();
+        assert!(series.get_gaps().is_empty());
+
+        // Christmas on a Wednesday
+        let daily = vec![
+            bar("2024-12-24 07:00:00", 1.0),
+            bar("2024-12-26 07:00:00", 2.0),
+        ];
+        let series = assemble(daily, &Interval::D1, time("2024-12-30 00:00:00")).unwrap();
+        assert!(series.get_gaps().is_empty());
+        assert_eq!(series.get_bars().len(), 2);
+    }
+}
@@ -0,0 +1,55 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+use crate::my_error::MyError;
+use crate::my_file_io::get_halt_file_path;
+
+/// Stops the reports going out, set and cleared from the chat ("halt", "resume").
+/// Reports are still written to their files, see `Dispatcher::for_kind`.
+/// It is a file next to config.json, so every machine syncing the folder sees it
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Halt {
+    reason: String,
+    /// "YYYY-MM-DD HH:MM:SS"
+    at: String,
+}
+
+impl Display for Halt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.reason.is_empty() {
+            true => write!(f, "{}", self.at),
+            false => write!(f, "{} {}", self.at, self.reason),
+        }
+    }
+}
+
+/// The halt in effect, None when running
+pub fn current() -> Result<Option<Halt>, MyError> {
+    let path = get_halt_file_path()?;
+    match path.exists() {
+        true => Ok(Some(serde_json::from_str(&std::fs::read_to_string(path)?)?)),
+        false => Ok(None),
+    }
+}
+
+pub fn set(reason: &str) -> Result<Halt, MyError> {
+    let halt = Halt {
+        reason: reason.to_owned(),
+        at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    };
+    std::fs::write(get_halt_file_path()?, serde_json::to_string(&halt)?)?;
+    Ok(halt)
+}
+
+/// false when it wasn't halted
+pub fn clear() -> Result<bool, MyError> {
+    let path = get_halt_file_path()?;
+    match path.exists() {
+        true => {
+            std::fs::remove_file(path)?;
+            Ok(true)
+        }
+        false => Ok(false),
+    }
+}
@@ -0,0 +1,407 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+use crate::config::GdriveJson;
+
+/// Language of reports and notifications, set by `language` in config.json
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    Ja,
+    #[default]
+    En,
+}
+
+impl Lang {
+    /// Falls back to English when config.json can't be read
+    pub fn from_config() -> Self {
+        GdriveJson::new()
+            .map(|config| config.language())
+            .unwrap_or_default()
+    }
+
+    pub fn yen<T: Display>(&self, amount: T) -> LocalizedYen<T> {
+        LocalizedYen {
+            lang: *self,
+            amount,
+        }
+    }
+}
+
+/// Amount with the currency sign of the language, formatted without allocating
+pub struct LocalizedYen<T> {
+    lang: Lang,
+    amount: T,
+}
+impl<T: Display> Display for LocalizedYen<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.lang {
+            Lang::Ja => write!(f, "{}円", self.amount),
+            Lang::En => write!(f, "¥{}", self.amount),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Msg {
+    // report
+    Nextday,
+    ThisAfternoon,
+    AfternoonStrategy,
+    Summary,
+    NumberOfStocks,
+    MarketRegime,
+    MorningGainers,
+    AfternoonGainers,
+    AlldayGainers,
+    Gainers,
+    ResistanceTop10,
+    SupportTop10,
+    RsTop10,
+    StrongTape,
+    WeakTape,
+    RequiredAmount,
+    Morning,
+    Afternoon,
+    Allday,
+    MorningResult,
+    AfternoonResult,
+    Resistance,
+    Support,
+    Chart,
+    History,
+    Code,
+    Name,
+    Sector,
+    Score,
+    Model,
+    Excluded,
+    Flagged,
+    ResultsOn,
+    Stale,
+    InvestorFlows,
+    Foreigners,
+    Individuals,
+    NetBuyers,
+    NetSellers,
+    Price,
+    Status,
+    Capacity,
+    AvailableCash,
+    Used,
+    Leftover,
+    StrategyCapacity,
+    UnfilledGaps,
+    Date,
+    SectorHeat,
+    Breakouts,
+    Breakdowns,
+    CorrelatedPairs,
+    // status
+    Rise,
+    RiseBounded,
+    Stable,
+    FallBounded,
+    Fall,
+    // briefing
+    Briefing,
+    FxSetups,
+    Positions,
+    Alerts,
+    Symbol,
+    Signal,
+    Notes,
+    NoReport,
+    Skipped,
+    UpcomingEvent,
+    DoubledExposure,
+    ExposureUnknown,
+    PendingDrafts,
+    Stress,
+    IndexGap,
+    AdverseMove,
+    // notification
+    NextdayStarted,
+    NextdaySucceeded,
+    AfternoonStarted,
+    AfternoonSucceeded,
+    FetchMorningFailed,
+    InsufficientCoverage,
+    MorningDataStale,
+    NotLatestData,
+    FeatureDrift,
+    DraftReady,
+    Published,
+    Failed,
+    Retryable,
+    Degraded,
+    // progress
+    Fetched,
+    Queries,
+    Eta,
+    Minutes,
+    // bot
+    BotHelp,
+    Running,
+    Halted,
+    HaltSet,
+    Resumed,
+    LatestBar,
+    Coverage,
+    NoBars,
+}
+
+impl Msg {
+    pub fn text(&self, lang: Lang) -> &'static str {
+        match lang {
+            Lang::Ja => self.ja(),
+            Lang::En => self.en(),
+        }
+    }
+
+    /// Status strings are stored in English (`StocksWindow.status` etc.)
+    pub fn from_status(status: &str) -> Option<Self> {
+        match status {
+            "Rise" => Some(Msg::Rise),
+            "Rise bounded" => Some(Msg::RiseBounded),
+            "Stable" => Some(Msg::Stable),
+            "Fall bounded" => Some(Msg::FallBounded),
+            "Fall" => Some(Msg::Fall),
+            _ => None,
+        }
+    }
+
+    fn ja(&self) -> &'static str {
+        match self {
+            Msg::Nextday => "翌日",
+            Msg::ThisAfternoon => "本日後場",
+            Msg::AfternoonStrategy => "後場戦略",
+            Msg::Summary => "概要",
+            Msg::NumberOfStocks => "銘柄数",
+            Msg::MarketRegime => "地合い (TOPIX)",
+            Msg::MorningGainers => "前場上昇率",
+            Msg::AfternoonGainers => "後場上昇率",
+            Msg::AlldayGainers => "終日上昇率",
+            Msg::Gainers => "上昇率",
+            Msg::ResistanceTop10 => "上値抵抗 上位10",
+            Msg::RsTop10 => "相対力 上位10",
+            Msg::StrongTape => "地合い強 (強い銘柄順)",
+            Msg::WeakTape => "地合い弱 (弱い銘柄順)",
+            Msg::SupportTop10 => "下値支持 上位10",
+            Msg::RequiredAmount => "必要金額",
+            Msg::Morning => "前場",
+            Msg::Afternoon => "後場",
+            Msg::Allday => "終日",
+            Msg::MorningResult => "前場結果",
+            Msg::AfternoonResult => "後場結果",
+            Msg::Resistance => "上値抵抗",
+            Msg::Support => "下値支持",
+            Msg::Chart => "チャート",
+            Msg::History => "候補履歴",
+            Msg::Code => "コード",
+            Msg::Name => "銘柄",
+            Msg::Sector => "業種",
+            Msg::Score => "スコア",
+            Msg::Model => "モデル",
+            Msg::Excluded => "除外",
+            Msg::Flagged => "注意",
+            Msg::ResultsOn => "決算発表",
+            Msg::Stale => "データ古い",
+            Msg::InvestorFlows => "投資部門別売買状況",
+            Msg::Foreigners => "海外投資家",
+            Msg::Individuals => "個人",
+            Msg::NetBuyers => "買い越し",
+            Msg::NetSellers => "売り越し",
+            Msg::Price => "株価",
+            Msg::Status => "状態",
+            Msg::Capacity => "資金配分",
+            Msg::AvailableCash => "余力",
+            Msg::Used => "使用",
+            Msg::Leftover => "残り",
+            Msg::StrategyCapacity => "戦略容量",
+            Msg::UnfilledGaps => "未埋めの窓",
+            Msg::Date => "日付",
+            Msg::SectorHeat => "業種別動向",
+            Msg::Breakouts => "高値更新",
+            Msg::Breakdowns => "安値更新",
+            Msg::CorrelatedPairs => "相関の高い候補",
+            Msg::Rise => "上昇",
+            Msg::RiseBounded => "上昇後反落",
+            Msg::Stable => "横ばい",
+            Msg::FallBounded => "下落後反発",
+            Msg::Fall => "下落",
+            Msg::Briefing => "デイリーブリーフィング",
+            Msg::FxSetups => "FXセットアップ",
+            Msg::Positions => "ポジション",
+            Msg::Alerts => "アラート",
+            Msg::Symbol => "通貨ペア",
+            Msg::Signal => "シグナル",
+            Msg::Notes => "備考",
+            Msg::NoReport => "レポートなし",
+            Msg::Skipped => "見送り",
+            Msg::UpcomingEvent => "重要指標",
+            Msg::DoubledExposure => "同方向ポジションの重複",
+            Msg::ExposureUnknown => "建玉を取得できず",
+            Msg::PendingDrafts => "未公開の下書き",
+            Msg::Stress => "ストレステスト",
+            Msg::IndexGap => "指数ギャップ",
+            Msg::AdverseMove => "逆行",
+            Msg::NextdayStarted => "翌日分の処理を開始",
+            Msg::NextdaySucceeded => "翌日分の処理が完了",
+            Msg::AfternoonStarted => "後場の処理を開始",
+            Msg::AfternoonSucceeded => "後場の処理が完了",
+            Msg::FetchMorningFailed => "前場データの取得に失敗",
+            Msg::InsufficientCoverage => "データ不足のためレポートを中止",
+            Msg::MorningDataStale => "前場データが古いためレポートを中止",
+            Msg::NotLatestData => "最新の株価データがないためレポートを中止",
+            Msg::FeatureDrift => "特徴量の分布が通常と異なります",
+            Msg::DraftReady => "レポートの下書きを作成、確認後に公開してください",
+            Msg::Published => "レポートを公開",
+            Msg::Failed => "失敗",
+            Msg::Retryable => "一時的なエラー、再実行で回復する可能性があります",
+            Msg::Degraded => "失敗した処理(続行)",
+            Msg::Fetched => "取得中",
+            Msg::Queries => "件",
+            Msg::Eta => "残り約",
+            Msg::Minutes => "分",
+            Msg::BotHelp => "コマンド: status, today, halt [理由], resume, chart [コード]",
+            Msg::Running => "稼働中",
+            Msg::Halted => "停止中",
+            Msg::HaltSet => "レポートの送信を停止",
+            Msg::Resumed => "レポートの送信を再開",
+            Msg::LatestBar => "最新の株価",
+            Msg::Coverage => "取得率",
+            Msg::NoBars => "株価データなし",
+        }
+    }
+
+    fn en(&self) -> &'static str {
+        match self {
+            Msg::Nextday => "Nextday",
+            Msg::ThisAfternoon => "This afternoon",
+            Msg::AfternoonStrategy => "Afternoon Strategy",
+            Msg::Summary => "Summary",
+            Msg::NumberOfStocks => "Number of Stocks",
+            Msg::MarketRegime => "Market Regime (TOPIX)",
+            Msg::MorningGainers => "Morning Gainers",
+            Msg::AfternoonGainers => "Afternoon Gainers",
+            Msg::AlldayGainers => "Allday Gainers",
+            Msg::Gainers => "Gainers",
+            Msg::ResistanceTop10 => "Resistance Candles Top 10",
+            Msg::RsTop10 => "Relative Strength Top 10",
+            Msg::StrongTape => "strong tape, leaders first",
+            Msg::WeakTape => "weak tape, laggards first",
+            Msg::SupportTop10 => "Support Candles Top 10",
+            Msg::RequiredAmount => "Required Amount",
+            Msg::Morning => "Morning",
+            Msg::Afternoon => "Afternoon",
+            Msg::Allday => "Allday",
+            Msg::MorningResult => "Morning Result",
+            Msg::AfternoonResult => "Afternoon Result",
+            Msg::Resistance => "Resistance",
+            Msg::Support => "Support",
+            Msg::Chart => "Chart",
+            Msg::History => "Candidate History",
+            Msg::Code => "Code",
+            Msg::Name => "Name",
+            Msg::Sector => "Sector",
+            Msg::Score => "Score",
+            Msg::Model => "Model",
+            Msg::Excluded => "Excluded",
+            Msg::Flagged => "Flagged",
+            Msg::ResultsOn => "results on",
+            Msg::Stale => "Stale",
+            Msg::InvestorFlows => "Investor Flows",
+            Msg::Foreigners => "Foreigners",
+            Msg::Individuals => "Individuals",
+            Msg::NetBuyers => "net buyers",
+            Msg::NetSellers => "net sellers",
+            Msg::Price => "Price",
+            Msg::Status => "Status",
+            Msg::Capacity => "Capacity",
+            Msg::AvailableCash => "Available Cash",
+            Msg::Used => "Used",
+            Msg::Leftover => "Leftover",
+            Msg::StrategyCapacity => "Strategy capacity",
+            Msg::UnfilledGaps => "Unfilled Gaps",
+            Msg::Date => "Date",
+            Msg::SectorHeat => "Sector Heat",
+            Msg::Breakouts => "Breakouts",
+            Msg::Breakdowns => "Breakdowns",
+            Msg::CorrelatedPairs => "Correlated Candidates",
+            Msg::Rise => "Rise",
+            Msg::RiseBounded => "Rise bounded",
+            Msg::Stable => "Stable",
+            Msg::FallBounded => "Fall bounded",
+            Msg::Fall => "Fall",
+            Msg::Briefing => "Daily Briefing",
+            Msg::FxSetups => "FX Setups",
+            Msg::Positions => "Positions",
+            Msg::Alerts => "Alerts",
+            Msg::Symbol => "Symbol",
+            Msg::Signal => "Signal",
+            Msg::Notes => "Notes",
+            Msg::NoReport => "no report",
+            Msg::Skipped => "skipped",
+            Msg::UpcomingEvent => "upcoming event",
+            Msg::DoubledExposure => "doubled exposure",
+            Msg::ExposureUnknown => "positions unknown",
+            Msg::PendingDrafts => "drafts pending",
+            Msg::Stress => "Stress",
+            Msg::IndexGap => "Index gap",
+            Msg::AdverseMove => "Adverse move",
+            Msg::NextdayStarted => "Starting Next day process",
+            Msg::NextdaySucceeded => "Next day process, success",
+            Msg::AfternoonStarted => "Starting Afternoon process",
+            Msg::AfternoonSucceeded => "Success",
+            Msg::FetchMorningFailed => "fetch morning market failed",
+            Msg::InsufficientCoverage => "report aborted, not enough data",
+            Msg::MorningDataStale => "report aborted, morning data too old",
+            Msg::NotLatestData => "report aborted, latest daily quotes missing",
+            Msg::FeatureDrift => "features far from the last 60 days",
+            Msg::DraftReady => "draft ready for review",
+            Msg::Published => "report published",
+            Msg::Failed => "failed",
+            Msg::Retryable => "transient error, a rerun may succeed",
+            Msg::Degraded => "degraded",
+            Msg::Fetched => "fetched",
+            Msg::Queries => "queries",
+            Msg::Eta => "ETA",
+            Msg::Minutes => " min",
+            Msg::BotHelp => "commands: status, today, halt [reason], resume, chart [code]",
+            Msg::Running => "running",
+            Msg::Halted => "halted",
+            Msg::HaltSet => "reports halted",
+            Msg::Resumed => "reports resumed",
+            Msg::LatestBar => "latest bar",
+            Msg::Coverage => "coverage",
+            Msg::NoBars => "no bars",
+        }
+    }
+}
+
+/// Status label in `lang`, unknown statuses are passed through
+pub fn status_text(status: &str, lang: Lang) -> &str {
+    match Msg::from_status(status) {
+        Some(msg) => msg.text(lang),
+        None => status,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lang_from_json() {
+        assert_eq!(serde_json::from_str::<Lang>("\"en\"").unwrap(), Lang::En);
+        assert_eq!(serde_json::from_str::<Lang>("\"ja\"").unwrap(), Lang::Ja);
+    }
+
+    #[test]
+    fn test_status_text() {
+        assert_eq!(status_text("Rise bounded", Lang::Ja), "上昇後反落");
+        assert_eq!(status_text("Rise bounded", Lang::En), "Rise bounded");
+        assert_eq!(status_text("Unknown", Lang::Ja), "Unknown");
+    }
+}
@@ -0,0 +1,180 @@
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, TimeZone, Weekday};
+use std::fmt::{Display, Formatter};
+
+use crate::analysis::indicators;
+use crate::gmo_coin::fx_public::Symbol;
+use crate::my_error::MyError;
+use crate::rate_limit::Provider;
+use crate::stock_code::StockCode;
+
+/// Where the bars of an instrument come from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataSource {
+    JQuants,
+    GmoCoinFx,
+}
+
+impl DataSource {
+    /// The limiter the requests for the bars go through
+    pub fn provider(&self) -> Provider {
+        match self {
+            DataSource::JQuants => Provider::JQuants,
+            DataSource::GmoCoinFx => Provider::Gmo,
+        }
+    }
+}
+
+/// Trading hours, in Tokyo time. Holidays aren't known here, see `trading_calendar`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Session {
+    /// Tokyo Stock Exchange, 9:00-11:30 and 12:30-15:30 on weekdays
+    Tse,
+    /// Monday 7:00 to Saturday 6:00
+    Fx,
+}
+
+impl Session {
+    pub fn is_open<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> bool {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let at = at.with_timezone(&jst);
+        let time = at.time();
+        let hm = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        match self {
+            Session::Tse => {
+                !matches!(at.weekday(), Weekday::Sat | Weekday::Sun)
+                    && ((hm(9, 0)..hm(11, 30)).contains(&time)
+                        || (hm(12, 30)..hm(15, 30)).contains(&time))
+            }
+            Session::Fx => match at.weekday() {
+                Weekday::Sun => false,
+                Weekday::Mon => time >= hm(7, 0),
+                Weekday::Sat => time < hm(6, 0),
+                _ => true,
+            },
+        }
+    }
+}
+
+/// Something the analysis runs on, with what differs between asset classes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instrument {
+    Stock(StockCode),
+    Fx(Symbol),
+}
+
+impl Instrument {
+    /// A stock code ("7203") or an FX symbol ("USD_JPY")
+    pub fn parse(code: &str) -> Result<Self, MyError> {
+        if let Some(symbol) = Symbol::ALL.into_iter().find(|x| x.to_string() == code) {
+            return Ok(Instrument::Fx(symbol));
+        }
+        StockCode::new(code).map(Instrument::Stock)
+    }
+
+    pub fn code(&self) -> String {
+        match self {
+            Instrument::Stock(code) => code.to_string(),
+            Instrument::Fx(symbol) => symbol.to_string(),
+        }
+    }
+
+    pub fn source(&self) -> DataSource {
+        match self {
+            Instrument::Stock(_) => DataSource::JQuants,
+            Instrument::Fx(_) => DataSource::GmoCoinFx,
+        }
+    }
+
+    pub fn session(&self) -> Session {
+        match self {
+            Instrument::Stock(_) => Session::Tse,
+            Instrument::Fx(_) => Session::Fx,
+        }
+    }
+
+    /// Price step, None for stocks whose TSE tick depends on the price and the index
+    pub fn tick_size(&self) -> Option<f64> {
+        match self {
+            Instrument::Stock(_) => None,
+            Instrument::Fx(symbol) => Some(symbol.pips()),
+        }
+    }
+
+    /// Rounds to the tick, prices without a fixed tick are left as they are
+    pub fn round_price(&self, price: f64) -> f64 {
+        match self.tick_size() {
+            Some(tick) => crate::rounding::round_to_step(price, tick),
+            None => price,
+        }
+    }
+
+    /// Units to trade so that `risk` (the J-Quants unit in yen for stocks) is lost
+    /// at `distance` from the entry, see `indicators::board_lot_units` for stocks.
+    /// FX risks 3000 yen, in the quote currency for the USD pairs at about 100 yen
+    /// a dollar. None for a stock without `risk`
+    pub fn units(&self, distance: f64, risk: Option<f64>) -> Option<i32> {
+        match self {
+            Instrument::Stock(_) => risk.map(|risk| indicators::board_lot_units(risk, distance)),
+            Instrument::Fx(symbol) => {
+                let coefficient = match symbol.currencies()[1] {
+                    "USD" => 0.01,
+                    _ => 1.0,
+                };
+                Some((3000.0 / distance * coefficient).round() as i32)
+            }
+        }
+    }
+}
+
+impl Display for Instrument {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instrument() {
+        let stock = Instrument::Stock(StockCode::new("7203").unwrap());
+        let fx = Instrument::Fx(Symbol::EurUsd);
+        assert_eq!(Instrument::parse("7203").unwrap(), stock);
+        assert_eq!(Instrument::parse("EUR_USD").unwrap(), fx);
+        assert!(Instrument::parse("EURUSD").is_err());
+        assert_eq!(stock.to_string(), "7203");
+        assert_eq!(fx.to_string(), "EUR_USD");
+        assert_eq!(stock.source().provider(), Provider::JQuants);
+        assert_eq!(fx.source().provider(), Provider::Gmo);
+
+        assert_eq!(stock.round_price(2512.37), 2512.37);
+        assert_eq!(fx.round_price(1.252244), 1.2522);
+        assert_eq!(stock.units(25.0, Some(100_000.0)), Some(4000));
+        // 137 shares are one lot, under a lot is still one as in the screening
+        assert_eq!(stock.units(730.0, Some(100_000.0)), Some(100));
+        assert_eq!(stock.units(1500.0, Some(100_000.0)), Some(100));
+        assert_eq!(stock.units(25.0, None), None);
+        assert_eq!(fx.units(0.003, None), Some(10000));
+        assert_eq!(Instrument::Fx(Symbol::UsdJpy).units(0.3, None), Some(10000));
+    }
+
+    #[test]
+    fn test_session() {
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        // Friday 2024-01-05
+        let at = |d, h, m| jst.with_ymd_and_hms(2024, 1, d, h, m, 0).unwrap();
+        assert!(Session::Tse.is_open(&at(5, 9, 0)));
+        assert!(!Session::Tse.is_open(&at(5, 12, 0)));
+        assert!(!Session::Tse.is_open(&at(5, 15, 30)));
+        assert!(!Session::Tse.is_open(&at(6, 10, 0)));
+
+        assert!(Session::Fx.is_open(&at(5, 23, 0)));
+        assert!(Session::Fx.is_open(&at(6, 5, 59)));
+        assert!(!Session::Fx.is_open(&at(6, 6, 0)));
+        assert!(!Session::Fx.is_open(&at(8, 6, 59)));
+        assert!(Session::Fx.is_open(&at(8, 7, 0)));
+        // the same instant in UTC
+        assert!(Session::Tse.is_open(&at(5, 9, 0).with_timezone(&chrono::Utc)));
+    }
+}
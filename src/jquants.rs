@@ -1,2 +1,5 @@
 pub mod backtesting;
 pub mod fetcher;
+pub mod http_cache;
+pub mod planner;
+pub mod retry;
@@ -1,18 +1,32 @@
 use crate::analysis::live::{Ohlc, OhlcPremium};
 use crate::config::GdriveJson;
-use crate::my_error::MyError;
-use crate::my_file_io::{get_fetched_ohlc_file_path, AssetType};
+use crate::database::market_regime;
+use crate::database::prices_am;
+use crate::database::runs::{self, Coverage};
+use crate::database::short_selling::{self, ShortSelling};
+use crate::database::statements::{self, Statement};
+use crate::database::stocks_master::{self, StockMaster};
+use crate::database::topix_ohlc;
+use crate::database::trades_spec::{self, TradesSpec};
+use crate::database::trading_calendar;
+use crate::i18n::Msg;
+use crate::jquants::http_cache;
+use crate::jquants::planner::{self, Cells, Query};
+use crate::jquants::retry;
+use crate::my_error::{MyError, ResultExt};
+use crate::my_file_io::Universe;
+use crate::profile::{self, Stage};
+use crate::progress::Progress;
+use crate::stock_code::StockCode;
 use anyhow::{anyhow, Result};
-use chrono::Timelike;
+use chrono::{NaiveDate, Timelike};
+use futures::stream::{FuturesUnordered, StreamExt};
 use log::error;
-use log::{debug, info};
+use log::{debug, info, warn};
 use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
-use std::fs::File;
-use std::time::Duration;
-use std::{env, thread};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 #[derive(Deserialize, Serialize, Debug)]
 struct RefreshToken {
@@ -28,17 +42,8 @@ impl RefreshToken {
         map.insert("mailaddress", gdrive_json.jquants_mail());
         map.insert("password", gdrive_json.jquants_pw());
 
-        let res = client
-            .post("https://api.jquants.com/v1/token/auth_user")
-            .json(&map)
-            .send()
-            .await?;
-
-        let (status, text) = {
-            let status = res.status();
-            let text = res.text().await?;
-            (status, text)
-        };
+        let url = &gdrive_json.jquants_url("/token/auth_user");
+        let (status, text) = retry::send(client.post(url).json(&map)).await?;
 
         match status {
             StatusCode::OK => {
@@ -49,11 +54,7 @@ impl RefreshToken {
                 gdrive_json.write_to_file()?;
                 Ok(())
             }
-            _ => Err(MyError::Anyhow(anyhow!(
-                "Status code: {}, {}",
-                status,
-                text
-            ))),
+            _ => Err(MyError::api(url, status, text)),
         }
     }
 }
@@ -68,16 +69,10 @@ impl IdToken {
     async fn fetch_and_save_to_file(client: &Client) -> Result<(), MyError> {
         info!("Fetch ID Token");
         let mut gdrive_json = GdriveJson::new()?;
-        let url = "https://api.jquants.com/v1/token/auth_refresh";
+        let url = &gdrive_json.jquants_url("/token/auth_refresh");
         let query = json!({"refreshtoken": gdrive_json.jquants_refresh_token()});
 
-        let res = client.post(url).query(&query).send().await?;
-
-        let (status, text) = {
-            let status = res.status();
-            let text = res.text().await?;
-            (status, text)
-        };
+        let (status, text) = retry::send(client.post(url).query(&query)).await?;
 
         match status {
             StatusCode::OK => {
@@ -92,11 +87,7 @@ impl IdToken {
                 info!("Status code 401 {}", text);
                 Err(MyError::RefreshTokenExpired)
             }
-            _ => Err(MyError::Anyhow(anyhow!(
-                "Status code: {}, {}",
-                status,
-                text
-            ))),
+            _ => Err(MyError::api(url, status, text)),
         }
     }
 }
@@ -168,44 +159,482 @@ impl IdToken {
 //     }
 // }
 
-#[allow(dead_code)]
-async fn fetch_listed_info(client: &Client, code: i32) -> Result<(), MyError> {
-    let id_token = env::var("JQUANTS_ID_TOKEN")?;
-    let base_url = "https://api.jquants.com/v1/listed/info";
-    let date = {
-        let now = chrono::Local::now();
-        now.format("%Y-%m-%d").to_string()
-    };
+/// All listed stocks of today, from `/listed/info`
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ListedInfo {
+    info: Vec<ListedInfoInner>,
+    pagination_key: Option<String>,
+}
 
-    // let url = base_url.to_string() + "?code=" + &code.to_string() + "&date=" + &date;
-    let query = json!({"code": code, "date": date});
+impl ListedInfo {
+    pub async fn fetch(client: &Client) -> Result<Self, MyError> {
+        let config = crate::config::GdriveJson::new()?;
+        let id_token = config.jquants_id_token();
+        let url = &config.jquants_url("/listed/info");
 
-    info!("Fetch Listed Info. code: {}", code);
-    let res = client
-        .get(base_url)
-        .query(&query)
-        .bearer_auth(id_token)
-        .send()
-        .await?;
+        info!("Fetch Listed Info");
+        let mut listed_info = ListedInfo {
+            info: Vec::new(),
+            pagination_key: None,
+        };
+        let mut query = HashMap::new();
+        loop {
+            let (status, text) =
+                http_cache::send(client.get(url).query(&query).bearer_auth(id_token)).await?;
+
+            let json = match status {
+                StatusCode::OK => serde_json::from_str::<ListedInfo>(&text)?,
+                StatusCode::UNAUTHORIZED => {
+                    info!("Status code 401 {}", text);
+                    return Err(MyError::IdTokenExpired(text));
+                }
+                _ => return Err(MyError::api(url, status, text)),
+            };
+            listed_info.info.extend(json.info);
+            match json.pagination_key {
+                Some(next_token) => query.insert("pagination_key", next_token),
+                None => break,
+            };
+        }
+        info!("Listed Info: {} stocks", listed_info.info.len());
+        Ok(listed_info)
+    }
 
-    match res.status() {
-        StatusCode::OK => {
-            info!("Status code: {}", res.status());
-            let body = res.text().await?;
-            info!("{}", body);
-            Ok(())
+    /// Codes J-Quants lists but `StockCode` doesn't take (e.g. preferred shares) are skipped
+    pub fn to_stocks_master(&self) -> Vec<StockMaster> {
+        self.info
+            .iter()
+            .filter_map(|x| match StockCode::new(&x.code) {
+                Ok(code) => Some(StockMaster::new(
+                    code,
+                    &x.company_name,
+                    &x.sector33_code_name,
+                    &x.sector33_code,
+                    &x.market_code_name,
+                )),
+                Err(e) => {
+                    debug!("listed info skipped: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Refreshes `stocks_master` with today's listed info, returns the number of stocks stored
+pub async fn update_stocks_master(client: &Client) -> Result<usize, MyError> {
+    first_fetch(client).await?;
+    let masters = ListedInfo::fetch(client).await?.to_stocks_master();
+    let mut conn = stocks_master::open_db()?;
+    stocks_master::upsert(&mut conn, &masters)?;
+    Ok(masters.len())
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct ListedInfoInner {
+    #[serde(rename = "Code")]
+    code: String,
+    #[serde(rename = "CompanyName")]
+    company_name: String,
+    #[serde(rename = "Sector33Code")]
+    sector33_code: String,
+    #[serde(rename = "Sector33CodeName")]
+    sector33_code_name: String,
+    #[serde(rename = "MarketCodeName")]
+    market_code_name: String,
+}
+
+/// Financial statements and forecast revisions from `/fins/statements`
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Statements {
+    statements: Vec<StatementsInner>,
+    pagination_key: Option<String>,
+}
+
+impl Statements {
+    async fn fetch(
+        client: &Client,
+        code: Option<&StockCode>,
+        date: Option<&str>,
+    ) -> Result<Self, MyError> {
+        let config = crate::config::GdriveJson::new()?;
+        let id_token = config.jquants_id_token();
+        let url = &config.jquants_url("/fins/statements");
+
+        let mut query = HashMap::new();
+        if let Some(code) = code {
+            query.insert("code", code.to_jquants());
+        }
+        if let Some(date) = date {
+            query.insert("date", date.to_owned());
         }
-        StatusCode::UNAUTHORIZED => {
-            let body = res.text().await?;
-            info!("Status code 401 {}", body);
-            Err(MyError::IdTokenExpired(body))
+
+        info!("Fetch Statements, {:?}", query);
+        let mut statements = Statements {
+            statements: Vec::new(),
+            pagination_key: None,
+        };
+        loop {
+            let (status, text) =
+                retry::send(client.get(url).query(&query).bearer_auth(id_token)).await?;
+
+            let json = match status {
+                StatusCode::OK => serde_json::from_str::<Statements>(&text)?,
+                StatusCode::UNAUTHORIZED => {
+                    info!("Status code 401 {}", text);
+                    return Err(MyError::IdTokenExpired(text));
+                }
+                _ => return Err(MyError::api(url, status, text)),
+            };
+            statements.statements.extend(json.statements);
+            match json.pagination_key {
+                Some(next_token) => query.insert("pagination_key", next_token),
+                None => break,
+            };
         }
-        _ => Err(MyError::Anyhow(anyhow!(
-            "Status code: {}, {}",
-            res.status(),
-            res.text().await?
-        ))),
+        Ok(statements)
+    }
+
+    pub async fn fetch_by_code(client: &Client, code: &StockCode) -> Result<Self, MyError> {
+        Self::fetch(client, Some(code), None).await
     }
+
+    /// Statements disclosed on `date` ("YYYY-MM-DD") by every company
+    pub async fn fetch_by_date(client: &Client, date: &str) -> Result<Self, MyError> {
+        Self::fetch(client, None, Some(date)).await
+    }
+
+    pub fn to_statements(&self) -> Vec<Statement> {
+        self.statements
+            .iter()
+            .filter_map(|x| match StockCode::new(&x.local_code) {
+                Ok(code) => Some(x.to_statement(code)),
+                Err(e) => {
+                    debug!("statement skipped: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Numbers are strings, empty when not disclosed
+#[derive(Deserialize, Serialize, Debug)]
+struct StatementsInner {
+    #[serde(rename = "DisclosedDate")]
+    disclosed_date: String,
+    #[serde(rename = "LocalCode")]
+    local_code: String,
+    #[serde(rename = "TypeOfDocument")]
+    type_of_document: String,
+    #[serde(rename = "CurrentFiscalYearEndDate")]
+    current_fiscal_year_end_date: String,
+    #[serde(rename = "NextFiscalYearEndDate", default)]
+    next_fiscal_year_end_date: String,
+    #[serde(rename = "EarningsPerShare")]
+    earnings_per_share: String,
+    #[serde(rename = "ForecastEarningsPerShare")]
+    forecast_earnings_per_share: String,
+    #[serde(rename = "NextYearForecastEarningsPerShare", default)]
+    next_year_forecast_earnings_per_share: String,
+}
+
+impl StatementsInner {
+    /// Annual results forecast the next fiscal year, the other documents the current one
+    fn to_statement(&self, code: StockCode) -> Statement {
+        let parse = |value: &str| value.parse::<f64>().ok();
+        let (fiscal_year_end, forecast_eps) = match (
+            parse(&self.forecast_earnings_per_share),
+            parse(&self.next_year_forecast_earnings_per_share),
+        ) {
+            (None, Some(next_year)) => (&self.next_fiscal_year_end_date, Some(next_year)),
+            (forecast, _) => (&self.current_fiscal_year_end_date, forecast),
+        };
+        Statement::new(
+            code,
+            &self.disclosed_date,
+            &self.type_of_document,
+            fiscal_year_end,
+            parse(&self.earnings_per_share),
+            forecast_eps,
+        )
+    }
+}
+
+/// Stores the statements of a code, or those disclosed on a date ("YYYY-MM-DD"),
+/// returns the number stored
+pub async fn update_statements(
+    client: &Client,
+    code: Option<&StockCode>,
+    date: Option<&str>,
+) -> Result<usize, MyError> {
+    first_fetch(client).await?;
+    let statements = Statements::fetch(client, code, date).await?.to_statements();
+    let mut conn = statements::open_db()?;
+    statements::insert(&mut conn, &statements)?;
+    Ok(statements.len())
+}
+
+/// Scheduled dates of the next results from `/fins/announcement`, covering the
+/// companies that have announced them
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Announcements {
+    announcement: Vec<AnnouncementInner>,
+    pagination_key: Option<String>,
+}
+
+impl Announcements {
+    pub async fn fetch(client: &Client) -> Result<Self, MyError> {
+        let config = crate::config::GdriveJson::new()?;
+        let id_token = config.jquants_id_token();
+        let url = &config.jquants_url("/fins/announcement");
+
+        info!("Fetch Announcements");
+        let mut query = HashMap::new();
+        let mut announcements = Announcements {
+            announcement: Vec::new(),
+            pagination_key: None,
+        };
+        loop {
+            let (status, text) =
+                retry::send(client.get(url).query(&query).bearer_auth(id_token)).await?;
+
+            let json = match status {
+                StatusCode::OK => serde_json::from_str::<Announcements>(&text)?,
+                StatusCode::UNAUTHORIZED => {
+                    info!("Status code 401 {}", text);
+                    return Err(MyError::IdTokenExpired(text));
+                }
+                _ => return Err(MyError::api(url, status, text)),
+            };
+            announcements.announcement.extend(json.announcement);
+            match json.pagination_key {
+                Some(next_token) => query.insert("pagination_key", next_token),
+                None => break,
+            };
+        }
+        Ok(announcements)
+    }
+
+    /// (code, "YYYY-MM-DD") of each scheduled announcement
+    pub fn to_announcements(&self) -> Vec<(StockCode, String)> {
+        self.announcement
+            .iter()
+            .filter_map(|x| match StockCode::new(&x.code) {
+                Ok(code) => Some((code, x.date.clone())),
+                Err(e) => {
+                    debug!("announcement skipped: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct AnnouncementInner {
+    #[serde(rename = "Date")]
+    date: String,
+    #[serde(rename = "Code")]
+    code: String,
+}
+
+/// Stores the announcement schedule of the next results, returns the number stored
+pub async fn update_announcements(client: &Client) -> Result<usize, MyError> {
+    first_fetch(client).await?;
+    let announcements = Announcements::fetch(client).await?.to_announcements();
+    let mut conn = statements::open_db()?;
+    statements::insert_announcements(&mut conn, &announcements)?;
+    Ok(announcements.len())
+}
+
+/// Short selling turnover by sector from `/markets/short_selling`
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ShortSellingValues {
+    short_selling: Vec<ShortSellingInner>,
+    pagination_key: Option<String>,
+}
+
+impl ShortSellingValues {
+    /// Days between `from` and `to` ("YYYY-MM-DD") of all sectors
+    pub async fn fetch(client: &Client, from: &str, to: &str) -> Result<Self, MyError> {
+        let config = crate::config::GdriveJson::new()?;
+        let id_token = config.jquants_id_token();
+        let url = &config.jquants_url("/markets/short_selling");
+
+        let mut query = HashMap::new();
+        query.insert("from", from.to_owned());
+        query.insert("to", to.to_owned());
+
+        info!("Fetch Short Selling, {:?}", query);
+        let mut values = ShortSellingValues {
+            short_selling: Vec::new(),
+            pagination_key: None,
+        };
+        loop {
+            let (status, text) =
+                retry::send(client.get(url).query(&query).bearer_auth(id_token)).await?;
+
+            let json = match status {
+                StatusCode::OK => serde_json::from_str::<ShortSellingValues>(&text)?,
+                StatusCode::UNAUTHORIZED => {
+                    info!("Status code 401 {}", text);
+                    return Err(MyError::IdTokenExpired(text));
+                }
+                _ => return Err(MyError::api(url, status, text)),
+            };
+            values.short_selling.extend(json.short_selling);
+            match json.pagination_key {
+                Some(next_token) => query.insert("pagination_key", next_token),
+                None => break,
+            };
+        }
+        Ok(values)
+    }
+
+    pub fn to_short_selling(&self) -> Vec<ShortSelling> {
+        self.short_selling
+            .iter()
+            .map(|x| {
+                ShortSelling::new(
+                    &x.date,
+                    &x.sector33_code,
+                    x.selling_excluding_short_selling_turnover_value,
+                    x.short_selling_with_restrictions_turnover_value,
+                    x.short_selling_without_restrictions_turnover_value,
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct ShortSellingInner {
+    #[serde(rename = "Date")]
+    date: String,
+    #[serde(rename = "Sector33Code")]
+    sector33_code: String,
+    #[serde(rename = "SellingExcludingShortSellingTurnoverValue")]
+    selling_excluding_short_selling_turnover_value: f64,
+    #[serde(rename = "ShortSellingWithRestrictionsTurnoverValue")]
+    short_selling_with_restrictions_turnover_value: f64,
+    #[serde(rename = "ShortSellingWithoutRestrictionsTurnoverValue")]
+    short_selling_without_restrictions_turnover_value: f64,
+}
+
+/// Stores the short selling of all sectors between `from` and `to` ("YYYY-MM-DD"),
+/// returns the number of rows stored
+pub async fn update_short_selling(client: &Client, from: &str, to: &str) -> Result<usize, MyError> {
+    first_fetch(client).await?;
+    let values = ShortSellingValues::fetch(client, from, to)
+        .await?
+        .to_short_selling();
+    let mut conn = short_selling::open_db()?;
+    short_selling::insert(&mut conn, &values)?;
+    Ok(values.len())
+}
+
+/// Weekly trading by investor type from `/markets/trades_spec`
+#[derive(Deserialize, Serialize, Debug)]
+pub struct TradesSpecValues {
+    trades_spec: Vec<TradesSpecInner>,
+    pagination_key: Option<String>,
+}
+
+impl TradesSpecValues {
+    /// Weeks of `section` (e.g. "TSEPrime") published between `from` and `to` ("YYYY-MM-DD")
+    pub async fn fetch(
+        client: &Client,
+        section: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Self, MyError> {
+        let config = crate::config::GdriveJson::new()?;
+        let id_token = config.jquants_id_token();
+        let url = &config.jquants_url("/markets/trades_spec");
+
+        let mut query = HashMap::new();
+        query.insert("section", section.to_owned());
+        query.insert("from", from.to_owned());
+        query.insert("to", to.to_owned());
+
+        info!("Fetch Trades Spec, {:?}", query);
+        let mut values = TradesSpecValues {
+            trades_spec: Vec::new(),
+            pagination_key: None,
+        };
+        loop {
+            let (status, text) =
+                retry::send(client.get(url).query(&query).bearer_auth(id_token)).await?;
+
+            let json = match status {
+                StatusCode::OK => serde_json::from_str::<TradesSpecValues>(&text)?,
+                StatusCode::UNAUTHORIZED => {
+                    info!("Status code 401 {}", text);
+                    return Err(MyError::IdTokenExpired(text));
+                }
+                _ => return Err(MyError::api(url, status, text)),
+            };
+            values.trades_spec.extend(json.trades_spec);
+            match json.pagination_key {
+                Some(next_token) => query.insert("pagination_key", next_token),
+                None => break,
+            };
+        }
+        Ok(values)
+    }
+
+    pub fn to_trades_spec(&self) -> Vec<TradesSpec> {
+        self.trades_spec
+            .iter()
+            .map(|x| {
+                TradesSpec::new(
+                    &x.section,
+                    &x.start_date,
+                    &x.end_date,
+                    &x.published_date,
+                    x.foreigners_balance,
+                    x.individuals_balance,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Only the balances are kept, the response has sales and purchases of every type
+#[derive(Deserialize, Serialize, Debug)]
+struct TradesSpecInner {
+    #[serde(rename = "PublishedDate")]
+    published_date: String,
+    #[serde(rename = "StartDate")]
+    start_date: String,
+    #[serde(rename = "EndDate")]
+    end_date: String,
+    #[serde(rename = "Section")]
+    section: String,
+    #[serde(rename = "ForeignersBalance")]
+    foreigners_balance: f64,
+    #[serde(rename = "IndividualsBalance")]
+    individuals_balance: f64,
+}
+
+/// Stores the weeks of `trades_spec::SECTION` published in the last 5 weeks,
+/// returns the number of weeks stored
+pub async fn update_trades_spec(client: &Client) -> Result<usize, MyError> {
+    first_fetch(client).await?;
+    let today = chrono::Local::now().date_naive();
+    let from = today - chrono::Duration::weeks(5);
+    let values = TradesSpecValues::fetch(
+        client,
+        trades_spec::SECTION,
+        &from.format("%Y-%m-%d").to_string(),
+        &today.format("%Y-%m-%d").to_string(),
+    )
+    .await?
+    .to_trades_spec();
+    let mut conn = trades_spec::open_db()?;
+    trades_spec::insert(&mut conn, &values)?;
+    Ok(values.len())
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -216,7 +645,7 @@ pub struct TradingCalender {
 impl TradingCalender {
     async fn fetch(client: &Client, from: Option<&str>, to: Option<&str>) -> Result<Self, MyError> {
         let config = crate::config::GdriveJson::new()?;
-        let url = "https://api.jquants.com/v1/markets/trading_calendar";
+        let url = &config.jquants_url("/markets/trading_calendar");
 
         let today = chrono::Local::now().format("%Y-%m-%d").to_string();
 
@@ -241,18 +670,13 @@ impl TradingCalender {
             }
         };
 
-        let res = client
-            .get(url)
-            .query(&query)
-            .bearer_auth(config.jquants_id_token())
-            .send()
-            .await?;
-
-        let (status, text) = {
-            let status = res.status();
-            let text = res.text().await?;
-            (status, text)
-        };
+        let (status, text) = http_cache::send(
+            client
+                .get(url)
+                .query(&query)
+                .bearer_auth(config.jquants_id_token()),
+        )
+        .await?;
 
         match status {
             StatusCode::OK => {
@@ -265,11 +689,7 @@ impl TradingCalender {
                 info!("Status code 401 {}", text);
                 Err(MyError::IdTokenExpired(text))
             }
-            _ => Err(MyError::Anyhow(anyhow!(
-                "Status code: {}, {}",
-                status,
-                text
-            ))),
+            _ => Err(MyError::api(url, status, text)),
         }
     }
 
@@ -282,7 +702,62 @@ impl TradingCalender {
                 today.format("%Y-%m-%d").to_string(),
             )
         };
-        Self::fetch(client, Some(&day100_before), Some(&today)).await
+        Self::load_or_fetch(client, &day100_before, &today).await
+    }
+
+    /// The calendar between `from` and `to` ("YYYY-MM-DD") from the trading_calendar
+    /// table, fetched and stored when the table misses any day of the range
+    pub async fn load_or_fetch(client: &Client, from: &str, to: &str) -> Result<Self, MyError> {
+        let days = (NaiveDate::parse_from_str(to, "%Y-%m-%d")
+            .map_err(|e| MyError::Anyhow(e.into()))?
+            - NaiveDate::parse_from_str(from, "%Y-%m-%d")
+                .map_err(|e| MyError::Anyhow(e.into()))?)
+        .num_days()
+            + 1;
+        let mut conn = match trading_calendar::open_db() {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("trading_calendar unavailable, fetching: {}", e);
+                return Self::fetch(client, Some(from), Some(to)).await;
+            }
+        };
+
+        let cached = trading_calendar::select_by_date_range(&conn, from, to)?;
+        if cached.len() as i64 == days {
+            debug!("Calender from {} to {} is cached", from, to);
+            return Ok(TradingCalender {
+                trading_calendar: cached
+                    .into_iter()
+                    .map(|(date, holiday_division)| TradingCalenderInner {
+                        date,
+                        holiday_division,
+                    })
+                    .collect(),
+            });
+        }
+
+        let calendar = Self::fetch(client, Some(from), Some(to)).await?;
+        trading_calendar::insert(
+            &mut conn,
+            &calendar
+                .trading_calendar
+                .iter()
+                .map(|x| (x.date.as_str(), x.holiday_division.as_str()))
+                .collect::<Vec<_>>(),
+        )?;
+        Ok(calendar)
+    }
+
+    /// Trading days of the calendar, oldest first
+    pub fn trading_days(&self) -> Vec<&str> {
+        let mut days = self
+            .trading_calendar
+            .iter()
+            .filter(|x| x.holiday_division == "1")
+            .map(|x| x.date.as_str())
+            .collect::<Vec<_>>();
+        days.sort();
+        days
     }
 
     pub fn is_date_trading_day(&self, date: &str) -> bool {
@@ -315,16 +790,10 @@ impl Topix {
     pub async fn new(client: &Client) -> Result<Self, MyError> {
         let config = crate::config::GdriveJson::new()?;
         let id_token = config.jquants_id_token();
-        let url = "https://api.jquants.com/v1/indices/topix";
+        let url = &config.jquants_url("/indices/topix");
 
         info!("Fetch Topix");
-        let res = client.get(url).bearer_auth(id_token).send().await?;
-
-        let (status, text) = {
-            let status = res.status();
-            let text = res.text().await?;
-            (status, text)
-        };
+        let (status, text) = retry::send(client.get(url).bearer_auth(id_token)).await?;
 
         match status {
             StatusCode::OK => {
@@ -337,11 +806,7 @@ impl Topix {
                 info!("Status code 401 {}", text);
                 Err(MyError::IdTokenExpired(text))
             }
-            _ => Err(MyError::Anyhow(anyhow!(
-                "Status code: {}, {}",
-                status,
-                text
-            ))),
+            _ => Err(MyError::api(url, status, text)),
         }
     }
 
@@ -359,13 +824,25 @@ impl Topix {
         )
     }
 
-    // pub fn save_to_json_file(&self) -> Result<(), MyError> {
-    //     let path = crate::my_file_io::get_topix_ohlc_file_path()?;
-    //     let file = File::create(&path)?;
-    //     serde_json::to_writer(file, &self)?;
-    //     info!("Topix has been saved, path: {:?}", path);
-    //     Ok(())
-    // }
+    pub fn to_ohlc_vec(&self) -> Vec<Ohlc> {
+        (0..self.topix.len()).map(|i| self.get_ohlc(i)).collect()
+    }
+}
+
+/// Stores TOPIX of all available days in topix_ohlc and classifies them into
+/// market_regime, returns the number of days
+pub async fn update_topix_ohlc(client: &Client) -> Result<usize, MyError> {
+    first_fetch(client).await?;
+    let ohlc_vec = Topix::new(client).await?.to_ohlc_vec();
+    let mut conn = topix_ohlc::open_db()?;
+    topix_ohlc::insert(&mut conn, &ohlc_vec)?;
+    market_regime::create_table(&conn)?;
+    let days = market_regime::update(
+        &mut conn,
+        crate::analysis::indicators::AnalysisParams::from_config().get_range_period(),
+    )?;
+    info!("market_regime has been updated, {} days", days);
+    Ok(ohlc_vec.len())
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -410,10 +887,11 @@ impl DailyQuotes {
         client: &Client,
         date: Option<&str>,
         code: Option<&str>,
+        range: Option<(&str, &str)>,
     ) -> Result<Self, MyError> {
         let config = crate::config::GdriveJson::new()?;
         let id_token = config.jquants_id_token();
-        let url = "https://api.jquants.com/v1/prices/daily_quotes";
+        let url = &config.jquants_url("/prices/daily_quotes");
 
         let mut query = HashMap::new();
         if let Some(date) = date {
@@ -422,19 +900,13 @@ impl DailyQuotes {
         if let Some(code) = code {
             query.insert("code", code);
         }
+        if let Some((from, to)) = range {
+            query.insert("from", from);
+            query.insert("to", to);
+        }
 
-        let res = client
-            .get(url)
-            .query(&query)
-            .bearer_auth(id_token)
-            .send()
-            .await?;
-
-        let (status, text) = {
-            let status = res.status();
-            let text = res.text().await?;
-            (status, text)
-        };
+        let (status, text) =
+            retry::send(client.get(url).query(&query).bearer_auth(id_token)).await?;
 
         match status {
             StatusCode::OK => {
@@ -443,14 +915,10 @@ impl DailyQuotes {
                 let mut json = serde_json::from_str::<DailyQuotes>(&text)?;
                 if let Some(next_token) = json.pagination_key.clone() {
                     query.insert("pagination_key", &next_token);
-                    let res2 = client
-                        .get(url)
-                        .query(&query)
-                        .bearer_auth(id_token)
-                        .send()
-                        .await?;
+                    let (_, text2) =
+                        retry::send(client.get(url).query(&query).bearer_auth(id_token)).await?;
 
-                    let json2 = serde_json::from_str::<DailyQuotes>(&res2.text().await?)?;
+                    let json2 = serde_json::from_str::<DailyQuotes>(&text2)?;
 
                     json.push(json2);
                     return Ok(json);
@@ -461,25 +929,47 @@ impl DailyQuotes {
                 info!("Status code 401 {}", text);
                 Err(MyError::IdTokenExpired(text))
             }
-            _ => Err(MyError::Anyhow(anyhow!(
-                "Status code: {}, {}",
-                status,
-                text
-            ))),
+            _ => Err(MyError::api(url, status, text)),
         }
     }
 
     pub async fn fetch_by_date(client: &Client, date: &str) -> Result<Self, MyError> {
-        Self::fetch(client, Some(date), None).await
+        Self::fetch(client, Some(date), None, None).await
+    }
+
+    /// Bars of `code` between `from` and `to` ("YYYY-MM-DD", both included)
+    pub async fn fetch_by_code(
+        client: &Client,
+        code: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Self, MyError> {
+        Self::fetch(client, None, Some(code), Some((from, to))).await
     }
 
-    pub async fn fetch_by_code(client: &Client, code: &str) -> Result<Self, MyError> {
-        Self::fetch(client, None, Some(code)).await
+    async fn fetch_query(
+        client: &Client,
+        query: &Query,
+        from: &str,
+        to: &str,
+    ) -> Result<Self, MyError> {
+        match query {
+            Query::ByDate(date) => Self::fetch_by_date(client, date).await,
+            Query::ByCode(code) => Self::fetch_by_code(client, code.as_str(), from, to).await,
+        }
     }
 
+    /// Codes `StockCode` doesn't take (e.g. preferred shares) are skipped
     pub fn get_ohlc_premium(&self) -> Vec<OhlcPremium> {
         let mut ohlc_vec = Vec::new();
         for jquants_ohlc in &self.daily_quotes {
+            let code = match StockCode::new(&jquants_ohlc.code) {
+                Ok(code) => code,
+                Err(e) => {
+                    debug!("daily quote skipped: {}", e);
+                    continue;
+                }
+            };
             if jquants_ohlc.open.is_none()
                 || jquants_ohlc.high.is_none()
                 || jquants_ohlc.low.is_none()
@@ -490,7 +980,7 @@ impl DailyQuotes {
                 continue;
             }
             let jquants_ohlc = OhlcPremium::new(
-                jquants_ohlc.get_code().to_owned(),
+                code,
                 jquants_ohlc.date.clone(),
                 jquants_ohlc.open.expect("Expected open to be Some"),
                 jquants_ohlc.high.expect("Expected high to be Some"),
@@ -502,7 +992,8 @@ impl DailyQuotes {
                 jquants_ohlc
                     .afternoon_open
                     .expect("Expected afternoon_open to be Some"),
-            );
+            )
+            .with_liquidity(jquants_ohlc.adjustment_volume, jquants_ohlc.turnover_value);
             ohlc_vec.push(jquants_ohlc);
         }
         ohlc_vec
@@ -533,8 +1024,8 @@ struct DailyQuotesInner {
     // lower_limit: String,
     // #[serde(rename = "Volume")]
     // volume: Option<f64>,
-    // #[serde(rename = "TurnoverValue")]
-    // turnover_value: Option<f64>,
+    #[serde(rename = "TurnoverValue")]
+    turnover_value: Option<f64>,
     // #[serde(rename = "AdjustmentFactor")]
     // adjustment_factor: f64,
     #[serde(rename = "AdjustmentOpen")]
@@ -545,33 +1036,35 @@ struct DailyQuotesInner {
     low: Option<f64>,
     #[serde(rename = "AdjustmentClose")]
     close: Option<f64>,
-    // #[serde(rename = "AdjustmentVolume")]
-    // adjustment_volume: Option<f64>,
+    #[serde(rename = "AdjustmentVolume")]
+    adjustment_volume: Option<f64>,
     #[serde(rename = "MorningAdjustmentClose")]
     morning_close: Option<f64>,
     #[serde(rename = "AfternoonAdjustmentOpen")]
     afternoon_open: Option<f64>,
 }
-impl DailyQuotesInner {
-    pub fn get_code(&self) -> String {
-        match self.code.chars().count() > 4 {
-            true => self.code.chars().take(4).collect::<String>(),
-            false => self.code.clone(),
-        }
+/// Checks the tokens and returns the calendar of the last 100 days. The ID token is
+/// refreshed when it may have expired, the calendar is fetched only when not cached.
+pub async fn first_fetch(client: &Client) -> Result<TradingCalender, MyError> {
+    if !GdriveJson::new()?.jquants_id_token_is_fresh() {
+        info!("ID token may be expired, attempting to fetch a new one...");
+        refresh_id_token(client).await?;
     }
-}
 
-pub async fn first_fetch(client: &Client) -> Result<TradingCalender, MyError> {
     match TradingCalender::fetch_default(client).await {
-        Ok(res) => return Ok(res),
         Err(MyError::IdTokenExpired(_)) => {
-            info!("ID token expired, attempting to fetch a new one...")
+            info!("ID token expired, attempting to fetch a new one...");
+            refresh_id_token(client).await?;
+            TradingCalender::fetch_default(client).await
         }
-        Err(e) => return Err(e),
-    };
+        res => res,
+    }
+}
 
+/// Fetches a new ID token, and a new refresh token first when that has expired too
+async fn refresh_id_token(client: &Client) -> Result<(), MyError> {
     match IdToken::fetch_and_save_to_file(client).await {
-        Ok(_) => return TradingCalender::fetch_default(client).await,
+        Ok(_) => return Ok(()),
         Err(MyError::RefreshTokenExpired) => {
             info!("Refresh token expired, attempting to fetch a new one...")
         }
@@ -587,21 +1080,26 @@ pub async fn first_fetch(client: &Client) -> Result<TradingCalender, MyError> {
 
     match IdToken::fetch_and_save_to_file(client).await {
         Ok(_) => {
-            info!("ID token has been updated. Attempting to fetch a new Trading Calender...");
+            info!("ID token has been updated");
+            Ok(())
         }
-        Err(e) => return Err(e),
+        Err(e) => Err(e),
     }
-
-    TradingCalender::fetch_default(client).await
 }
 
+/// The morning session closes at 11:30, the data is as of then
+const MORNING_CLOSE: (u32, u32) = (11, 30);
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct PricesAm {
     prices_am: Vec<PricesAmInner>,
+    #[serde(skip, default = "chrono::Local::now")]
+    fetched_at: chrono::DateTime<chrono::Local>,
 }
 
 impl PricesAm {
     pub async fn new(client: &Client, force: bool) -> Result<Self, MyError> {
+        let _span = profile::span(Stage::Fetch);
         info!("Starting Fetch Morning Market OHLC");
 
         let first_fetched = first_fetch(client).await?;
@@ -616,47 +1114,120 @@ impl PricesAm {
 
         let config = crate::config::GdriveJson::new()?;
         let id_token = config.jquants_id_token();
-        let url = "https://api.jquants.com/v1/prices/prices_am";
+        let url = &config.jquants_url("/prices/prices_am");
 
         info!("Fetch morning market OHLC");
-        let res = client.get(url).bearer_auth(id_token).send().await?;
+        let (status, body) = retry::send(client.get(url).bearer_auth(id_token)).await?;
 
-        match res.status() {
+        match status {
             StatusCode::OK => {
-                info!("Status code: {}", res.status());
-                let body = res.text().await?;
-                let json = serde_json::from_str::<PricesAm>(&body)?;
+                info!("Status code: {}", status);
+                let json = serde_json::from_str::<PricesAm>(&body)?.listed();
                 debug!("{:?}", json);
+                // recorded for backtests, the live run goes on without it
+                if let Err(e) = json.save() {
+                    warn!("prices_am not recorded: {}", e);
+                }
 
                 Ok(json)
             }
             StatusCode::UNAUTHORIZED => {
-                let body = res.text().await?;
                 info!("Status code 401 {}", body);
                 Err(MyError::IdTokenExpired(body))
             }
             _ => Err(MyError::Anyhow(anyhow!(
                 "Status code: {}, {}",
-                res.status(),
-                res.text().await?
+                status,
+                body
             ))),
         }
     }
 
-    pub fn get_stock_am(&self, code: &str) -> Result<PricesAmInner, MyError> {
-        let code = {
-            let str = code.to_string();
-            str + "0"
-        };
+    /// Sessions of codes `StockCode` doesn't take (e.g. preferred shares) are dropped,
+    /// the others get the 4-character code
+    fn listed(mut self) -> Self {
+        self.prices_am
+            .retain_mut(|x| match StockCode::new(&x.code) {
+                Ok(code) => {
+                    x.code = code.to_string();
+                    true
+                }
+                Err(e) => {
+                    debug!("prices_am skipped: {}", e);
+                    false
+                }
+            });
+        self
+    }
+
+    fn save(&self) -> Result<(), MyError> {
+        let mut conn = prices_am::open_db()?;
+        prices_am::insert(&mut conn, &self.prices_am, self.fetched_at)
+    }
+
+    /// The morning session recorded on `date`, None when it wasn't fetched that day
+    pub fn from_db(date: &str) -> Result<Option<Self>, MyError> {
+        let (prices_am, fetched_at) = prices_am::select_by_date(&prices_am::open_db()?, date)?;
+        Ok(fetched_at.map(|fetched_at| PricesAm {
+            prices_am,
+            fetched_at,
+        }))
+    }
+
+    pub fn get_fetched_at(&self) -> chrono::DateTime<chrono::Local> {
+        self.fetched_at
+    }
+
+    /// When the data was live: the morning close of its date, or the fetch if earlier
+    fn as_of(&self) -> chrono::DateTime<chrono::Local> {
+        let morning_close = self.prices_am.first().and_then(|x| {
+            chrono::NaiveDate::parse_from_str(&x.date, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(MORNING_CLOSE.0, MORNING_CLOSE.1, 0)?
+                .and_local_timezone(chrono::Local)
+                .single()
+        });
+        match morning_close {
+            Some(morning_close) => morning_close.min(self.fetched_at),
+            None => self.fetched_at,
+        }
+    }
+
+    fn age_at(&self, now: chrono::DateTime<chrono::Local>) -> chrono::Duration {
+        now - self.as_of()
+    }
+
+    /// Time since the morning data was live, e.g. about 4 hours when re-run after the close
+    pub fn age(&self) -> chrono::Duration {
+        self.age_at(chrono::Local::now())
+    }
+
+    /// `MyError::StaleData` when `age` is over `max_minutes`
+    pub fn check_age(&self, max_minutes: i64) -> Result<(), MyError> {
+        let age_minutes = self.age().num_minutes();
+        match age_minutes > max_minutes {
+            true => Err(MyError::StaleData {
+                what: "prices_am",
+                age_minutes,
+                max_minutes,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    pub fn get_stock_am(&self, code: &StockCode) -> Result<PricesAmInner, MyError> {
         self.prices_am
             .iter()
-            .filter(|x| x.code == code)
+            .filter(|x| x.code == code.as_str())
             .map(|x| x.to_owned())
             .next()
-            .ok_or(MyError::Anyhow(anyhow!(
-                "Failed to get stock ohlc premium, code: {}",
-                code
-            )))
+            .ok_or_else(|| MyError::DataGap {
+                code: code.to_string(),
+                date: match self.prices_am.first() {
+                    Some(x) => x.date.to_owned(),
+                    None => self.fetched_at.format("%Y-%m-%d").to_string(),
+                },
+            })
     }
 }
 
@@ -664,6 +1235,7 @@ impl PricesAm {
 pub struct PricesAmInner {
     #[serde(rename = "Date")]
     date: String,
+    /// 4 characters once `PricesAm::listed`, as J-Quants sends it before
     #[serde(rename = "Code")]
     code: String,
     #[serde(rename = "MorningOpen")]
@@ -680,6 +1252,64 @@ pub struct PricesAmInner {
     morning_turnover_value: Option<f64>,
 }
 impl PricesAmInner {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        date: String,
+        code: StockCode,
+        morning_open: Option<f64>,
+        morning_high: Option<f64>,
+        morning_low: Option<f64>,
+        morning_close: Option<f64>,
+        morning_volume: Option<f64>,
+        morning_turnover_value: Option<f64>,
+    ) -> Self {
+        Self {
+            date,
+            code: code.to_string(),
+            morning_open,
+            morning_high,
+            morning_low,
+            morning_close,
+            morning_volume,
+            morning_turnover_value,
+        }
+    }
+
+    /// Reconstructs a morning session from a stored daily bar for backtesting.
+    /// The morning high/low are not stored, so they are bounded by open and morning close.
+    pub fn from_ohlc_premium(ohlc: &OhlcPremium) -> Self {
+        let (open, close) = (ohlc.get_open(), ohlc.get_morning_close());
+        Self {
+            date: ohlc.get_date().to_owned(),
+            code: ohlc.get_code().to_string(),
+            morning_open: Some(open),
+            morning_high: Some(open.max(close)),
+            morning_low: Some(open.min(close)),
+            morning_close: Some(close),
+            morning_volume: None,
+            morning_turnover_value: None,
+        }
+    }
+
+    /// false when the stock had no trade in the morning session
+    pub fn has_ohlc(&self) -> bool {
+        self.morning_open.is_some()
+            && self.morning_high.is_some()
+            && self.morning_low.is_some()
+            && self.morning_close.is_some()
+    }
+    pub fn get_date(&self) -> &str {
+        &self.date
+    }
+    pub fn get_code(&self) -> &str {
+        &self.code
+    }
+    pub fn get_volume(&self) -> Option<f64> {
+        self.morning_volume
+    }
+    pub fn get_turnover_value(&self) -> Option<f64> {
+        self.morning_turnover_value
+    }
     pub fn get_open(&self) -> f64 {
         self.morning_open.expect("Expected morning_open to be Some")
     }
@@ -718,7 +1348,7 @@ impl PricesAmInner {
 //     let unit = config.jquants_unit();
 //     info!("unit: {}", unit);
 
-//     info!("Starting Fetch Nikkei225");
+//     info!("Starting Fetch {}", universe);
 
 //     for row in nikkei225 {
 //         thread::sleep(Duration::from_secs(1));
@@ -748,10 +1378,18 @@ impl PricesAmInner {
 //     Ok(())
 // }
 
-pub async fn fetch_nikkei225_db(client: &Client, force: bool) -> Result<(), MyError> {
+/// `kind` of the coverage rows written to the runs table
+pub const FETCH_NIKKEI225: &str = "fetch_nikkei225";
+
+pub async fn fetch_nikkei225_db(
+    client: &Client,
+    universe: &Universe,
+    _force: bool,
+) -> Result<(), MyError> {
+    let _span = profile::span(Stage::Fetch);
     info!("Starting First Fetch");
 
-    let trading_calender = first_fetch(client).await?;
+    first_fetch(client).await?;
     // match (trading_calender.is_today_trading_day(), force) {
     //     (true, _) => info!("Today is Trading Day"),
     //     (false, true) => info!("Today is Holiday, but force is true"),
@@ -761,8 +1399,8 @@ pub async fn fetch_nikkei225_db(client: &Client, force: bool) -> Result<(), MyEr
     //     }
     // };
 
-    let nikkei225 = crate::my_file_io::load_nikkei225_list()?;
-    info!("Nikkei225 list has been loaded");
+    let nikkei225 = universe.load()?;
+    info!("{} list has been loaded", universe);
 
     let config = crate::config::GdriveJson::new()?;
     let unit = config.jquants_unit();
@@ -770,57 +1408,221 @@ pub async fn fetch_nikkei225_db(client: &Client, force: bool) -> Result<(), MyEr
 
     info!("Starting Fetch Nikkei225");
 
-    let conn = crate::database::stocks_ohlc::open_db()?;
+    let mut conn = crate::database::stocks_ohlc::open_db()?;
+    let runs_conn = runs::open_db()?;
 
     let now = chrono::Local::now();
-    let i_from = match now.hour() {
-        0..=15 => 1,
-        _ => 0,
+    let to = latest_expected_date(now);
+    let from = (now - chrono::Duration::days(config.gap_lookback_days()))
+        .format("%Y-%m-%d")
+        .to_string();
+    let trading_calender = TradingCalender::load_or_fetch(client, &from, &to).await?;
+    let stored = crate::database::stocks_ohlc::select_cells(&conn, &from, &to)?;
+    let codes = nikkei225
+        .iter()
+        .map(|x| x.get_code().clone())
+        .collect::<Vec<_>>();
+    let listed_on = nikkei225
+        .iter()
+        .filter_map(|x| Some((x.get_code().clone(), x.get_listed_on()?.to_owned())))
+        .collect::<HashMap<_, _>>();
+    let mut missing = missing_cells(
+        &trading_calender.trading_days(),
+        &codes,
+        &listed_on,
+        &stored,
+    );
+    // J-Quants had no bar for them on an earlier run, e.g. a suspension
+    let known_empty = runs::select_missing(&runs_conn, FETCH_NIKKEI225, &from, &to)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    missing.retain(|x| !known_empty.contains(x));
+    let queries = planner::plan(&missing);
+    info!(
+        "{} bars missing between {} and {}, {} queries",
+        missing.len(),
+        from,
+        to,
+        queries.len()
+    );
+    let dates = missing.iter().map(|x| x.1.clone()).collect::<BTreeSet<_>>();
+    // cells of a query whose bars couldn't be stored, planned again by the next run
+    let mut failed = Cells::new();
+
+    // fetched concurrently, the retry layer keeps the requests within the J-Quants limits,
+    // stored one date at a time as they arrive
+    let mut progress = Progress::new(client, Msg::Queries, queries.len());
+    let mut fetches = queries
+        .into_iter()
+        .map(|query| {
+            let (from, to) = (&from, &to);
+            async move {
+                let daily_quotes = DailyQuotes::fetch_query(client, &query, from, to).await;
+                (query, daily_quotes)
+            }
+        })
+        .collect::<FuturesUnordered<_>>();
+
+    while let Some((query, daily_quotes)) = fetches.next().await {
+        let daily_quotes = daily_quotes.with_context(|| format!("{:?}", query))?;
+        // only the missing cells are stored, the table has no unique key
+        let ohlcs = daily_quotes
+            .get_ohlc_premium()
+            .into_iter()
+            .filter(|x| missing.contains(&(x.get_code().clone(), x.get_date().to_owned())))
+            .collect::<Vec<_>>();
+        match crate::database::stocks_ohlc::insert_batch(&mut conn, &ohlcs) {
+            Ok(_) => {
+                for ohlc in &ohlcs {
+                    missing.remove(&(ohlc.get_code().clone(), ohlc.get_date().to_owned()));
+                }
+                info!("{:?} has been fetched, {} bars", query, ohlcs.len());
+            }
+            Err(e) => {
+                error!("{:?}: {}", query, e);
+                failed.extend(
+                    ohlcs
+                        .iter()
+                        .map(|x| (x.get_code().clone(), x.get_date().to_owned())),
+                );
+            }
+        }
+        progress.tick().await;
+    }
+
+    // constituents added after the date or suspended that day have no bar
+    for date in dates {
+        let listed = nikkei225
+            .iter()
+            .filter(|x| x.is_listed_on(&date))
+            .map(|x| x.get_code())
+            .collect::<Vec<_>>();
+        let mut coverage = Coverage::new(&date, listed.len());
+        for code in listed {
+            let cell = (code.clone(), date.clone());
+            if failed.contains(&cell) {
+                // neither fetched nor known to have no bar
+                continue;
+            }
+            match missing.contains(&cell) {
+                true => {
+                    warn!("{} {}: no daily quote, skipped", date, code);
+                    coverage.add_missing(code);
+                }
+                false => coverage.add_fetched(),
+            }
+        }
+        runs::upsert(&runs_conn, FETCH_NIKKEI225, &coverage)?;
+        info!("{} has been fetched, {}", date, coverage);
+    }
+    info!("{} has been fetched", universe);
+
+    Ok(())
+}
+
+/// The latest date daily quotes can be out for, today's bars come after the close
+fn latest_expected_date(now: chrono::DateTime<chrono::Local>) -> String {
+    match now.hour() {
+        0..=15 => now - chrono::Duration::days(1),
+        _ => now,
+    }
+    .format("%Y-%m-%d")
+    .to_string()
+}
+
+/// Trading days after `latest`, oldest first
+fn missing_latest_dates(trading_days: &[&str], latest: &str) -> Vec<String> {
+    trading_days
+        .iter()
+        .filter(|x| **x > latest)
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// Latest date of stocks_ohlc, `MyError::NotLatestData` with the trading days after
+/// it when it is older than the last trading day out
+pub async fn check_latest_data(client: &Client) -> Result<String, MyError> {
+    let conn = crate::database::stocks_ohlc::open_db()?;
+    let to = latest_expected_date(chrono::Local::now());
+    let tomorrow = (chrono::Local::now() + chrono::Duration::days(1))
+        .format("%Y-%m-%d")
+        .to_string();
+    let latest = match crate::database::stocks_ohlc::select_latest_dates(&conn, &tomorrow, 1)?.pop()
+    {
+        Some(date) => date,
+        None => return Err(MyError::Anyhow(anyhow!("stocks_ohlc has no data"))),
     };
+    if latest >= to {
+        return Ok(latest);
+    }
 
-    for i in i_from..100 {
-        let date = (now - chrono::Duration::days(i))
-            .format("%Y-%m-%d")
-            .to_string();
+    let trading_calender = TradingCalender::load_or_fetch(client, &latest, &to).await?;
+    let missing = missing_latest_dates(&trading_calender.trading_days(), &latest);
+    match missing.is_empty() {
+        true => Ok(latest),
+        false => Err(MyError::NotLatestData { latest, missing }),
+    }
+}
 
-        match trading_calender.is_date_trading_day(&date) {
-            true => info!("{} is Trading Day", date),
-            false => {
-                info!("{} is Holiday", date);
+/// (code, date) of `trading_days` without a bar of `codes` in stocks_ohlc, days
+/// before the listing date of a code (universe v2) are not missing
+fn missing_cells(
+    trading_days: &[&str],
+    codes: &[StockCode],
+    listed_on: &HashMap<StockCode, String>,
+    stored: &Cells,
+) -> Cells {
+    let mut missing = Cells::new();
+    for date in trading_days {
+        for code in codes {
+            if listed_on.get(code).is_some_and(|x| x.as_str() > *date) {
                 continue;
             }
+            let cell = (code.clone(), date.to_string());
+            if !stored.contains(&cell) {
+                missing.insert(cell);
+            }
         }
+    }
+    missing
+}
 
-        let records = crate::database::stocks_ohlc::select_by_date(&conn, &date)?;
-        if !records.is_empty() {
-            info!("Already fetched, date: {}", date);
-            break;
-        }
+/// Coverage of the latest date in stocks_ohlc, `MyError::InsufficientCoverage`
+/// when it is below `minCoverage` of config.json
+pub fn check_nikkei225_coverage(universe: &Universe) -> Result<Coverage, MyError> {
+    let min_coverage = crate::config::GdriveJson::new()?.min_coverage();
+    let conn = crate::database::stocks_ohlc::open_db()?;
 
-        thread::sleep(Duration::from_secs(1));
-        let daily_quotes: DailyQuotes = DailyQuotes::fetch_by_date(client, &date).await?;
-        if daily_quotes.daily_quotes.is_empty() {
-            info!("No data, date: {}", date);
-            continue;
-        }
+    let tomorrow = (chrono::Local::now() + chrono::Duration::days(1))
+        .format("%Y-%m-%d")
+        .to_string();
+    let date = match crate::database::stocks_ohlc::select_latest_dates(&conn, &tomorrow, 1)?.pop() {
+        Some(date) => date,
+        None => return Err(MyError::Anyhow(anyhow!("stocks_ohlc has no data"))),
+    };
 
-        nikkei225.iter().for_each(|row| {
-            let code = row.get_code();
-            let ohlc = daily_quotes
-                .get_ohlc_premium()
-                .iter()
-                .find(|x| x.get_code() == *code)
-                .expect("Expected ohlc to be Some")
-                .to_owned();
-            if let Err(e) = crate::database::stocks_ohlc::insert(&conn, &ohlc) {
-                error!("{}", e);
-            };
-        });
-        info!("{} has been fetched", date);
-    }
-    info!("Nikkei225 has been fetched");
+    let coverage = match runs::select_latest(&runs::open_db()?, FETCH_NIKKEI225, &date)? {
+        Some(coverage) => coverage,
+        // fetched before the runs table existed
+        None => {
+            let stored = crate::database::stocks_ohlc::select_by_date(&conn, &date)?
+                .into_iter()
+                .map(|x| x.get_inner().get_code().clone())
+                .collect::<HashSet<_>>();
+            let nikkei225 = universe.load()?;
+            let mut coverage = Coverage::new(&date, nikkei225.len());
+            for row in &nikkei225 {
+                match stored.contains(row.get_code()) {
+                    true => coverage.add_fetched(),
+                    false => coverage.add_missing(row.get_code()),
+                }
+            }
+            coverage
+        }
+    };
 
-    Ok(())
+    coverage.check(min_coverage)?;
+    Ok(coverage)
 }
 
 // pub async fn fetch_daily_quotes_once(client: &Client, code: i32) -> Result<String, MyError> {
@@ -864,3 +1666,199 @@ pub async fn fetch_nikkei225_db(client: &Client, force: bool) -> Result<(), MyEr
 //         assert_eq!(now_string, "2022-12-31")
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statements_to_statement() {
+        let json = r#"{"statements":[
+            {"DisclosedDate":"2023-11-01","DisclosedTime":"13:25:00","LocalCode":"72030","TypeOfDocument":"2QFinancialStatements_Consolidated_IFRS","CurrentFiscalYearEndDate":"2024-03-31","NextFiscalYearEndDate":"","EarningsPerShare":"193.23","ForecastEarningsPerShare":"331.51","NextYearForecastEarningsPerShare":""},
+            {"DisclosedDate":"2024-05-08","DisclosedTime":"13:55:00","LocalCode":"72030","TypeOfDocument":"FYFinancialStatements_Consolidated_IFRS","CurrentFiscalYearEndDate":"2024-03-31","NextFiscalYearEndDate":"2025-03-31","EarningsPerShare":"365.94","ForecastEarningsPerShare":"","NextYearForecastEarningsPerShare":"260.44"},
+            {"DisclosedDate":"2024-05-08","DisclosedTime":"15:00:00","LocalCode":"25935","TypeOfDocument":"FYFinancialStatements_Consolidated_JP","CurrentFiscalYearEndDate":"2024-03-31","EarningsPerShare":"1.0","ForecastEarningsPerShare":""}
+        ],"pagination_key":null}"#;
+        let statements = serde_json::from_str::<Statements>(json)
+            .unwrap()
+            .to_statements();
+        let code = StockCode::new("7203").unwrap();
+        assert_eq!(
+            statements,
+            vec![
+                Statement::new(
+                    code.clone(),
+                    "2023-11-01",
+                    "2QFinancialStatements_Consolidated_IFRS",
+                    "2024-03-31",
+                    Some(193.23),
+                    Some(331.51)
+                ),
+                Statement::new(
+                    code,
+                    "2024-05-08",
+                    "FYFinancialStatements_Consolidated_IFRS",
+                    "2025-03-31",
+                    Some(365.94),
+                    Some(260.44)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_announcements() {
+        let json = r#"{"announcement":[
+            {"Date":"2024-08-01","Code":"72030","CompanyName":"トヨタ自動車","FiscalYear":"3月31日","SectorName":"輸送用機器","FiscalQuarter":"第１四半期","Section":"プライム"},
+            {"Date":"2024-08-01","Code":"","CompanyName":"","FiscalYear":"","SectorName":"","FiscalQuarter":"","Section":""}
+        ]}"#;
+        let announcements = serde_json::from_str::<Announcements>(json)
+            .unwrap()
+            .to_announcements();
+        assert_eq!(
+            announcements,
+            vec![(StockCode::new("7203").unwrap(), "2024-08-01".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_missing_latest_dates() {
+        let trading_days = ["2024-01-04", "2024-01-05", "2024-01-09"];
+        assert_eq!(
+            missing_latest_dates(&trading_days, "2024-01-04"),
+            vec!["2024-01-05".to_owned(), "2024-01-09".to_owned()]
+        );
+        assert!(missing_latest_dates(&trading_days, "2024-01-09").is_empty());
+
+        let e = MyError::NotLatestData {
+            latest: "2024-01-04".to_owned(),
+            missing: missing_latest_dates(&trading_days, "2024-01-04"),
+        };
+        assert!(e
+            .to_string()
+            .starts_with("stocks_ohlc ends on 2024-01-04, missing 2024-01-05, 2024-01-09;"));
+    }
+
+    #[test]
+    fn test_missing_cells() {
+        let calendar = serde_json::from_str::<TradingCalender>(
+            r#"{"trading_calendar":[
+                {"Date":"2024-01-04","HolidayDivision":"1"},
+                {"Date":"2024-01-05","HolidayDivision":"1"},
+                {"Date":"2024-01-06","HolidayDivision":"0"},
+                {"Date":"2024-01-08","HolidayDivision":"3"},
+                {"Date":"2024-01-09","HolidayDivision":"1"},
+                {"Date":"2024-01-10","HolidayDivision":"1"}
+            ]}"#,
+        )
+        .unwrap();
+        let toyota = StockCode::new("7203").unwrap();
+        let sony = StockCode::new("6758").unwrap();
+        let mut stored = Cells::new();
+        for date in ["2024-01-04", "2024-01-09"] {
+            stored.insert((toyota.clone(), date.to_owned()));
+            stored.insert((sony.clone(), date.to_owned()));
+        }
+        stored.insert((toyota.clone(), "2024-01-10".to_owned()));
+
+        // the hole on the 5th is found behind a stored day
+        let mut missing = missing_cells(
+            &calendar.trading_days(),
+            &[toyota.clone(), sony.clone()],
+            &HashMap::new(),
+            &stored,
+        )
+        .into_iter()
+        .collect::<Vec<_>>();
+        missing.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+        assert_eq!(
+            missing,
+            vec![
+                (sony.clone(), "2024-01-05".to_owned()),
+                (toyota, "2024-01-05".to_owned()),
+                (sony.clone(), "2024-01-10".to_owned()),
+            ]
+        );
+
+        // sony listed on the 10th has no hole before it
+        let listed_on = HashMap::from([(sony.clone(), "2024-01-10".to_owned())]);
+        let missing = missing_cells(
+            &calendar.trading_days(),
+            std::slice::from_ref(&sony),
+            &listed_on,
+            &stored,
+        );
+        assert_eq!(missing, Cells::from([(sony, "2024-01-10".to_owned())]));
+    }
+
+    #[test]
+    fn test_short_selling_values() {
+        let json = r#"{"short_selling":[
+            {"Date":"2024-01-04","Sector33Code":"3700","SellingExcludingShortSellingTurnoverValue":60000000.0,"ShortSellingWithRestrictionsTurnoverValue":30000000.0,"ShortSellingWithoutRestrictionsTurnoverValue":10000000.0}
+        ],"pagination_key":null}"#;
+        let values = serde_json::from_str::<ShortSellingValues>(json)
+            .unwrap()
+            .to_short_selling();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].ratio(), Some(40.0));
+    }
+
+    #[test]
+    fn test_trades_spec_values() {
+        let json = r#"{"trades_spec":[
+            {"PublishedDate":"2024-01-18","StartDate":"2024-01-09","EndDate":"2024-01-12","Section":"TSEPrime","ProprietarySales":1.0,"ForeignersSales":2.0,"ForeignersPurchases":5.0,"ForeignersBalance":3.0,"IndividualsBalance":-4.0}
+        ],"pagination_key":null}"#;
+        let values = serde_json::from_str::<TradesSpecValues>(json)
+            .unwrap()
+            .to_trades_spec();
+        assert_eq!(
+            values,
+            vec![TradesSpec::new(
+                "TSEPrime",
+                "2024-01-09",
+                "2024-01-12",
+                "2024-01-18",
+                3.0,
+                -4.0
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unknown_codes_skipped() {
+        let json = r#"{"daily_quotes":[
+            {"Date":"2024-01-04","Code":"72030","TurnoverValue":250000.0,"AdjustmentOpen":2500.0,"AdjustmentHigh":2510.0,"AdjustmentLow":2490.0,"AdjustmentClose":2505.0,"AdjustmentVolume":100.0,"MorningAdjustmentClose":2500.0,"AfternoonAdjustmentOpen":2502.0},
+            {"Date":"2024-01-04","Code":"25935","TurnoverValue":1000.0,"AdjustmentOpen":100.0,"AdjustmentHigh":100.0,"AdjustmentLow":100.0,"AdjustmentClose":100.0,"AdjustmentVolume":10.0,"MorningAdjustmentClose":100.0,"AfternoonAdjustmentOpen":100.0}
+        ],"pagination_key":null}"#;
+        let ohlc_vec = serde_json::from_str::<DailyQuotes>(json)
+            .unwrap()
+            .get_ohlc_premium();
+        assert_eq!(ohlc_vec.len(), 1);
+        assert_eq!(ohlc_vec[0].get_code().as_str(), "7203");
+
+        let json = r#"{"prices_am":[
+            {"Date":"2024-01-04","Code":"72030","MorningOpen":2500.0,"MorningHigh":2510.0,"MorningLow":2490.0,"MorningClose":2505.0,"MorningVolume":1000.0,"MorningTurnoverValue":2500000.0},
+            {"Date":"2024-01-04","Code":"25935","MorningOpen":100.0,"MorningHigh":100.0,"MorningLow":100.0,"MorningClose":100.0,"MorningVolume":10.0,"MorningTurnoverValue":1000.0}
+        ]}"#;
+        let prices_am = serde_json::from_str::<PricesAm>(json).unwrap().listed();
+        assert_eq!(prices_am.prices_am.len(), 1);
+        let toyota = StockCode::new("7203").unwrap();
+        assert_eq!(prices_am.get_stock_am(&toyota).unwrap().get_close(), 2505.0);
+    }
+
+    #[test]
+    fn test_prices_am_age() {
+        use chrono::TimeZone;
+
+        let json = r#"{"prices_am":[
+            {"Date":"2024-01-04","Code":"72030","MorningOpen":2500.0,"MorningHigh":2510.0,"MorningLow":2490.0,"MorningClose":2505.0,"MorningVolume":1000.0,"MorningTurnoverValue":2500000.0}
+        ]}"#;
+        let mut prices_am = serde_json::from_str::<PricesAm>(json).unwrap();
+        let at = |h, m| chrono::Local.with_ymd_and_hms(2024, 1, 4, h, m, 0).unwrap();
+
+        prices_am.fetched_at = at(12, 10);
+        assert_eq!(prices_am.age_at(at(12, 40)).num_minutes(), 70);
+        // re-run after the close, the data is still as of the morning close
+        prices_am.fetched_at = at(15, 40);
+        assert_eq!(prices_am.age_at(at(15, 45)).num_minutes(), 255);
+    }
+}
@@ -0,0 +1,150 @@
+use log::{debug, warn};
+use reqwest::header::{
+    HeaderMap, HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{RequestBuilder, StatusCode};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::jquants::retry;
+use crate::my_error::MyError;
+
+/// The last 200 of a URL with its validators, stored as
+/// `GDRIVE_PATH/trading23/http_cache/<sha256 of the URL>.json`
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+struct Entry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+impl Entry {
+    /// None when the response has no validator to revalidate it with
+    fn from_response(url: &str, headers: &HeaderMap, body: &str) -> Option<Self> {
+        let header = |name: HeaderName| {
+            headers
+                .get(name)
+                .and_then(|x| x.to_str().ok())
+                .map(|x| x.to_owned())
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+        Some(Entry {
+            url: url.to_owned(),
+            etag,
+            last_modified,
+            body: body.to_owned(),
+        })
+    }
+
+    fn load(dir: &Path, url: &str) -> Option<Self> {
+        let text = fs::read_to_string(entry_path(dir, url)).ok()?;
+        let entry = serde_json::from_str::<Entry>(&text).ok()?;
+        // a hash collision or a hand edited file
+        (entry.url == url).then_some(entry)
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), MyError> {
+        fs::create_dir_all(dir)?;
+        fs::write(entry_path(dir, &self.url), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let gdrive_path = env::var("GDRIVE_PATH").ok()?;
+    Some(Path::new(&gdrive_path).join("trading23").join("http_cache"))
+}
+
+/// The URL includes the query, the id token is left out as it changes every day
+fn entry_path(dir: &Path, url: &str) -> PathBuf {
+    let hash = hex::encode(digest(&SHA256, url.as_bytes()));
+    dir.join(format!("{}.json", hash))
+}
+
+/// [`retry::send`] revalidating the last response of the same URL with
+/// `If-None-Match` / `If-Modified-Since`. A 304 returns the cached body as a 200,
+/// for endpoints that rarely change like `/listed/info` and the calendar.
+///
+/// Responses without an ETag or Last-Modified are not cached, and a missing
+/// GDRIVE_PATH or an unreadable cache falls back to a plain request.
+pub async fn send(request: RequestBuilder) -> Result<(StatusCode, String), MyError> {
+    let url = request
+        .try_clone()
+        .and_then(|x| x.build().ok())
+        .map(|x| x.url().to_string());
+    let (Some(dir), Some(url)) = (cache_dir(), url) else {
+        return retry::send(request).await;
+    };
+
+    let cached = Entry::load(&dir, &url);
+    let request = match &cached {
+        Some(entry) => {
+            let request = match &entry.etag {
+                Some(etag) => request.header(IF_NONE_MATCH, etag),
+                None => request,
+            };
+            match &entry.last_modified {
+                Some(last_modified) => request.header(IF_MODIFIED_SINCE, last_modified),
+                None => request,
+            }
+        }
+        None => request,
+    };
+
+    let (status, headers, text) = retry::send_with_headers(request).await?;
+    match (status, cached) {
+        (StatusCode::NOT_MODIFIED, Some(entry)) => {
+            debug!("Not modified: {}", url);
+            Ok((StatusCode::OK, entry.body))
+        }
+        (StatusCode::OK, _) => {
+            if let Some(entry) = Entry::from_response(&url, &headers, &text) {
+                if let Err(e) = entry.save(&dir) {
+                    warn!("Failed to cache {}: {}", url, e);
+                }
+            }
+            Ok((status, text))
+        }
+        _ => Ok((status, text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_entry() {
+        let url = "https://api.jquants.com/v1/listed/info?pagination_key=abc";
+        let mut headers = HeaderMap::new();
+        assert_eq!(Entry::from_response(url, &headers, "{}"), None);
+
+        headers.insert(ETAG, HeaderValue::from_static("\"v1\""));
+        let entry = Entry::from_response(url, &headers, "{\"info\":[]}").unwrap();
+        assert_eq!(entry.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(entry.last_modified, None);
+
+        let dir = env::temp_dir().join(format!("trading23_http_cache_{}", std::process::id()));
+        entry.save(&dir).unwrap();
+        assert_eq!(Entry::load(&dir, url), Some(entry));
+        assert_eq!(
+            Entry::load(&dir, "https://api.jquants.com/v1/listed/info"),
+            None
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_ne!(
+            entry_path(&dir, url),
+            entry_path(&dir, "https://api.jquants.com/v1/listed/info")
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::stock_code::StockCode;
+
+/// A `/prices/daily_quotes` call
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Every code on one date
+    ByDate(String),
+    /// One code over the backfill range
+    ByCode(StockCode),
+}
+
+/// Missing (code, date) cells of stocks_ohlc
+pub type Cells = HashSet<(StockCode, String)>;
+
+/// Queries covering every cell of `missing`, greedily taking the date or the code
+/// with the most cells left. A missing day costs one call instead of 225 by code,
+/// and a code added to the Nikkei 225 one call instead of one per date.
+///
+/// Cells J-Quants had no bar for (e.g. a suspension) are recorded in runs and left out
+/// by the fetcher before planning, see `runs::select_missing`.
+pub fn plan(missing: &Cells) -> Vec<Query> {
+    let mut left = missing.clone();
+    let mut queries = Vec::new();
+    while !left.is_empty() {
+        let mut by_date: HashMap<&str, usize> = HashMap::new();
+        let mut by_code: HashMap<&StockCode, usize> = HashMap::new();
+        for (code, date) in &left {
+            *by_date.entry(date).or_default() += 1;
+            *by_code.entry(code).or_default() += 1;
+        }
+        // ties go to the date, then to the smallest key so the plan is stable
+        let (date, date_cells) = by_date
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+            .expect("left is not empty");
+        let (code, code_cells) = by_code
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+            .expect("left is not empty");
+        let query = match date_cells >= code_cells {
+            true => Query::ByDate(date.to_owned()),
+            false => Query::ByCode(code.clone()),
+        };
+        match &query {
+            Query::ByDate(date) => left.retain(|x| &x.1 != date),
+            Query::ByCode(code) => left.retain(|x| &x.0 != code),
+        }
+        queries.push(query);
+    }
+    queries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(codes: &[&str], dates: &[&str]) -> Cells {
+        let mut cells = Cells::new();
+        for code in codes {
+            for date in dates {
+                cells.insert((StockCode::new(code).unwrap(), date.to_string()));
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn test_plan() {
+        let codes = ["7203", "6758", "9984", "8306"];
+        let dates = ["2024-01-04", "2024-01-05", "2024-01-09"];
+
+        // two whole days missing
+        let missing = cells(&codes, &dates[..2]);
+        assert_eq!(
+            plan(&missing),
+            vec![
+                Query::ByDate("2024-01-04".to_owned()),
+                Query::ByDate("2024-01-05".to_owned())
+            ]
+        );
+
+        // a new code without history and one missing day
+        let mut missing = cells(&["1605"], &dates);
+        missing.extend(cells(&codes, &["2024-01-09"]));
+        assert_eq!(
+            plan(&missing),
+            vec![
+                Query::ByDate("2024-01-09".to_owned()),
+                Query::ByCode(StockCode::new("1605").unwrap())
+            ]
+        );
+
+        assert!(plan(&Cells::new()).is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use log::warn;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, StatusCode};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::GdriveJson;
+use crate::my_error::MyError;
+use crate::rate_limit::Provider;
+
+/// A request taking longer than this is retried like a 5xx
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Tries of a request, including the first one
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: BASE_DELAY,
+            max_delay: MAX_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// `jquantsMaxAttempts` of the config, read once per run
+    pub fn from_config() -> Self {
+        static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+        *POLICY.get_or_init(|| match GdriveJson::new() {
+            Ok(config) => RetryPolicy {
+                max_attempts: config.jquants_max_attempts().max(1),
+                ..Default::default()
+            },
+            Err(_) => RetryPolicy::default(),
+        })
+    }
+
+    /// Wait before the retry following `attempt` (1-based): the base doubled per attempt,
+    /// capped, then scaled by `jitter` (0.0..1.0) into its upper half
+    fn delay(&self, attempt: u32, jitter: f64) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        exp.mul_f64(0.5 + 0.5 * jitter.clamp(0.0, 1.0))
+    }
+}
+
+/// 0.0..1.0 from the clock, enough to keep parallel retries apart
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.subsec_nanos())
+        .unwrap_or_default();
+    nanos as f64 / 1_000_000_000.0
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Seconds of a `Retry-After` header, J-Quants sends one with some 429s
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Sends the request and returns the status and body, retrying 429s, 5xxs and timeouts
+/// with exponential backoff. Other statuses are returned as they are, the callers
+/// handle 401 and 400.
+pub async fn send(request: RequestBuilder) -> Result<(StatusCode, String), MyError> {
+    let (status, _, text) = send_with_headers(request).await?;
+    Ok((status, text))
+}
+
+/// [`send`] keeping the response headers of the last try
+pub async fn send_with_headers(
+    request: RequestBuilder,
+) -> Result<(StatusCode, HeaderMap, String), MyError> {
+    let policy = RetryPolicy::from_config();
+    let request = request.timeout(REQUEST_TIMEOUT);
+    let mut attempt = 1;
+    loop {
+        // each try takes a slot of the J-Quants limiter, released before the backoff
+        let permit = Provider::JQuants.limiter().acquire().await;
+        let Some(this_try) = request.try_clone() else {
+            // streamed bodies can't be sent twice
+            let res = request.send().await?;
+            let status = res.status();
+            let headers = res.headers().clone();
+            return Ok((status, headers, res.text().await?));
+        };
+        let wait = match this_try.send().await {
+            Ok(res) => {
+                let status = res.status();
+                let headers = res.headers().clone();
+                let text = res.text().await?;
+                if !is_retryable_status(status) || attempt >= policy.max_attempts {
+                    return Ok((status, headers, text));
+                }
+                warn!(
+                    "Status code: {}, retrying ({}/{})",
+                    status, attempt, policy.max_attempts
+                );
+                retry_after(&headers)
+            }
+            Err(e) => {
+                let e = MyError::from(e);
+                if !e.is_retryable() || attempt >= policy.max_attempts {
+                    return Err(e);
+                }
+                warn!("{}, retrying ({}/{})", e, attempt, policy.max_attempts);
+                None
+            }
+        };
+        let delay = wait
+            .map(|x| x.min(policy.max_delay))
+            .unwrap_or_else(|| policy.delay(attempt, jitter()));
+        drop(permit);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, 1.0), Duration::from_secs(1));
+        assert_eq!(policy.delay(3, 1.0), Duration::from_secs(4));
+        assert_eq!(policy.delay(3, 0.0), Duration::from_secs(2));
+        assert_eq!(policy.delay(10, 1.0), MAX_DELAY);
+        assert_eq!(policy.delay(40, 1.0), MAX_DELAY);
+
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+    }
+}
@@ -0,0 +1,29 @@
+pub mod analysis;
+pub mod bot;
+pub mod briefing;
+pub mod config;
+pub mod database;
+pub mod draft;
+pub mod economic_calendar;
+pub mod feed;
+pub mod gmo_coin;
+pub mod halt;
+pub mod i18n;
+pub mod instrument;
+pub mod jquants;
+pub mod line_notify;
+pub mod markdown;
+pub mod my_error;
+pub mod my_file_io;
+pub mod notion;
+pub mod output_sink;
+pub mod pipeline;
+pub mod profile;
+pub mod progress;
+pub mod rate_limit;
+pub mod report_diff;
+pub mod report_kind;
+pub mod rounding;
+pub mod signal;
+pub mod stock_code;
+pub mod units;
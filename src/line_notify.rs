@@ -1,10 +1,15 @@
 use std::{thread, time::Duration};
 
-use crate::{database::stocks::Output, my_error::MyError};
+use crate::{
+    database::stocks::Output,
+    my_error::MyError,
+    profile::{self, Stage},
+};
 use log::{error, info};
 use reqwest::Client;
 
 pub async fn send_message(client: &Client, message: &str) -> Result<(), MyError> {
+    let _span = profile::span(Stage::Notify);
     let url = "https://notify-api.line.me/api/notify";
     let config = crate::config::GdriveJson::new()?;
     let token = config.line_token();
@@ -1,29 +1,44 @@
+use anyhow::anyhow;
+use briefing::{Briefing, BRIEFING};
 use clap::{Args, Parser, Subcommand};
 use database::stocks::SelectDate;
-use log::{error, info};
+use i18n::{Lang, Msg};
+use log::{error, info, warn};
+use markdown::ReportFormat;
+use my_error::MyError;
+use output_sink::{Dispatcher, NotifySink};
+use report_diff::{ReportDiff, ReportSnapshot};
 use reqwest::Client;
 use std::env;
-
-mod analysis;
-mod config;
-mod database;
-mod gmo_coin;
-mod jquants;
-mod line_notify;
-mod markdown;
-mod my_error;
-mod my_file_io;
-mod notion;
+use std::path::PathBuf;
+use trading23::{
+    analysis, bot, briefing, config, database,
+    database::export::ExportFormat,
+    database::prune::ArchiveFormat,
+    draft, gmo_coin, i18n,
+    instrument::Instrument,
+    jquants, line_notify, markdown, my_error,
+    my_file_io::{self, Universe},
+    notion, output_sink,
+    pipeline::{Pipeline, Step},
+    profile, report_diff,
+    signal::SignalId,
+    stock_code::StockCode,
+};
 
 #[derive(Parser)]
 pub struct Cli {
     #[clap(subcommand)]
     command: Commands,
+    /// SQLite file to use instead of trading23.sqlite (also TRADING23_DB), ":memory:" for
+    /// a database dropped at the end of the run
+    #[arg(long, global = true)]
+    db: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    Stocks(MyArgs),
+    Stocks(StocksArgs),
     Fx(MyArgs),
     /// date: YYYYMMDD
     Db {
@@ -33,8 +48,248 @@ enum Commands {
         date: Option<String>,
         #[arg(long)]
         notify: bool,
+        /// Deletes the rows past `retention` of config.json, then runs ANALYZE and VACUUM
+        /// on trading23.sqlite
+        #[arg(long)]
+        optimize: bool,
+        #[command(subcommand)]
+        command: Option<DbCommands>,
     },
     Notion,
+    /// Stores code, name, sector and market of all listed stocks in stocks_master
+    Master,
+    /// Stores EPS and forecasts from /fins/statements, for a code or a date (YYYY-MM-DD),
+    /// and the schedule of the next results from /fins/announcement
+    Statements {
+        #[arg(long)]
+        code: Option<String>,
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Features and next day results of the Nikkei 225 between two dates (YYYY-MM-DD)
+    /// to trading23/datasets as csv, one row per code and date
+    Dataset {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        /// nikkei225, topix500 or the path of a CSV with code and name columns
+        #[arg(long, default_value = "nikkei225")]
+        universe: Universe,
+    },
+    Report {
+        #[command(subcommand)]
+        command: ReportCommands,
+    },
+    /// Codes the nextday and afternoon screenings leave out
+    Blacklist {
+        #[command(subcommand)]
+        command: BlacklistCommands,
+    },
+    /// Tender offers, MBOs and offerings; the screening drops stocks under a tender offer
+    /// and flags those under an offering
+    Events {
+        #[command(subcommand)]
+        command: EventsCommands,
+    },
+    Universe {
+        #[command(subcommand)]
+        command: UniverseCommands,
+    },
+    /// Positions taken from the reports, to reconcile the realized P&L with the signals
+    Trade {
+        #[command(subcommand)]
+        command: TradeCommands,
+    },
+    /// Moves the drafts of a date (YYYY-MM-DD) to the report folders, see `--draft`
+    Publish {
+        #[arg(long)]
+        date: String,
+    },
+    /// Nextday summary, FX setups, positions and alerts in one page and one message,
+    /// run after the nextday report
+    Briefing {
+        /// YYYY-MM-DD, today by default
+        #[arg(long)]
+        date: Option<String>,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Html)]
+        format: ReportFormat,
+        #[arg(long)]
+        notify: bool,
+    },
+    /// Answers "status", "today", "halt", "resume" and "chart CODE" from the LINE and
+    /// Telegram webhooks (POST /line, /telegram), see `bot` in config.json
+    Serve {
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+    },
+}
+
+#[derive(Args)]
+struct StocksArgs {
+    #[command(subcommand)]
+    command: Option<StocksCommands>,
+    #[command(flatten)]
+    args: MyArgs,
+}
+
+#[derive(Subcommand)]
+enum StocksCommands {
+    /// Ranks stocks by morning gap and move in ATR, without the window analysis
+    Quick {
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// The nextday reports of a past date (YYYY-MM-DD) from the bars stored by the end of
+    /// that day, to trading23/asof/{date}. Nothing is fetched or sent; the nextday filters
+    /// apply, --min-short-ratio with the stored ratios
+    Asof {
+        #[arg(long)]
+        date: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Writes stocks_ohlc to trading23/exports/stocks_ohlc_{code or all}_{from}_{to},
+    /// dates are YYYY-MM-DD and open ended when left out
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        #[arg(long)]
+        code: Option<String>,
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: Option<String>,
+        /// Path to write to instead of trading23/exports
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Adds the bars of csv files with the columns of `export --format csv` to
+    /// stocks_ohlc, skipping those stored already
+    Import {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Rows, dates and retention of the tables `--optimize` prunes
+    Status,
+    /// Adds the stocks_ohlc bars of other trading23.sqlite files, e.g. the copy of a second
+    /// machine or of a Drive conflict, skipping those stored already
+    Merge {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Moves the stocks_ohlc bars older than the latest N trading days to
+    /// trading23/archive and compacts trading23.sqlite
+    Prune {
+        /// At least as many as the analyses and the fetcher read back
+        #[arg(long)]
+        keep_days: usize,
+        /// sqlite adds to stocks_ohlc_archive.sqlite, parquet writes
+        /// stocks_ohlc_before_{date}.parquet
+        #[arg(long, value_enum, default_value_t = ArchiveFormat::Sqlite)]
+        format: ArchiveFormat,
+        /// Path to write to instead of trading23/archive
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReportCommands {
+    /// Compares candidates and summary of two outputs for the same date (.html or .json),
+    /// exits with 1 when they differ
+    Diff { old: PathBuf, new: PathBuf },
+    /// Prints the candidates and summary of a report as json, for later `diff`
+    Snapshot { path: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum BlacklistCommands {
+    /// Adds a code, or updates its reason and expiry
+    Add {
+        code: String,
+        #[arg(long, default_value = "")]
+        reason: String,
+        /// YYYY-MM-DD, the last day excluded; until removed when not set
+        #[arg(long)]
+        until: Option<String>,
+    },
+    Remove {
+        code: String,
+    },
+    List,
+}
+
+#[derive(Subcommand)]
+enum EventsCommands {
+    Add {
+        code: String,
+        #[arg(long, value_enum)]
+        kind: database::corporate_events::EventKind,
+        /// YYYY-MM-DD, usually the announcement
+        #[arg(long)]
+        from: String,
+        /// YYYY-MM-DD, e.g. the end of the tender offer period; until removed when not set
+        #[arg(long)]
+        until: Option<String>,
+        #[arg(long, default_value = "")]
+        note: String,
+    },
+    /// Removes an event by the id shown in `list`
+    Remove {
+        id: i64,
+    },
+    List,
+}
+
+#[derive(Subcommand)]
+enum TradeCommands {
+    /// Records an entry, the id shown is the one to `close`
+    Open {
+        /// stock code or FX symbol (USD_JPY)
+        code: String,
+        #[arg(long, value_enum)]
+        side: database::journal::Side,
+        #[arg(long)]
+        quantity: f64,
+        #[arg(long)]
+        price: f64,
+        /// YYYY-MM-DD, today when not set
+        #[arg(long)]
+        date: Option<String>,
+        #[arg(long)]
+        stop: Option<f64>,
+        /// ID of the report row the entry came from (the ID column)
+        #[arg(long)]
+        signal: Option<String>,
+        #[arg(long, default_value = "")]
+        note: String,
+    },
+    /// Closes a position by the id shown in `list`
+    Close {
+        id: i64,
+        #[arg(long)]
+        price: f64,
+        /// YYYY-MM-DD, today when not set
+        #[arg(long)]
+        date: Option<String>,
+    },
+    /// Positions with their P&L and the next day results of their signals
+    List,
+}
+
+#[derive(Subcommand)]
+enum UniverseCommands {
+    /// Writes a universe as v2 CSV, the sector code and market from stocks_master
+    /// (`trading23 master`). The listing date is kept as written in the CSV
+    Convert {
+        /// nikkei225, topix500 or the path of a CSV with code and name columns
+        universe: Universe,
+        #[arg(long)]
+        output: PathBuf,
+    },
 }
 
 #[derive(Args)]
@@ -53,137 +308,165 @@ struct MyArgs {
     code: Option<i32>,
     #[arg(long)]
     force: bool,
+    #[arg(long, value_enum, default_value_t = ReportFormat::Html)]
+    format: ReportFormat,
+    /// keep reports as drafts until `trading23 publish --date YYYY-MM-DD`
+    #[arg(long)]
+    draft: bool,
+    /// print elapsed time per stage (fetch, db read, window, render, notify)
+    #[arg(long)]
+    profile_report: bool,
+    /// nextday: keep stocks whose sector short selling ratio (%) is at least this,
+    /// for squeeze candidates
+    #[arg(long)]
+    min_short_ratio: Option<f64>,
+    /// nextday: keep stocks whose MACD crossed its signal line this way on the day
+    #[arg(long, value_enum)]
+    macd_cross: Option<analysis::macd::MacdCross>,
+    /// nextday: keep stocks closing above their 20-day moving average
+    #[arg(long)]
+    above_ma20: bool,
+    /// nextday: keep stocks whose 20-day moving average rose over the last 5 days
+    #[arg(long)]
+    ma20_rising: bool,
+    /// nextday: keep stocks whose 20-day moving average crossed the 60-day one this way
+    #[arg(long, value_enum)]
+    ma_cross: Option<analysis::indicators::MaCross>,
+    /// nextday: keep stocks whose weekly bars trend this way
+    #[arg(long, value_enum)]
+    weekly_trend: Option<analysis::live::BullBear>,
+    /// nikkei225, topix500 or the path of a CSV with code and name columns
+    #[arg(long, default_value = "nikkei225")]
+    universe: Universe,
 }
 
-#[tokio::main]
-async fn main() {
-    // 環境変数の読み込み
+fn main() {
+    // 環境変数の読み込み, before the runtime's threads could read them
     env::set_var("RUST_LOG", "info");
     env_logger::init();
 
     let cli = Cli::parse();
+    if let Some(db) = &cli.db {
+        env::set_var(database::DB_ENV, db);
+    }
 
-    let client = Client::new();
-
-    match &cli.command {
-        Commands::Stocks(args) => {
-            if args.nextday {
-                line_notify::send_message(&client, "Starting Next day process")
-                    .await
-                    .unwrap();
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime")
+        .block_on(run(cli));
+}
 
-                match jquants::fetcher::fetch_nikkei225_db(&client, args.force).await {
-                    Ok(_) => {
-                        info!("fetch_nikkei225 success");
-                    }
-                    Err(e) => return error!("fetch_nikkei225 failed: {}", e),
-                };
+async fn run(cli: Cli) {
+    // the commands writing trading23.sqlite, one machine at a time
+    let job = match &cli.command {
+        Commands::Stocks(StocksArgs {
+            command: Some(StocksCommands::Asof { .. }),
+            ..
+        }) => None,
+        Commands::Stocks(_) => Some("stocks"),
+        Commands::Db { .. } => Some("db"),
+        Commands::Master => Some("master"),
+        Commands::Statements { .. } => Some("statements"),
+        // economic_events
+        Commands::Fx(_) => Some("fx"),
+        Commands::Briefing { .. } => Some("briefing"),
+        Commands::Trade { .. } => Some("trade"),
+        Commands::Blacklist { .. } => Some("blacklist"),
+        Commands::Events { .. } => Some("events"),
+        _ => None,
+    };
+    let _lease = match job.map(database::lease::acquire_for).transpose() {
+        Ok(lease) => lease.flatten(),
+        Err(e) => return error!("{}", e),
+    };
 
-                let today = chrono::Local::now().format("%Y-%m-%d").to_string();
-                let day_before_5 = chrono::Local::now()
-                    .checked_sub_signed(chrono::Duration::days(5))
-                    .unwrap()
-                    .format("%Y-%m-%d")
-                    .to_string();
+    let client = Client::new();
 
-                let stocks_window_list =
-                    match analysis::stocks_window::create_stocks_window_list_db(
-                        &day_before_5,
-                        &today,
-                    )
-                    .await
-                    {
-                        Ok(output) => output,
-                        Err(e) => {
-                            error!("create_stocks_window_list_db failed: {}", e);
-                            line_notify::send_message(
-                                &client,
-                                "create_stocks_window_list_db failed",
-                            )
-                            .await
-                            .unwrap();
-                            return;
-                        }
-                    };
+    match &cli.command {
+        Commands::Stocks(StocksArgs { command, args }) => {
+            let lang = Lang::from_config();
 
-                if let Err(e) = stocks_window_list.for_resistance_strategy_default() {
-                    error!("for_resistance_strategy failed: {}", e);
-                    line_notify::send_message(&client, "for_resistance_strategy failed")
-                        .await
-                        .unwrap();
-                    return;
+            if let Some(StocksCommands::Quick { top }) = command {
+                let prices_am = match jquants::fetcher::PricesAm::new(&client, args.force).await {
+                    Ok(prices_am) => prices_am,
+                    Err(e) => return error!("fetch morning market failed: {}", e),
                 };
+                match analysis::stocks_quick::StocksQuickList::from_nikkei225(
+                    &prices_am,
+                    &args.universe,
+                )
+                .and_then(|list| list.top(*top).output(lang))
+                {
+                    Ok(output) => info!("\n{}", output),
+                    Err(e) => error!("quick scan failed: {}", e),
+                }
+                if args.profile_report {
+                    info!("\n{}", profile::report());
+                }
+                return;
+            }
 
-                if let Err(e) = stocks_window_list.for_resistance_strategy(true) {
-                    error!("for_resistance_consolidating_strategy failed: {}", e);
-                    line_notify::send_message(
-                        &client,
-                        "for_resistance_consolidating_strategy failed",
-                    )
-                    .await
-                    .unwrap();
-                    return;
-                };
+            if let Some(StocksCommands::Asof { date }) = command {
+                if let Err(e) = run_as_of(args, date).await {
+                    error!("asof {} failed: {}", date, e);
+                }
+                return;
+            }
 
-                line_notify::send_message(&client, "Next day process, success")
-                    .await
-                    .unwrap();
+            if args.nextday {
+                run_nextday(&client, args, lang).await;
             }
 
-            if args.afternoon {
-                line_notify::send_message(&client, "Starting Afternoon process")
-                    .await
-                    .unwrap();
+            if args.afternoon && !args.backtest {
+                run_afternoon(&client, args, lang).await;
+            }
 
-                let prices_am = match jquants::fetcher::PricesAm::new(&client, true).await {
-                    Ok(prices_am) => prices_am,
-                    Err(e) => {
-                        error!("fetch morning market failed: {}", e);
+            if args.backtest && args.fetch {
+                // the backtests read the stored bars when the update fails
+                match Pipeline::new("backtest").run(
+                    Step::Topix,
+                    jquants::fetcher::update_topix_ohlc(&client).await,
+                ) {
+                    Ok(Some(len)) => info!("topix_ohlc has been updated, {} days", len),
+                    Ok(None) => {}
+                    Err(e) => return error!("update topix_ohlc failed: {}", e),
+                }
+            }
 
-                        line_notify::send_message(&client, "fetch morning market failed")
-                            .await
-                            .unwrap();
-                        return;
-                    }
+            if args.afternoon && args.backtest {
+                let (day_before_100, today) = {
+                    let today = chrono::Local::now();
+                    let day_before_100 = today - chrono::Duration::days(100);
+                    (
+                        day_before_100.format("%Y-%m-%d").to_string(),
+                        today.format("%Y-%m-%d").to_string(),
+                    )
                 };
 
-                let mut stocks_afternoon_list =
-                    match analysis::stocks_afternoon::StocksAfternoonList::from_nikkei225(
-                        &prices_am,
+                let stocks_afternoon_list =
+                    match analysis::stocks_afternoon::StocksAfternoonList::from_backtest(
+                        &args.universe,
+                        &day_before_100,
+                        &today,
                     ) {
                         Ok(output) => output,
                         Err(e) => {
-                            error!("StocksAfternoonList::from_nikkei225_db failed: {}", e);
-                            line_notify::send_message(
-                                &client,
-                                "StocksAfternoonList::from_nikkei225_db failed",
-                            )
-                            .await
-                            .unwrap();
-                            return;
+                            return error!("StocksAfternoonList::from_backtest failed: {}", e)
                         }
                     };
 
-                if let Err(e) = stocks_afternoon_list.for_resistance_strategy_default() {
-                    error!("for_afternoon_strategy failed: {}", e);
-                    line_notify::send_message(&client, "for_afternoon_strategy failed")
-                        .await
-                        .unwrap();
-                    return;
-                };
-
-                if let Err(e) = stocks_afternoon_list.for_resistance_strategy(true) {
-                    error!("for_afternoon_strategy failed: {}", e);
-                    line_notify::send_message(&client, "for_afternoon_strategy failed")
-                        .await
-                        .unwrap();
-                    return;
-                };
+                let topix_daily_window_list =
+                    analysis::backtesting_topix::TopixDailyWindowList::new(
+                        &analysis::backtesting_topix::BacktestingTopixList::from_db().unwrap(),
+                    );
 
-                line_notify::send_message(&client, "Success").await.unwrap();
+                let result =
+                    stocks_afternoon_list.get_windows_related_result(&topix_daily_window_list);
+                info!("result: {}", result);
             }
 
-            if args.backtest {
+            if args.backtest && !args.afternoon {
                 // if let true = args.fetch {
                 //     match jquants::backtesting::fetch_ohlcs_and_save().await {
                 //         Ok(_) => info!("fetch_nikkei225 success"),
@@ -191,18 +474,28 @@ async fn main() {
                 //     };
                 // }
                 // jquants::backtesting::backtesting_to_json().unwrap();
-                let stocks_daytrading_list =
-                    analysis::stocks_daytrading::async_exec("2023-07-01", "2024-01-01")
-                        .await
-                        .unwrap();
+                let stocks_daytrading_list = analysis::stocks_daytrading::async_exec(
+                    &args.universe,
+                    "2023-07-01",
+                    "2024-01-01",
+                )
+                .await
+                .unwrap();
+                for (msg, codes) in [
+                    (Msg::Excluded, stocks_daytrading_list.get_excluded()),
+                    (Msg::Flagged, stocks_daytrading_list.get_flagged()),
+                ] {
+                    if !codes.is_empty() {
+                        info!("{}: {}", msg.text(lang), codes.join(", "));
+                    }
+                }
                 // let topix_list =
                 //     analysis::backtesting_topix::BacktestingTopixList::from_json_file()
                 //         .unwrap();
 
                 let topix_daily_window_list =
                     analysis::backtesting_topix::TopixDailyWindowList::new(
-                        &analysis::backtesting_topix::BacktestingTopixList::from_json_file()
-                            .unwrap(),
+                        &analysis::backtesting_topix::BacktestingTopixList::from_db().unwrap(),
                     );
 
                 let status = [
@@ -231,7 +524,7 @@ async fn main() {
                 // };
 
                 let force = args.force;
-                match jquants::fetcher::fetch_nikkei225_db(&client, force).await {
+                match jquants::fetcher::fetch_nikkei225_db(&client, &args.universe, force).await {
                     Ok(_) => info!("fetch_nikkei225 success"),
                     Err(e) => return error!("fetch_nikkei225 failed: {}", e),
                 }
@@ -263,6 +556,10 @@ async fn main() {
 
                 // aaa.for_resistance_strategy().unwrap();
             }
+
+            if args.profile_report {
+                info!("\n{}", profile::report());
+            }
         }
 
         Commands::Fx(args) => {
@@ -279,10 +576,211 @@ async fn main() {
                 _ => {}
             }
         }
+        Commands::Db {
+            command:
+                Some(DbCommands::Export {
+                    format,
+                    code,
+                    from,
+                    to,
+                    output,
+                }),
+            ..
+        } => {
+            let result = from
+                .iter()
+                .chain(to.iter())
+                .try_for_each(|x| check_date(x))
+                .and_then(|_| match (from, to) {
+                    (Some(from), Some(to)) if from > to => Err(MyError::Anyhow(anyhow!(
+                        "--from {} is after --to {}",
+                        from,
+                        to
+                    ))),
+                    _ => Ok(()),
+                })
+                .and_then(|_| code.as_deref().map(StockCode::new).transpose())
+                .and_then(|code| {
+                    let path = match output {
+                        Some(output) => output.clone(),
+                        None => my_file_io::get_export_file_path(&format!(
+                            "stocks_ohlc_{}_{}_{}.{}",
+                            code.as_ref().map_or("all".to_owned(), |x| x.to_string()),
+                            from.as_deref().unwrap_or("start"),
+                            to.as_deref().unwrap_or("end"),
+                            format.extension()
+                        ))?,
+                    };
+                    let conn = database::stocks_ohlc::open_db()?;
+                    let bars = database::export::export(
+                        &conn,
+                        *format,
+                        code.as_ref(),
+                        from.as_deref(),
+                        to.as_deref(),
+                        &path,
+                    )?;
+                    Ok((path, bars))
+                });
+            match result {
+                Ok((path, bars)) => info!("exported {} bars to {}", bars, path.display()),
+                Err(e) => error!("export failed: {}", e),
+            }
+        }
+        Commands::Db {
+            command: Some(DbCommands::Import { paths }),
+            ..
+        } => {
+            let mut conn = match database::stocks_ohlc::open_db() {
+                Ok(conn) => conn,
+                Err(e) => return error!("{}", e),
+            };
+            for path in paths {
+                let result = std::fs::File::open(path)
+                    .map_err(MyError::from)
+                    .and_then(|file| database::import::import(&mut conn, file));
+                match result {
+                    Ok(summary) => {
+                        info!(
+                            "{}: {} bars imported, {} duplicates, {} invalid",
+                            path.display(),
+                            summary.get_inserted(),
+                            summary.get_duplicates(),
+                            summary.get_invalid()
+                        );
+                        for line in summary.get_reported() {
+                            warn!("{}: {}", path.display(), line);
+                        }
+                    }
+                    Err(e) => error!("{}: import failed: {}", path.display(), e),
+                }
+            }
+        }
+        Commands::Db {
+            command: Some(DbCommands::Merge { paths }),
+            ..
+        } => {
+            let mut conn = match database::stocks_ohlc::open_db() {
+                Ok(conn) => conn,
+                Err(e) => return error!("{}", e),
+            };
+            for path in paths {
+                match database::merge::merge(&mut conn, path) {
+                    Ok(summary) => info!(
+                        "{}: {} bars merged, {} duplicates, {} conflicts kept as stored, \
+                        {} positions, {} trades, {} blacklisted codes, {} corporate events",
+                        path.display(),
+                        summary.get_inserted(),
+                        summary.get_duplicates(),
+                        summary.get_conflicts(),
+                        summary.get_positions(),
+                        summary.get_trades(),
+                        summary.get_blacklist(),
+                        summary.get_corporate_events()
+                    ),
+                    Err(e) => error!("{}: merge failed: {}", path.display(), e),
+                }
+            }
+        }
+        Commands::Db {
+            command:
+                Some(DbCommands::Prune {
+                    keep_days,
+                    format,
+                    output,
+                }),
+            ..
+        } => {
+            match database::prune::min_keep_days() {
+                Ok(min) if *keep_days < min => {
+                    return error!(
+                        "keep at least {} trading days, the analyses and the fetcher read back that far",
+                        min
+                    )
+                }
+                Ok(_) => {}
+                Err(e) => return error!("prune failed: {}", e),
+            }
+            let mut conn = match database::stocks_ohlc::open_db() {
+                Ok(conn) => conn,
+                Err(e) => return error!("{}", e),
+            };
+            let before = match database::prune::cutoff(&conn, *keep_days) {
+                Ok(Some(before)) => before,
+                Ok(None) => return info!("no bar older than {} trading days", keep_days),
+                Err(e) => return error!("prune failed: {}", e),
+            };
+            let result = match output {
+                Some(output) => Ok(output.clone()),
+                None => my_file_io::get_archive_file_path(&match format {
+                    ArchiveFormat::Sqlite => "stocks_ohlc_archive.sqlite".to_owned(),
+                    ArchiveFormat::Parquet => {
+                        format!("stocks_ohlc_before_{}.{}", before, format.extension())
+                    }
+                }),
+            }
+            .and_then(|path| {
+                let bars = database::prune::prune(&mut conn, &before, *format, &path)?;
+                Ok((path, bars))
+            });
+            match result {
+                Ok((path, bars)) => {
+                    info!(
+                        "moved {} bars before {} to {}",
+                        bars,
+                        before,
+                        path.display()
+                    )
+                }
+                Err(e) => return error!("prune failed: {}", e),
+            }
+            // deleted rows only free pages, the file shrinks with VACUUM
+            match database::optimize(&conn) {
+                Ok((before, after)) => info!("optimized, {} -> {} bytes", before, after),
+                Err(e) => error!("optimize failed: {}", e),
+            }
+        }
+        Commands::Db {
+            command: Some(DbCommands::Status),
+            ..
+        } => {
+            let retention = config::GdriveJson::new()
+                .map(|config| config.retention().clone())
+                .unwrap_or_default();
+            match database::stocks_ohlc::open_db()
+                .and_then(|conn| database::retention::status(&conn, &retention))
+            {
+                Ok(status) => {
+                    for table in status {
+                        println!("{}", table);
+                    }
+                }
+                Err(e) => error!("status failed: {}", e),
+            }
+        }
+        Commands::Db { optimize: true, .. } => {
+            let mut conn = match database::stocks_ohlc::open_db() {
+                Ok(conn) => conn,
+                Err(e) => return error!("{}", e),
+            };
+            let retention = config::GdriveJson::new()
+                .map(|config| config.retention().clone())
+                .unwrap_or_default();
+            let today = chrono::Local::now().date_naive();
+            match database::retention::apply(&mut conn, &retention, today) {
+                Ok(pruned) => pruned.iter().for_each(|x| info!("{}", x)),
+                Err(e) => return error!("retention failed: {}", e),
+            }
+            match database::optimize(&conn) {
+                Ok((before, after)) => info!("optimized, {} -> {} bytes", before, after),
+                Err(e) => error!("optimize failed: {}", e),
+            }
+        }
         Commands::Db {
             testrun,
             date,
             notify,
+            ..
         } => match testrun {
             // live
             false => {
@@ -318,5 +816,726 @@ async fn main() {
             info!("notion");
             notion::get_notion_data().await.unwrap();
         }
+        Commands::Master => match jquants::fetcher::update_stocks_master(&client).await {
+            Ok(len) => info!("stocks_master has been updated, {} stocks", len),
+            Err(e) => error!("update stocks_master failed: {}", e),
+        },
+        Commands::Statements { code, date } => {
+            let code = match code.as_deref().map(StockCode::new).transpose() {
+                Ok(code) => code,
+                Err(e) => return error!("{}", e),
+            };
+            if code.is_none() && date.is_none() {
+                return error!("code or date is required");
+            }
+            match jquants::fetcher::update_statements(&client, code.as_ref(), date.as_deref()).await
+            {
+                Ok(len) => info!("{} statements have been stored", len),
+                Err(e) => return error!("update statements failed: {}", e),
+            }
+            match jquants::fetcher::update_announcements(&client).await {
+                Ok(len) => info!("{} scheduled results have been stored", len),
+                Err(e) => error!("update announcements failed: {}", e),
+            }
+        }
+        Commands::Dataset { from, to, universe } => {
+            match analysis::dataset::export(universe, from, to).await {
+                Ok((path, len)) => info!("{} rows: {}", len, path.display()),
+                Err(e) => error!("dataset export failed: {}", e),
+            }
+        }
+        Commands::Blacklist { command } => {
+            let conn = match database::blacklist::open_db() {
+                Ok(conn) => conn,
+                Err(e) => return error!("{}", e),
+            };
+            let result = match command {
+                BlacklistCommands::Add {
+                    code,
+                    reason,
+                    until,
+                } => StockCode::new(code).and_then(|code| {
+                    if let Some(until) = until {
+                        check_date(until)?;
+                    }
+                    let entry =
+                        database::blacklist::BlacklistEntry::new(code, reason, until.as_deref());
+                    database::blacklist::add(&conn, &entry)?;
+                    info!("added: {}", entry);
+                    Ok(())
+                }),
+                BlacklistCommands::Remove { code } => StockCode::new(code).and_then(|code| {
+                    match database::blacklist::remove(&conn, &code)? {
+                        true => info!("removed: {}", code),
+                        false => info!("{} is not in the blacklist", code),
+                    }
+                    Ok(())
+                }),
+                BlacklistCommands::List => database::blacklist::select_all(&conn).map(|entries| {
+                    for entry in entries {
+                        info!("{}", entry);
+                    }
+                }),
+            };
+            if let Err(e) = result {
+                error!("blacklist failed: {}", e);
+            }
+        }
+        Commands::Events { command } => {
+            let conn = match database::corporate_events::open_db() {
+                Ok(conn) => conn,
+                Err(e) => return error!("{}", e),
+            };
+            let result = match command {
+                EventsCommands::Add {
+                    code,
+                    kind,
+                    from,
+                    until,
+                    note,
+                } => StockCode::new(code).and_then(|code| {
+                    check_date(from)?;
+                    if let Some(until) = until {
+                        check_date(until)?;
+                        if until < from {
+                            return Err(MyError::Anyhow(anyhow!(
+                                "--until {} is before --from {}",
+                                until,
+                                from
+                            )));
+                        }
+                    }
+                    let event = database::corporate_events::CorporateEvent::new(
+                        code,
+                        *kind,
+                        from,
+                        until.as_deref(),
+                        note,
+                    );
+                    let id = database::corporate_events::insert(&conn, &event)?;
+                    info!("added {}: {}", id, event);
+                    Ok(())
+                }),
+                EventsCommands::Remove { id } => database::corporate_events::remove(&conn, *id)
+                    .map(|removed| match removed {
+                        true => info!("removed: {}", id),
+                        false => info!("no event {}", id),
+                    }),
+                EventsCommands::List => {
+                    database::corporate_events::select_all(&conn).map(|events| {
+                        for event in events {
+                            info!("{}: {}", event.get_id(), event);
+                        }
+                    })
+                }
+            };
+            if let Err(e) = result {
+                error!("events failed: {}", e);
+            }
+        }
+        Commands::Trade { command } => {
+            let mut conn = match database::journal::open_db() {
+                Ok(conn) => conn,
+                Err(e) => return error!("{}", e),
+            };
+            let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+            let result = match command {
+                TradeCommands::Open {
+                    code,
+                    side,
+                    quantity,
+                    price,
+                    date,
+                    stop,
+                    signal,
+                    note,
+                } => Instrument::parse(code).and_then(|instrument| {
+                    if let Some(date) = date {
+                        check_date(date)?;
+                    }
+                    check_positive("--quantity", *quantity)?;
+                    check_positive("--price", *price)?;
+                    if let Some(stop) = stop {
+                        check_positive("--stop", *stop)?;
+                    }
+                    let mut position = database::journal::Position::new(
+                        instrument,
+                        *side,
+                        *quantity,
+                        *price,
+                        date.as_deref().unwrap_or(&today),
+                        *stop,
+                        None,
+                        note,
+                    );
+                    if let Some(signal) = signal {
+                        position = position.with_signal(SignalId::try_from(signal.clone())?);
+                        position.check_signal(&database::stocks_window::open_db()?)?;
+                    }
+                    let id = database::journal::open(&mut conn, &position)?;
+                    info!("opened {}", id);
+                    Ok(())
+                }),
+                TradeCommands::Close { id, price, date } => date
+                    .as_deref()
+                    .map_or(Ok(()), check_date)
+                    .and_then(|_| check_positive("--price", *price))
+                    .and_then(|_| {
+                        database::journal::close(
+                            &mut conn,
+                            *id,
+                            *price,
+                            date.as_deref().unwrap_or(&today),
+                        )
+                    })
+                    .map(|position| info!("closed: {}", position)),
+                TradeCommands::List => database::journal::select_all(&conn).and_then(|positions| {
+                    let windows = database::stocks_window::open_db()?;
+                    for position in positions {
+                        info!("{}", position);
+                        let (Instrument::Stock(code), Some(signal_on)) =
+                            (position.get_instrument(), position.get_signal_on())
+                        else {
+                            continue;
+                        };
+                        let window = match position.get_signal_id() {
+                            Some(signal_id) => {
+                                database::stocks_window::select_by_signal_id(&windows, signal_id)?
+                            }
+                            // recorded with the date only
+                            None => database::stocks_window::select_one(&windows, code, signal_on)?,
+                        };
+                        match window {
+                            Some(window) => {
+                                let [morning, afternoon, allday] = window
+                                    .get_results()
+                                    .map(|x| x.map_or("-".to_owned(), |x| x.to_string()));
+                                info!(
+                                    "  signal {} {}: morning {}, afternoon {}, allday {} ATR",
+                                    signal_on,
+                                    window.get_status(),
+                                    morning,
+                                    afternoon,
+                                    allday
+                                );
+                            }
+                            None => info!("  signal {}: not stored", signal_on),
+                        }
+                    }
+                    Ok(())
+                }),
+            };
+            if let Err(e) = result {
+                error!("trade failed: {}", e);
+            }
+        }
+        Commands::Universe { command } => match command {
+            UniverseCommands::Convert { universe, output } => {
+                let result = universe.load().and_then(|rows| {
+                    let masters =
+                        database::stocks_master::select_all(&database::stocks_master::open_db()?)?;
+                    let rows = my_file_io::convert_universe(rows, &masters);
+                    my_file_io::write_universe(output, &rows)?;
+                    Ok(rows.len())
+                });
+                match result {
+                    Ok(len) => info!("{} stocks: {}", len, output.display()),
+                    Err(e) => error!("universe convert failed: {}", e),
+                }
+            }
+        },
+        Commands::Report { command } => match command {
+            ReportCommands::Diff { old, new } => {
+                let (old, new) = match (ReportSnapshot::load(old), ReportSnapshot::load(new)) {
+                    (Ok(old), Ok(new)) => (old, new),
+                    (Err(e), _) | (_, Err(e)) => return error!("loading report failed: {}", e),
+                };
+                let diff = ReportDiff::new(&old, &new);
+                println!("{}", diff.to_string().trim_end());
+                if !diff.is_empty() {
+                    std::process::exit(1);
+                }
+            }
+            ReportCommands::Snapshot { path } => {
+                match ReportSnapshot::load(path).and_then(|x| x.to_json()) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => error!("loading report failed: {}", e),
+                }
+            }
+        },
+        Commands::Publish { date } => {
+            let lang = Lang::from_config();
+            match draft::publish(date) {
+                Ok(drafts) => {
+                    for draft in &drafts {
+                        info!("published {}", draft.get_target().display());
+                        let Some(report) = draft.to_report() else {
+                            warn!(
+                                "{}: unknown report kind, published to the file only",
+                                draft.get_target().display()
+                            );
+                            continue;
+                        };
+                        if let Err(e) = Dispatcher::for_published(report.get_kind())
+                            .dispatch(&client, &report)
+                            .await
+                        {
+                            error!("publish {} failed: {}", report.get_title(), e);
+                        }
+                    }
+                    line_notify::send_message(
+                        &client,
+                        &format!("{} {}", date, Msg::Published.text(lang)),
+                    )
+                    .await
+                    .unwrap();
+                }
+                Err(e) => error!("publish failed: {}", e),
+            }
+        }
+        Commands::Briefing {
+            date,
+            format,
+            notify,
+        } => {
+            let lang = Lang::from_config();
+            let date = date
+                .clone()
+                .unwrap_or_else(|| chrono::Local::now().format("%Y-%m-%d").to_string());
+            let report = match Briefing::collect(&date).await.to_report(lang) {
+                Ok(report) => report,
+                Err(e) => return error!("briefing failed: {}", e),
+            };
+            info!("\n{}", report.message());
+            let mut dispatcher = Dispatcher::for_kind(BRIEFING, *format, false);
+            if *notify {
+                dispatcher = dispatcher.with(Box::new(NotifySink));
+            }
+            if let Err(e) = dispatcher.dispatch(&client, &report).await {
+                error!("dispatch failed: {}", e);
+            }
+        }
+        Commands::Serve { port } => {
+            if let Err(e) = bot::serve(&client, *port).await {
+                error!("serve failed: {}", e);
+            }
+        }
+    }
+}
+
+/// The nextday job: fetch, windows, reports. Its failures are notified here and
+/// don't stop the other jobs of the run, see `pipeline::Step` for the steps that
+/// may fail without stopping it
+async fn run_nextday(client: &Client, args: &MyArgs, lang: Lang) {
+    line_notify::send_message(client, Msg::NextdayStarted.text(lang))
+        .await
+        .unwrap();
+    let mut pipeline = Pipeline::new("nextday");
+
+    match pipeline.run(
+        Step::FetchDaily,
+        jquants::fetcher::fetch_nikkei225_db(client, &args.universe, args.force).await,
+    ) {
+        Ok(Some(_)) => {
+            info!("fetch_nikkei225 success");
+        }
+        Ok(None) => {}
+        Err(e) => {
+            error!("fetch_nikkei225 failed: {}", e);
+            line_notify::send_message(client, &failure_text(lang, Msg::Failed, &e))
+                .await
+                .unwrap();
+            return;
+        }
+    };
+
+    match pipeline.run(
+        Step::LatestData,
+        jquants::fetcher::check_latest_data(client).await,
+    ) {
+        Ok(Some(latest)) => info!("latest data {}", latest),
+        Ok(None) => {}
+        Err(e) => {
+            error!("check_latest_data failed: {}", e);
+            let msg = match e {
+                MyError::NotLatestData { .. } => Msg::NotLatestData,
+                _ => Msg::Failed,
+            };
+            line_notify::send_message(client, &failure_text(lang, msg, &e))
+                .await
+                .unwrap();
+            return;
+        }
+    };
+
+    match pipeline.run(
+        Step::Coverage,
+        jquants::fetcher::check_nikkei225_coverage(&args.universe),
+    ) {
+        Ok(Some(coverage)) => info!("coverage {}", coverage),
+        Ok(None) => {}
+        Err(e) => {
+            error!("check_nikkei225_coverage failed: {}", e);
+            line_notify::send_message(
+                client,
+                &format!("{}\n{}", Msg::InsufficientCoverage.text(lang), e),
+            )
+            .await
+            .unwrap();
+            return;
+        }
+    };
+
+    // weekly, published on Thursdays; the report shows the latest stored
+    if let Err(e) = pipeline.run(
+        Step::TradesSpec,
+        jquants::fetcher::update_trades_spec(client).await,
+    ) {
+        line_notify::send_message(client, &failure_text(lang, Msg::Failed, &e))
+            .await
+            .unwrap();
+        return;
+    }
+
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    // a warning only, the report still goes out
+    match pipeline.run(
+        Step::Drift,
+        analysis::drift::check(&args.universe, &today).await,
+    ) {
+        Ok(Some(alerts)) if !alerts.is_empty() => {
+            let alerts = alerts.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            warn!("feature drift: {}", alerts.join(", "));
+            line_notify::send_message(
+                client,
+                &format!("{}\n{}", Msg::FeatureDrift.text(lang), alerts.join("\n")),
+            )
+            .await
+            .unwrap();
+        }
+        Ok(_) => {}
+        Err(e) => {
+            line_notify::send_message(client, &failure_text(lang, Msg::Failed, &e))
+                .await
+                .unwrap();
+            return;
+        }
+    }
+
+    // the relative strength of the windows, from the stored bars when it fails
+    match pipeline.run(
+        Step::Topix,
+        jquants::fetcher::update_topix_ohlc(client).await,
+    ) {
+        Ok(Some(len)) => info!("topix_ohlc has been updated, {} days", len),
+        Ok(None) => {}
+        Err(e) => {
+            line_notify::send_message(client, &failure_text(lang, Msg::Failed, &e))
+                .await
+                .unwrap();
+            return;
+        }
+    }
+    let day_before_5 = chrono::Local::now()
+        .checked_sub_signed(chrono::Duration::days(5))
+        .unwrap()
+        .format("%Y-%m-%d")
+        .to_string();
+
+    let mut stocks_window_list = match analysis::stocks_window::create_stocks_window_list_db(
+        &args.universe,
+        &day_before_5,
+        &today,
+    )
+    .await
+    {
+        Ok(output) => output,
+        Err(e) => {
+            error!("create_stocks_window_list_db failed: {}", e);
+            line_notify::send_message(
+                client,
+                &format!("create_stocks_window_list_db {}", Msg::Failed.text(lang)),
+            )
+            .await
+            .unwrap();
+            return;
+        }
+    };
+    // kept for querying the results later, the reports don't depend on it
+    match stocks_window_list.save() {
+        Ok(stored) => info!("{} windows stored", stored),
+        Err(e) => warn!("stocks_window save failed: {}", e),
+    }
+
+    if let Some(min_short_ratio) = args.min_short_ratio {
+        // the ratios stored by earlier runs when the update fails
+        let ratios = match pipeline
+            .run(
+                Step::ShortSelling,
+                jquants::fetcher::update_short_selling(client, &day_before_5, &today).await,
+            )
+            .and_then(|_| {
+                let conn = database::short_selling::open_db()?;
+                database::short_selling::select_ratios(&conn, &day_before_5, &today)
+            }) {
+            Ok(ratios) => ratios,
+            Err(e) => {
+                error!("short selling unavailable: {}", e);
+                line_notify::send_message(client, &failure_text(lang, Msg::Failed, &e))
+                    .await
+                    .unwrap();
+                return;
+            }
+        };
+        stocks_window_list.filter_by_short_ratio(&ratios, min_short_ratio);
+        info!("short ratio >= {}%", min_short_ratio);
+    }
+    filter_by_args(&mut stocks_window_list, args);
+
+    let reports = match args.draft {
+        true => stocks_window_list.for_resistance_strategy_draft(),
+        false => stocks_window_list.for_resistance_strategy_default(),
+    };
+    let mut reports = match reports {
+        Ok(reports) => reports,
+        Err(e) => {
+            error!("for_resistance_strategy failed: {}", e);
+            line_notify::send_message(
+                client,
+                &format!("for_resistance_strategy {}", Msg::Failed.text(lang)),
+            )
+            .await
+            .unwrap();
+            return;
+        }
+    };
+
+    match stocks_window_list.for_resistance_strategy(true) {
+        Ok(consolidating) => reports.extend(consolidating),
+        Err(e) => {
+            error!("for_resistance_consolidating_strategy failed: {}", e);
+            line_notify::send_message(
+                client,
+                &format!(
+                    "for_resistance_consolidating_strategy {}",
+                    Msg::Failed.text(lang)
+                ),
+            )
+            .await
+            .unwrap();
+            return;
+        }
+    };
+
+    if let Err(e) = output_sink::dispatch_all(client, &reports, args.format, args.draft).await {
+        error!("dispatch failed: {}", e);
+        line_notify::send_message(client, &failure_text(lang, Msg::Failed, &e))
+            .await
+            .unwrap();
+        return;
+    }
+
+    line_notify::send_message(client, &pipeline.summary(lang, Msg::NextdaySucceeded))
+        .await
+        .unwrap();
+    if args.draft {
+        notify_drafts(client, lang).await;
+    }
+}
+
+/// The nextday filters of the flags, but --min-short-ratio
+fn filter_by_args(
+    stocks_window_list: &mut analysis::stocks_window::StocksWindowList,
+    args: &MyArgs,
+) {
+    if let Some(macd_cross) = args.macd_cross {
+        stocks_window_list.filter_by_macd_cross(macd_cross);
+        info!("MACD cross {:?}", macd_cross);
+    }
+    if args.above_ma20 {
+        stocks_window_list.filter_by_above_ma20();
+        info!("above the 20-day MA");
+    }
+    if args.ma20_rising {
+        stocks_window_list.filter_by_ma20_rising();
+        info!("20-day MA rising");
+    }
+    if let Some(ma_cross) = args.ma_cross {
+        stocks_window_list.filter_by_ma_cross(ma_cross);
+        info!("20/60-day MA cross {:?}", ma_cross);
+    }
+    if let Some(weekly_trend) = args.weekly_trend {
+        stocks_window_list.filter_by_weekly_trend(weekly_trend);
+        info!("weekly trend {}", weekly_trend);
+    }
+}
+
+/// Rejects a date argument that isn't YYYY-MM-DD
+fn check_date(date: &str) -> Result<(), MyError> {
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|_| ())
+        .map_err(|e| MyError::Anyhow(anyhow!("{}: {}", date, e)))
+}
+
+/// Rejects a quantity or price that isn't above zero
+fn check_positive(name: &str, value: f64) -> Result<(), MyError> {
+    match value > 0.0 {
+        true => Ok(()),
+        false => Err(MyError::Anyhow(anyhow!(
+            "{} must be positive: {}",
+            name,
+            value
+        ))),
+    }
+}
+
+/// `stocks asof`, the reports go to files only
+async fn run_as_of(args: &MyArgs, date: &str) -> Result<(), MyError> {
+    check_date(date)?;
+    let mut stocks_window_list =
+        analysis::stocks_window::create_stocks_window_list_as_of(&args.universe, date).await?;
+    if let Some(min_short_ratio) = args.min_short_ratio {
+        let conn = database::short_selling::open_db()?;
+        let ratios = database::short_selling::select_ratios(&conn, date, date)?;
+        stocks_window_list.filter_by_short_ratio(&ratios, min_short_ratio);
+        info!("short ratio >= {}%", min_short_ratio);
+    }
+    filter_by_args(&mut stocks_window_list, args);
+
+    for report in stocks_window_list.as_of_reports()? {
+        let path = my_file_io::get_as_of_file_path(date, report.get_kind().get_name())?;
+        report.get_markdown().write(&path, args.format)?;
+        info!("{}\n{}", path.display(), report.message());
+    }
+    Ok(())
+}
+
+/// The afternoon job on the morning session, its failures are notified here
+async fn run_afternoon(client: &Client, args: &MyArgs, lang: Lang) {
+    line_notify::send_message(client, Msg::AfternoonStarted.text(lang))
+        .await
+        .unwrap();
+    let mut pipeline = Pipeline::new("afternoon");
+
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let prices_am = match pipeline
+        .run(
+            Step::PricesAm,
+            jquants::fetcher::PricesAm::new(client, true).await,
+        )
+        .and_then(|prices_am| match prices_am {
+            Some(prices_am) => Ok(prices_am),
+            // stored by an earlier run, `from_nikkei225` still checks its age
+            None => jquants::fetcher::PricesAm::from_db(&today)?
+                .ok_or_else(|| MyError::Anyhow(anyhow!("no morning session stored"))),
+        }) {
+        Ok(prices_am) => prices_am,
+        Err(e) => {
+            error!("fetch morning market failed: {}", e);
+
+            line_notify::send_message(client, &failure_text(lang, Msg::FetchMorningFailed, &e))
+                .await
+                .unwrap();
+            return;
+        }
+    };
+
+    let mut stocks_afternoon_list =
+        match analysis::stocks_afternoon::StocksAfternoonList::from_nikkei225(
+            &prices_am,
+            &args.universe,
+        ) {
+            Ok(output) => output,
+            Err(e @ MyError::InsufficientCoverage { .. }) => {
+                error!("StocksAfternoonList::from_nikkei225_db failed: {}", e);
+                line_notify::send_message(
+                    client,
+                    &format!("{}\n{}", Msg::InsufficientCoverage.text(lang), e),
+                )
+                .await
+                .unwrap();
+                return;
+            }
+            Err(e @ MyError::StaleData { .. }) => {
+                error!("StocksAfternoonList::from_nikkei225_db failed: {}", e);
+                line_notify::send_message(
+                    client,
+                    &format!("{}\n{}", Msg::MorningDataStale.text(lang), e),
+                )
+                .await
+                .unwrap();
+                return;
+            }
+            Err(e) => {
+                error!("StocksAfternoonList::from_nikkei225_db failed: {}", e);
+                line_notify::send_message(
+                    client,
+                    &format!(
+                        "StocksAfternoonList::from_nikkei225_db {}",
+                        Msg::Failed.text(lang)
+                    ),
+                )
+                .await
+                .unwrap();
+                return;
+            }
+        };
+
+    let reports = match stocks_afternoon_list
+        .for_resistance_strategy_default()
+        .and_then(|report| {
+            Ok(vec![
+                report,
+                stocks_afternoon_list.for_resistance_strategy(true)?,
+            ])
+        }) {
+        Ok(reports) => reports,
+        Err(e) => {
+            error!("for_afternoon_strategy failed: {}", e);
+            line_notify::send_message(
+                client,
+                &format!("for_afternoon_strategy {}", Msg::Failed.text(lang)),
+            )
+            .await
+            .unwrap();
+            return;
+        }
+    };
+
+    if let Err(e) = output_sink::dispatch_all(client, &reports, args.format, args.draft).await {
+        error!("dispatch failed: {}", e);
+        line_notify::send_message(client, &failure_text(lang, Msg::Failed, &e))
+            .await
+            .unwrap();
+        return;
+    }
+
+    line_notify::send_message(client, &pipeline.summary(lang, Msg::AfternoonSucceeded))
+        .await
+        .unwrap();
+    if args.draft {
+        notify_drafts(client, lang).await;
+    }
+}
+
+/// Sends a summary of each pending date with the command to approve it
+/// `msg` and the error, with a hint when a rerun may get through
+fn failure_text(lang: Lang, msg: Msg, e: &MyError) -> String {
+    match e.is_retryable() {
+        true => format!("{}\n{}\n{}", msg.text(lang), e, Msg::Retryable.text(lang)),
+        false => format!("{}\n{}", msg.text(lang), e),
+    }
+}
+
+async fn notify_drafts(client: &Client, lang: Lang) {
+    let pending = match draft::pending() {
+        Ok(pending) => pending,
+        Err(e) => return error!("loading drafts failed: {}", e),
+    };
+    for (date, drafts) in pending {
+        match draft::summary(&date, &drafts, lang) {
+            Ok(summary) => line_notify::send_message(client, &summary).await.unwrap(),
+            Err(e) => error!("draft summary failed: {}", e),
+        }
     }
 }
@@ -1,15 +1,148 @@
-use pulldown_cmark::{html, Event, Options, Parser};
+use anyhow::anyhow;
+use pulldown_cmark::{html, Event, Options};
+use std::fmt::{Display, Formatter};
+use std::io::Write as _;
+use std::process::{Command, Stdio};
 use std::{fmt::Write, path::Path};
 
 use crate::my_error::MyError;
+use crate::profile::{self, Stage};
 
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReportFormat {
+    #[default]
+    Html,
+    /// rendered with `weasyprint`, which must be on PATH
+    Pdf,
+}
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Html => "html",
+            ReportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+// A4ページ分割用
+const PRINT_STYLE: &str = "<style>\n\
+@page { size: A4; margin: 15mm; @bottom-center { content: counter(page) \" / \" counter(pages); } }\n\
+body { font-family: sans-serif; font-size: 10pt; }\n\
+h2, h3 { page-break-after: avoid; }\n\
+p { page-break-inside: avoid; }\n\
+</style>\n";
+
+#[derive(Debug, Default)]
 pub struct Markdown {
     buffer: String,
+    /// heading level of the enclosing `section`, 0 at the top
+    depth: usize,
+}
+/// Rows can be written straight into the buffer with `write!`
+impl Write for Markdown {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.buffer.push_str(s);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+impl Align {
+    fn delimiter(&self) -> &'static str {
+        match self {
+            Align::Left => " :--- ",
+            Align::Center => " :---: ",
+            Align::Right => " ---: ",
+        }
+    }
+}
+
+/// Header and alignment of a `Markdown::table` column
+#[derive(Debug, Clone, Copy)]
+pub struct Column<'a> {
+    header: &'a str,
+    align: Align,
+}
+impl<'a> Column<'a> {
+    pub fn left(header: &'a str) -> Self {
+        Column {
+            header,
+            align: Align::Left,
+        }
+    }
+    pub fn center(header: &'a str) -> Self {
+        Column {
+            header,
+            align: Align::Center,
+        }
+    }
+    /// for numbers
+    pub fn right(header: &'a str) -> Self {
+        Column {
+            header,
+            align: Align::Right,
+        }
+    }
+}
+
+/// Escapes `|` while writing a table cell
+struct EscapePipes<'a>(&'a mut String);
+impl Write for EscapePipes<'_> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        for (i, part) in s.split('|').enumerate() {
+            if i > 0 {
+                self.0.push_str("\\|");
+            }
+            self.0.push_str(part);
+        }
+        Ok(())
+    }
+}
+
+/// `[text](url)`, formatted without allocating
+pub fn link<'a>(text: &'a str, url: &'a str) -> Link<'a> {
+    Link { text, url }
+}
+pub struct Link<'a> {
+    text: &'a str,
+    url: &'a str,
+}
+impl Display for Link<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}]({})",
+            self.text.replace('[', "\\[").replace(']', "\\]"),
+            self.url.replace(' ', "%20")
+        )
+    }
+}
+
+/// Unit column, marked when a single board lot risks more than the budget
+pub fn unit_text(unit: i32, over_budget: bool) -> String {
+    match over_budget {
+        true => format!("{}⚠", unit),
+        false => unit.to_string(),
+    }
+}
+
+/// First 4 characters when the name is longer than 5, without allocating
+pub fn short_name(name: &str) -> &str {
+    match name.char_indices().nth(5) {
+        Some(_) => &name[..name.char_indices().nth(4).map_or(name.len(), |(i, _)| i)],
+        None => name,
+    }
 }
 impl Markdown {
     pub fn new() -> Self {
         Markdown {
             buffer: String::new(),
+            depth: 0,
         }
     }
     pub fn h1(&mut self, text: &str) -> Result<(), MyError> {
@@ -29,9 +162,68 @@ impl Markdown {
         Ok(())
     }
 
-    // pub fn append(&mut self, markdown: Markdown) {
-    //     self.buffer.push_str(&markdown.buffer);
-    // }
+    /// Heading one level below the enclosing section, followed by what `f` writes.
+    /// Sections nest, so a report reads top-down instead of picking h1..h3 by hand.
+    pub fn section<F>(&mut self, title: &str, f: F) -> Result<(), MyError>
+    where
+        F: FnOnce(&mut Markdown) -> Result<(), MyError>,
+    {
+        let level = (self.depth + 1).min(6);
+        writeln!(&mut self.buffer, "{} {}", "#".repeat(level), title)?;
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Horizontal rule, after a blank line so the previous line isn't read as a heading
+    pub fn hr(&mut self) -> Result<(), MyError> {
+        writeln!(&mut self.buffer, "\n---")?;
+        Ok(())
+    }
+
+    /// Table with a header row, `|` in headers and cells is escaped.
+    /// Rows shorter than `columns` are rendered with empty cells.
+    pub fn table<R, C>(&mut self, columns: &[Column], rows: R) -> Result<(), MyError>
+    where
+        R: IntoIterator,
+        R::Item: IntoIterator<Item = C>,
+        C: Display,
+    {
+        writeln!(&mut self.buffer)?;
+        self.buffer.push('|');
+        for column in columns {
+            write!(EscapePipes(&mut self.buffer), " {} ", column.header)?;
+            self.buffer.push('|');
+        }
+        self.buffer.push('\n');
+        self.buffer.push('|');
+        for column in columns {
+            self.buffer.push_str(column.align.delimiter());
+            self.buffer.push('|');
+        }
+        self.buffer.push('\n');
+        for row in rows {
+            self.buffer.push('|');
+            for cell in row {
+                write!(EscapePipes(&mut self.buffer), " {} ", cell)?;
+                self.buffer.push('|');
+            }
+            self.buffer.push('\n');
+        }
+        writeln!(&mut self.buffer)?;
+        Ok(())
+    }
+
+    /// Appends another report as is, e.g. sections built separately
+    pub fn append(&mut self, markdown: Markdown) {
+        self.buffer.push_str(&markdown.buffer);
+    }
+
+    /// Reserves room for `additional` bytes before writing rows
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.reserve(additional);
+    }
 
     pub fn buffer(&self) -> &str {
         &self.buffer
@@ -50,7 +242,19 @@ impl Markdown {
     //     Ok(())
     // }
 
+    pub fn to_html(&self) -> String {
+        let parser = pulldown_cmark::Parser::new_ext(&self.buffer, Options::ENABLE_TABLES);
+        let parser = parser.map(|event| match event {
+            Event::SoftBreak => Event::HardBreak,
+            _ => event,
+        });
+        let mut html_output = String::new();
+        html::push_html(&mut html_output, parser);
+        html_output
+    }
+
     pub fn write_to_html(&self, path: &Path) -> Result<(), MyError> {
+        let _span = profile::span(Stage::Render);
         // create parent directory if not exists
         if let Some(parent) = path.parent() {
             if !parent.exists() {
@@ -59,14 +263,122 @@ impl Markdown {
         }
 
         let path_with_extension = path.with_extension("html");
-        let mut parser = pulldown_cmark::Parser::new(&self.buffer);
-        let parser = parser.map(|event| match event {
-            Event::SoftBreak => Event::HardBreak,
-            _ => event,
-        });
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
-        std::fs::write(path_with_extension, html_output)?;
+        std::fs::write(path_with_extension, self.to_html())?;
         Ok(())
     }
+
+    pub fn write_to_pdf(&self, path: &Path) -> Result<(), MyError> {
+        let _span = profile::span(Stage::Render);
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+
+        let path_with_extension = path.with_extension("pdf");
+        let mut child = Command::new("weasyprint")
+            .arg("--encoding")
+            .arg("utf-8")
+            .arg("-")
+            .arg(&path_with_extension)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(PRINT_STYLE.as_bytes())?;
+            stdin.write_all(self.to_html().as_bytes())?;
+        }
+
+        let output = child.wait_with_output()?;
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(MyError::Anyhow(anyhow!(
+                "weasyprint failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ))),
+        }
+    }
+
+    pub fn write(&self, path: &Path, format: ReportFormat) -> Result<(), MyError> {
+        match format {
+            ReportFormat::Html => self.write_to_html(path),
+            ReportFormat::Pdf => self.write_to_pdf(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(short_name("トヨタ自動車"), "トヨタ自");
+        assert_eq!(short_name("ソニーG"), "ソニーG");
+        assert_eq!(short_name("ファーストリテイリング"), "ファース");
+    }
+
+    #[test]
+    fn test_section() {
+        let mut markdown = Markdown::new();
+        markdown
+            .section("2024-01-05", |m| {
+                m.section("Nextday", |m| {
+                    m.section("Summary", |m| m.body("Number of Stocks: 20"))
+                })?;
+                m.section("Note", |_| Ok(()))
+            })
+            .unwrap();
+        markdown.body("end").unwrap();
+        assert_eq!(
+            markdown.buffer(),
+            "# 2024-01-05\n## Nextday\n### Summary\nNumber of Stocks: 20\n## Note\nend\n"
+        );
+    }
+
+    #[test]
+    fn test_append_and_hr() {
+        let mut markdown = Markdown::new();
+        markdown.body("a").unwrap();
+        markdown.hr().unwrap();
+        let mut other = Markdown::new();
+        other.body("b").unwrap();
+        markdown.append(other);
+        assert_eq!(markdown.buffer(), "a\n\n---\nb\n");
+        assert!(markdown.to_html().contains("<hr />"));
+        assert!(!markdown.to_html().contains("<h2>"));
+    }
+
+    #[test]
+    fn test_table() {
+        let mut markdown = Markdown::new();
+        markdown.body("rows").unwrap();
+        markdown
+            .table(
+                &[
+                    Column::left("Code"),
+                    Column::center("Name"),
+                    Column::right("ATR"),
+                ],
+                [["7203", "トヨタ", "1.5"], ["6758", "A|B", "12"]],
+            )
+            .unwrap();
+        assert_eq!(
+            markdown.buffer(),
+            "rows\n\n| Code | Name | ATR |\n| :--- | :---: | ---: |\n\
+            | 7203 | トヨタ | 1.5 |\n| 6758 | A\\|B | 12 |\n\n"
+        );
+        let html = markdown.to_html();
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<td style=\"text-align: center\">A|B</td>"));
+        assert!(html.contains("<td style=\"text-align: right\">12</td>"));
+    }
+
+    #[test]
+    fn test_link() {
+        assert_eq!(
+            link("7203 [ETF]", "../codes/7203 a.html").to_string(),
+            "[7203 \\[ETF\\]](../codes/7203%20a.html)"
+        );
+    }
 }
@@ -1,3 +1,6 @@
+use reqwest::StatusCode;
+use std::fmt::Display;
+
 #[derive(thiserror::Error, Debug)]
 pub enum MyError {
     #[error("401 Unauthorized {0}")]
@@ -6,10 +9,69 @@ pub enum MyError {
     RefreshTokenExpired,
     #[error("It is holiday")]
     Holiday,
-    // #[error("Not Latest Data")]
-    // NotLatestData,
+    /// stocks_ohlc ends before the last trading day, see `fetcher::check_latest_data`
+    #[error(
+        "stocks_ohlc ends on {latest}, missing {}; rerun `stocks --nextday` once J-Quants has published them",
+        .missing.join(", ")
+    )]
+    NotLatestData {
+        latest: String,
+        missing: Vec<String>,
+    },
+    /// Data too old to be treated as live, e.g. the morning session re-read after the close
+    #[error("{what} is {age_minutes} minutes old, over {max_minutes}")]
+    StaleData {
+        what: &'static str,
+        age_minutes: i64,
+        max_minutes: i64,
+    },
     #[error("out of range for slice of length")]
     OutOfRange,
+    /// high == low over the bars a value is divided by
+    #[error("zero price range: {0}")]
+    ZeroRange(&'static str),
+    #[error("invalid stock code: {0}")]
+    InvalidStockCode(String),
+    #[error("coverage of {date} is {percent:.1}%, below {min_coverage}%")]
+    InsufficientCoverage {
+        date: String,
+        percent: f64,
+        min_coverage: f64,
+    },
+    /// Non-200 response, or `status` != 0 in the body (e.g. 5 during maintenance)
+    #[error("GMO Coin API error, status {status}: {message}")]
+    GmoApi { status: u16, message: String },
+    #[error("invalid kline {field}: {value}")]
+    InvalidKline { field: &'static str, value: String },
+    /// A request without a response, `retryable` for timeouts, failed connections and
+    /// 429s and 5xxs of `error_for_status`
+    #[error("network error: {source}")]
+    Network {
+        source: reqwest::Error,
+        retryable: bool,
+    },
+    /// An unexpected status from `endpoint`, see [`MyError::api`]
+    #[error("{endpoint} returned {status}: {body}")]
+    Api {
+        endpoint: String,
+        status: u16,
+        body: String,
+    },
+    /// No data for a code on a date it should have some
+    #[error("no data for {code} on {date}")]
+    DataGap { code: String, date: String },
+    /// Another machine writes trading23.sqlite, see `database::lease`
+    #[error("trading23.sqlite is leased to {0}")]
+    Leased(String),
+    /// config.json or a file it points to is missing or invalid
+    #[error("config: {0}")]
+    Config(String),
+    /// `source` with what was being done, see [`ResultExt`]
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        source: Box<MyError>,
+    },
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -17,13 +79,123 @@ pub enum MyError {
     #[error(transparent)]
     VarError(#[from] std::env::VarError),
     #[error(transparent)]
-    Reqwest(#[from] reqwest::Error),
-    #[error(transparent)]
     Serde(#[from] serde_json::Error),
     #[error(transparent)]
     Rusqlite(#[from] rusqlite::Error),
     #[error(transparent)]
     Csv(#[from] csv::Error),
     #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error(transparent)]
     Anyhow(#[from] anyhow::Error),
 }
+
+impl From<reqwest::Error> for MyError {
+    fn from(e: reqwest::Error) -> Self {
+        let retryable = e.is_timeout()
+            || e.is_connect()
+            || e.status()
+                .is_some_and(|x| x == StatusCode::TOO_MANY_REQUESTS || x.is_server_error());
+        MyError::Network {
+            source: e,
+            retryable,
+        }
+    }
+}
+
+impl MyError {
+    pub fn api(endpoint: impl Into<String>, status: StatusCode, body: impl Into<String>) -> Self {
+        MyError::Api {
+            endpoint: endpoint.into(),
+            status: status.as_u16(),
+            body: body.into(),
+        }
+    }
+
+    pub fn context(self, context: impl Display) -> Self {
+        MyError::Context {
+            context: context.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The error under the contexts
+    pub fn root(&self) -> &MyError {
+        match self {
+            MyError::Context { source, .. } => source.root(),
+            _ => self,
+        }
+    }
+
+    /// Worth another try: network errors, rate limits, 5xx and maintenance
+    pub fn is_retryable(&self) -> bool {
+        match self.root() {
+            MyError::Network { retryable, .. } => *retryable,
+            MyError::Api { status, .. } => *status == 429 || *status >= 500,
+            MyError::GmoApi { status, message } => {
+                *status == 429 || *status >= 500 || message.starts_with("status 5,")
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Attaches what was being done to the error of a `Result`
+pub trait ResultExt<T> {
+    fn context(self, context: impl Display) -> Result<T, MyError>;
+    fn with_context<C: Display>(self, f: impl FnOnce() -> C) -> Result<T, MyError>;
+}
+
+impl<T, E: Into<MyError>> ResultExt<T> for Result<T, E> {
+    fn context(self, context: impl Display) -> Result<T, MyError> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<C: Display>(self, f: impl FnOnce() -> C) -> Result<T, MyError> {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_and_retryable() {
+        let e = MyError::api(
+            "/prices/daily_quotes",
+            StatusCode::SERVICE_UNAVAILABLE,
+            "busy",
+        );
+        assert!(e.is_retryable());
+        assert_eq!(e.to_string(), "/prices/daily_quotes returned 503: busy");
+
+        let result: Result<(), MyError> = Err(e);
+        let e = result.context("fetch 2024-01-05").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "fetch 2024-01-05: /prices/daily_quotes returned 503: busy"
+        );
+        assert!(e.is_retryable());
+        assert!(matches!(e.root(), MyError::Api { status: 503, .. }));
+
+        let e = MyError::api("/listed/info", StatusCode::FORBIDDEN, "").context("listed info");
+        assert!(!e.is_retryable());
+        assert!(!MyError::DataGap {
+            code: "7203".to_owned(),
+            date: "2024-01-05".to_owned()
+        }
+        .is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_request_error_is_not_retryable() {
+        // a URL without a host fails before anything is sent
+        let e = reqwest::Client::new()
+            .get("http://")
+            .send()
+            .await
+            .unwrap_err();
+        assert!(!MyError::from(e).is_retryable());
+    }
+}
@@ -1,46 +1,221 @@
+use crate::database::stocks_master::StockMaster;
 use crate::my_error::MyError;
+use crate::stock_code::StockCode;
 use anyhow::{anyhow, Result};
-use chrono::Datelike;
+use chrono::NaiveDate;
 use log::debug;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Deserialize, Serialize, Debug)]
+/// A row of a universe CSV. v1 is code,name[,category], v2 adds the 33-sector code,
+/// the market segment and the listing date (`trading23 universe convert`).
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Nikkei225 {
-    code: String,
+    code: StockCode,
     name: String,
+    #[serde(default)]
     category: String,
+    /// e.g. "3700", as in stocks_master
+    #[serde(default)]
+    sector33_code: Option<String>,
+    /// e.g. "プライム"
+    #[serde(default)]
+    market: Option<String>,
+    /// "YYYY-MM-DD", no bar is expected before it
+    #[serde(default)]
+    listed_on: Option<String>,
 }
 
 impl Nikkei225 {
+    /// false before the listing date, true when it is unknown
+    pub fn is_listed_on(&self, date: &str) -> bool {
+        self.listed_on.as_deref().is_none_or(|x| x <= date)
+    }
+
     //getter
-    pub fn get_code(&self) -> &str {
+    pub fn get_code(&self) -> &StockCode {
         &self.code
     }
     pub fn get_name(&self) -> &str {
         &self.name
     }
+    pub fn get_sector33_code(&self) -> Option<&str> {
+        self.sector33_code.as_deref()
+    }
+    pub fn get_market(&self) -> Option<&str> {
+        self.market.as_deref()
+    }
+    pub fn get_listed_on(&self) -> Option<&str> {
+        self.listed_on.as_deref()
+    }
 }
 
-pub fn load_nikkei225_list() -> Result<Vec<Nikkei225>, MyError> {
-    let gdrive_path = std::env::var("GDRIVE_PATH")?;
-    let nikkei225_path = Path::new(&gdrive_path)
-        .join("trading23")
-        .join("nikkei225_lists")
-        .join("20231002.csv");
-    let mut rdr = csv::Reader::from_path(nikkei225_path)?;
-    let mut nikkei225_vec = Vec::new();
+/// Stocks the fetch and the screenings cover, see `--universe`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Universe {
+    #[default]
+    Nikkei225,
+    Topix500,
+    /// Any CSV with code and name columns
+    Csv(PathBuf),
+}
+
+impl FromStr for Universe {
+    type Err = MyError;
+
+    /// "nikkei225", "topix500" or the path of a CSV
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nikkei225" => Ok(Universe::Nikkei225),
+            "topix500" => Ok(Universe::Topix500),
+            _ if s.ends_with(".csv") => Ok(Universe::Csv(PathBuf::from(s))),
+            _ => Err(MyError::Anyhow(anyhow!(
+                "unknown universe {}, nikkei225, topix500 or a .csv path",
+                s
+            ))),
+        }
+    }
+}
+
+impl Display for Universe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Universe::Nikkei225 => write!(f, "Nikkei225"),
+            Universe::Topix500 => write!(f, "TOPIX500"),
+            Universe::Csv(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl Universe {
+    fn path(&self) -> Result<PathBuf, MyError> {
+        let gdrive_path = std::env::var("GDRIVE_PATH")?;
+        let trading23 = Path::new(&gdrive_path).join("trading23");
+        Ok(match self {
+            Universe::Nikkei225 => trading23.join("nikkei225_lists").join("20231002.csv"),
+            Universe::Topix500 => trading23.join("topix500_lists").join("topix500.csv"),
+            Universe::Csv(path) => path.clone(),
+        })
+    }
+
+    /// UTF-8 or Shift_JIS (exchange downloads), see `parse_universe`
+    pub fn load(&self) -> Result<Vec<Nikkei225>, MyError> {
+        let path = self.path()?;
+        let rows = decode_csv(&std::fs::read(&path)?)
+            .and_then(|text| parse_universe(&text))
+            .map_err(|e| MyError::Config(format!("{}: {}", path.display(), e)))?;
+        debug!("{:?}", rows);
+        Ok(rows)
+    }
+}
+
+/// The text of a CSV in UTF-8 (with or without BOM) or Shift_JIS
+fn decode_csv(bytes: &[u8]) -> Result<String, MyError> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok(text.to_owned());
+    }
+    let (text, had_errors) = encoding_rs::SHIFT_JIS.decode_without_bom_handling(bytes);
+    match had_errors {
+        true => Err(MyError::Anyhow(anyhow!("neither UTF-8 nor Shift_JIS"))),
+        false => Ok(text.into_owned()),
+    }
+}
+
+/// Header of a universe CSV, Japanese headers of exchange downloads included
+fn normalize_header(header: &str) -> &str {
+    match header.trim() {
+        "コード" | "銘柄コード" => "code",
+        "銘柄名" | "会社名" => "name",
+        "業種" | "区分" => "category",
+        "33業種コード" => "sector33_code",
+        "市場・商品区分" | "市場区分" => "market",
+        "上場日" => "listed_on",
+        x => x,
+    }
+}
+
+/// Rows of a universe CSV, which needs code and name columns and each code once
+fn parse_universe(text: &str) -> Result<Vec<Nikkei225>, MyError> {
+    let mut rdr = csv::Reader::from_reader(text.as_bytes());
+    let headers = rdr
+        .headers()?
+        .iter()
+        .map(|x| normalize_header(x).to_lowercase())
+        .collect::<csv::StringRecord>();
+    for required in ["code", "name"] {
+        if !headers.iter().any(|x| x == required) {
+            return Err(MyError::Anyhow(anyhow!(
+                "no {} column in {:?}",
+                required,
+                headers.iter().collect::<Vec<_>>()
+            )));
+        }
+    }
+    rdr.set_headers(headers);
+
+    let mut rows: Vec<Nikkei225> = Vec::new();
+    let mut duplicates = Vec::new();
     for result in rdr.deserialize() {
-        let nikkei225 = result.map_err(|e| MyError::Anyhow(anyhow!(e.to_string())))?;
+        let row: Nikkei225 = result.map_err(|e| MyError::Anyhow(anyhow!(e.to_string())))?;
+        if let Some(listed_on) = &row.listed_on {
+            NaiveDate::parse_from_str(listed_on, "%Y-%m-%d").map_err(|_| {
+                MyError::Anyhow(anyhow!("{}: invalid listed_on {}", row.code, listed_on))
+            })?;
+        }
+        if rows.iter().any(|x| x.code == row.code) {
+            duplicates.push(row.code.to_string());
+        }
+        rows.push(row);
+    }
+    match duplicates.is_empty() {
+        true => Ok(rows),
+        false => Err(MyError::Anyhow(anyhow!(
+            "duplicate codes {}",
+            duplicates.join(", ")
+        ))),
+    }
+}
 
-        nikkei225_vec.push(nikkei225);
+/// `rows` with the sector code and market left empty filled from stocks_master.
+/// The listing date is kept as written, the bars stored don't tell when a code was
+/// listed (they start where the fetching did)
+pub fn convert_universe(rows: Vec<Nikkei225>, masters: &[StockMaster]) -> Vec<Nikkei225> {
+    rows.into_iter()
+        .map(|row| {
+            let master = masters.iter().find(|x| x.get_code() == &row.code);
+            Nikkei225 {
+                sector33_code: row
+                    .sector33_code
+                    .or_else(|| master.map(|x| x.get_sector33_code().to_owned()))
+                    .filter(|x| !x.is_empty()),
+                market: row
+                    .market
+                    .or_else(|| master.map(|x| x.get_market().to_owned())),
+                ..row
+            }
+        })
+        .collect()
+}
+
+/// Writes a v2 universe CSV
+pub fn write_universe(path: &Path, rows: &[Nikkei225]) -> Result<(), MyError> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    for row in rows {
+        wtr.serialize(row)?;
     }
-    debug!("{:?}", nikkei225_vec);
-    Ok(nikkei225_vec)
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn load_nikkei225_list() -> Result<Vec<Nikkei225>, MyError> {
+    Universe::Nikkei225.load()
 }
 
 pub enum AssetType {
-    Stocks { code: Option<String> },
+    Stocks { code: Option<StockCode> },
     Fx { symbol: Option<String> },
 }
 
@@ -92,49 +267,81 @@ pub fn get_topix_ohlc_file_path() -> Result<PathBuf, MyError> {
     Ok(backtest_json_parent_dir_path.join("topix.json"))
 }
 
-pub enum JquantsStyle {
-    // Break,
-    // Window,
-    // Cloud,
-    Afternoon,
-    Resistance,
-    Consolidating,
-    ConsolidatingAfternoon,
+/// trading23/drafts, reports waiting for `trading23 publish`
+pub fn get_drafts_root() -> Result<PathBuf, MyError> {
+    let gdrive_path = std::env::var("GDRIVE_PATH")?;
+    Ok(Path::new(&gdrive_path).join("trading23").join("drafts"))
 }
 
-pub fn get_jquants_path(jquants_style: JquantsStyle, file_name: &str) -> Result<PathBuf, MyError> {
-    let dir_name = match jquants_style {
-        JquantsStyle::Afternoon => "jquants_afternoon",
-        JquantsStyle::Resistance => "jquants_resistance",
-        JquantsStyle::Consolidating => "jquants_consolidating",
-        JquantsStyle::ConsolidatingAfternoon => "jquants_consolidating_an",
-    };
+/// trading23/drafts/{date}
+pub fn get_draft_dir(date: &str) -> Result<PathBuf, MyError> {
+    Ok(get_drafts_root()?.join(date))
+}
 
+/// trading23/jquants_codes/{code} (without extension)
+pub fn get_code_page_path(code: &str) -> Result<PathBuf, MyError> {
     let gdrive_path = std::env::var("GDRIVE_PATH")?;
-    let backtest_json_parent_dir_path = Path::new(&gdrive_path).join("trading23").join(dir_name);
-
-    // "YYYY-MM-DD" であれば NaiveDateに変換してpathを作成
-    match chrono::NaiveDate::parse_from_str(file_name, "%Y-%m-%d") {
-        Ok(datetime) => {
-            let year = datetime.year();
-            let month = datetime.month();
-            let day = datetime.day();
-
-            let path = backtest_json_parent_dir_path
-                .join(format!("{}-{}", year, month))
-                .join(format!("{}", day));
-            Ok(path)
-        }
-        Err(_) => {
-            debug!("{}", file_name);
-            Ok(backtest_json_parent_dir_path.join(file_name))
-        }
-    }
+    Ok(Path::new(&gdrive_path)
+        .join("trading23")
+        .join("jquants_codes")
+        .join(code))
+}
+
+/// trading23/datasets/{from}_{to}.csv
+pub fn get_dataset_file_path(from: &str, to: &str) -> Result<PathBuf, MyError> {
+    let gdrive_path = std::env::var("GDRIVE_PATH")?;
+    Ok(Path::new(&gdrive_path)
+        .join("trading23")
+        .join("datasets")
+        .join(format!("{}_{}.csv", from, to)))
+}
+
+/// trading23/exports/{name}
+pub fn get_export_file_path(name: &str) -> Result<PathBuf, MyError> {
+    let gdrive_path = std::env::var("GDRIVE_PATH")?;
+    Ok(Path::new(&gdrive_path)
+        .join("trading23")
+        .join("exports")
+        .join(name))
+}
+
+/// trading23/archive/{name}
+pub fn get_archive_file_path(name: &str) -> Result<PathBuf, MyError> {
+    let gdrive_path = std::env::var("GDRIVE_PATH")?;
+    Ok(Path::new(&gdrive_path)
+        .join("trading23")
+        .join("archive")
+        .join(name))
+}
+
+/// trading23/asof/{date}/{name}, the reports of `trading23 stocks asof`
+pub fn get_as_of_file_path(date: &str, name: &str) -> Result<PathBuf, MyError> {
+    let gdrive_path = std::env::var("GDRIVE_PATH")?;
+    Ok(Path::new(&gdrive_path)
+        .join("trading23")
+        .join("asof")
+        .join(date)
+        .join(name))
+}
+
+/// trading23/trading23.sqlite.lease, see `database::lease`
+pub fn get_lease_file_path() -> Result<PathBuf, MyError> {
+    let gdrive_path = std::env::var("GDRIVE_PATH")?;
+    Ok(Path::new(&gdrive_path)
+        .join("trading23")
+        .join("trading23.sqlite.lease"))
+}
+
+/// trading23/halt.json, present while the bot's "halt" is in effect
+pub fn get_halt_file_path() -> Result<PathBuf, MyError> {
+    let gdrive_path = std::env::var("GDRIVE_PATH")?;
+    Ok(Path::new(&gdrive_path).join("trading23").join("halt.json"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Datelike;
 
     #[test]
     fn test_csv_reader() {
@@ -143,6 +350,82 @@ mod tests {
         assert_eq!(nikkei225_vec.len(), 225);
     }
 
+    #[test]
+    fn test_universe_from_str() {
+        assert_eq!(
+            "nikkei225".parse::<Universe>().unwrap(),
+            Universe::Nikkei225
+        );
+        assert_eq!("TOPIX500".parse::<Universe>().unwrap(), Universe::Topix500);
+        assert_eq!(
+            "/tmp/watchlist.csv".parse::<Universe>().unwrap(),
+            Universe::Csv(PathBuf::from("/tmp/watchlist.csv"))
+        );
+        assert!("jpx400".parse::<Universe>().is_err());
+    }
+
+    #[test]
+    fn test_parse_universe() {
+        let text = "code,name,category\n7203,トヨタ自動車,自動車\n9984,ソフトバンクグループ,通信\n";
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode(text);
+        for bytes in [
+            text.as_bytes().to_vec(),
+            [b"\xEF\xBB\xBF".as_slice(), text.as_bytes()].concat(),
+            sjis.into_owned(),
+        ] {
+            let rows = parse_universe(&decode_csv(&bytes).unwrap()).unwrap();
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows[0].get_name(), "トヨタ自動車");
+        }
+
+        let rows = parse_universe("コード,銘柄名\n7203,トヨタ自動車\n").unwrap();
+        assert_eq!(rows[0].get_code().as_str(), "7203");
+
+        let e = parse_universe("code,company\n7203,トヨタ自動車\n").unwrap_err();
+        assert!(e.to_string().starts_with("no name column"));
+        let e = parse_universe("code,name\n7203,a\n9984,b\n7203,c\n").unwrap_err();
+        assert_eq!(e.to_string(), "duplicate codes 7203");
+        assert!(decode_csv(&[0x82, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_convert_universe() {
+        let rows = parse_universe(
+            "code,name,category,listed_on\n7203,トヨタ自動車,自動車,\n5032,ANYCOLOR,サービス,2023-06-01\n",
+        )
+        .unwrap();
+        assert_eq!(rows[0].get_listed_on(), None);
+        let toyota = StockCode::new("7203").unwrap();
+        let masters = [StockMaster::new(
+            toyota.clone(),
+            "トヨタ自動車",
+            "輸送用機器",
+            "3700",
+            "プライム",
+        )];
+
+        let rows = convert_universe(rows, &masters);
+        assert_eq!(rows[0].get_sector33_code(), Some("3700"));
+        assert_eq!(rows[0].get_market(), Some("プライム"));
+        assert_eq!(rows[0].get_listed_on(), None);
+        assert_eq!(rows[1].get_sector33_code(), None);
+        assert_eq!(rows[1].get_listed_on(), Some("2023-06-01"));
+        assert!(!rows[1].is_listed_on("2023-05-31"));
+        assert!(rows[1].is_listed_on("2023-06-01"));
+
+        // v2 round trip
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        for row in &rows {
+            wtr.serialize(row).unwrap();
+        }
+        let text = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert!(text.starts_with("code,name,category,sector33_code,market,listed_on\n"));
+        assert_eq!(parse_universe(&text).unwrap(), rows);
+
+        let e = parse_universe("code,name,listed_on\n7203,a,2023/01/04\n").unwrap_err();
+        assert_eq!(e.to_string(), "7203: invalid listed_on 2023/01/04");
+    }
+
     #[test]
     fn test_chrono_parse() {
         let file_name = "2021-01-01";
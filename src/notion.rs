@@ -1,4 +1,5 @@
 use crate::my_error::MyError;
+use crate::rate_limit::Provider;
 use log::info;
 use reqwest::Client;
 use serde_json::Value;
@@ -10,6 +11,7 @@ pub async fn get_notion_data() -> Result<(), MyError> {
     let token = "my_secret_token";
 
     info!("fetch notion data");
+    let _permit = Provider::Notion.limiter().acquire().await;
     let res = client
         .post(url)
         .header("Notion-Version", "2022-06-28")
@@ -0,0 +1,494 @@
+use anyhow::anyhow;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use log::{error, info, warn};
+use reqwest::Client;
+use ring::hmac::{sign, Key, HMAC_SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::GdriveJson;
+use crate::line_notify;
+use crate::markdown::{Markdown, ReportFormat};
+use crate::my_error::MyError;
+use crate::rate_limit::Provider;
+use crate::report_kind::ReportKind;
+use crate::signal::{Signal, SIGNAL_FORMAT_VERSION};
+
+/// A rendered report, handed to every sink configured for its kind
+#[derive(Debug)]
+pub struct Report {
+    kind: ReportKind,
+    date: String,
+    title: String,
+    /// a few lines for notifications and feeds
+    summary: Vec<String>,
+    markdown: Markdown,
+    /// the recommendations of the report, referred to by their ids later
+    signals: Vec<Signal>,
+}
+
+impl Report {
+    pub fn new(
+        kind: ReportKind,
+        date: &str,
+        title: &str,
+        summary: Vec<String>,
+        markdown: Markdown,
+    ) -> Self {
+        Report {
+            kind,
+            date: date.to_owned(),
+            title: title.to_owned(),
+            summary,
+            markdown,
+            signals: Vec::new(),
+        }
+    }
+
+    pub fn with_signals(mut self, signals: Vec<Signal>) -> Self {
+        self.signals = signals;
+        self
+    }
+
+    //getters
+    pub fn get_kind(&self) -> ReportKind {
+        self.kind
+    }
+    pub fn get_date(&self) -> &str {
+        &self.date
+    }
+    pub fn get_title(&self) -> &str {
+        &self.title
+    }
+    pub fn get_summary(&self) -> &[String] {
+        &self.summary
+    }
+    pub fn get_markdown(&self) -> &Markdown {
+        &self.markdown
+    }
+    pub fn get_signals(&self) -> &[Signal] {
+        &self.signals
+    }
+
+    /// Title, summary and "code id" of the signals, one per line
+    pub fn message(&self) -> String {
+        let mut lines = vec![self.title.clone()];
+        lines.extend(self.summary.iter().cloned());
+        lines.extend(
+            self.signals
+                .iter()
+                .map(|x| format!("{} {}", x.get_code(), x.get_id())),
+        );
+        lines.join("\n")
+    }
+}
+
+/// Somewhere a report goes, e.g. a file or a notification
+pub trait OutputSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn send<'a>(
+        &'a self,
+        client: &'a Client,
+        report: &'a Report,
+    ) -> BoxFuture<'a, Result<(), MyError>>;
+}
+
+/// The report folder and its feed, or a draft, see `draft::write_report`
+pub struct FileSink {
+    format: ReportFormat,
+    draft: bool,
+}
+
+impl OutputSink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+    fn send<'a>(
+        &'a self,
+        _client: &'a Client,
+        report: &'a Report,
+    ) -> BoxFuture<'a, Result<(), MyError>> {
+        Box::pin(async move {
+            let path = crate::draft::write_report(report, self.format, self.draft)?;
+            info!("{}", path.display());
+            Ok(())
+        })
+    }
+}
+
+/// LINE Notify with the title and summary
+pub struct NotifySink;
+
+impl OutputSink for NotifySink {
+    fn name(&self) -> &'static str {
+        "notify"
+    }
+    fn send<'a>(
+        &'a self,
+        client: &'a Client,
+        report: &'a Report,
+    ) -> BoxFuture<'a, Result<(), MyError>> {
+        Box::pin(async move { line_notify::send_message(client, &report.message()).await })
+    }
+}
+
+/// A page in a Notion database, titled with the report title
+pub struct NotionSink {
+    database_id: String,
+    token: String,
+}
+
+impl OutputSink for NotionSink {
+    fn name(&self) -> &'static str {
+        "notion"
+    }
+    fn send<'a>(
+        &'a self,
+        client: &'a Client,
+        report: &'a Report,
+    ) -> BoxFuture<'a, Result<(), MyError>> {
+        Box::pin(async move {
+            let children = report
+                .summary
+                .iter()
+                .map(|line| {
+                    json!({
+                        "object": "block",
+                        "type": "paragraph",
+                        "paragraph": {"rich_text": [{"type": "text", "text": {"content": line}}]}
+                    })
+                })
+                .collect::<Vec<_>>();
+            let body = json!({
+                "parent": {"database_id": self.database_id},
+                "properties": {
+                    "Name": {"title": [{"text": {"content": report.title}}]}
+                },
+                "children": children,
+            });
+            let _permit = Provider::Notion.limiter().acquire().await;
+            let res = client
+                .post("https://api.notion.com/v1/pages")
+                .header("Notion-Version", "2022-06-28")
+                .bearer_auth(&self.token)
+                .json(&body)
+                .send()
+                .await?;
+            check_status(res).await
+        })
+    }
+}
+
+/// A row (date, report, title, summary...) appended through an Apps Script web app
+pub struct SheetsSink {
+    url: String,
+}
+
+impl OutputSink for SheetsSink {
+    fn name(&self) -> &'static str {
+        "sheets"
+    }
+    fn send<'a>(
+        &'a self,
+        client: &'a Client,
+        report: &'a Report,
+    ) -> BoxFuture<'a, Result<(), MyError>> {
+        Box::pin(async move {
+            let mut row = vec![
+                report.date.clone(),
+                report.kind.get_name().to_owned(),
+                report.title.clone(),
+            ];
+            row.extend(report.summary.iter().cloned());
+            let res = client
+                .post(&self.url)
+                .json(&json!({"values": [row]}))
+                .send()
+                .await?;
+            check_status(res).await
+        })
+    }
+}
+
+/// The whole report as json to any endpoint, e.g. Home Assistant or a broker bridge.
+/// With a secret the body is signed, see `webhook_signature`
+pub struct WebhookSink {
+    url: String,
+    secret: Option<String>,
+}
+
+/// Header of the unix time (seconds) the webhook was sent at
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "X-Trading23-Timestamp";
+/// Header of the `webhook_signature`
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Trading23-Signature";
+
+/// "sha256=" and the hex HMAC-SHA256 of "{timestamp}.{body}" with the secret.
+/// Receivers recompute it over the raw body and drop old timestamps against replays
+pub fn webhook_signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let key = Key::new(HMAC_SHA256, secret.as_bytes());
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    format!("sha256={}", hex::encode(sign(&key, &message).as_ref()))
+}
+
+impl OutputSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+    fn send<'a>(
+        &'a self,
+        client: &'a Client,
+        report: &'a Report,
+    ) -> BoxFuture<'a, Result<(), MyError>> {
+        Box::pin(async move {
+            let body = json!({
+                "kind": report.kind.get_name(),
+                "date": report.date,
+                "title": report.title,
+                "summary": report.summary,
+                "markdown": report.markdown.buffer(),
+                "version": SIGNAL_FORMAT_VERSION,
+                "signals": report.signals,
+            });
+            let body = serde_json::to_vec(&body)?;
+            let mut req = client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json");
+            if let Some(secret) = &self.secret {
+                let timestamp = Utc::now().timestamp();
+                req = req.header(WEBHOOK_TIMESTAMP_HEADER, timestamp).header(
+                    WEBHOOK_SIGNATURE_HEADER,
+                    webhook_signature(secret, timestamp, &body),
+                );
+            }
+            let res = req.body(body).send().await?;
+            check_status(res).await
+        })
+    }
+}
+
+async fn check_status(res: reqwest::Response) -> Result<(), MyError> {
+    let status = res.status();
+    match status.is_success() {
+        true => Ok(()),
+        false => Err(MyError::Anyhow(anyhow!(
+            "Status code: {}, {}",
+            status,
+            res.text().await?
+        ))),
+    }
+}
+
+/// One entry of `outputs.{report name}` in config.json
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    File,
+    Notify,
+    Notion {
+        #[serde(rename = "databaseId")]
+        database_id: String,
+        token: String,
+    },
+    Sheets {
+        url: String,
+    },
+    Webhook {
+        url: String,
+        /// Signs the body when set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret: Option<String>,
+    },
+}
+
+impl SinkConfig {
+    fn to_sink(&self, format: ReportFormat, draft: bool) -> Box<dyn OutputSink> {
+        match self {
+            SinkConfig::File => Box::new(FileSink { format, draft }),
+            SinkConfig::Notify => Box::new(NotifySink),
+            SinkConfig::Notion { database_id, token } => Box::new(NotionSink {
+                database_id: database_id.clone(),
+                token: token.clone(),
+            }),
+            SinkConfig::Sheets { url } => Box::new(SheetsSink { url: url.clone() }),
+            SinkConfig::Webhook { url, secret } => Box::new(WebhookSink {
+                url: url.clone(),
+                secret: secret.clone(),
+            }),
+        }
+    }
+}
+
+/// Sends a report to several sinks at once
+pub struct Dispatcher {
+    sinks: Vec<Box<dyn OutputSink>>,
+}
+
+impl Dispatcher {
+    pub fn new(sinks: Vec<Box<dyn OutputSink>>) -> Self {
+        Dispatcher { sinks }
+    }
+
+    /// `outputs.{name}` in config.json, only the file when not set.
+    /// With `draft` only the file sink runs, the others would publish before `trading23 publish`.
+    /// While halted from the chat (see `halt`) only the file sink runs too.
+    pub fn for_kind(kind: ReportKind, format: ReportFormat, draft: bool) -> Self {
+        let mut configs = GdriveJson::new()
+            .ok()
+            .and_then(|config| config.outputs(kind.get_name()).map(|x| x.to_vec()))
+            .unwrap_or_else(|| vec![SinkConfig::File]);
+        if let Ok(Some(halt)) = crate::halt::current() {
+            warn!(
+                "halted since {}, {} goes to the file only",
+                halt,
+                kind.get_name()
+            );
+            configs.retain(|x| *x == SinkConfig::File);
+        }
+        Dispatcher::from_configs(&configs, format, draft)
+    }
+
+    fn from_configs(configs: &[SinkConfig], format: ReportFormat, draft: bool) -> Self {
+        let sinks = configs
+            .iter()
+            .filter(|x| !draft || **x == SinkConfig::File)
+            .map(|x| x.to_sink(format, draft))
+            .collect();
+        Dispatcher::new(sinks)
+    }
+
+    /// The sinks a draft of `kind` was held back from, for `trading23 publish`: all but the
+    /// file, which the published draft already is
+    pub fn for_published(kind: ReportKind) -> Self {
+        let mut dispatcher = Dispatcher::for_kind(kind, ReportFormat::default(), false);
+        dispatcher.sinks.retain(|x| x.name() != "file");
+        dispatcher
+    }
+
+    pub fn with(mut self, sink: Box<dyn OutputSink>) -> Self {
+        if self.sinks.iter().all(|x| x.name() != sink.name()) {
+            self.sinks.push(sink);
+        }
+        self
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.sinks.iter().map(|x| x.name()).collect()
+    }
+
+    /// Every sink is tried, failures are logged and reported together
+    pub async fn dispatch(&self, client: &Client, report: &Report) -> Result<(), MyError> {
+        let results =
+            futures::future::join_all(self.sinks.iter().map(|x| x.send(client, report))).await;
+        let mut failed = Vec::new();
+        for (sink, result) in self.sinks.iter().zip(results) {
+            if let Err(e) = result {
+                error!("{} {} failed: {}", report.kind.get_name(), sink.name(), e);
+                failed.push(format!("{}: {}", sink.name(), e));
+            }
+        }
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(MyError::Anyhow(anyhow!(
+                "{} {}",
+                report.title,
+                failed.join(", ")
+            ))),
+        }
+    }
+}
+
+/// Each report to the sinks of its kind
+pub async fn dispatch_all(
+    client: &Client,
+    reports: &[Report],
+    format: ReportFormat,
+    draft: bool,
+) -> Result<(), MyError> {
+    for report in reports {
+        Dispatcher::for_kind(report.kind, format, draft)
+            .dispatch(client, report)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sink_configs() {
+        let configs: Vec<SinkConfig> = serde_json::from_str(
+            r#"[
+                {"type": "file"},
+                {"type": "notify"},
+                {"type": "webhook", "url": "https://example.com/hook"},
+                {"type": "notion", "databaseId": "abc", "token": "secret"},
+                {"type": "webhook", "url": "https://example.com/signed", "secret": "s3cret"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            configs[2],
+            SinkConfig::Webhook {
+                url: "https://example.com/hook".to_owned(),
+                secret: None,
+            }
+        );
+        assert_eq!(
+            configs[4],
+            SinkConfig::Webhook {
+                url: "https://example.com/signed".to_owned(),
+                secret: Some("s3cret".to_owned()),
+            }
+        );
+
+        let dispatcher = Dispatcher::from_configs(&configs, ReportFormat::Html, false);
+        assert_eq!(
+            dispatcher.names(),
+            vec!["file", "notify", "webhook", "notion", "webhook"]
+        );
+        let dispatcher = Dispatcher::from_configs(&configs, ReportFormat::Html, true);
+        assert_eq!(dispatcher.names(), vec!["file"]);
+        let dispatcher = dispatcher
+            .with(Box::new(NotifySink))
+            .with(Box::new(NotifySink));
+        assert_eq!(dispatcher.names(), vec!["file", "notify"]);
+    }
+
+    #[test]
+    fn test_webhook_signature() {
+        let body = br#"{"kind":"resistance"}"#;
+        let signature = webhook_signature("s3cret", 1704412800, body);
+        assert_eq!(
+            signature,
+            "sha256=f89d053d9d9edbc78441af03230af1d5964ede42536d285654d949d5a582d289"
+        );
+        assert_ne!(signature, webhook_signature("s3cret", 1704412801, body));
+        assert_ne!(signature, webhook_signature("other", 1704412800, body));
+    }
+
+    #[test]
+    fn test_report_message() {
+        let report = Report::new(
+            crate::analysis::stocks_window::RESISTANCE,
+            "2024-01-05",
+            "2024-01-05 Nextday",
+            vec!["Number of Stocks: 20".to_owned()],
+            Markdown::new(),
+        );
+        assert_eq!(report.message(), "2024-01-05 Nextday\nNumber of Stocks: 20");
+
+        let toyota = crate::stock_code::StockCode::new("7203").unwrap();
+        let id = crate::signal::SignalId::new(crate::signal::WINDOW_SOURCE, &toyota, "2024-01-05")
+            .unwrap();
+        let report =
+            report.with_signals(vec![Signal::new(id.clone(), &toyota, "2024-01-05", "Rise")]);
+        assert_eq!(
+            report.message(),
+            format!("2024-01-05 Nextday\nNumber of Stocks: 20\n7203 {}", id)
+        );
+    }
+}
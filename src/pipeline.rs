@@ -0,0 +1,220 @@
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::config::GdriveJson;
+use crate::i18n::{Lang, Msg};
+use crate::my_error::MyError;
+
+/// A step of the nextday, afternoon and backtest jobs that can fail without
+/// the job failing. The others (windows, reports, dispatch) always stop their job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Step {
+    /// daily quotes of the universe into stocks_ohlc
+    FetchDaily,
+    /// stocks_ohlc reaches the last trading day
+    LatestData,
+    /// enough of the universe was fetched
+    Coverage,
+    /// investor flows of the report
+    TradesSpec,
+    /// feature drift warning
+    Drift,
+    /// short selling ratios of `--min-short-ratio`
+    ShortSelling,
+    /// TOPIX bars of the backtests and of the relative strength
+    Topix,
+    /// morning session of the afternoon job
+    PricesAm,
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Step::FetchDaily => "fetchDaily",
+            Step::LatestData => "latestData",
+            Step::Coverage => "coverage",
+            Step::TradesSpec => "tradesSpec",
+            Step::Drift => "drift",
+            Step::ShortSelling => "shortSelling",
+            Step::Topix => "topix",
+            Step::PricesAm => "pricesAm",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a failed step does to its job, `degradation` of config.json overrides
+/// `Step::default_fallback`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Fallback {
+    /// The job stops with a notification, the other jobs of the run still go
+    Abort,
+    /// The job goes on with what is already stored (bars, flows, ratios), the last
+    /// fetch's data. The success notification lists it
+    UseStored,
+    /// The job goes on without it, e.g. no drift warning. Listed too
+    Skip,
+}
+
+impl Step {
+    /// The matrix:
+    ///
+    /// | step         | on failure |
+    /// |--------------|------------|
+    /// | fetchDaily   | useStored, latestData and coverage decide if the bars will do |
+    /// | latestData   | abort, an older day under today's title misleads |
+    /// | coverage     | abort |
+    /// | tradesSpec   | useStored, the report shows the latest stored week |
+    /// | drift        | skip |
+    /// | shortSelling | useStored, the ratios stored before |
+    /// | topix        | useStored, the regime of the stored bars |
+    /// | pricesAm     | abort, the afternoon job only. useStored reads the morning session stored by an earlier run, still checked for age |
+    pub fn default_fallback(&self) -> Fallback {
+        match self {
+            Step::FetchDaily | Step::TradesSpec | Step::ShortSelling | Step::Topix => {
+                Fallback::UseStored
+            }
+            Step::Drift => Fallback::Skip,
+            Step::LatestData | Step::Coverage | Step::PricesAm => Fallback::Abort,
+        }
+    }
+}
+
+/// A step that failed without stopping its job
+#[derive(Debug, Clone, PartialEq)]
+pub struct Degraded {
+    step: Step,
+    fallback: Fallback,
+    error: String,
+}
+
+impl Degraded {
+    //getters
+    pub fn get_step(&self) -> Step {
+        self.step
+    }
+    pub fn get_fallback(&self) -> Fallback {
+        self.fallback
+    }
+}
+
+impl Display for Degraded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?}): {}", self.step, self.fallback, self.error)
+    }
+}
+
+/// Runs the steps of one job (nextday, afternoon) under the fallbacks
+#[derive(Debug)]
+pub struct Pipeline {
+    job: &'static str,
+    fallbacks: HashMap<Step, Fallback>,
+    degraded: Vec<Degraded>,
+}
+
+impl Pipeline {
+    /// The fallbacks of `degradation` in config.json over the defaults
+    pub fn new(job: &'static str) -> Self {
+        let fallbacks = GdriveJson::new()
+            .map(|config| config.degradation().clone())
+            .unwrap_or_default();
+        Pipeline::with_fallbacks(job, fallbacks)
+    }
+
+    pub fn with_fallbacks(job: &'static str, fallbacks: HashMap<Step, Fallback>) -> Self {
+        Pipeline {
+            job,
+            fallbacks,
+            degraded: Vec::new(),
+        }
+    }
+
+    pub fn fallback(&self, step: Step) -> Fallback {
+        self.fallbacks
+            .get(&step)
+            .copied()
+            .unwrap_or_else(|| step.default_fallback())
+    }
+
+    /// Some on success, None when `step` failed and the job goes on without its result,
+    /// the error when the job stops
+    pub fn run<T>(&mut self, step: Step, result: Result<T, MyError>) -> Result<Option<T>, MyError> {
+        let e = match result {
+            Ok(x) => return Ok(Some(x)),
+            Err(e) => e,
+        };
+        match self.fallback(step) {
+            Fallback::Abort => {
+                error!("{} {} failed: {}", self.job, step, e);
+                Err(e)
+            }
+            fallback => {
+                warn!("{} {} failed, {:?}: {}", self.job, step, fallback, e);
+                self.degraded.push(Degraded {
+                    step,
+                    fallback,
+                    error: e.to_string(),
+                });
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn get_degraded(&self) -> &[Degraded] {
+        &self.degraded
+    }
+
+    /// `msg` of the success notification, with the degraded steps under it
+    pub fn summary(&self, lang: Lang, msg: Msg) -> String {
+        let mut lines = vec![msg.text(lang).to_owned()];
+        if !self.degraded.is_empty() {
+            lines.push(format!("[{}]", Msg::Degraded.text(lang)));
+            lines.extend(self.degraded.iter().map(|x| format!("- {}", x)));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipeline() {
+        let fallbacks: HashMap<Step, Fallback> =
+            serde_json::from_str(r#"{"fetchDaily": "abort", "drift": "skip"}"#).unwrap();
+        let mut pipeline = Pipeline::with_fallbacks("nextday", fallbacks);
+        assert_eq!(pipeline.fallback(Step::FetchDaily), Fallback::Abort);
+        assert_eq!(pipeline.fallback(Step::TradesSpec), Fallback::UseStored);
+
+        assert_eq!(pipeline.run(Step::Coverage, Ok(3)).unwrap(), Some(3));
+        let failed = || Err::<(), _>(MyError::Holiday);
+        assert_eq!(pipeline.run(Step::TradesSpec, failed()).unwrap(), None);
+        assert_eq!(pipeline.run(Step::Drift, failed()).unwrap(), None);
+        assert!(matches!(
+            pipeline.run(Step::FetchDaily, failed()),
+            Err(MyError::Holiday)
+        ));
+        assert_eq!(
+            pipeline
+                .get_degraded()
+                .iter()
+                .map(|x| (x.get_step(), x.get_fallback()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Step::TradesSpec, Fallback::UseStored),
+                (Step::Drift, Fallback::Skip)
+            ]
+        );
+        assert_eq!(
+            pipeline.summary(Lang::En, Msg::NextdaySucceeded),
+            "Next day process, success\n[degraded]\n\
+            - tradesSpec (UseStored): It is holiday\n\
+            - drift (Skip): It is holiday"
+        );
+    }
+}
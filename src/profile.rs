@@ -0,0 +1,103 @@
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Fetch,
+    DbRead,
+    Window,
+    Render,
+    Notify,
+}
+impl Stage {
+    const ALL: [Stage; 5] = [
+        Stage::Fetch,
+        Stage::DbRead,
+        Stage::Window,
+        Stage::Render,
+        Stage::Notify,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::Fetch => "fetch",
+            Stage::DbRead => "db read",
+            Stage::Window => "window",
+            Stage::Render => "render",
+            Stage::Notify => "notify",
+        }
+    }
+}
+
+// (elapsed, count) per Stage::ALL
+static STAGES: Mutex<[(Duration, usize); 5]> = Mutex::new([(Duration::ZERO, 0); 5]);
+
+/// Adds the elapsed time to the stage when dropped
+pub struct Span {
+    stage: Stage,
+    start: Instant,
+}
+impl Drop for Span {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let index = Stage::ALL.iter().position(|x| *x == self.stage).unwrap();
+        if let Ok(mut stages) = STAGES.lock() {
+            stages[index].0 += elapsed;
+            stages[index].1 += 1;
+        }
+    }
+}
+
+pub fn span(stage: Stage) -> Span {
+    Span {
+        stage,
+        start: Instant::now(),
+    }
+}
+
+/// Stage breakdown for `--profile-report`.
+/// Spans running in parallel tasks are summed, so the total can exceed wall time.
+pub fn report() -> String {
+    let stages = match STAGES.lock() {
+        Ok(stages) => *stages,
+        Err(_) => return String::new(),
+    };
+    let total: Duration = stages.iter().map(|(elapsed, _)| *elapsed).sum();
+
+    let mut buffer = String::new();
+    writeln!(buffer, "stage        elapsed    count  share").unwrap();
+    for (stage, (elapsed, count)) in Stage::ALL.iter().zip(stages.iter()) {
+        let share = match total.is_zero() {
+            true => 0.0,
+            false => elapsed.as_secs_f64() / total.as_secs_f64() * 100.0,
+        };
+        writeln!(
+            buffer,
+            "{:<10} {:>8.3}s {:>8} {:>5.1}%",
+            stage.name(),
+            elapsed.as_secs_f64(),
+            count,
+            share
+        )
+        .unwrap();
+    }
+    writeln!(buffer, "{:<10} {:>8.3}s", "total", total.as_secs_f64()).unwrap();
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span() {
+        {
+            let _span = span(Stage::Render);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let report = report();
+        let render = report.lines().find(|x| x.starts_with("render")).unwrap();
+        assert!(!render.contains(" 0.000s"));
+    }
+}
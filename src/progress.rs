@@ -0,0 +1,132 @@
+use log::warn;
+use reqwest::Client;
+use std::time::{Duration, Instant};
+
+use crate::config::GdriveJson;
+use crate::i18n::{Lang, Msg};
+use crate::line_notify;
+
+/// Notifies the progress of a long loop, e.g. "fetched 60/100 queries, ETA 12 min",
+/// at most once per `progressIntervalMinutes` of config.json. Silent when it is not set.
+pub struct Progress<'a> {
+    client: &'a Client,
+    lang: Lang,
+    what: Msg,
+    total: usize,
+    done: usize,
+    interval: Option<Duration>,
+    started: Instant,
+    last_sent: Instant,
+}
+
+impl<'a> Progress<'a> {
+    /// `what` names the `total` items, e.g. `Msg::Queries`
+    pub fn new(client: &'a Client, what: Msg, total: usize) -> Self {
+        let (lang, interval) = match GdriveJson::new() {
+            Ok(config) => (
+                config.language(),
+                config
+                    .progress_interval_minutes()
+                    .map(|x| Duration::from_secs(x * 60)),
+            ),
+            Err(_) => (Lang::default(), None),
+        };
+        let now = Instant::now();
+        Progress {
+            client,
+            lang,
+            what,
+            total,
+            done: 0,
+            interval,
+            started: now,
+            last_sent: now,
+        }
+    }
+
+    /// Counts one item done, notifying when the interval has passed since the last one.
+    /// The last item is not notified, the caller reports the end.
+    pub async fn tick(&mut self) {
+        self.done += 1;
+        let Some(interval) = self.interval else {
+            return;
+        };
+        if self.done >= self.total || self.last_sent.elapsed() < interval {
+            return;
+        }
+        self.last_sent = Instant::now();
+        let message = message(
+            self.lang,
+            self.what,
+            self.done,
+            self.total,
+            eta(self.started.elapsed(), self.done, self.total),
+        );
+        if let Err(e) = line_notify::send_message(self.client, &message).await {
+            warn!("progress not notified: {}", e);
+        }
+    }
+}
+
+/// Time left when the rest goes as fast as the `done` items did
+fn eta(elapsed: Duration, done: usize, total: usize) -> Option<Duration> {
+    match done {
+        0 => None,
+        done => Some(elapsed.mul_f64(total.saturating_sub(done) as f64 / done as f64)),
+    }
+}
+
+fn message(lang: Lang, what: Msg, done: usize, total: usize, eta: Option<Duration>) -> String {
+    let fetched = format!(
+        "{} {}/{} {}",
+        Msg::Fetched.text(lang),
+        done,
+        total,
+        what.text(lang)
+    );
+    // rounded up, "0 min" left reads like done
+    match eta.map(|x| x.as_secs().div_ceil(60)) {
+        Some(minutes) => format!(
+            "{}, {} {}{}",
+            fetched,
+            Msg::Eta.text(lang),
+            minutes,
+            Msg::Minutes.text(lang)
+        ),
+        None => fetched,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let eta = eta(Duration::from_secs(18 * 60), 60, 100);
+        assert_eq!(eta, Some(Duration::from_secs(12 * 60)));
+        assert_eq!(
+            message(Lang::En, Msg::Queries, 60, 100, eta),
+            "fetched 60/100 queries, ETA 12 min"
+        );
+        assert_eq!(
+            message(Lang::Ja, Msg::Queries, 60, 100, eta),
+            "取得中 60/100 件, 残り約 12分"
+        );
+        assert_eq!(
+            message(
+                Lang::En,
+                Msg::Queries,
+                99,
+                100,
+                Some(Duration::from_secs(10))
+            ),
+            "fetched 99/100 queries, ETA 1 min"
+        );
+        assert_eq!(
+            message(Lang::En, Msg::Queries, 0, 100, None),
+            "fetched 0/100 queries"
+        );
+        assert_eq!(super::eta(Duration::from_secs(5), 0, 100), None);
+    }
+}
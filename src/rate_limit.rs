@@ -0,0 +1,104 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+
+use crate::config::GdriveJson;
+
+/// An API whose requests share one limiter per run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Provider {
+    JQuants,
+    Gmo,
+    Notion,
+    /// The replies of the chat bot
+    Line,
+}
+
+impl Provider {
+    /// Requests in flight and milliseconds between starts when the config doesn't say
+    pub fn default_limits(&self) -> (usize, u64) {
+        match self {
+            Provider::JQuants => (3, 300),
+            Provider::Gmo => (4, 250),
+            // Notion allows about 3 requests a second
+            Provider::Notion => (1, 350),
+            Provider::Line => (2, 100),
+        }
+    }
+
+    /// The limiter of the provider, built from the config on first use
+    pub fn limiter(&self) -> &'static RateLimiter {
+        static JQUANTS: OnceLock<RateLimiter> = OnceLock::new();
+        static GMO: OnceLock<RateLimiter> = OnceLock::new();
+        static NOTION: OnceLock<RateLimiter> = OnceLock::new();
+        static LINE: OnceLock<RateLimiter> = OnceLock::new();
+        let cell = match self {
+            Provider::JQuants => &JQUANTS,
+            Provider::Gmo => &GMO,
+            Provider::Notion => &NOTION,
+            Provider::Line => &LINE,
+        };
+        cell.get_or_init(|| {
+            let (max_concurrent, interval_ms) = match GdriveJson::new() {
+                Ok(config) => config.rate_limit(*self),
+                Err(_) => self.default_limits(),
+            };
+            RateLimiter::new(max_concurrent, Duration::from_millis(interval_ms))
+        })
+    }
+}
+
+/// Caps the requests in flight and spaces out their starts by `min_interval`
+#[derive(Debug)]
+pub struct RateLimiter {
+    semaphore: Semaphore,
+    min_interval: Duration,
+    /// The earliest start of the next request
+    next_start: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(max_concurrent: usize, min_interval: Duration) -> Self {
+        RateLimiter {
+            semaphore: Semaphore::new(max_concurrent.max(1)),
+            min_interval,
+            next_start: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits for a free slot and the pacing, the slot is held until the permit drops
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        // the semaphore is never closed
+        let permit = self.semaphore.acquire().await.expect("semaphore closed");
+        let start = {
+            let mut next_start = self.next_start.lock().await;
+            let start = (*next_start).max(Instant::now());
+            *next_start = start + self.min_interval;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire() {
+        let interval = Duration::from_millis(20);
+        let limiter = RateLimiter::new(2, interval);
+        let started = Instant::now();
+
+        let first = limiter.acquire().await;
+        let _second = limiter.acquire().await;
+        assert!(started.elapsed() >= interval);
+        assert_eq!(limiter.semaphore.available_permits(), 0);
+
+        drop(first);
+        let _third = limiter.acquire().await;
+        assert!(started.elapsed() >= interval * 2);
+    }
+}
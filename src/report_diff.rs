@@ -0,0 +1,264 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::Path;
+
+use crate::my_error::MyError;
+
+/// Candidates and summary lines of a report, enough to tell whether two
+/// versions of the analysis produced the same output
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ReportSnapshot {
+    date: String,
+    /// section heading -> codes in report order
+    sections: BTreeMap<String, Vec<String>>,
+    /// "key: value" lines, e.g. the summary
+    stats: BTreeMap<String, String>,
+}
+
+fn strip_tags(line: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&amp;", "&").trim().to_owned()
+}
+
+impl ReportSnapshot {
+    /// Reads the html written by `Markdown::write_to_html`.
+    /// Codes are taken from the links to the code pages.
+    pub fn from_html(html: &str) -> Self {
+        let mut snapshot = ReportSnapshot::default();
+        let mut section = String::new();
+        for line in html.lines() {
+            if line.starts_with("<h1>") {
+                snapshot.date = strip_tags(line);
+                continue;
+            }
+            if line.starts_with("<h2>") || line.starts_with("<h3>") {
+                section = strip_tags(line);
+                continue;
+            }
+
+            let mut rest = line;
+            let mut has_code = false;
+            while let Some(start) = rest.find("jquants_codes/") {
+                rest = &rest[start + "jquants_codes/".len()..];
+                if let Some(end) = rest.find(".html") {
+                    snapshot
+                        .sections
+                        .entry(section.clone())
+                        .or_default()
+                        .push(rest[..end].to_owned());
+                    has_code = true;
+                }
+            }
+            if has_code || line.starts_with("<t") {
+                continue;
+            }
+            for text in line.split("<br />").map(strip_tags) {
+                if let Some((key, value)) = text.split_once(": ") {
+                    snapshot
+                        .stats
+                        .insert(key.trim().to_owned(), value.trim().to_owned());
+                }
+            }
+        }
+        snapshot
+    }
+
+    /// `.html` reports or `.json` snapshots (`trading23 report snapshot`)
+    pub fn load(path: &Path) -> Result<Self, MyError> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("html") => Ok(Self::from_html(&std::fs::read_to_string(path)?)),
+            Some("json") => Ok(serde_json::from_reader(File::open(path)?)?),
+            _ => Err(MyError::Anyhow(anyhow!(
+                "{}: expected .html or .json",
+                path.display()
+            ))),
+        }
+    }
+
+    //getters
+    pub fn get_date(&self) -> &str {
+        &self.date
+    }
+    pub fn get_sections(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.sections
+    }
+    pub fn get_stats(&self) -> &BTreeMap<String, String> {
+        &self.stats
+    }
+
+    pub fn to_json(&self) -> Result<String, MyError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SectionDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    /// same codes in a different order
+    reordered: bool,
+}
+
+/// Differences between two snapshots, empty when the reports match
+#[derive(Debug, Default, PartialEq)]
+pub struct ReportDiff {
+    date: Option<(String, String)>,
+    sections: BTreeMap<String, SectionDiff>,
+    /// key -> (old, new), "-" when missing on one side
+    stats: BTreeMap<String, (String, String)>,
+}
+
+impl ReportDiff {
+    pub fn new(old: &ReportSnapshot, new: &ReportSnapshot) -> Self {
+        let mut diff = ReportDiff::default();
+        if old.date != new.date {
+            diff.date = Some((old.date.clone(), new.date.clone()));
+        }
+
+        let empty = Vec::new();
+        for name in old.sections.keys().chain(new.sections.keys()) {
+            let old_codes = old.sections.get(name).unwrap_or(&empty);
+            let new_codes = new.sections.get(name).unwrap_or(&empty);
+            if old_codes == new_codes {
+                continue;
+            }
+            let added = new_codes
+                .iter()
+                .filter(|x| !old_codes.contains(x))
+                .cloned()
+                .collect::<Vec<_>>();
+            let removed = old_codes
+                .iter()
+                .filter(|x| !new_codes.contains(x))
+                .cloned()
+                .collect::<Vec<_>>();
+            let reordered = added.is_empty() && removed.is_empty();
+            diff.sections.insert(
+                name.clone(),
+                SectionDiff {
+                    added,
+                    removed,
+                    reordered,
+                },
+            );
+        }
+
+        for key in old.stats.keys().chain(new.stats.keys()) {
+            let old_value = old.stats.get(key).map_or("-", |x| x.as_str());
+            let new_value = new.stats.get(key).map_or("-", |x| x.as_str());
+            if old_value != new_value {
+                diff.stats
+                    .insert(key.clone(), (old_value.to_owned(), new_value.to_owned()));
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.date.is_none() && self.sections.is_empty() && self.stats.is_empty()
+    }
+}
+
+impl Display for ReportDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no difference");
+        }
+        if let Some((old, new)) = &self.date {
+            writeln!(f, "date: {} -> {}", old, new)?;
+        }
+        for (name, section) in &self.sections {
+            writeln!(f, "{}:", name)?;
+            if !section.added.is_empty() {
+                writeln!(f, "  + {}", section.added.join(", "))?;
+            }
+            if !section.removed.is_empty() {
+                writeln!(f, "  - {}", section.removed.join(", "))?;
+            }
+            if section.reordered {
+                writeln!(f, "  order changed")?;
+            }
+        }
+        for (key, (old, new)) in &self.stats {
+            writeln!(f, "{}: {} -> {}", key, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::{Column, Markdown};
+
+    fn report_html(stocks: &str, codes: &[&str]) -> String {
+        let mut markdown = Markdown::new();
+        markdown
+            .section("2024-01-05", |m| {
+                m.section("Nextday", |m| {
+                    m.section("Summary", |m| {
+                        m.body(&format!("Number of Stocks: {}", stocks))?;
+                        m.body("Morning Gainers: 50%")
+                    })?;
+                    m.section("Resistance Candles Top 10", |m| {
+                        m.table(
+                            &[Column::left("Code"), Column::right("R")],
+                            codes.iter().map(|code| {
+                                [
+                                    format!("[{}](../../jquants_codes/{}.html)", code, code),
+                                    "3".to_owned(),
+                                ]
+                            }),
+                        )
+                    })
+                })
+            })
+            .unwrap();
+        markdown.to_html()
+    }
+
+    #[test]
+    fn test_from_html() {
+        let snapshot = ReportSnapshot::from_html(&report_html("20", &["7203", "6758"]));
+        assert_eq!(snapshot.date, "2024-01-05");
+        assert_eq!(
+            snapshot.sections["Resistance Candles Top 10"],
+            vec!["7203", "6758"]
+        );
+        assert_eq!(snapshot.stats["Number of Stocks"], "20");
+        assert_eq!(snapshot.stats["Morning Gainers"], "50%");
+        assert_eq!(snapshot.stats.len(), 2);
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = ReportSnapshot::from_html(&report_html("20", &["7203", "6758"]));
+        assert!(ReportDiff::new(&old, &old).is_empty());
+
+        let new = ReportSnapshot::from_html(&report_html("21", &["6758", "9984"]));
+        let diff = ReportDiff::new(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            "Resistance Candles Top 10:\n  + 9984\n  - 7203\nNumber of Stocks: 20 -> 21\n"
+        );
+
+        let reordered = ReportSnapshot::from_html(&report_html("20", &["6758", "7203"]));
+        let diff = ReportDiff::new(&old, &reordered);
+        assert_eq!(
+            diff.to_string(),
+            "Resistance Candles Top 10:\n  order changed\n"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::{Datelike, NaiveDate};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::config::GdriveJson;
+use crate::my_error::MyError;
+
+/// A report and the directory under `trading23/` it's written to.
+/// Each strategy declares its own kinds next to the code producing them
+/// (e.g. `stocks_window::RESISTANCE`), so adding a report only needs a new const, and an
+/// entry in `KINDS` when it can be drafted.
+/// The directory can be moved per name with `reportDirs` in config.json.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportKind {
+    name: &'static str,
+    dir_name: &'static str,
+}
+
+impl ReportKind {
+    pub const fn new(name: &'static str, dir_name: &'static str) -> Self {
+        ReportKind { name, dir_name }
+    }
+
+    //getters
+    pub fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    /// `reportDirs.{name}` in config.json, read once per run, or the default directory
+    pub fn dir_name(&self) -> String {
+        static REPORT_DIRS: OnceLock<HashMap<String, String>> = OnceLock::new();
+        REPORT_DIRS
+            .get_or_init(|| {
+                GdriveJson::new()
+                    .map(|config| config.report_dirs().clone())
+                    .unwrap_or_default()
+            })
+            .get(self.name)
+            .cloned()
+            .unwrap_or_else(|| self.dir_name.to_owned())
+    }
+
+    /// trading23/{dir_name}, which also holds feed.xml
+    pub fn dir(&self) -> Result<PathBuf, MyError> {
+        let gdrive_path = std::env::var("GDRIVE_PATH")?;
+        Ok(Path::new(&gdrive_path)
+            .join("trading23")
+            .join(self.dir_name()))
+    }
+
+    /// "YYYY-MM-DD" -> {dir}/{YYYY-M}/{D}, other names are joined as is (without extension)
+    pub fn path(&self, file_name: &str) -> Result<PathBuf, MyError> {
+        Ok(dated_path(&self.dir()?, file_name))
+    }
+}
+
+/// The kinds a draft can be of, its kind is found again by name when it's published
+pub const KINDS: [ReportKind; 5] = [
+    crate::briefing::BRIEFING,
+    crate::analysis::stocks_window::RESISTANCE,
+    crate::analysis::stocks_window::CONSOLIDATING,
+    crate::analysis::stocks_afternoon::AFTERNOON,
+    crate::analysis::stocks_afternoon::CONSOLIDATING_AFTERNOON,
+];
+
+/// The kind of `KINDS` named `name`
+pub fn find(name: &str) -> Option<ReportKind> {
+    KINDS.into_iter().find(|x| x.name == name)
+}
+
+fn dated_path(dir: &Path, file_name: &str) -> PathBuf {
+    match NaiveDate::parse_from_str(file_name, "%Y-%m-%d") {
+        Ok(date) => dir
+            .join(format!("{}-{}", date.year(), date.month()))
+            .join(format!("{}", date.day())),
+        Err(_) => dir.join(file_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dated_path() {
+        let dir = Path::new("/tmp/jquants_resistance");
+        assert_eq!(
+            dated_path(dir, "2024-01-05"),
+            Path::new("/tmp/jquants_resistance/2024-1/5")
+        );
+        assert_eq!(
+            dated_path(dir, "backtest"),
+            Path::new("/tmp/jquants_resistance/backtest")
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(
+            find("afternoon"),
+            Some(crate::analysis::stocks_afternoon::AFTERNOON)
+        );
+        assert_eq!(find("unknown"), None);
+    }
+}
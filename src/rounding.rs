@@ -0,0 +1,42 @@
+/// Rounds half away from zero to `dp` decimal places
+pub fn round_dp(value: f64, dp: i32) -> f64 {
+    let coefficient = 10_f64.powi(dp);
+    (value * coefficient).round() / coefficient
+}
+
+/// Truncates toward zero to `dp` decimal places
+pub fn trunc_dp(value: f64, dp: i32) -> f64 {
+    let coefficient = 10_f64.powi(dp);
+    (value * coefficient).trunc() / coefficient
+}
+
+/// Rounds to the nearest multiple of `step` (e.g. pips: 0.01, 0.0001)
+pub fn round_to_step(value: f64, step: f64) -> f64 {
+    let coefficient = 1_f64 / step;
+    (value * coefficient).round() / coefficient
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_dp() {
+        assert_eq!(round_dp(142.3466, 2), 142.35);
+        assert_eq!(round_dp(-0.125, 2), -0.13);
+        assert_eq!(round_dp(12.34, 1), 12.3);
+        assert_eq!(round_dp(2.5, 0), 3.0);
+    }
+
+    #[test]
+    fn test_trunc_dp() {
+        assert_eq!(trunc_dp(0.12399, 3), 0.123);
+        assert_eq!(trunc_dp(-0.12399, 3), -0.123);
+    }
+
+    #[test]
+    fn test_round_to_step() {
+        assert_eq!(round_to_step(142.3466, 0.01), 142.35);
+        assert_eq!(round_to_step(1.252244, 0.0001), 1.2522);
+    }
+}
@@ -0,0 +1,223 @@
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDate};
+use ring::digest::{digest, SHA256};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+use crate::my_error::MyError;
+use crate::stock_code::StockCode;
+
+/// Version of the `Signal` json of the reports, raised when a field changes meaning
+/// or goes away. Fields may be added within a version
+pub const SIGNAL_FORMAT_VERSION: u32 = 1;
+
+/// The analyses in the `SignalId`s
+pub const WINDOW_SOURCE: &str = "stocks_window";
+pub const AFTERNOON_SOURCE: &str = "stocks_afternoon";
+pub const DAYTRADING_SOURCE: &str = "stocks_daytrading";
+
+/// Crockford's base32, the alphabet of ULIDs
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// ULID of a recommendation, the same for the same analysis of a code on a date so
+/// reruns, outcome updates, journal entries and fills all refer to one signal.
+/// The time part is the analysis date (00:00 UTC), the random part a hash of the
+/// analysis, the code and the date, so IDs sort by date.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct SignalId(String);
+
+impl SignalId {
+    /// `source` names the analysis, e.g. `WINDOW_SOURCE`, `date` is "YYYY-MM-DD"
+    pub fn new(source: &str, code: &StockCode, date: &str) -> Result<Self, MyError> {
+        let millis = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| MyError::Anyhow(anyhow!("{}: {}", date, e)))?
+            .and_time(chrono::NaiveTime::MIN)
+            .and_utc()
+            .timestamp_millis() as u128;
+        let hash = digest(&SHA256, format!("{}/{}/{}", source, code, date).as_bytes());
+        let random = hash.as_ref()[..10]
+            .iter()
+            .fold(0u128, |acc, x| (acc << 8) | *x as u128);
+        Ok(SignalId(encode(
+            ((millis & 0xFFFF_FFFF_FFFF) << 80) | random,
+        )))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// "YYYY-MM-DD" of the analysis, from the time part
+    pub fn date(&self) -> String {
+        let millis = self.0.as_bytes()[..10].iter().fold(0i64, |acc, x| {
+            let digit = ALPHABET.iter().position(|c| c == x).unwrap_or_default();
+            (acc << 5) | digit as i64
+        });
+        DateTime::from_timestamp_millis(millis)
+            .map(|x| x.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// 128 bits as 26 characters, the first one holding the top 3
+fn encode(value: u128) -> String {
+    (0..26)
+        .rev()
+        .map(|i| ALPHABET[((value >> (i * 5)) & 0x1F) as usize] as char)
+        .collect()
+}
+
+impl Display for SignalId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl TryFrom<String> for SignalId {
+    type Error = MyError;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let value = value.to_ascii_uppercase();
+        let valid = value.len() == 26
+            && value.bytes().all(|x| ALPHABET.contains(&x))
+            && value.as_bytes()[0] <= b'7';
+        match valid {
+            true => Ok(SignalId(value)),
+            false => Err(MyError::Anyhow(anyhow!("{} is not a ULID", value))),
+        }
+    }
+}
+
+impl From<SignalId> for String {
+    fn from(id: SignalId) -> Self {
+        id.0
+    }
+}
+
+impl ToSql for SignalId {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0.as_str()))
+    }
+}
+
+impl FromSql for SignalId {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        SignalId::try_from(value.as_str()?.to_owned()).map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+/// A recommendation of a report, in the json of the webhooks and snapshots
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Signal {
+    id: SignalId,
+    code: StockCode,
+    /// "YYYY-MM-DD" of the analysis
+    date: String,
+    /// e.g. "Rise", the status of the window
+    status: String,
+    /// The candidate to act on without reading the report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    candidate: Option<SignalCandidate>,
+}
+
+/// Price and size of the recommendation at the analysis
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SignalCandidate {
+    pub name: String,
+    /// close of the analysis date
+    pub price: f64,
+    pub atr: f64,
+    /// shares, a multiple of the board lot
+    pub unit: i32,
+    pub short: bool,
+}
+
+impl Signal {
+    pub fn new(id: SignalId, code: &StockCode, date: &str, status: &str) -> Self {
+        Signal {
+            id,
+            code: code.clone(),
+            date: date.to_owned(),
+            status: status.to_owned(),
+            candidate: None,
+        }
+    }
+
+    pub fn with_candidate(mut self, candidate: SignalCandidate) -> Self {
+        self.candidate = Some(candidate);
+        self
+    }
+
+    //getters
+    pub fn get_id(&self) -> &SignalId {
+        &self.id
+    }
+    pub fn get_code(&self) -> &StockCode {
+        &self.code
+    }
+    pub fn get_date(&self) -> &str {
+        &self.date
+    }
+    pub fn get_status(&self) -> &str {
+        &self.status
+    }
+    pub fn get_candidate(&self) -> Option<&SignalCandidate> {
+        self.candidate.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_id() {
+        let toyota = StockCode::new("7203").unwrap();
+        let id = SignalId::new(WINDOW_SOURCE, &toyota, "2024-01-05").unwrap();
+        assert_eq!(id.as_str().len(), 26);
+        // 2024-01-05T00:00:00Z is 1704412800000 ms
+        assert_eq!(&id.as_str()[..10], &encode(1704412800000u128 << 80)[..10]);
+        assert_eq!(id.date(), "2024-01-05");
+        assert_eq!(
+            id,
+            SignalId::new(WINDOW_SOURCE, &toyota, "2024-01-05").unwrap()
+        );
+        assert_ne!(
+            id,
+            SignalId::new(AFTERNOON_SOURCE, &toyota, "2024-01-05").unwrap()
+        );
+        assert_ne!(
+            id,
+            SignalId::new(
+                WINDOW_SOURCE,
+                &StockCode::new("6758").unwrap(),
+                "2024-01-05"
+            )
+            .unwrap()
+        );
+        assert!(id < SignalId::new(WINDOW_SOURCE, &toyota, "2024-01-09").unwrap());
+        assert!(SignalId::new(WINDOW_SOURCE, &toyota, "2024-13-01").is_err());
+
+        assert_eq!(SignalId::try_from(id.as_str().to_lowercase()).unwrap(), id);
+        assert!(SignalId::try_from("01HN".to_owned()).is_err());
+        assert!(SignalId::try_from("8ZZZZZZZZZZZZZZZZZZZZZZZZZ".to_owned()).is_err());
+        assert_eq!(
+            serde_json::to_string(&id).unwrap(),
+            format!("\"{}\"", id.as_str())
+        );
+
+        let signal = Signal::new(id.clone(), &toyota, "2024-01-05", "Rise");
+        let json = serde_json::to_value(&signal).unwrap();
+        assert!(json.get("candidate").is_none());
+        let signal = signal.with_candidate(SignalCandidate {
+            name: "トヨタ自動車".to_owned(),
+            price: 2500.0,
+            atr: 73.0,
+            unit: 100,
+            short: false,
+        });
+        let json = serde_json::to_string(&signal).unwrap();
+        assert_eq!(serde_json::from_str::<Signal>(&json).unwrap(), signal);
+    }
+}
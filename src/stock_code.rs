@@ -0,0 +1,122 @@
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+use std::str::FromStr;
+
+use crate::my_error::MyError;
+
+/// Securities code in the 4-character form ("7203", "130A").
+/// J-Quants returns 5 characters with a trailing "0", which is dropped on parse,
+/// so codes from the Nikkei 225 csv, the API and the DB compare equal.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct StockCode(String);
+
+impl StockCode {
+    pub fn new(code: &str) -> Result<Self, MyError> {
+        let code = code.trim().to_ascii_uppercase();
+        let code = match code.len() {
+            5 if code.ends_with('0') => &code[..4],
+            _ => &code,
+        };
+
+        let mut chars = code.chars();
+        let valid = code.len() == 4
+            && chars.next().is_some_and(|x| x.is_ascii_digit())
+            && chars.all(|x| x.is_ascii_alphanumeric());
+        match valid {
+            true => Ok(StockCode(code.to_owned())),
+            false => Err(MyError::InvalidStockCode(code.to_owned())),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// 5-character form used by J-Quants ("72030")
+    pub fn to_jquants(&self) -> String {
+        format!("{}0", self.0)
+    }
+}
+
+impl Display for StockCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Deref for StockCode {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for StockCode {
+    type Err = MyError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StockCode::new(s)
+    }
+}
+
+impl TryFrom<String> for StockCode {
+    type Error = MyError;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        StockCode::new(&value)
+    }
+}
+
+impl From<StockCode> for String {
+    fn from(code: StockCode) -> Self {
+        code.0
+    }
+}
+
+/// Stored as TEXT. The old `stocks` table has INTEGER codes, which are read as well.
+impl ToSql for StockCode {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0.as_str()))
+    }
+}
+impl FromSql for StockCode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let code = match value {
+            ValueRef::Integer(x) => x.to_string(),
+            _ => value.as_str()?.to_owned(),
+        };
+        StockCode::new(&code).map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(StockCode::new("7203").unwrap().as_str(), "7203");
+        assert_eq!(StockCode::new("72030").unwrap().as_str(), "7203");
+        assert_eq!(StockCode::new(" 130a0").unwrap().as_str(), "130A");
+        assert_eq!(StockCode::new("7203").unwrap().to_jquants(), "72030");
+        assert_eq!(
+            StockCode::new("72030").unwrap(),
+            StockCode::new("7203").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        for code in ["", "720", "72031", "A203", "72-3", "720300"] {
+            assert!(StockCode::new(code).is_err(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        let code: StockCode = serde_json::from_str("\"86970\"").unwrap();
+        assert_eq!(serde_json::to_string(&code).unwrap(), "\"8697\"");
+        assert!(serde_json::from_str::<StockCode>("\"xyz\"").is_err());
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Amount of money in yen
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(transparent)]
+pub struct Yen(pub i32);
+impl Display for Yen {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}円", self.0)
+    }
+}
+
+/// Price move measured in multiples of ATR
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(transparent)]
+pub struct AtrUnits(pub f64);
+impl Display for AtrUnits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Percentage (50.0 == 50%)
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(transparent)]
+pub struct Pct(pub f64);
+impl Display for Pct {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Yen(125000).to_string(), "125000円");
+        assert_eq!(AtrUnits(-0.35).to_string(), "-0.35");
+        assert_eq!(Pct(52.0).to_string(), "52%");
+    }
+
+    #[test]
+    fn test_serde_transparent() {
+        assert_eq!(serde_json::to_string(&Yen(1000)).unwrap(), "1000");
+        assert_eq!(
+            serde_json::from_str::<AtrUnits>("0.5").unwrap(),
+            AtrUnits(0.5)
+        );
+    }
+}
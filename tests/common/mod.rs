@@ -0,0 +1,194 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use trading23::bot;
+
+/// Codes of the test universe with their names
+pub const CODES: [(&str, &str); 3] = [
+    ("7203", "トヨタ自動車"),
+    ("6758", "ソニーグループ"),
+    ("9984", "ソフトバンクグループ"),
+];
+
+/// A GDRIVE_PATH of its own with config.json pointing at `api_url` and a universe of
+/// `CODES`, removed on drop
+pub struct TempEnv {
+    root: PathBuf,
+}
+
+impl TempEnv {
+    pub fn new(name: &str, api_url: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("trading23_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("trading23")).unwrap();
+
+        let config = json!({
+            "jquantsMail": "test@example.com",
+            "jquantsPw": "password",
+            "jquantsRefreshToken": "refresh",
+            "jquantsIdToken": "id",
+            "jquantsIdTokenAt": chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            "jquantsUnit": "1000000",
+            "jquantsApiUrl": api_url,
+            "jquantsIntervalMs": 0,
+            "lineToken": "",
+            "gmoCoinFxApiKey": "",
+            "gmoCoinFxApiSecret": "",
+            // `daily_quote` is narrow against the range, not a Bollinger squeeze
+            "consolidationFilter": "standardizedDiff",
+        });
+        std::fs::write(
+            root.join("trading23").join("config.json"),
+            serde_json::to_string_pretty(&config).unwrap(),
+        )
+        .unwrap();
+
+        let mut universe = String::from("code,name\n");
+        for (code, name) in CODES {
+            universe.push_str(&format!("{},{}\n", code, name));
+        }
+        std::fs::write(root.join("universe.csv"), universe).unwrap();
+
+        std::env::set_var("GDRIVE_PATH", &root);
+        std::env::remove_var("TRADING23_DB");
+        TempEnv { root }
+    }
+
+    pub fn universe_path(&self) -> PathBuf {
+        self.root.join("universe.csv")
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.root.join("trading23").join("trading23.sqlite")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for TempEnv {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Serves the J-Quants endpoints the nextday run calls, with every weekday a trading
+/// day and deterministic bars for `CODES`. Returns the base URL.
+pub async fn start_mock_jquants() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(_) => return,
+            };
+            tokio::spawn(handle(stream));
+        }
+    });
+    format!("http://{}/v1", addr)
+}
+
+async fn handle(mut stream: TcpStream) {
+    let Ok(request) = bot::read_request(&mut stream).await else {
+        return;
+    };
+
+    let target = request.get_path();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|x| x.split_once('='))
+        .collect::<HashMap<_, _>>();
+    let (status, body) = respond(path.trim_start_matches("/v1"), &query);
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+fn respond(path: &str, query: &HashMap<&str, &str>) -> (&'static str, String) {
+    let body = match path {
+        "/token/auth_user" => json!({"refreshToken": "refresh"}),
+        "/token/auth_refresh" => json!({"idToken": "id"}),
+        "/markets/trading_calendar" => {
+            let (from, to) = (date(query.get("from")), date(query.get("to")));
+            json!({ "trading_calendar": days(from, to)
+                .map(|x| json!({
+                    "Date": x.format("%Y-%m-%d").to_string(),
+                    "HolidayDivision": if is_weekday(x) { "1" } else { "0" },
+                }))
+                .collect::<Vec<_>>() })
+        }
+        "/prices/daily_quotes" => {
+            let bars = match (query.get("date"), query.get("code")) {
+                (Some(x), _) => {
+                    let x = date(Some(x));
+                    CODES
+                        .iter()
+                        .filter(|_| is_weekday(x))
+                        .map(|(code, _)| daily_quote(code, x))
+                        .collect()
+                }
+                (None, Some(code)) => {
+                    let code = &code[..4];
+                    days(date(query.get("from")), date(query.get("to")))
+                        .filter(|x| is_weekday(*x))
+                        .map(|x| daily_quote(code, x))
+                        .collect::<Vec<_>>()
+                }
+                (None, None) => Vec::new(),
+            };
+            json!({ "daily_quotes": bars })
+        }
+        "/markets/trades_spec" => json!({ "trades_spec": [] }),
+        _ => return ("404 Not Found", json!({"message": "not found"}).to_string()),
+    };
+    ("200 OK", body.to_string())
+}
+
+fn date(x: Option<&&str>) -> NaiveDate {
+    x.and_then(|x| NaiveDate::parse_from_str(x, "%Y-%m-%d").ok())
+        .unwrap_or_else(|| chrono::Local::now().date_naive())
+}
+
+fn days(from: NaiveDate, to: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+    (0..=(to - from).num_days()).map(move |i| from + Duration::days(i))
+}
+
+fn is_weekday(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// Deterministic wavy bar of `code` on `date` in an uptrend, narrow against the
+/// 60-day range and liquid enough for the screening filters
+fn daily_quote(code: &str, date: NaiveDate) -> Value {
+    let day = (date - NaiveDate::from_ymd_opt(2020, 1, 1).unwrap()).num_days() as f64;
+    let x = day + code.parse::<f64>().unwrap() * 7.0;
+    let open = 1000.0 + (x * 0.7).sin() * 50.0 + day * 3.0;
+    let close = open + (x * 1.3).cos() * 15.0;
+    let high = open.max(close) + 5.0 + (x % 4.0);
+    let low = open.min(close) - 5.0 - (x % 3.0);
+    json!({
+        "Date": date.format("%Y-%m-%d").to_string(),
+        "Code": format!("{}0", code),
+        "TurnoverValue": 500_000_000.0,
+        "AdjustmentOpen": open,
+        "AdjustmentHigh": high,
+        "AdjustmentLow": low,
+        "AdjustmentClose": close,
+        "AdjustmentVolume": 500_000.0,
+        "MorningAdjustmentClose": (open + close) / 2.0,
+        "AfternoonAdjustmentOpen": (open + close) / 2.0 + 1.0,
+    })
+}
@@ -0,0 +1,99 @@
+//! The nightly nextday run (fetch → analyze → report) against a mock J-Quants server
+//! in a temporary GDRIVE_PATH, `cargo test --features integration`
+use reqwest::Client;
+use rusqlite::Connection;
+use trading23::analysis::stocks_window::{self, RESISTANCE};
+use trading23::database::{self, runs};
+use trading23::jquants::fetcher;
+use trading23::markdown::ReportFormat;
+use trading23::my_file_io::Universe;
+use trading23::output_sink;
+
+mod common;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_nextday_pipeline() {
+    let api_url = common::start_mock_jquants().await;
+    let env = common::TempEnv::new("nextday", &api_url);
+    let universe = Universe::Csv(env.universe_path());
+    let client = Client::new();
+
+    // fetch
+    fetcher::fetch_nikkei225_db(&client, &universe, false)
+        .await
+        .unwrap();
+    let latest = fetcher::check_latest_data(&client).await.unwrap();
+    let coverage = fetcher::check_nikkei225_coverage(&universe).unwrap();
+    assert_eq!(coverage.get_date(), latest);
+    assert!(coverage.get_missing().is_empty());
+    fetcher::update_trades_spec(&client).await.unwrap();
+
+    let conn = Connection::open(env.db_path()).unwrap();
+    let codes: i64 = conn
+        .query_row("SELECT COUNT(DISTINCT code) FROM stocks_ohlc", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(codes, common::CODES.len() as i64);
+    let latest_bars: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM stocks_ohlc WHERE date = ?1",
+            [&latest],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(latest_bars, common::CODES.len() as i64);
+    assert!(
+        runs::select_latest(&conn, fetcher::FETCH_NIKKEI225, &latest)
+            .unwrap()
+            .is_some()
+    );
+
+    // a second run finds nothing missing and stores no duplicate
+    let bars = |conn: &Connection| -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM stocks_ohlc", [], |row| row.get(0))
+            .unwrap()
+    };
+    let before = bars(&conn);
+    fetcher::fetch_nikkei225_db(&client, &universe, false)
+        .await
+        .unwrap();
+    assert_eq!(bars(&conn), before);
+
+    // analyze
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let day_before_5 = (chrono::Local::now() - chrono::Duration::days(5))
+        .format("%Y-%m-%d")
+        .to_string();
+    let stocks_window_list =
+        stocks_window::create_stocks_window_list_db(&universe, &day_before_5, &today)
+            .await
+            .unwrap();
+    let stored = stocks_window_list.save().unwrap();
+    assert!(stored > 0);
+    let windows = database::stocks_window::select_between(&conn, &day_before_5, &today).unwrap();
+    assert_eq!(windows.len(), stored);
+    assert!(windows.iter().all(|x| common::CODES
+        .iter()
+        .any(|(code, _)| x.get_code().as_str() == *code)));
+
+    // report
+    let mut reports = stocks_window_list
+        .for_resistance_strategy_default()
+        .unwrap();
+    reports.extend(stocks_window_list.for_resistance_strategy(true).unwrap());
+    assert!(!reports.is_empty());
+    output_sink::dispatch_all(&client, &reports, ReportFormat::Html, false)
+        .await
+        .unwrap();
+
+    for report in reports.iter().filter(|x| x.get_kind() == RESISTANCE) {
+        let path = RESISTANCE
+            .path(report.get_date())
+            .unwrap()
+            .with_extension("html");
+        assert!(path.starts_with(env.path()));
+        let html = std::fs::read_to_string(&path).unwrap();
+        assert!(html.contains(report.get_date()), "{}", path.display());
+    }
+}